@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use env_logger::Env;
+use log::{error, warn};
+use symmetry_core::{
+    configuration::Configuration,
+    context::SymmetryContext,
+    daemon::Daemon,
+    network::NetworkManager,
+    sync::providers::{self, crdt::CrdtSync},
+};
+
+/// How often to synchronize while everything is reachable.
+const INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+fn main() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("warn")).init();
+    if let Some(data_dir) = data_dir_arg() {
+        SymmetryContext::set_current(SymmetryContext::new(data_dir))
+            .expect("The data directory is only set once.");
+    }
+    // Synchronizing a half moved install would publish it as is.
+    if let Err(err) = SymmetryContext::current().and_then(|context| context.migrate()) {
+        error!("The data couldn't be moved to the current layout: {err:#}");
+        std::process::exit(1);
+    }
+    let mut daemon = Daemon::new(providers::current, INTERVAL);
+    // Other devices on the LAN synchronize with this one for as long as the daemon runs,
+    // and what they leave on the relay is merged as soon as they do.
+    let crdt = Configuration::current()
+        .filter(|config| config.service_config.crdt.enabled)
+        .and_then(|_| CrdtSync::new().map_err(|err| error!("{err:#}")).ok());
+    let _server = crdt.as_ref().and_then(|sync| {
+        sync.listen(("0.0.0.0", sync.port()))
+            .map_err(|err| error!("{err:#}"))
+            .ok()
+    });
+    let _subscription = crdt
+        .as_ref()
+        .filter(|sync| sync.relay().is_some())
+        .and_then(|sync| sync.subscribe().map_err(|err| error!("{err:#}")).ok());
+    match NetworkManager::system() {
+        Ok(monitor) => daemon.set_monitor(Box::new(monitor)),
+        Err(err) => warn!("Connectivity changes won't be detected: {err:#}"),
+    }
+    daemon.run();
+}
+
+/// Reads `--data-dir <path>` or `--data-dir=<path>` from the command line.
+fn data_dir_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--data-dir" {
+            return args.next();
+        }
+        if let Some(data_dir) = arg.strip_prefix("--data-dir=") {
+            return Some(data_dir.into());
+        }
+    }
+    None
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use env_logger::Env;
+use symmetry_core::sync::relay::{server::Server, DEFAULT_PORT};
+
+/// Relays the sealed states of devices that can't reach each other directly.
+///
+/// Usage: `symmetry-relay [address] [directory]`, listening on all interfaces and storing
+/// the states in the data directory by default.
+fn main() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("warn")).init();
+    let mut args = std::env::args().skip(1);
+    let address = args
+        .next()
+        .unwrap_or_else(|| format!("0.0.0.0:{DEFAULT_PORT}"));
+    let storage = args
+        .next()
+        .map(PathBuf::from)
+        .or_else(|| dirs::data_dir().map(|data| data.join("symmetry-relay")));
+    let Some(storage) = storage else {
+        eprintln!("There's no data directory, pass one to store the states in.");
+        std::process::exit(1);
+    };
+    match Server::bind(&address, &storage) {
+        Ok(server) => {
+            println!(
+                "Relaying on {}, storing states in {}",
+                server.address(),
+                storage.display()
+            );
+            loop {
+                std::thread::park();
+            }
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
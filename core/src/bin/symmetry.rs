@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use symmetry_core::{
+    configuration::bundle::{Bundle, Mode},
+    context::SymmetryContext,
+};
+
+const USAGE: &str = "Usage:
+  symmetry export <file>                      Writes the configuration, files and wallpaper
+                                              to a signed bundle
+  symmetry import <file> [--merge|--replace]  Takes what this device lacks from a bundle,
+                                              or replaces everything with it
+
+Options:
+  --data-dir <path>                           Uses the data directory at <path>";
+
+/// Exports and imports configuration bundles, to set up devices that can't synchronize yet.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(args) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+/// Takes `--data-dir <path>` or `--data-dir=<path>` out of the arguments.
+fn data_dir_arg(args: &mut Vec<String>) -> Result<Option<String>> {
+    let Some(index) = args
+        .iter()
+        .position(|arg| arg == "--data-dir" || arg.starts_with("--data-dir="))
+    else {
+        return Ok(None);
+    };
+    let arg = args.remove(index);
+    if let Some(data_dir) = arg.strip_prefix("--data-dir=") {
+        return Ok(Some(data_dir.into()));
+    }
+    if index == args.len() {
+        bail!("{USAGE}");
+    }
+    Ok(Some(args.remove(index)))
+}
+
+fn run(mut args: Vec<String>) -> Result<()> {
+    if let Some(data_dir) = data_dir_arg(&mut args)? {
+        SymmetryContext::set_current(SymmetryContext::new(data_dir))?;
+    }
+    let context = SymmetryContext::current()?;
+    match args.as_slice() {
+        [command, file] if command == "export" => {
+            let bundle = Bundle::export(&context, Path::new(file))?;
+            println!(
+                "Exported the configuration of {} to {file}",
+                bundle.signer.name
+            );
+        }
+        [command, file, options @ ..] if command == "import" => {
+            let mode = match options {
+                [] => Mode::Merge,
+                [option] if option == "--merge" => Mode::Merge,
+                [option] if option == "--replace" => Mode::Replace,
+                _ => bail!("{USAGE}"),
+            };
+            let bundle = Bundle::open(Path::new(file))?;
+            println!(
+                "Importing the bundle exported by {} ({}) on {}",
+                bundle.signer.name,
+                bundle.signer.id(),
+                bundle.created
+            );
+            for failure in bundle.import(&context, mode)? {
+                eprintln!("Couldn't apply {failure}");
+            }
+        }
+        _ => bail!("{USAGE}"),
+    }
+    Ok(())
+}
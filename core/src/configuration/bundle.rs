@@ -0,0 +1,236 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    context::SymmetryContext,
+    sync::{self, store::mirror::Manifest},
+    trust::{
+        identity::{self, Identity},
+        TrustedDevice,
+    },
+};
+
+use super::{changes, Configuration, CONFIG_FILE};
+
+/// Directory in the app directory holding the wallpapers taken from bundles.
+const WALLPAPERS: &str = "wallpapers";
+/// Name of an imported wallpaper whose path has none.
+const WALLPAPER: &str = "wallpaper";
+
+/// How an imported bundle is combined with what's already on the device.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Keeps this device's settings and files where both have one, adding the others.
+    #[default]
+    Merge,
+    /// Drops this device's settings and files for those in the bundle.
+    Replace,
+}
+
+/// The configuration, synchronized files and wallpaper of a device in a single signed
+/// file, to set up another device that can't synchronize yet.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// The device that exported the bundle, whose signature was checked.
+    pub signer: TrustedDevice,
+    /// When the bundle was exported.
+    pub created: String,
+    pub configuration: Configuration,
+    contents: Contents,
+}
+
+/// The signed part of a bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Contents {
+    signer: TrustedDevice,
+    created: String,
+    /// The synchronized files by path, base64 encoded, the configuration included.
+    files: BTreeMap<String, String>,
+    /// The image the wallpaper setting points to, base64 encoded.
+    #[serde(default)]
+    wallpaper: Option<String>,
+}
+
+/// A bundle as written to disk.
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    /// The contents serialized, exactly as they were signed.
+    contents: String,
+    signature: String,
+}
+
+impl Bundle {
+    /// Writes the configuration, synchronized files and wallpaper of `context` to `path`,
+    /// signed by this device.
+    pub fn export(context: &SymmetryContext, path: &Path) -> Result<Self> {
+        let repo = context.repo_path();
+        sync::capture(context)?;
+        let Some(configuration) = Configuration::read_from(&repo.join(CONFIG_FILE)) else {
+            bail!("There's no configuration to export.");
+        };
+        let mut files = BTreeMap::new();
+        for file in Manifest::scan(&repo)?.files.into_keys() {
+            files.insert(
+                file.clone(),
+                STANDARD.encode(std::fs::read(repo.join(&file))?),
+            );
+        }
+        let wallpaper = PathBuf::from(configuration.rendered(context)?.wallpaper);
+        let wallpaper = match wallpaper.is_file() {
+            true => Some(STANDARD.encode(std::fs::read(wallpaper)?)),
+            false => None,
+        };
+        let identity = Identity::load(&context.state_path())?;
+        let contents = Contents {
+            signer: identity.device(),
+            created: sync::timestamp(SystemTime::now()),
+            files,
+            wallpaper,
+        };
+        let serialized = ron::to_string(&contents)?;
+        let archive = Archive {
+            signature: identity.sign(serialized.as_bytes()),
+            contents: serialized,
+        };
+        std::fs::write(
+            path,
+            ron::ser::to_string_pretty(&archive, PrettyConfig::new())?,
+        )
+        .with_context(|| format!("Failed to write the bundle to {}.", path.display()))?;
+        Ok(Self {
+            signer: contents.signer.clone(),
+            created: contents.created.clone(),
+            configuration,
+            contents,
+        })
+    }
+
+    /// Reads the bundle at `path`, checking it wasn't altered since it was signed.
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the bundle at {}.", path.display()))?;
+        let archive: Archive = ron::from_str(&data).context("This isn't a Symmetry bundle.")?;
+        let contents: Contents =
+            ron::from_str(&archive.contents).context("The bundle is damaged.")?;
+        identity::verify(
+            &contents.signer.signing_key,
+            archive.contents.as_bytes(),
+            &archive.signature,
+        )
+        .context("The bundle was altered after it was exported.")?;
+        let Some(configuration) = contents.files.get(CONFIG_FILE) else {
+            bail!("The bundle holds no configuration.");
+        };
+        let configuration = ron::from_str(std::str::from_utf8(&STANDARD.decode(configuration)?)?)
+            .context("The configuration in the bundle is damaged.")?;
+        Ok(Self {
+            signer: contents.signer.clone(),
+            created: contents.created.clone(),
+            configuration,
+            contents,
+        })
+    }
+
+    /// Brings the bundle into `context` and applies it to the device, returning what
+    /// couldn't be applied.
+    ///
+    /// A device already paired with others only takes bundles signed by one of them, the
+    /// others take any bundle, as that's how they are first set up. Either way the devices
+    /// this one trusts are left as they are: a bundle only proves it matches the key it
+    /// carries, so trusting its signer is left to pairing.
+    pub fn import(&self, context: &SymmetryContext, mode: Mode) -> Result<Vec<String>> {
+        let repo = context.repo_path();
+        let state = context.state_path();
+        let local = Configuration::read_from(&repo.join(CONFIG_FILE)).unwrap_or_default();
+        let identity = Identity::load(&state)?;
+        if local.devices.is_paired(&identity)
+            && local.devices.find(&self.signer.signing_key).is_none()
+        {
+            bail!(
+                "The bundle was signed by {} ({}), which this device doesn't trust.",
+                self.signer.name,
+                self.signer.id()
+            );
+        }
+        let files = self
+            .contents
+            .files
+            .iter()
+            .filter(|(path, _)| *path != CONFIG_FILE)
+            .map(|(path, data)| Ok((path, sync::inside(&repo, path)?, data)))
+            .collect::<Result<Vec<_>>>()?;
+        std::fs::create_dir_all(&repo)?;
+        let current = Manifest::scan(&repo)?;
+        for (path, file, data) in files {
+            if mode == Mode::Merge && current.files.contains_key(path) {
+                continue;
+            }
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(file, STANDARD.decode(data)?)?;
+        }
+        if mode == Mode::Replace {
+            for path in current.files.keys() {
+                if !self.contents.files.contains_key(path) {
+                    std::fs::remove_file(repo.join(path))?;
+                }
+            }
+        }
+        let mut configuration = match mode {
+            Mode::Merge => merge(&local, &self.configuration),
+            Mode::Replace => self.configuration.clone(),
+        };
+        configuration.devices = local.devices;
+        self.place_wallpaper(context, &mut configuration)?;
+        configuration.write_to(&repo.join(CONFIG_FILE))?;
+        Ok(sync::apply(context))
+    }
+
+    /// Writes the wallpaper of the bundle to the app directory of `context` and points
+    /// `configuration` at it, unless the device already has the image it points to.
+    fn place_wallpaper(
+        &self,
+        context: &SymmetryContext,
+        configuration: &mut Configuration,
+    ) -> Result<()> {
+        let Some(data) = &self.contents.wallpaper else {
+            return Ok(());
+        };
+        if configuration.wallpaper != self.configuration.wallpaper {
+            return Ok(());
+        }
+        let wallpaper = PathBuf::from(configuration.rendered(context)?.wallpaper);
+        if wallpaper.as_os_str().is_empty() || wallpaper.is_file() {
+            return Ok(());
+        }
+        let name = wallpaper
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| WALLPAPER.into());
+        let path = context.local_path().join(WALLPAPERS).join(name);
+        std::fs::create_dir_all(context.local_path().join(WALLPAPERS))?;
+        std::fs::write(&path, STANDARD.decode(data)?)?;
+        configuration.wallpaper = path.to_string_lossy().to_string();
+        Ok(())
+    }
+}
+
+/// Takes the settings of `bundle` this device doesn't have, keeping its own where both
+/// set one differently.
+fn merge(local: &Configuration, bundle: &Configuration) -> Configuration {
+    let base = Configuration::default();
+    let mut theirs = bundle.clone();
+    for field in changes::conflicts(&base, local, bundle) {
+        field.copy(local, &mut theirs);
+    }
+    Configuration::merge(&base, local, &theirs).unwrap_or_else(|| local.clone())
+}
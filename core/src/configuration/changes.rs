@@ -0,0 +1,224 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::Configuration;
+
+/// A top-level field of the configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    ColorScheme,
+    Wallpaper,
+    ActiveService,
+    ServiceConfig,
+    Desktop,
+    Files,
+    Variables,
+    Secrets,
+    Devices,
+}
+
+/// A field that differs between two configurations, with a short description of how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub field: Field,
+    pub summary: String,
+}
+
+impl Field {
+    pub const ALL: [Field; 9] = [
+        Field::ColorScheme,
+        Field::Wallpaper,
+        Field::ActiveService,
+        Field::ServiceConfig,
+        Field::Desktop,
+        Field::Files,
+        Field::Variables,
+        Field::Secrets,
+        Field::Devices,
+    ];
+
+    /// The name of the field as stored in the configuration file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::ColorScheme => "color_scheme",
+            Field::Wallpaper => "wallpaper",
+            Field::ActiveService => "active_service",
+            Field::ServiceConfig => "service_config",
+            Field::Desktop => "desktop",
+            Field::Files => "files",
+            Field::Variables => "variables",
+            Field::Secrets => "secrets",
+            Field::Devices => "devices",
+        }
+    }
+
+    /// Finds a field by the name it's stored under.
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL.into_iter().find(|field| field.name() == name)
+    }
+
+    /// Serializes this field of `config` on its own, so it can be synchronized separately.
+    pub fn value(&self, config: &Configuration) -> Result<String> {
+        Ok(match self {
+            Field::ColorScheme => ron::to_string(&config.color_scheme)?,
+            Field::Wallpaper => ron::to_string(&config.wallpaper)?,
+            Field::ActiveService => ron::to_string(&config.active_service)?,
+            Field::ServiceConfig => ron::to_string(&config.service_config)?,
+            Field::Desktop => ron::to_string(&config.desktop)?,
+            Field::Files => ron::to_string(&config.files)?,
+            Field::Variables => ron::to_string(&config.variables)?,
+            Field::Secrets => ron::to_string(&config.secrets)?,
+            Field::Devices => ron::to_string(&config.devices)?,
+        })
+    }
+
+    /// Sets this field of `config` from a value serialized by [`Field::value`].
+    pub fn set(&self, config: &mut Configuration, value: &str) -> Result<()> {
+        match self {
+            Field::ColorScheme => config.color_scheme = ron::from_str(value)?,
+            Field::Wallpaper => config.wallpaper = ron::from_str(value)?,
+            Field::ActiveService => config.active_service = ron::from_str(value)?,
+            Field::ServiceConfig => config.service_config = ron::from_str(value)?,
+            Field::Desktop => config.desktop = ron::from_str(value)?,
+            Field::Files => config.files = ron::from_str(value)?,
+            Field::Variables => config.variables = ron::from_str(value)?,
+            Field::Secrets => config.secrets = ron::from_str(value)?,
+            Field::Devices => config.devices = ron::from_str(value)?,
+        }
+        Ok(())
+    }
+
+    /// Copies this field from `source` into `target`.
+    pub fn copy(&self, source: &Configuration, target: &mut Configuration) {
+        match self {
+            Field::ColorScheme => target.color_scheme = source.color_scheme,
+            Field::Wallpaper => target.wallpaper = source.wallpaper.clone(),
+            Field::ActiveService => target.active_service = source.active_service.clone(),
+            Field::ServiceConfig => target.service_config = source.service_config.clone(),
+            Field::Desktop => target.desktop = source.desktop.clone(),
+            Field::Files => target.files = source.files.clone(),
+            Field::Variables => target.variables = source.variables.clone(),
+            Field::Secrets => target.secrets = source.secrets.clone(),
+            Field::Devices => target.devices = source.devices.clone(),
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.field.name(), self.summary)
+    }
+}
+
+/// Lists the fields changed from `old` to `new`, in declaration order.
+pub fn diff(old: &Configuration, new: &Configuration) -> Vec<Change> {
+    Field::ALL
+        .into_iter()
+        .filter_map(|field| {
+            let summary = summary(field, old, new)?;
+            Some(Change { field, summary })
+        })
+        .collect()
+}
+
+/// Lists the fields both sides changed from `base`, to different values.
+pub fn conflicts(
+    base: &Configuration,
+    local: &Configuration,
+    remote: &Configuration,
+) -> Vec<Field> {
+    let remote_changes = diff(base, remote);
+    diff(base, local)
+        .into_iter()
+        .map(|change| change.field)
+        .filter(|field| {
+            let mut theirs = local.clone();
+            field.copy(remote, &mut theirs);
+            remote_changes.iter().any(|other| other.field == *field) && theirs != *local
+        })
+        .collect()
+}
+
+/// Describes how a field changed, or returns `None` if it didn't.
+fn summary(field: Field, old: &Configuration, new: &Configuration) -> Option<String> {
+    let changed = " changed".to_string();
+    match field {
+        Field::ColorScheme => (old.color_scheme != new.color_scheme)
+            .then(|| format!(": {:?} → {:?}", old.color_scheme, new.color_scheme)),
+        Field::Wallpaper => (old.wallpaper != new.wallpaper).then_some(changed),
+        Field::ActiveService => (old.active_service != new.active_service)
+            .then(|| format!(": {:?} → {:?}", old.active_service, new.active_service)),
+        Field::ServiceConfig => (old.service_config != new.service_config).then_some(changed),
+        Field::Desktop => {
+            let count = changed_keys(&old.desktop.cosmic.entries, &new.desktop.cosmic.entries)
+                + changed_keys(&old.desktop.kde.entries, &new.desktop.kde.entries);
+            if count > 0 {
+                Some(format!(": {count} {} changed", plural(count, "setting")))
+            } else {
+                (old.desktop != new.desktop).then_some(changed)
+            }
+        }
+        Field::Files => {
+            let sources = |config: &Configuration| -> Vec<String> {
+                config
+                    .files
+                    .iter()
+                    .map(|file| file.source.clone())
+                    .collect()
+            };
+            let (old_sources, new_sources) = (sources(old), sources(new));
+            let mut parts = vec![];
+            for source in new_sources.iter().filter(|s| !old_sources.contains(s)) {
+                parts.push(format!("added {source}"));
+            }
+            for source in old_sources.iter().filter(|s| !new_sources.contains(s)) {
+                parts.push(format!("removed {source}"));
+            }
+            if !parts.is_empty() {
+                Some(format!(": {}", parts.join(", ")))
+            } else {
+                (old.files != new.files).then_some(changed)
+            }
+        }
+        Field::Variables => (old.variables != new.variables).then_some(changed),
+        Field::Secrets => {
+            let count = changed_keys(&old.secrets, &new.secrets);
+            (count > 0).then(|| format!(": {count} {} changed", plural(count, "secret")))
+        }
+        Field::Devices => {
+            let (before, after) = (&old.devices.trusted, &new.devices.trusted);
+            let mut parts = vec![];
+            for (id, device) in after.iter().filter(|(id, _)| !before.contains_key(*id)) {
+                parts.push(format!("added {} ({id})", device.name));
+            }
+            for (id, device) in before.iter().filter(|(id, _)| !after.contains_key(*id)) {
+                parts.push(format!("removed {} ({id})", device.name));
+            }
+            if !parts.is_empty() {
+                Some(format!(": {}", parts.join(", ")))
+            } else {
+                (old.devices != new.devices).then_some(changed)
+            }
+        }
+    }
+}
+
+fn changed_keys(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> usize {
+    let removed = old.keys().filter(|key| !new.contains_key(*key)).count();
+    let changed = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(value))
+        .count();
+    removed + changed
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        word.to_string()
+    } else {
+        format!("{word}s")
+    }
+}
@@ -1,17 +1,34 @@
+pub mod bundle;
+pub mod changes;
 pub mod repository_type;
 
-use std::{io::Write, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
+use log::warn;
 use ron::ser::PrettyConfig;
 use serde::{Deserialize, Serialize};
 
-use crate::{color_scheme::ColorScheme, sync::providers::config::Services};
+use crate::{
+    color_scheme::ColorScheme,
+    context::SymmetryContext,
+    desktop::{merge::pick, Desktop},
+    files::tracked_file::TrackedFile,
+    sync::providers::config::Services,
+    template::Variables,
+    trust::Devices,
+};
 
-use self::repository_type::Service;
+use self::{changes::Change, repository_type::Service};
 
 pub const APP_NAME: &str = "symmetry";
-pub const CONFIG_PATH: &str = "symmetry/configuration.ron";
+pub const CONFIG_FILE: &str = "configuration.ron";
+/// Where backups were kept before they moved into the state directory.
+pub const BACKUP_PATH: &str = "symmetry-backups";
 
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Configuration {
@@ -19,6 +36,19 @@ pub struct Configuration {
     pub wallpaper: String,
     pub active_service: Service,
     pub service_config: Services,
+    #[serde(default)]
+    pub desktop: Desktop,
+    #[serde(default)]
+    pub files: Vec<TrackedFile>,
+    /// Custom template variables, keyed by device name.
+    #[serde(default)]
+    pub variables: BTreeMap<String, BTreeMap<String, String>>,
+    /// Secrets encrypted with the sync key, the plaintext only lives in the keyring.
+    #[serde(default)]
+    pub secrets: BTreeMap<String, String>,
+    /// The devices allowed to synchronize directly with each other.
+    #[serde(default)]
+    pub devices: Devices,
 }
 
 impl Configuration {
@@ -27,19 +57,24 @@ impl Configuration {
         Self::default()
     }
 
-    /// Gets the current configuration.
+    /// Gets the configuration of the context of this process, see [`Configuration::load`]
+    /// for another one.
     pub fn current() -> Option<Self> {
-        let data_dir = dirs::data_dir()
-            .context("Data directory not available.")
-            .ok();
-        if data_dir.is_none() {
-            return None;
-        }
-        if let Ok(data) = std::fs::read_to_string(data_dir.unwrap().join(CONFIG_PATH)) {
+        Self::load(&SymmetryContext::current().ok()?)
+    }
+
+    /// Gets the configuration of the given context.
+    pub fn load(context: &SymmetryContext) -> Option<Self> {
+        Self::read_from(&context.config_path())
+    }
+
+    /// Reads the configuration stored at `path`, if it exists and is valid.
+    pub fn read_from(path: &Path) -> Option<Self> {
+        if let Ok(data) = std::fs::read_to_string(path) {
             return match ron::from_str(data.as_str()) {
                 Ok(config) => Some(config),
                 Err(err) => {
-                    eprintln!("{err}");
+                    warn!("{}: {err}", path.display());
                     None
                 }
             };
@@ -48,17 +83,21 @@ impl Configuration {
     }
 
     pub fn path() -> Result<PathBuf> {
-        let path = dirs::data_dir()
-            .context("Data directory not available.")?
-            .join(CONFIG_PATH);
-        Ok(path)
+        Ok(SymmetryContext::current()?.config_path())
     }
 
     pub fn local_path() -> Result<PathBuf> {
-        let path = dirs::data_dir()
-            .context("Data directory not available.")?
-            .join(APP_NAME);
-        Ok(path)
+        Ok(SymmetryContext::current()?.local_path())
+    }
+
+    /// The working tree of the sync repository.
+    pub fn repo_path() -> Result<PathBuf> {
+        Ok(SymmetryContext::current()?.repo_path())
+    }
+
+    /// The directory where originals of adopted files are kept, outside of the repository.
+    pub fn backup_path() -> Result<PathBuf> {
+        Ok(SymmetryContext::current()?.backup_path())
     }
 
     /// Creates a new instance from a path.
@@ -83,12 +122,13 @@ impl Configuration {
     /// }
     /// ```
     pub fn init(&self) -> Result<()> {
-        let data_dir = dirs::data_dir().context("Data directory not available.")?;
-        let app_config_dir = data_dir.join(APP_NAME);
-        std::fs::create_dir_all(&app_config_dir)?;
-        std::fs::File::create(data_dir.join(CONFIG_PATH))?;
-        Self::write(self)?;
-        Ok(())
+        self.init_in(&SymmetryContext::current()?)
+    }
+
+    /// Creates the app directory of the given context and saves the configuration to it.
+    pub fn init_in(&self, context: &SymmetryContext) -> Result<()> {
+        std::fs::create_dir_all(context.repo_path())?;
+        self.write_to(&context.config_path())
     }
 
     /// Writes the current configuration to `configuration.toml`.
@@ -107,10 +147,73 @@ impl Configuration {
     /// }
     /// ```
     pub fn write(&self) -> Result<()> {
-        let config_file_dir = dirs::data_dir().unwrap().join(CONFIG_PATH);
-        let config = ron::ser::to_string_pretty(self, PrettyConfig::new().struct_names(true))?;
-        let mut file = std::fs::File::create(&config_file_dir)?;
+        self.write_in(&SymmetryContext::current()?)
+    }
+
+    /// Writes the configuration of the given context.
+    pub fn write_in(&self, context: &SymmetryContext) -> Result<()> {
+        self.write_to(&context.config_path())
+    }
+
+    /// Writes the configuration to `path`.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let config = self.to_ron()?;
+        let mut file = std::fs::File::create(path)?;
         file.write_all(config.as_bytes())?;
         Ok(())
     }
+
+    /// Serializes the configuration the same way it is stored on disk.
+    pub fn to_ron(&self) -> Result<String> {
+        let config = ron::ser::to_string_pretty(self, PrettyConfig::new().struct_names(true))?;
+        Ok(config)
+    }
+
+    /// Renders the templated string fields for the device of `context`, the wallpaper and
+    /// the desktop settings. The configuration itself is always stored unrendered.
+    pub fn rendered(&self, context: &SymmetryContext) -> Result<Self> {
+        let variables = Variables::new(context, self);
+        let mut config = self.clone();
+        config.wallpaper = variables.render(&self.wallpaper)?;
+        let desktop = &mut config.desktop;
+        for entries in [&mut desktop.cosmic.entries, &mut desktop.kde.entries] {
+            for value in entries.values_mut() {
+                *value = variables.render(value)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Lists the fields that changed from this configuration to `newer`.
+    pub fn changes(&self, newer: &Self) -> Vec<Change> {
+        changes::diff(self, newer)
+    }
+
+    /// Merges two diverged configurations against their common ancestor, field by field.
+    /// Returns `None` if the same value was changed differently on both sides.
+    pub fn merge(base: &Self, local: &Self, remote: &Self) -> Option<Self> {
+        Some(Self {
+            color_scheme: pick(
+                &base.color_scheme,
+                &local.color_scheme,
+                &remote.color_scheme,
+            )?,
+            wallpaper: pick(&base.wallpaper, &local.wallpaper, &remote.wallpaper)?,
+            active_service: pick(
+                &base.active_service,
+                &local.active_service,
+                &remote.active_service,
+            )?,
+            service_config: pick(
+                &base.service_config,
+                &local.service_config,
+                &remote.service_config,
+            )?,
+            desktop: Desktop::merge(&base.desktop, &local.desktop, &remote.desktop)?,
+            files: pick(&base.files, &local.files, &remote.files)?,
+            variables: pick(&base.variables, &local.variables, &remote.variables)?,
+            secrets: pick(&base.secrets, &local.secrets, &remote.secrets)?,
+            devices: pick(&base.devices, &local.devices, &remote.devices)?,
+        })
+    }
 }
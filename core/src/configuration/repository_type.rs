@@ -6,3 +6,14 @@ pub enum Service {
     Git,
     Crdt,
 }
+
+impl Service {
+    /// The name of the [registered backend](crate::sync::providers::registry) providing
+    /// the service.
+    pub fn backend(&self) -> &'static str {
+        match self {
+            Service::Git => "git",
+            Service::Crdt => "crdt",
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    configuration::{Configuration, APP_NAME, BACKUP_PATH, CONFIG_FILE},
+    device::Device,
+    files::Files,
+};
+
+/// Environment variable overriding the data directory, e.g. for portable installs.
+pub const DATA_DIR_ENV: &str = "SYMMETRY_DATA_DIR";
+/// Directory holding the synchronized repository, inside the app directory.
+pub const REPO_DIR: &str = "repo";
+/// Directory holding files that only concern this device, inside the app directory.
+pub const STATE_DIR: &str = "state";
+
+static CURRENT: OnceCell<SymmetryContext> = OnceCell::new();
+
+/// Where an instance of Symmetry keeps its configuration, repository and backups, and the
+/// device and user directories it synchronizes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymmetryContext {
+    data_dir: PathBuf,
+    /// Stands in for the user's home directory, where `~` leads.
+    home_dir: Option<PathBuf>,
+    /// Stands in for the user's config directory, holding the desktop settings.
+    config_dir: Option<PathBuf>,
+    /// Stands in for the name of this device, its hostname otherwise.
+    device: Option<String>,
+}
+
+impl SymmetryContext {
+    /// Creates a context rooted at `data_dir`, the app files live in `data_dir/symmetry`.
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            data_dir: data_dir.into(),
+            home_dir: None,
+            config_dir: None,
+            device: None,
+        }
+    }
+
+    /// The context of this process: the one set with [`SymmetryContext::set_current`],
+    /// then the `SYMMETRY_DATA_DIR` environment variable, then the user's data directory.
+    pub fn current() -> Result<Self> {
+        if let Some(context) = CURRENT.get() {
+            return Ok(context.clone());
+        }
+        Self::from_env(std::env::var_os(DATA_DIR_ENV))
+    }
+
+    /// The context `data_dir`, the value of `SYMMETRY_DATA_DIR`, points at. The user's data
+    /// directory when it's unset or empty.
+    pub fn from_env(data_dir: Option<OsString>) -> Result<Self> {
+        if let Some(data_dir) = data_dir.filter(|dir| !dir.is_empty()) {
+            return Ok(Self::new(data_dir));
+        }
+        let data_dir = dirs::data_dir().context("Data directory not available.")?;
+        Ok(Self::new(data_dir))
+    }
+
+    pub fn with_home_dir(mut self, home_dir: impl Into<PathBuf>) -> Self {
+        self.home_dir = Some(home_dir.into());
+        self
+    }
+
+    pub fn with_config_dir(mut self, config_dir: impl Into<PathBuf>) -> Self {
+        self.config_dir = Some(config_dir.into());
+        self
+    }
+
+    pub fn with_device(mut self, name: impl Into<String>) -> Self {
+        self.device = Some(name.into());
+        self
+    }
+
+    /// Overrides the context of this process, e.g. from a command line flag.
+    /// Fails if it was already set.
+    pub fn set_current(context: SymmetryContext) -> Result<()> {
+        CURRENT
+            .set(context)
+            .ok()
+            .context("The data directory was already set.")
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// The user's home directory.
+    pub fn home_dir(&self) -> Result<PathBuf> {
+        self.home_dir
+            .clone()
+            .or_else(dirs::home_dir)
+            .context("Home directory not available.")
+    }
+
+    /// The user's config directory, where the desktops keep their settings.
+    pub fn config_dir(&self) -> Result<PathBuf> {
+        self.config_dir
+            .clone()
+            .or_else(dirs::config_dir)
+            .context("Config directory not available.")
+    }
+
+    /// The device this context runs on.
+    pub fn device(&self) -> Device {
+        match &self.device {
+            Some(name) => Device { name: name.clone() },
+            None => Device::current(),
+        }
+    }
+
+    /// The app directory, holding the sync repository and the device state.
+    pub fn local_path(&self) -> PathBuf {
+        self.data_dir.join(APP_NAME)
+    }
+
+    /// The working tree of the sync repository, everything in it is synchronized.
+    pub fn repo_path(&self) -> PathBuf {
+        self.local_path().join(REPO_DIR)
+    }
+
+    /// Caches, logs and other files that are never synchronized.
+    pub fn state_path(&self) -> PathBuf {
+        self.local_path().join(STATE_DIR)
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.repo_path().join(CONFIG_FILE)
+    }
+
+    /// The directory where originals of adopted files are kept.
+    pub fn backup_path(&self) -> PathBuf {
+        self.state_path().join("backups")
+    }
+
+    /// Moves installs where the app directory was the repository itself to the current layout,
+    /// pointing symlinked files at their new location.
+    pub fn migrate(&self) -> Result<()> {
+        let local = self.local_path();
+        let repo = self.repo_path();
+        if !repo.exists() && (local.join(".git").exists() || local.join(CONFIG_FILE).exists()) {
+            std::fs::create_dir_all(&repo)?;
+            for entry in std::fs::read_dir(&local)? {
+                let name = entry?.file_name();
+                if name != REPO_DIR && name != STATE_DIR {
+                    std::fs::rename(local.join(&name), repo.join(&name))?;
+                }
+            }
+            if let Some(config) = Configuration::load(self) {
+                Files::new(self).relink(&config, &local)?;
+            }
+        }
+
+        let backups = self.data_dir.join(BACKUP_PATH);
+        if backups.exists() && !self.backup_path().exists() {
+            std::fs::create_dir_all(self.state_path())?;
+            std::fs::rename(backups, self.backup_path())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+pub mod backoff;
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{error, warn};
+
+use crate::{
+    network::{Connectivity, MeteredPolicy, NetworkMonitor},
+    sync::status::Status,
+};
+
+use self::backoff::Backoff;
+
+pub use crate::sync::providers::Provider;
+
+/// How often connectivity is checked while waiting for the next run, when the monitor
+/// can't tell about changes.
+const NETWORK_POLL: Duration = Duration::from_secs(30);
+
+/// Synchronizes in the background, retrying with backoff while the remote is unreachable
+/// so devices converge once connectivity returns.
+pub struct Daemon<F: FnMut() -> Result<Option<Provider>>> {
+    /// Creates the provider for every run, so configuration changes are picked up.
+    provider: F,
+    interval: Duration,
+    backoff: Backoff,
+    monitor: Option<Box<dyn NetworkMonitor>>,
+    poll: Duration,
+    /// Connectivity as of the last check.
+    connectivity: Connectivity,
+    /// Whether the monitor failed the last check, so the failure isn't reported again.
+    monitor_failing: bool,
+    /// Whether the monitor failed to wait for changes, so the failure isn't reported again.
+    waiting_failing: bool,
+}
+
+impl<F: FnMut() -> Result<Option<Provider>>> Daemon<F> {
+    pub fn new(provider: F, interval: Duration) -> Self {
+        Self {
+            provider,
+            interval,
+            backoff: Backoff::default(),
+            monitor: None,
+            poll: NETWORK_POLL,
+            connectivity: Connectivity::Unmetered,
+            monitor_failing: false,
+            waiting_failing: false,
+        }
+    }
+
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    /// Holds syncs back while offline, unless the provider doesn't need the network, or on
+    /// a metered link the provider shouldn't use,
+    /// and syncs as soon as a usable link comes back.
+    pub fn set_monitor(&mut self, monitor: Box<dyn NetworkMonitor>) {
+        self.monitor = Some(monitor);
+    }
+
+    /// Sets how often connectivity is checked while waiting for the next run.
+    pub fn set_poll_interval(&mut self, poll: Duration) {
+        self.poll = poll;
+    }
+
+    /// Runs a single synchronization. Remote changes are reported and left pending, for the
+    /// user to review before they're pulled.
+    /// Returns the resulting status along with how long to wait before the next run, sooner
+    /// than the interval when the provider holds changes back until then.
+    /// No status is returned when the run was skipped or failed.
+    pub fn tick(&mut self) -> (Option<Status>, Duration) {
+        let offline = self.check_connectivity() == Connectivity::Offline;
+        let provider = match (self.provider)() {
+            Ok(Some(provider)) => provider,
+            Ok(None) => return (None, self.interval),
+            Err(err) => {
+                error!("{err:#}");
+                return (None, self.interval);
+            }
+        };
+        if offline && provider.requires_network() {
+            return (None, self.interval);
+        }
+        let metered = self.connectivity == Connectivity::Metered;
+        if metered && provider.metered_policy() == MeteredPolicy::Defer {
+            return (None, self.interval);
+        }
+        let status = if offline {
+            provider.sync_offline()
+        } else if metered {
+            provider.sync_metered()
+        } else {
+            provider.sync()
+        };
+        match status {
+            Ok(status @ (Status::Offline { .. } | Status::AuthRequired)) => {
+                (Some(status), self.backoff.next_delay())
+            }
+            Ok(status) => {
+                self.backoff.reset();
+                let delay = provider
+                    .pending()
+                    .map_or(self.interval, |due| due.min(self.interval));
+                (Some(status), delay)
+            }
+            Err(err) => {
+                error!("{err:#}");
+                (None, self.backoff.next_delay())
+            }
+        }
+    }
+
+    /// Checks connectivity again and tells whether it improved since the last check,
+    /// either coming back online or moving off a metered link, so a sync should run now.
+    pub fn network_changed(&mut self) -> bool {
+        let previous = self.connectivity;
+        let current = self.check_connectivity();
+        let improved = matches!(
+            (previous, current),
+            (
+                Connectivity::Offline,
+                Connectivity::Metered | Connectivity::Unmetered
+            ) | (Connectivity::Metered, Connectivity::Unmetered)
+        );
+        if improved {
+            self.backoff.reset();
+        }
+        improved
+    }
+
+    /// Keeps synchronizing until the process ends.
+    pub fn run(&mut self) {
+        loop {
+            let (_, delay) = self.tick();
+            self.wait(delay);
+        }
+    }
+
+    /// Sleeps for `delay`, waking up early when the network comes back.
+    pub fn wait(&mut self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return;
+            }
+            let Some(monitor) = self.monitor.as_ref() else {
+                return std::thread::sleep(remaining);
+            };
+            let changed = match monitor.wait(remaining) {
+                Ok(changed) => {
+                    self.waiting_failing = false;
+                    changed
+                }
+                // Checks every so often instead.
+                Err(err) => {
+                    if !std::mem::replace(&mut self.waiting_failing, true) {
+                        warn!("Connectivity changes won't be noticed right away: {err:#}");
+                    }
+                    std::thread::sleep(remaining.min(self.poll));
+                    true
+                }
+            };
+            if changed && self.network_changed() {
+                return;
+            }
+        }
+    }
+
+    /// Asks the monitor for the current connectivity. Without a monitor, or when it can't
+    /// tell, the network is assumed usable and the sync itself finds out.
+    fn check_connectivity(&mut self) -> Connectivity {
+        let Some(monitor) = self.monitor.as_ref() else {
+            return self.connectivity;
+        };
+        self.connectivity = match monitor.connectivity() {
+            Ok(connectivity) => {
+                self.monitor_failing = false;
+                connectivity
+            }
+            Err(err) => {
+                if !std::mem::replace(&mut self.monitor_failing, true) {
+                    warn!("The connectivity can't be checked: {err:#}");
+                }
+                Connectivity::Unmetered
+            }
+        };
+        self.connectivity
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{context::SymmetryContext, traits::adapter::Adapter};
+
+use super::Entries;
+
+/// The COSMIC components whose configuration can be synchronized.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CosmicComponent {
+    Panel,
+    Dock,
+    Theme,
+    Workspaces,
+}
+
+impl CosmicComponent {
+    pub const ALL: [CosmicComponent; 4] = [
+        CosmicComponent::Panel,
+        CosmicComponent::Dock,
+        CosmicComponent::Theme,
+        CosmicComponent::Workspaces,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            CosmicComponent::Panel => "Panel",
+            CosmicComponent::Dock => "Dock",
+            CosmicComponent::Theme => "Theme",
+            CosmicComponent::Workspaces => "Workspaces",
+        }
+    }
+
+    /// The cosmic-config ids owned by this component, along with the keys to sync.
+    /// An empty key list means every key under that id is synchronized.
+    fn sources(&self) -> &'static [(&'static str, &'static [&'static str])] {
+        match self {
+            CosmicComponent::Panel => &[
+                ("com.system76.CosmicPanel", &[]),
+                ("com.system76.CosmicPanel.Panel", &[]),
+            ],
+            CosmicComponent::Dock => &[("com.system76.CosmicPanel.Dock", &[])],
+            CosmicComponent::Theme => &[
+                ("com.system76.CosmicTheme.Mode", &[]),
+                ("com.system76.CosmicTheme.Dark", &[]),
+                ("com.system76.CosmicTheme.Dark.Builder", &[]),
+                ("com.system76.CosmicTheme.Light", &[]),
+                ("com.system76.CosmicTheme.Light.Builder", &[]),
+            ],
+            CosmicComponent::Workspaces => &[("com.system76.CosmicComp", &["workspaces"])],
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct CosmicConfig {
+    /// Components selected by the user on the Desktop page.
+    pub components: BTreeSet<CosmicComponent>,
+    /// Raw cosmic-config values, keyed by `<id>/<version>/<key>`.
+    pub entries: Entries,
+}
+
+/// Reads and writes COSMIC's per-component configuration directories.
+pub struct Cosmic {
+    root: PathBuf,
+    components: BTreeSet<CosmicComponent>,
+}
+
+impl Cosmic {
+    pub fn new(context: &SymmetryContext, components: BTreeSet<CosmicComponent>) -> Result<Self> {
+        let root = context.config_dir()?.join("cosmic");
+        Ok(Self::with_root(root, components))
+    }
+
+    /// Creates an adapter rooted at a custom cosmic-config directory.
+    pub fn with_root(root: PathBuf, components: BTreeSet<CosmicComponent>) -> Self {
+        Self { root, components }
+    }
+
+    /// Whether the given entry key belongs to one of the selected components.
+    fn is_selected(&self, key: &str) -> bool {
+        let Some((id, _, name)) = split(key) else {
+            return false;
+        };
+        self.components.iter().any(|component| {
+            component
+                .sources()
+                .iter()
+                .any(|(source, keys)| *source == id && (keys.is_empty() || keys.contains(&name)))
+        })
+    }
+}
+
+impl Adapter for Cosmic {
+    fn read(&self) -> Result<Entries> {
+        let mut entries = Entries::new();
+        for component in &self.components {
+            for (id, keys) in component.sources() {
+                let dir = self.root.join(id);
+                if !dir.is_dir() {
+                    continue;
+                }
+                for version in std::fs::read_dir(&dir)? {
+                    let version = version?;
+                    let version_name = version.file_name().to_string_lossy().to_string();
+                    if !version.file_type()?.is_dir() || !version_name.starts_with('v') {
+                        continue;
+                    }
+                    for key in std::fs::read_dir(version.path())? {
+                        let key = key?;
+                        let key_name = key.file_name().to_string_lossy().to_string();
+                        if !key.file_type()?.is_file()
+                            || (!keys.is_empty() && !keys.contains(&key_name.as_str()))
+                        {
+                            continue;
+                        }
+                        let value = std::fs::read_to_string(key.path())?;
+                        entries.insert(format!("{id}/{version_name}/{key_name}"), value);
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn write(&self, entries: &Entries) -> Result<()> {
+        // Keys come from other devices, only those naming a file of a selected component
+        // are written, which keeps them inside the cosmic-config directory.
+        for (key, value) in entries.iter().filter(|(key, _)| self.is_selected(key)) {
+            let path = self.root.join(key);
+            if std::fs::read_to_string(&path).ok().as_ref() == Some(value) {
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits an entry key into its id, version and name, each a single file name, so the key
+/// can't lead out of the cosmic-config directory.
+fn split(key: &str) -> Option<(&str, &str, &str)> {
+    let parts: Vec<&str> = key.split('/').collect();
+    let [id, version, name] = parts[..] else {
+        return None;
+    };
+    let normal = |part: &str| {
+        let mut components = Path::new(part).components();
+        matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        )
+    };
+    (normal(id) && normal(version) && normal(name)).then_some((id, version, name))
+}
@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{color_scheme::ColorScheme, context::SymmetryContext, traits::adapter::Adapter};
+
+use super::Entries;
+
+/// The Plasma rc files synchronized by the adapter.
+pub const FILES: [&str; 3] = ["kdeglobals", "kwinrc", "kcminputrc"];
+
+const COLOR_SCHEME_KEY: &str = "kdeglobals/General/ColorScheme";
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct KdeConfig {
+    pub enabled: bool,
+    /// Individual rc keys, keyed by `<file>/<group>/<key>`.
+    pub entries: Entries,
+}
+
+/// Reads and writes Plasma's INI-like rc files one key at a time.
+pub struct Kde {
+    root: PathBuf,
+}
+
+impl Kde {
+    pub fn new(context: &SymmetryContext) -> Result<Self> {
+        Ok(Self::with_root(context.config_dir()?))
+    }
+
+    /// Creates an adapter rooted at a custom config directory.
+    pub fn with_root(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Maps the Plasma color scheme stored in the entries to a `ColorScheme`.
+    pub fn color_scheme(entries: &Entries) -> ColorScheme {
+        match entries.get(COLOR_SCHEME_KEY) {
+            Some(name) if name.contains("Dark") => ColorScheme::Dark,
+            Some(_) => ColorScheme::Light,
+            None => ColorScheme::Default,
+        }
+    }
+
+    /// Sets the Plasma color scheme matching the given `ColorScheme`, if it differs.
+    pub fn set_color_scheme(entries: &mut Entries, color_scheme: ColorScheme) {
+        if Self::color_scheme(entries) == color_scheme {
+            return;
+        }
+        match color_scheme {
+            ColorScheme::Dark => {
+                entries.insert(COLOR_SCHEME_KEY.into(), "BreezeDark".into());
+            }
+            ColorScheme::Light => {
+                entries.insert(COLOR_SCHEME_KEY.into(), "BreezeLight".into());
+            }
+            ColorScheme::Default => (),
+        }
+    }
+
+    /// Renders the file with the desired keys, keeping comments, ordering and the keys
+    /// that aren't synchronized intact.
+    fn render(file: &str, contents: &str, entries: &Entries) -> String {
+        let prefix = format!("{file}/");
+        let mut desired: Vec<(String, String, String)> = entries
+            .iter()
+            .filter_map(|(key, value)| {
+                let (group, key) = key.strip_prefix(&prefix)?.rsplit_once('/')?;
+                Some((group.to_string(), key.to_string(), value.clone()))
+            })
+            .collect();
+
+        let mut output: Vec<String> = vec![];
+        let mut group = String::new();
+        for line in contents.lines() {
+            let trimmed = line.trim();
+            if let Some(header) = parse_group(trimmed) {
+                append_missing(&mut output, &mut desired, &group);
+                group = header.to_string();
+                output.push(line.to_string());
+            } else if let Some((key, _)) = parse_key(trimmed) {
+                let position = desired.iter().position(|(g, k, _)| *g == group && k == key);
+                if let Some(position) = position {
+                    let (_, key, value) = desired.remove(position);
+                    output.push(format!("{key}={value}"));
+                } else {
+                    output.push(line.to_string());
+                }
+            } else {
+                output.push(line.to_string());
+            }
+        }
+        append_missing(&mut output, &mut desired, &group);
+
+        while !desired.is_empty() {
+            let group = desired[0].0.clone();
+            if output.last().is_some_and(|line| !line.is_empty()) {
+                output.push(String::new());
+            }
+            output.push(format!("[{group}]"));
+            append_missing(&mut output, &mut desired, &group);
+        }
+
+        let mut rendered = output.join("\n");
+        rendered.push('\n');
+        rendered
+    }
+}
+
+impl Adapter for Kde {
+    fn read(&self) -> Result<Entries> {
+        let mut entries = Entries::new();
+        for file in FILES {
+            let Ok(contents) = std::fs::read_to_string(self.root.join(file)) else {
+                continue;
+            };
+            let mut group = "";
+            for line in contents.lines() {
+                let line = line.trim();
+                if let Some(header) = parse_group(line) {
+                    group = header;
+                } else if let Some((key, value)) = parse_key(line) {
+                    entries.insert(format!("{file}/{group}/{key}"), value.to_string());
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn write(&self, entries: &Entries) -> Result<()> {
+        for file in FILES {
+            let prefix = format!("{file}/");
+            if !entries.keys().any(|key| key.starts_with(&prefix)) {
+                continue;
+            }
+            let path = self.root.join(file);
+            let contents = std::fs::read_to_string(&path).unwrap_or_default();
+            let rendered = Self::render(file, &contents, entries);
+            if rendered != contents {
+                std::fs::write(&path, rendered)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the group name if the line is a group header, e.g. `[Colors:Button]`.
+fn parse_group(line: &str) -> Option<&str> {
+    line.strip_prefix('[')?.strip_suffix(']')
+}
+
+/// Returns the key and value if the line is a `key=value` pair.
+fn parse_key(line: &str) -> Option<(&str, &str)> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let (key, value) = line.split_once('=')?;
+    Some((key.trim(), value.trim()))
+}
+
+/// Appends the desired keys belonging to `group` that were not already present in the file.
+fn append_missing(
+    output: &mut Vec<String>,
+    desired: &mut Vec<(String, String, String)>,
+    group: &str,
+) {
+    let mut blank_lines = vec![];
+    while output.last().is_some_and(|line| line.trim().is_empty()) {
+        blank_lines.push(output.pop().unwrap());
+    }
+    desired.retain(|(g, key, value)| {
+        if g == group {
+            output.push(format!("{key}={value}"));
+            false
+        } else {
+            true
+        }
+    });
+    output.extend(blank_lines);
+}
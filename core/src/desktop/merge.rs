@@ -0,0 +1,49 @@
+use std::collections::BTreeSet;
+
+use super::Entries;
+
+/// The result of merging two sets of desktop entries against their common ancestor.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Merge {
+    pub entries: Entries,
+    /// Keys changed differently on both sides. The remote value is kept for these.
+    pub conflicts: Vec<String>,
+}
+
+/// Performs a key-level three-way merge, so a key changed on one side doesn't clobber
+/// unrelated keys changed on the other.
+pub fn merge(base: &Entries, local: &Entries, remote: &Entries) -> Merge {
+    let mut merge = Merge::default();
+    let keys: BTreeSet<&String> = base
+        .keys()
+        .chain(local.keys())
+        .chain(remote.keys())
+        .collect();
+    for key in keys {
+        let (base, local, remote) = (base.get(key), local.get(key), remote.get(key));
+        let value = if local == remote || base == local {
+            remote
+        } else if base == remote {
+            local
+        } else {
+            merge.conflicts.push(key.clone());
+            remote.or(local)
+        };
+        if let Some(value) = value {
+            merge.entries.insert(key.clone(), value.clone());
+        }
+    }
+    merge
+}
+
+/// Picks the value from the side that changed it.
+/// Returns `None` if both sides changed it differently.
+pub fn pick<T: PartialEq + Clone>(base: &T, local: &T, remote: &T) -> Option<T> {
+    if local == remote || base == local {
+        Some(remote.clone())
+    } else if base == remote {
+        Some(local.clone())
+    } else {
+        None
+    }
+}
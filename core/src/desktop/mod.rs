@@ -0,0 +1,101 @@
+pub mod cosmic;
+pub mod kde;
+pub mod merge;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    configuration::Configuration, context::SymmetryContext, template::Variables,
+    traits::adapter::Adapter,
+};
+
+use self::{
+    cosmic::{Cosmic, CosmicConfig},
+    kde::{Kde, KdeConfig},
+};
+
+/// Desktop settings keyed by a path-like identifier, holding their raw value.
+pub type Entries = BTreeMap<String, String>;
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Desktop {
+    pub cosmic: CosmicConfig,
+    #[serde(default)]
+    pub kde: KdeConfig,
+}
+
+impl Desktop {
+    /// Reads the selected desktop settings of `context` into the configuration.
+    /// Returns `true` if any entry changed. Entries still holding what their template
+    /// renders to keep the template.
+    pub fn capture(context: &SymmetryContext, config: &mut Configuration) -> Result<bool> {
+        let mut changed = false;
+        let variables = Variables::new(context, config);
+
+        let cosmic = Cosmic::new(context, config.desktop.cosmic.components.clone())?;
+        let mut entries = cosmic.read()?;
+        variables.keep_templates(&mut entries, &config.desktop.cosmic.entries);
+        changed |= entries != config.desktop.cosmic.entries;
+        config.desktop.cosmic.entries = entries;
+
+        if config.desktop.kde.enabled {
+            let mut entries = Kde::new(context)?.read()?;
+            variables.keep_templates(&mut entries, &config.desktop.kde.entries);
+            if Kde::color_scheme(&entries) != Kde::color_scheme(&config.desktop.kde.entries) {
+                config.color_scheme = Kde::color_scheme(&entries);
+                changed = true;
+            }
+            changed |= entries != config.desktop.kde.entries;
+            config.desktop.kde.entries = entries;
+        }
+
+        Ok(changed)
+    }
+
+    /// Writes the desktop settings stored in the configuration back to the desktop of
+    /// `context`, as
+    /// they are. Templated ones are rendered with [`Configuration::rendered`] first.
+    pub fn apply(context: &SymmetryContext, config: &Configuration) -> Result<()> {
+        let cosmic = Cosmic::new(context, config.desktop.cosmic.components.clone())?;
+        cosmic.write(&config.desktop.cosmic.entries)?;
+
+        if config.desktop.kde.enabled {
+            let mut entries = config.desktop.kde.entries.clone();
+            Kde::set_color_scheme(&mut entries, config.color_scheme);
+            Kde::new(context)?.write(&entries)?;
+        }
+
+        Ok(())
+    }
+
+    /// Merges the desktop entries of both sides key by key.
+    /// Returns `None` if the same key was changed differently on both sides.
+    pub fn merge(base: &Desktop, local: &Desktop, remote: &Desktop) -> Option<Desktop> {
+        let cosmic = merge::merge(
+            &base.cosmic.entries,
+            &local.cosmic.entries,
+            &remote.cosmic.entries,
+        );
+        let kde = merge::merge(&base.kde.entries, &local.kde.entries, &remote.kde.entries);
+        if !cosmic.conflicts.is_empty() || !kde.conflicts.is_empty() {
+            return None;
+        }
+        Some(Desktop {
+            cosmic: CosmicConfig {
+                components: merge::pick(
+                    &base.cosmic.components,
+                    &local.cosmic.components,
+                    &remote.cosmic.components,
+                )?,
+                entries: cosmic.entries,
+            },
+            kde: KdeConfig {
+                enabled: merge::pick(&base.kde.enabled, &local.kde.enabled, &remote.kde.enabled)?,
+                entries: kde.entries,
+            },
+        })
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// Identifies the machine Symmetry is currently running on.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Device {
+    pub name: String,
+}
+
+impl Device {
+    /// Returns the current device, named after its hostname.
+    pub fn current() -> Self {
+        let name = gethostname::gethostname().to_string_lossy().to_string();
+        Self { name }
+    }
+}
@@ -0,0 +1,505 @@
+pub mod diff;
+pub mod tracked_file;
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ron::ser::PrettyConfig;
+use walkdir::WalkDir;
+
+use crate::{
+    configuration::Configuration, context::SymmetryContext, device::Device, secrets::Secrets, sync,
+    template::Variables,
+};
+
+use self::tracked_file::{DeployMode, FileState, TrackedFile};
+
+/// File in the state directory listing the device files held back for review.
+pub const HELD_FILE: &str = "held-files.ron";
+
+/// A device file that differs from its synchronized version and wasn't replaced yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// Where the file is on this device.
+    pub source: PathBuf,
+    /// Where the synchronized version is in the repository.
+    pub target: PathBuf,
+    /// Changes from the device version to the synchronized one, see [`diff::diff`].
+    pub diff: String,
+}
+
+/// Moves tracked files between their location on this device and the sync repository.
+pub struct Files {
+    context: SymmetryContext,
+    repo: PathBuf,
+    backups: PathBuf,
+    device: Device,
+}
+
+impl Files {
+    /// Moves the tracked files of the device of `context` in and out of its repository.
+    pub fn new(context: &SymmetryContext) -> Self {
+        Self {
+            context: context.clone(),
+            repo: context.repo_path(),
+            backups: context.backup_path(),
+            device: context.device(),
+        }
+    }
+
+    /// Brings every enabled tracked file from the device into the repository.
+    /// Files deployed as symlinks already live in the repository, pre-existing
+    /// files are moved into it and replaced with a link. Templates are only ever
+    /// edited in the repository, so they are skipped. Secret files are encrypted.
+    /// Files held back by [`Files::deploy`] wait for the user to accept or keep them.
+    pub fn capture(&self, config: &Configuration) -> Result<()> {
+        let variables = Variables::new(&self.context, config);
+        let secrets = self.secrets(config, true)?;
+        let held = self.held();
+        for file in self.enabled(config).filter(|file| !file.template) {
+            for (source, target) in self.pairs(file, &variables)? {
+                if is_link_to(&source, &target) || !source.is_file() || held.contains(&source) {
+                    continue;
+                }
+                if file.secret {
+                    let contents = std::fs::read(&source)?;
+                    if self.synced_contents(file, &target, &variables, secrets.as_ref())?
+                        != Some(contents.clone())
+                    {
+                        let secrets = secrets.as_ref().context("Secrets not available.")?;
+                        let parent = target.parent().context("Invalid destination path.")?;
+                        std::fs::create_dir_all(parent)?;
+                        std::fs::write(&target, secrets.encrypt(&contents)?)?;
+                    }
+                    continue;
+                }
+                self.adopt(file, &source, &target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deploys every enabled tracked file from the repository onto the device.
+    /// Pre-existing files are backed up before they're replaced, for [`Files::restore`] to
+    /// put back. Templates are rendered for this device and secrets decrypted, both are
+    /// always copied. Pre-existing files that differ from their synchronized version are
+    /// left in place and returned, for the user to [`Files::accept`] or [`Files::keep`].
+    pub fn deploy(&self, config: &Configuration) -> Result<Vec<Difference>> {
+        let variables = Variables::new(&self.context, config);
+        let secrets = self.secrets(config, false)?;
+        let mut differences = vec![];
+        for file in self.enabled(config) {
+            for (source, target) in self.pairs(file, &variables)? {
+                if file.template || file.secret {
+                    if let Some(contents) =
+                        self.synced_contents(file, &target, &variables, secrets.as_ref())?
+                    {
+                        self.write(&source, &contents)?;
+                    }
+                    continue;
+                }
+                let linked = file.mode == DeployMode::Symlink;
+                let is_link = is_link_to(&source, &target);
+                if !target.is_file() || (linked && is_link) {
+                    continue;
+                }
+                if !is_link && same_contents(&target, &source) {
+                    if linked {
+                        self.backup(&source)?;
+                        link(&target, &source)?;
+                    }
+                    continue;
+                }
+                if !is_link && source.exists() && !self.backup_path(&source).exists() {
+                    differences.push(difference(&source, &target));
+                    continue;
+                }
+                self.replace(file, &source, &target)?;
+            }
+        }
+        self.hold(
+            differences
+                .iter()
+                .map(|difference| difference.source.clone()),
+        )?;
+        Ok(differences)
+    }
+
+    /// Lists the device files [`Files::deploy`] held back, with their current differences.
+    pub fn differences(&self, config: &Configuration) -> Result<Vec<Difference>> {
+        let variables = Variables::new(&self.context, config);
+        let held = self.held();
+        let mut differences = vec![];
+        for file in self.enabled(config) {
+            for (source, target) in self.pairs(file, &variables)? {
+                if held.contains(&source) {
+                    differences.push(difference(&source, &target));
+                }
+            }
+        }
+        Ok(differences)
+    }
+
+    /// Replaces the held device file at `source` with its synchronized version,
+    /// backing up the original for [`Files::restore`].
+    pub fn accept(&self, config: &Configuration, source: &Path) -> Result<()> {
+        let (file, target) = self.find(config, source)?;
+        self.replace(&file, source, &target)?;
+        self.release(source)
+    }
+
+    /// Keeps the held device file at `source`, bringing it into the repository in place
+    /// of the synchronized version.
+    pub fn keep(&self, config: &Configuration, source: &Path) -> Result<()> {
+        let (file, target) = self.find(config, source)?;
+        self.adopt(&file, source, &target)?;
+        self.release(source)
+    }
+
+    /// Copies the device file into the repository, linking it back in symlink mode.
+    fn adopt(&self, file: &TrackedFile, source: &Path, target: &Path) -> Result<()> {
+        if !same_contents(source, target) {
+            copy(source, target)?;
+        }
+        if file.mode == DeployMode::Symlink {
+            self.backup(source)?;
+            link(target, source)?;
+        }
+        Ok(())
+    }
+
+    /// Backs up the device file and puts the synchronized version in its place.
+    fn replace(&self, file: &TrackedFile, source: &Path, target: &Path) -> Result<()> {
+        if !is_link_to(source, target) && source.exists() {
+            self.backup(source)?;
+        }
+        if file.mode == DeployMode::Symlink {
+            link(target, source)
+        } else {
+            copy(target, source)
+        }
+    }
+
+    /// Finds the enabled tracked entry matching the device file at `source`.
+    fn find(&self, config: &Configuration, source: &Path) -> Result<(TrackedFile, PathBuf)> {
+        let variables = Variables::new(&self.context, config);
+        for file in self.enabled(config) {
+            for (path, target) in self.pairs(file, &variables)? {
+                if path == source {
+                    return Ok((file.clone(), target));
+                }
+            }
+        }
+        anyhow::bail!("{} isn't tracked on this device.", source.display())
+    }
+
+    fn held_path(&self) -> PathBuf {
+        self.context.state_path().join(HELD_FILE)
+    }
+
+    /// The device files held back for review, kept in the state directory.
+    fn held(&self) -> BTreeSet<PathBuf> {
+        std::fs::read_to_string(self.held_path())
+            .ok()
+            .and_then(|data| ron::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the held device files, removing the file once there are none.
+    fn hold(&self, sources: impl IntoIterator<Item = PathBuf>) -> Result<()> {
+        let held: BTreeSet<PathBuf> = sources.into_iter().collect();
+        let path = self.held_path();
+        if held.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        let state = self.context.state_path();
+        std::fs::create_dir_all(&state).context("Failed to create the state directory.")?;
+        std::fs::write(
+            path,
+            ron::ser::to_string_pretty(&held, PrettyConfig::new())?,
+        )?;
+        Ok(())
+    }
+
+    fn release(&self, source: &Path) -> Result<()> {
+        let mut held = self.held();
+        held.remove(source);
+        self.hold(held)
+    }
+
+    /// Undoes deployment, putting back the files that existed before Symmetry adopted them.
+    /// Links without a backup are replaced with a copy of the synchronized file. The files
+    /// are disabled on this device, so the next sync doesn't adopt them again.
+    pub fn restore(&self, config: &mut Configuration) -> Result<()> {
+        let variables = Variables::new(&self.context, config);
+        for file in &config.files {
+            for (source, target) in self.pairs(file, &variables)? {
+                let backup = self.backup_path(&source);
+                if backup.is_file() {
+                    remove(&source)?;
+                    std::fs::rename(&backup, &source)?;
+                } else if is_link_to(&source, &target) {
+                    remove(&source)?;
+                    std::fs::copy(&target, &source)?;
+                }
+            }
+        }
+        for file in &mut config.files {
+            file.set_enabled(&self.device, false);
+        }
+        Ok(())
+    }
+
+    /// Points symlinked device files that still target `old_repo` at this repository,
+    /// after the repository was moved.
+    pub fn relink(&self, config: &Configuration, old_repo: &Path) -> Result<()> {
+        let variables = Variables::new(&self.context, config);
+        for file in &config.files {
+            for (source, target) in self.pairs(file, &variables)? {
+                let relative = target.strip_prefix(&self.repo)?;
+                if is_link_to(&source, &old_repo.join(relative)) {
+                    link(&target, &source)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns every tracked file along with its current state on this device.
+    pub fn status(&self, config: &Configuration) -> Vec<(TrackedFile, FileState)> {
+        let variables = Variables::new(&self.context, config);
+        let secrets = self.secrets(config, false).ok().flatten();
+        config
+            .files
+            .iter()
+            .map(|file| (file.clone(), self.state(file, &variables, secrets.as_ref())))
+            .collect()
+    }
+
+    fn state(
+        &self,
+        file: &TrackedFile,
+        variables: &Variables,
+        secrets: Option<&Secrets>,
+    ) -> FileState {
+        if !file.is_enabled(&self.device) {
+            return FileState::Disabled;
+        }
+        let Ok(pairs) = self.pairs(file, variables) else {
+            return FileState::Missing;
+        };
+        if pairs.is_empty() {
+            return FileState::Missing;
+        }
+        if pairs.iter().any(|(_, target)| !target.exists()) {
+            return FileState::NotInRepo;
+        }
+        if pairs
+            .iter()
+            .all(|(source, target)| is_link_to(source, target))
+        {
+            return FileState::Linked;
+        }
+        let modified = pairs.iter().any(|(source, target)| {
+            if file.template || file.secret {
+                let synced = self
+                    .synced_contents(file, target, variables, secrets)
+                    .ok()
+                    .flatten();
+                synced != std::fs::read(source).ok()
+            } else {
+                !same_contents(source, target)
+            }
+        });
+        if modified {
+            return FileState::Modified;
+        }
+        FileState::Synced
+    }
+
+    fn enabled<'a>(&'a self, config: &'a Configuration) -> impl Iterator<Item = &'a TrackedFile> {
+        config
+            .files
+            .iter()
+            .filter(|file| file.is_enabled(&self.device))
+    }
+
+    /// Lists the files matched by a tracked entry as `(device path, repository path)` pairs.
+    /// Directories are walked on the device and, failing that, in the repository.
+    fn pairs(&self, file: &TrackedFile, variables: &Variables) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let source = file.source_path(variables)?;
+        // The tracked files are synchronized, so another device may have set any path.
+        let target = sync::inside(&self.repo, &file.repo_path)?;
+        if !source.is_dir() && !target.is_dir() {
+            return Ok(vec![(source, target)]);
+        }
+
+        let include = glob_set(&file.include)?;
+        let exclude = glob_set(&file.exclude)?;
+        let root = if source.is_dir() { &source } else { &target };
+        let mut pairs = vec![];
+        for entry in WalkDir::new(root)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+        {
+            if !entry.path().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(root)?;
+            if (!file.include.is_empty() && !include.is_match(relative))
+                || exclude.is_match(relative)
+            {
+                continue;
+            }
+            pairs.push((source.join(relative), target.join(relative)));
+        }
+        Ok(pairs)
+    }
+
+    /// Loads the sync key if any tracked file is secret. With `start`, a key is generated
+    /// if there's none and no secret file is in the repository yet, encrypted with another.
+    fn secrets(&self, config: &Configuration, start: bool) -> Result<Option<Secrets>> {
+        let mut secret = config.files.iter().filter(|file| file.secret).peekable();
+        if secret.peek().is_none() {
+            return Ok(None);
+        }
+        let encrypted = secret
+            .any(|file| sync::inside(&self.repo, &file.repo_path).is_ok_and(|path| path.exists()));
+        if start && !encrypted {
+            Ok(Some(Secrets::init(&self.context, config)?))
+        } else {
+            Ok(Some(Secrets::new(&self.context)?))
+        }
+    }
+
+    /// Returns the contents of the repository file as they should appear on this device,
+    /// decrypted and rendered as needed, or `None` if it isn't in the repository yet.
+    fn synced_contents(
+        &self,
+        file: &TrackedFile,
+        target: &Path,
+        variables: &Variables,
+        secrets: Option<&Secrets>,
+    ) -> Result<Option<Vec<u8>>> {
+        let Ok(mut contents) = std::fs::read(target) else {
+            return Ok(None);
+        };
+        if file.secret {
+            let secrets = secrets.context("Secrets not available.")?;
+            contents = secrets.decrypt(&contents)?;
+        }
+        if file.template {
+            contents = variables
+                .render(&String::from_utf8(contents)?)?
+                .into_bytes();
+        }
+        Ok(Some(contents))
+    }
+
+    /// Writes generated contents to the device.
+    /// Pre-existing files are adopted the same way as regular files.
+    fn write(&self, source: &Path, contents: &[u8]) -> Result<()> {
+        let current = std::fs::read(source).ok();
+        if current.as_deref() == Some(contents) {
+            return Ok(());
+        }
+        if current.is_some() && !self.backup_path(source).exists() {
+            self.backup(source)?;
+        }
+        remove(source)?;
+        let parent = source.parent().context("Invalid destination path.")?;
+        std::fs::create_dir_all(parent)?;
+        std::fs::write(source, contents)?;
+        Ok(())
+    }
+
+    /// Where the original version of a device file is kept once adopted.
+    fn backup_path(&self, source: &Path) -> PathBuf {
+        self.backups
+            .join(source.strip_prefix("/").unwrap_or(source))
+    }
+
+    /// Keeps the original device file around, unless a backup already exists.
+    fn backup(&self, source: &Path) -> Result<()> {
+        let backup = self.backup_path(source);
+        if !backup.exists() && source.is_file() {
+            copy(source, &backup)?;
+        }
+        Ok(())
+    }
+}
+
+fn difference(source: &Path, target: &Path) -> Difference {
+    let read = |path: &Path| {
+        std::fs::read(path)
+            .map(|contents| String::from_utf8_lossy(&contents).into_owned())
+            .unwrap_or_default()
+    };
+    Difference {
+        source: source.to_path_buf(),
+        target: target.to_path_buf(),
+        diff: diff::diff(&read(source), &read(target)),
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+fn same_contents(a: &Path, b: &Path) -> bool {
+    match (std::fs::read(a), std::fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn is_link_to(link: &Path, target: &Path) -> bool {
+    std::fs::read_link(link).is_ok_and(|destination| destination == target)
+}
+
+fn remove(path: &Path) -> Result<()> {
+    if path.symlink_metadata().is_ok() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    let parent = to.parent().context("Invalid destination path.")?;
+    std::fs::create_dir_all(parent)?;
+    remove(to)?;
+    std::fs::copy(from, to)?;
+    Ok(())
+}
+
+fn link(target: &Path, link: &Path) -> Result<()> {
+    let parent = link.parent().context("Invalid destination path.")?;
+    std::fs::create_dir_all(parent)?;
+    remove(link)?;
+    symlink(target, link)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, link: &Path) -> Result<()> {
+    anyhow::bail!(
+        "{} can't be linked on this system, deploy it as a copy instead.",
+        link.display()
+    )
+}
@@ -0,0 +1,107 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{device::Device, template::Variables};
+
+/// A file or directory synchronized alongside the configuration.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TrackedFile {
+    /// Location on the device, a leading `~` expands to the home directory.
+    pub source: String,
+    /// Location inside the sync repository, relative to its root.
+    pub repo_path: String,
+    /// Globs matched against paths inside a tracked directory. Empty means everything.
+    pub include: Vec<String>,
+    /// Globs excluded from a tracked directory, even if included.
+    pub exclude: Vec<String>,
+    /// Per-device overrides, keyed by device name. Devices not listed are enabled.
+    pub devices: BTreeMap<String, bool>,
+    #[serde(default)]
+    pub mode: DeployMode,
+    /// Whether the file is a template, rendered for each device when deployed.
+    #[serde(default)]
+    pub template: bool,
+    /// Whether the file holds secrets, it is only ever stored encrypted in the repository.
+    #[serde(default)]
+    pub secret: bool,
+}
+
+/// How a tracked file is put in place on the device.
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum DeployMode {
+    /// The device file is a regular copy of the synchronized file.
+    #[default]
+    Copy,
+    /// The device file is a symlink into the sync repository, so edits are staged instantly.
+    Symlink,
+}
+
+/// The state of a tracked file on the current device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    /// The device and repository copies are identical.
+    Synced,
+    /// The device file is a symlink into the repository.
+    Linked,
+    /// The device copy differs from the repository.
+    Modified,
+    /// The file doesn't exist on the device.
+    Missing,
+    /// The file hasn't been copied into the repository yet.
+    NotInRepo,
+    /// Synchronization is disabled on this device.
+    Disabled,
+}
+
+impl FileState {
+    pub fn title(&self) -> &'static str {
+        match self {
+            FileState::Synced => "Synced",
+            FileState::Linked => "Linked",
+            FileState::Modified => "Modified",
+            FileState::Missing => "Missing",
+            FileState::NotInRepo => "Not synced yet",
+            FileState::Disabled => "Disabled",
+        }
+    }
+}
+
+impl TrackedFile {
+    /// Tracks a file, storing it in the repository under `files/` with the same relative path.
+    pub fn new(source: impl Into<String>) -> Self {
+        let source = source.into();
+        let repo_path = format!(
+            "files/{}",
+            source.trim_start_matches('~').trim_start_matches('/')
+        );
+        Self {
+            source,
+            repo_path,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self, device: &Device) -> bool {
+        self.devices.get(&device.name).copied().unwrap_or(true)
+    }
+
+    pub fn set_enabled(&mut self, device: &Device, enabled: bool) {
+        self.devices.insert(device.name.clone(), enabled);
+    }
+
+    /// The absolute location of this file on the device, the source may use template variables.
+    pub fn source_path(&self, variables: &Variables) -> Result<PathBuf> {
+        let source = variables.render(&self.source)?;
+        match source.strip_prefix('~') {
+            Some(relative) => Ok(PathBuf::from(
+                variables
+                    .get("home")
+                    .context("Home directory not available.")?,
+            )
+            .join(relative.trim_start_matches('/'))),
+            None => Ok(PathBuf::from(&source)),
+        }
+    }
+}
@@ -1,5 +1,14 @@
 pub mod color_scheme;
 pub mod configuration;
+pub mod context;
+pub mod daemon;
+pub mod desktop;
+pub mod device;
+pub mod files;
+pub mod network;
 pub mod resources;
+pub mod secrets;
 pub mod sync;
+pub mod template;
 pub mod traits;
+pub mod trust;
@@ -0,0 +1,137 @@
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+const NM_DESTINATION: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// `NMConnectivityState` values reported by NetworkManager.
+const NM_CONNECTIVITY_UNKNOWN: u32 = 0;
+const NM_CONNECTIVITY_FULL: u32 = 4;
+/// `NMMetered` values that mean the link is, or is guessed to be, metered.
+const NM_METERED_YES: u32 = 1;
+const NM_METERED_GUESS_YES: u32 = 3;
+
+/// How the device is currently connected to the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Offline,
+    /// Online through a link that costs data, like a phone hotspot.
+    Metered,
+    Unmetered,
+}
+
+/// What a provider is allowed to do while the device is on a metered link.
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum MeteredPolicy {
+    /// Wait for an unmetered link before synchronizing.
+    #[default]
+    Defer,
+    /// Synchronize as usual.
+    Allow,
+}
+
+/// Reports the current connectivity so background syncs can be held back or triggered.
+pub trait NetworkMonitor {
+    fn connectivity(&self) -> Result<Connectivity>;
+
+    /// Blocks until the connectivity may have changed or `timeout` passes, telling which.
+    fn wait(&self, timeout: Duration) -> Result<bool>;
+}
+
+/// Reads unsigned integer properties from the NetworkManager D-Bus interface.
+pub trait Bus {
+    fn property(&self, name: &str) -> Result<u32>;
+
+    /// Blocks until NetworkManager reports changed properties or `timeout` passes, telling
+    /// which.
+    fn wait(&self, timeout: Duration) -> Result<bool>;
+}
+
+/// The system D-Bus.
+pub struct SystemBus {
+    proxy: zbus::blocking::Proxy<'static>,
+    changes: Receiver<()>,
+}
+
+impl SystemBus {
+    pub fn new() -> Result<Self> {
+        let connection = zbus::blocking::Connection::system()?;
+        let proxy = zbus::blocking::Proxy::new(&connection, NM_DESTINATION, NM_PATH, NM_INTERFACE)?;
+        let properties =
+            zbus::blocking::Proxy::new(&connection, NM_DESTINATION, NM_PATH, PROPERTIES_INTERFACE)?;
+        let signals = properties.receive_signal("PropertiesChanged")?;
+        let (sender, changes) = mpsc::channel();
+        // Stops at the first signal after the bus is dropped.
+        std::thread::spawn(move || {
+            for _ in signals {
+                if sender.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { proxy, changes })
+    }
+}
+
+impl Bus for SystemBus {
+    fn property(&self, name: &str) -> Result<u32> {
+        Ok(self.proxy.get_property(name)?)
+    }
+
+    fn wait(&self, timeout: Duration) -> Result<bool> {
+        match self.changes.recv_timeout(timeout) {
+            Ok(()) => {
+                // Several properties usually change at once, one check covers them all.
+                while self.changes.try_recv().is_ok() {}
+                Ok(true)
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(false),
+            Err(RecvTimeoutError::Disconnected) => {
+                bail!("NetworkManager changes can't be followed anymore.")
+            }
+        }
+    }
+}
+
+/// Monitors connectivity through NetworkManager.
+pub struct NetworkManager<B: Bus = SystemBus> {
+    bus: B,
+}
+
+impl NetworkManager {
+    /// Connects to NetworkManager on the system bus.
+    pub fn system() -> Result<Self> {
+        Ok(Self::new(SystemBus::new()?))
+    }
+}
+
+impl<B: Bus> NetworkManager<B> {
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+}
+
+impl<B: Bus> NetworkMonitor for NetworkManager<B> {
+    fn connectivity(&self) -> Result<Connectivity> {
+        // Unknown means connectivity checking is disabled, so let the sync itself find out.
+        match self.bus.property("Connectivity")? {
+            NM_CONNECTIVITY_FULL | NM_CONNECTIVITY_UNKNOWN => {}
+            _ => return Ok(Connectivity::Offline),
+        }
+        match self.bus.property("Metered")? {
+            NM_METERED_YES | NM_METERED_GUESS_YES => Ok(Connectivity::Metered),
+            _ => Ok(Connectivity::Unmetered),
+        }
+    }
+
+    fn wait(&self, timeout: Duration) -> Result<bool> {
+        self.bus.wait(timeout)
+    }
+}
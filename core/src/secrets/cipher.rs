@@ -0,0 +1,68 @@
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+/// Prefix identifying data encrypted by Symmetry.
+const MAGIC: &[u8] = b"SYMMETRY-SECRET-1\n";
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypts secrets before they leave the device, using the sync key shared between devices.
+pub struct Cipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// Creates a cipher from a base64 encoded sync key.
+    pub fn new(key: &str) -> Result<Self> {
+        Ok(Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&Self::decode_key(key)?)),
+        })
+    }
+
+    /// Decodes a base64 encoded sync key into its raw bytes.
+    pub fn decode_key(key: &str) -> Result<[u8; 32]> {
+        let key = STANDARD.decode(key.trim())?;
+        key.try_into()
+            .map_err(|_| anyhow!("The sync key must be 32 bytes long."))
+    }
+
+    /// Generates a new base64 encoded sync key.
+    pub fn generate_key() -> String {
+        STANDARD.encode(ChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("Failed to encrypt secret."))?;
+        Ok([MAGIC, nonce.as_slice(), &ciphertext].concat())
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(data) = data.strip_prefix(MAGIC) else {
+            bail!("The data is not an encrypted secret.");
+        };
+        if data.len() < NONCE_LENGTH {
+            bail!("The encrypted secret is truncated.");
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt secret, is the sync key correct?"))
+    }
+
+    /// Encrypts a string into base64, suitable for storing in the configuration.
+    pub fn encrypt_string(&self, plaintext: &str) -> Result<String> {
+        Ok(STANDARD.encode(self.encrypt(plaintext.as_bytes())?))
+    }
+
+    pub fn decrypt_string(&self, ciphertext: &str) -> Result<String> {
+        let plaintext = self.decrypt(&STANDARD.decode(ciphertext)?)?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
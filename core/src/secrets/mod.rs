@@ -0,0 +1,95 @@
+pub mod cipher;
+pub mod scanner;
+pub mod store;
+
+use anyhow::{Context, Result};
+
+use crate::{configuration::Configuration, context::SymmetryContext};
+
+use self::cipher::Cipher;
+
+/// Name of the keyring entry holding the key used to encrypt synchronized secrets.
+const SYNC_KEY: &str = "sync-key";
+const NO_KEY: &str = "There's no sync key on this device, enter the one from your other device.";
+
+/// Keeps secrets in the system keyring locally and only synchronizes them encrypted.
+pub struct Secrets {
+    context: SymmetryContext,
+    cipher: Cipher,
+}
+
+impl Secrets {
+    /// Loads the sync key of `context` from the keyring. Every device uses the same key, so
+    /// a device has none until it's entered from another one or [`Secrets::init`] starts one.
+    pub fn new(context: &SymmetryContext) -> Result<Self> {
+        Ok(Self {
+            context: context.clone(),
+            cipher: Cipher::new(&Self::key(context)?)?,
+        })
+    }
+
+    /// Loads the sync key, generating one if none of the secrets in `config` were encrypted
+    /// yet, as on the first device to keep secrets.
+    pub fn init(context: &SymmetryContext, config: &Configuration) -> Result<Self> {
+        if store::get(context, SYNC_KEY)?.is_none() && config.secrets.is_empty() {
+            store::set(context, SYNC_KEY, &Cipher::generate_key())?;
+        }
+        Self::new(context)
+    }
+
+    /// Returns the sync key, so it can be entered on other devices.
+    pub fn key(context: &SymmetryContext) -> Result<String> {
+        store::get(context, SYNC_KEY)?.context(NO_KEY)
+    }
+
+    /// Replaces the sync key with one generated on another device. Refuses keys that can't
+    /// decrypt the secrets in `config`, as they'd be lost to this device.
+    pub fn set_key(context: &SymmetryContext, config: &Configuration, key: &str) -> Result<()> {
+        let cipher = Cipher::new(key)?;
+        for ciphertext in config.secrets.values() {
+            cipher.decrypt_string(ciphertext).context(
+                "This sync key can't read the synchronized secrets, enter the one your other devices use.",
+            )?;
+        }
+        store::set(context, SYNC_KEY, key.trim())
+    }
+
+    /// Reads a secret from the keyring.
+    pub fn get(context: &SymmetryContext, name: &str) -> Result<Option<String>> {
+        store::get(context, name)
+    }
+
+    /// Stores a secret in the keyring and its encrypted form in the configuration.
+    pub fn set(&self, config: &mut Configuration, name: &str, secret: &str) -> Result<()> {
+        store::set(&self.context, name, secret)?;
+        config
+            .secrets
+            .insert(name.into(), self.cipher.encrypt_string(secret)?);
+        Ok(())
+    }
+
+    pub fn remove(context: &SymmetryContext, config: &mut Configuration, name: &str) -> Result<()> {
+        store::delete(context, name)?;
+        config.secrets.remove(name);
+        Ok(())
+    }
+
+    /// Decrypts the secrets received from other devices into the keyring.
+    pub fn import(&self, config: &Configuration) -> Result<()> {
+        for (name, ciphertext) in &config.secrets {
+            let secret = self.cipher.decrypt_string(ciphertext)?;
+            if store::get(&self.context, name)?.as_ref() != Some(&secret) {
+                store::set(&self.context, name, &secret)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.cipher.encrypt(plaintext)
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.cipher.decrypt(ciphertext)
+    }
+}
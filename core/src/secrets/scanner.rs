@@ -0,0 +1,29 @@
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+
+/// Patterns of well-known tokens and private keys that must never be committed.
+static PATTERNS: Lazy<Vec<(&str, Regex)>> = Lazy::new(|| {
+    [
+        ("private key", r"-----BEGIN [A-Z ]*PRIVATE KEY-----"),
+        (
+            "GitHub token",
+            r"\b(gh[pousr]_[A-Za-z0-9]{36}|github_pat_[A-Za-z0-9_]{22,})",
+        ),
+        ("GitLab token", r"\bglpat-[A-Za-z0-9_-]{20}"),
+        ("Slack token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}"),
+        ("AWS access key", r"\b(AKIA|ASIA)[A-Z0-9]{16}\b"),
+        ("Google API key", r"\bAIza[A-Za-z0-9_-]{35}"),
+        ("OpenAI key", r"\bsk-[A-Za-z0-9]{32,}"),
+    ]
+    .into_iter()
+    .map(|(name, pattern)| (name, Regex::new(pattern).unwrap()))
+    .collect()
+});
+
+/// Returns the kind of secret found in the contents, if any.
+pub fn scan(contents: &[u8]) -> Option<&'static str> {
+    PATTERNS
+        .iter()
+        .find(|(_, pattern)| pattern.is_match(contents))
+        .map(|(name, _)| *name)
+}
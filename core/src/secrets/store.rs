@@ -0,0 +1,42 @@
+use anyhow::Result;
+use keyring::Entry;
+use sha2::{Digest, Sha256};
+
+use crate::{configuration::APP_NAME, context::SymmetryContext};
+
+/// The keyring service holding the secrets of `context`. Instances with their own data
+/// directory, such as portable installs, keep their secrets apart from the user's one.
+pub fn service(context: &SymmetryContext) -> String {
+    if dirs::data_dir().as_deref() == Some(context.data_dir()) {
+        return APP_NAME.into();
+    }
+    let digest = Sha256::digest(context.data_dir().to_string_lossy().as_bytes());
+    let hash: String = digest[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("{APP_NAME}-{hash}")
+}
+
+/// Reads a secret from the system keyring.
+pub fn get(context: &SymmetryContext, name: &str) -> Result<Option<String>> {
+    match Entry::new(&service(context), name)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Stores a secret in the system keyring, replacing any previous value.
+pub fn set(context: &SymmetryContext, name: &str, secret: &str) -> Result<()> {
+    Entry::new(&service(context), name)?.set_password(secret)?;
+    Ok(())
+}
+
+/// Removes a secret from the system keyring.
+pub fn delete(context: &SymmetryContext, name: &str) -> Result<()> {
+    match Entry::new(&service(context), name)?.delete_password() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
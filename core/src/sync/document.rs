@@ -0,0 +1,286 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use crdts::{CmRDT, CvRDT, Dot, VClock};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Identifies the device that made a change.
+pub type Actor = String;
+
+/// The synchronized state as a conflict-free replicated document: a register per key,
+/// holding a configuration field or the contents of a synchronized file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Document {
+    /// Every change this document has seen.
+    clock: VClock<Actor>,
+    /// The changes known to have reached other devices.
+    #[serde(default)]
+    synced: VClock<Actor>,
+    /// What each device had seen when it last synchronized with this one.
+    #[serde(default)]
+    peers: BTreeMap<Actor, VClock<Actor>>,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// The latest value of a key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// `None` once the key was removed, kept so the removal reaches other devices.
+    pub value: Option<String>,
+    /// The change that wrote this value.
+    pub dot: Dot<Actor>,
+    /// Every change seen when the value was written, telling overwrites from concurrent writes.
+    pub clock: VClock<Actor>,
+    /// Milliseconds since the epoch, deciding between concurrent writes.
+    pub timestamp: i64,
+}
+
+/// The entries another device is missing, along with everything the sender has seen.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delta {
+    pub clock: VClock<Actor>,
+    pub entries: BTreeMap<String, Entry>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the document stored at `path`, or starts an empty one.
+    ///
+    /// Falls back to the previous version when the latest one is missing or unreadable,
+    /// as after a crash while saving.
+    pub fn load(path: &Path) -> Result<Self> {
+        let latest = match Self::read(path) {
+            Ok(Some(document)) => return Ok(document),
+            Ok(None) => None,
+            Err(err) => Some(err),
+        };
+        match Self::read(&sibling(path, "bak")) {
+            Ok(Some(document)) => {
+                if let Some(err) = latest {
+                    warn!("{err:#}, using the previous version.");
+                }
+                Ok(document)
+            }
+            Ok(None) => latest.map_or_else(|| Ok(Self::new()), Err),
+            Err(err) => Err(latest.unwrap_or(err)),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(data) => ron::from_str(&data)
+                .map(Some)
+                .with_context(|| format!("The document at {} is corrupted", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Stores the document at `path`, keeping the previous version next to it.
+    ///
+    /// The document is written to a temporary file first and moved over the current one
+    /// once it reached the disk, so a crash leaves either version at `path`, never none.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let parent = path.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)?;
+        let temporary = sibling(path, "tmp");
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(ron::to_string(self)?.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        if path.exists() {
+            let backup = sibling(path, "bak");
+            if backup.exists() {
+                std::fs::remove_file(&backup)?;
+            }
+            if std::fs::hard_link(path, &backup).is_err() {
+                std::fs::copy(path, &backup)?;
+            }
+        }
+        std::fs::rename(&temporary, path)?;
+        // Makes the rename itself durable.
+        if let Ok(directory) = std::fs::File::open(parent) {
+            let _ = directory.sync_all();
+        }
+        Ok(())
+    }
+
+    /// Whether the document has never seen a change, as on a device that hasn't synchronized yet.
+    pub fn is_empty(&self) -> bool {
+        self.clock.is_empty()
+    }
+
+    pub fn clock(&self) -> &VClock<Actor> {
+        &self.clock
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key)?.value.as_deref()
+    }
+
+    /// Lists the keys that currently hold a value.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.value.is_some())
+            .map(|(key, _)| key)
+    }
+
+    /// Writes `value` to `key` on behalf of `actor`, or removes the key with `None`.
+    /// Returns whether anything changed.
+    pub fn set(&mut self, actor: &str, key: &str, value: Option<String>) -> bool {
+        let current = self.entries.get(key).map(|entry| &entry.value);
+        if current == Some(&value) || (current.is_none() && value.is_none()) {
+            return false;
+        }
+        let dot = self.clock.inc(actor.to_string());
+        self.clock.apply(dot.clone());
+        let entry = Entry {
+            value,
+            dot,
+            clock: self.clock.clone(),
+            timestamp: now(),
+        };
+        self.entries.insert(key.to_string(), entry);
+        true
+    }
+
+    /// Collects the entries written since `since`, for a device that has seen that much.
+    pub fn delta(&self, since: &VClock<Actor>) -> Delta {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.dot.counter > since.get(&entry.dot.actor))
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        Delta {
+            clock: self.clock.clone(),
+            entries,
+        }
+    }
+
+    /// Merges changes from another device. Returns the keys whose value changed.
+    pub fn merge(&mut self, delta: Delta) -> Vec<String> {
+        let mut changed = vec![];
+        for (key, theirs) in delta.entries {
+            let replace = match self.entries.get(&key) {
+                Some(ours) => theirs.supersedes(ours),
+                // A write this document has seen without keeping it was removed since,
+                // and its removal compacted away.
+                None => theirs.dot.counter > self.clock.get(&theirs.dot.actor),
+            };
+            if replace {
+                if self.get(&key) != theirs.value.as_deref() {
+                    changed.push(key.clone());
+                }
+                self.entries.insert(key, theirs);
+            }
+        }
+        // Changes missing from the delta were overwritten by ones it holds.
+        self.clock.merge(delta.clock);
+        changed
+    }
+
+    /// Whether the document was ever synchronized with another device holding changes.
+    pub fn has_synced(&self) -> bool {
+        !self.synced.is_empty()
+    }
+
+    /// Records that the changes in `clock` reached another device, leaving out those made
+    /// since it was taken.
+    pub fn mark_synced_to(&mut self, clock: VClock<Actor>) {
+        self.synced.merge(clock);
+    }
+
+    /// Records that the device `peer` has seen the changes in `clock`.
+    pub fn record_peer_clock(&mut self, peer: &str, clock: VClock<Actor>) {
+        self.peers.entry(peer.to_string()).or_default().merge(clock);
+    }
+
+    /// Drops what no device needs anymore: the devices that aren't in `devices` and the
+    /// removals every one of them has seen. Returns how many removals were dropped.
+    ///
+    /// `devices` lists the other devices this one synchronizes with.
+    pub fn compact(&mut self, devices: &BTreeSet<Actor>) -> usize {
+        self.peers.retain(|peer, _| devices.contains(peer));
+        let seen = |entry: &Entry| {
+            devices.iter().all(|device| {
+                self.peers
+                    .get(device)
+                    .is_some_and(|clock| clock.get(&entry.dot.actor) >= entry.dot.counter)
+            })
+        };
+        let removed: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.value.is_none() && seen(entry))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &removed {
+            self.entries.remove(key);
+        }
+        removed.len()
+    }
+
+    /// Counts the entries, removals included.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Counts the changes made by `actor` that no other device has received yet.
+    pub fn pending(&self, actor: &str) -> usize {
+        let actor = actor.to_string();
+        self.clock
+            .get(&actor)
+            .saturating_sub(self.synced.get(&actor)) as usize
+    }
+
+    /// Identifies the state of the document, the same on every device that saw the same changes.
+    pub fn version(&self) -> String {
+        let clock = ron::to_string(&self.clock).unwrap_or_default();
+        Sha256::digest(clock.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+impl Entry {
+    /// Whether this value should replace `other`: when it was written after seeing it,
+    /// or, for concurrent writes, when it is the latest one.
+    fn supersedes(&self, other: &Entry) -> bool {
+        match self.clock.partial_cmp(&other.clock) {
+            Some(ordering) => ordering.is_gt(),
+            None => {
+                (self.timestamp, &self.dot.actor, self.dot.counter)
+                    > (other.timestamp, &other.dot.actor, other.dot.counter)
+            }
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// A file next to `path`, with `extension` added to its name.
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
@@ -0,0 +1,91 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use log::warn;
+
+use crate::trust::{identity::Identity, Devices};
+
+use super::{
+    document::{Actor, Delta},
+    mailbox::{Envelope, Mailbox},
+};
+
+const EXTENSION: &str = "ron";
+
+/// A directory kept in sync by another tool, such as Syncthing, Nextcloud or a USB drive
+/// carried around, used as a meeting point between devices.
+///
+/// Each device only ever writes its own file, holding everything it knows, and reads the
+/// files of the others. Since no two devices write the same file, the tool moving the
+/// files around never has conflicts to deal with.
+#[derive(Clone)]
+pub struct Folder {
+    path: PathBuf,
+}
+
+impl Folder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the file at `path`, or returns `None` if it isn't meant for this device.
+    fn open(path: &Path, identity: &Identity, devices: &Devices) -> Result<Option<(Actor, Delta)>> {
+        let envelope: Envelope = ron::from_str(&std::fs::read_to_string(path)?)?;
+        let Some(state) = envelope.open(identity, devices)? else {
+            return Ok(None);
+        };
+        let writer = envelope.writer();
+        if path.file_stem().and_then(|stem| stem.to_str()) != Some(writer.as_str()) {
+            bail!("The file was written by another device than its name says.");
+        }
+        Ok(Some((writer, state)))
+    }
+
+    fn file(&self, id: &str) -> PathBuf {
+        self.path.join(format!("{id}.{EXTENSION}"))
+    }
+}
+
+impl Mailbox for Folder {
+    /// Fails when the folder can't be reached, as when a drive isn't mounted.
+    fn read(&mut self, identity: &Identity, devices: &Devices) -> Result<Vec<(Actor, Delta)>> {
+        if !self.path.is_dir() {
+            bail!("The shared folder {} isn't available.", self.path.display());
+        }
+        let own = self.file(&identity.id());
+        let mut states = vec![];
+        for entry in std::fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            if path == own || path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            match Self::open(&path, identity, devices) {
+                Ok(Some(state)) => states.push(state),
+                Ok(None) => {}
+                Err(err) => warn!("{}: {err:#}", path.display()),
+            }
+        }
+        Ok(states)
+    }
+
+    fn write(&mut self, identity: &Identity, devices: &Devices, state: &Delta) -> Result<()> {
+        let id = identity.id();
+        let envelope = Envelope::seal(identity, devices, state)?;
+        // Written aside and moved into place, so other devices never read half a file.
+        // The temporary name doesn't end in the extension, so they skip it.
+        let path = self.file(&id);
+        let temporary = self.path.join(format!(".{id}.{EXTENSION}.tmp"));
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(ron::to_string(&envelope)?.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
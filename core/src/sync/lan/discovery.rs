@@ -0,0 +1,57 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+/// The mDNS service devices advertise themselves under.
+pub const SERVICE_TYPE: &str = "_symmetry._tcp.local.";
+const DEVICE_PROPERTY: &str = "device";
+
+/// Advertises this device on the LAN for as long as it's kept.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+/// Announces that `device` accepts sync sessions on `port`.
+pub fn advertise(device: &str, port: u16) -> Result<Advertisement> {
+    let daemon = ServiceDaemon::new()?;
+    let host = format!("{device}.local.");
+    let properties = [(DEVICE_PROPERTY, device)];
+    let info = ServiceInfo::new(SERVICE_TYPE, device, &host, (), port, &properties[..])?
+        .enable_addr_auto();
+    let fullname = info.get_fullname().to_string();
+    daemon.register(info)?;
+    Ok(Advertisement { daemon, fullname })
+}
+
+/// Looks for other devices on the LAN for up to `timeout`, leaving out `device` itself.
+pub fn browse(device: &str, timeout: Duration) -> Result<Vec<SocketAddr>> {
+    let daemon = ServiceDaemon::new()?;
+    let receiver = daemon.browse(SERVICE_TYPE)?;
+    let deadline = Instant::now() + timeout;
+    let mut peers = vec![];
+    while let Ok(event) = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        if let ServiceEvent::ServiceResolved(info) = event {
+            if info.get_property_val_str(DEVICE_PROPERTY) == Some(device) {
+                continue;
+            }
+            for address in info.get_addresses() {
+                peers.push(SocketAddr::new(*address, info.get_port()));
+            }
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(peers)
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
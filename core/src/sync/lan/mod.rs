@@ -0,0 +1,71 @@
+pub mod discovery;
+pub mod pairing;
+pub mod session;
+
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+
+use anyhow::Result;
+use crdts::VClock;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    document::{Actor, Delta},
+    listener::Listener,
+};
+
+use self::discovery::Advertisement;
+
+/// The port devices listen on unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 47474;
+
+/// A message exchanged between devices during a sync session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Frame {
+    /// Opens the session with the signing key of the sender, and its signature over the
+    /// session keys.
+    Hello { key: String, signature: String },
+    /// Whether the sender trusts the receiver.
+    Trusted(bool),
+    /// Everything the sender has seen, so the receiver knows what it's missing,
+    /// and whether the sender synchronized with another device before.
+    Clock { clock: VClock<Actor>, joined: bool },
+    /// The changes the receiver is missing.
+    Delta(Delta),
+    /// Sent by the device that accepted the session once it saved what it received.
+    Done,
+}
+
+/// Accepts connections from other devices in the background until dropped.
+pub struct Server {
+    listener: Listener,
+    advertisement: Option<Advertisement>,
+}
+
+impl Server {
+    /// Listens on `address`, handing each connection to `handler` one at a time.
+    pub fn bind<F>(address: impl ToSocketAddrs, handler: F) -> Result<Self>
+    where
+        F: Fn(TcpStream) -> Result<()> + Send + 'static,
+    {
+        let listener = Listener::bind(address, move |stream, _| {
+            if let Err(err) = handler(stream) {
+                warn!("{err:#}");
+            }
+        })?;
+        Ok(Self {
+            listener,
+            advertisement: None,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.listener.address()
+    }
+
+    /// Announces the server on the LAN as `device`, so other devices find it.
+    pub fn advertise(&mut self, device: &str) -> Result<()> {
+        self.advertisement = Some(discovery::advertise(device, self.address().port())?);
+        Ok(())
+    }
+}
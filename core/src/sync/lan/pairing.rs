@@ -0,0 +1,331 @@
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::aead::OsRng;
+use hkdf::Hkdf;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::{
+    configuration::Configuration,
+    secrets::cipher::Cipher,
+    trust::{
+        identity::{verify, Identity},
+        invitation::Invitation,
+        Devices, TrustedDevice,
+    },
+};
+
+use super::session::{read_frame, write_frame, Direction};
+
+const PROTOCOL: &[u8] = b"SYMMETRY-PAIR-2\n";
+/// Frames larger than this are refused: until the codes are compared the other device
+/// could be anyone, and pairing only hands over keys and the list of devices.
+const MAX_FRAME: usize = 64 * 1024;
+/// How long to wait for the user on the other device to compare the codes.
+const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// An encrypted channel between two devices pairing over the LAN.
+///
+/// Both devices show a short code derived from the keys they exchanged. The hosting device
+/// commits to its key before seeing the other one, so a device in the middle can't make the
+/// codes match, and nothing is handed over until the user confirmed they do.
+struct Pairing {
+    stream: TcpStream,
+    code: String,
+    transcript: Vec<u8>,
+    sending: Direction,
+    receiving: Direction,
+}
+
+/// Asks to join, sent by the new device.
+#[derive(Serialize, Deserialize)]
+struct JoinRequest {
+    device: TrustedDevice,
+    /// Proves the new device holds the signing key it presents.
+    signature: String,
+    /// The token of the invitation, if the new device was given one.
+    token: Option<String>,
+}
+
+/// Lets the new device in, sent once the user approved it.
+#[derive(Serialize, Deserialize)]
+struct Welcome {
+    key: String,
+    devices: Devices,
+    /// The signing key of the host, and its signature over the pairing.
+    host: String,
+    signature: String,
+}
+
+/// What a new device receives once it's let in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Joined {
+    /// The sync key, to store in the keyring.
+    pub key: String,
+    /// The devices it can now synchronize with, to store in the configuration.
+    pub devices: Devices,
+}
+
+/// Offers to pair with new devices, waiting for them on a listening socket.
+pub struct Invite {
+    listener: TcpListener,
+    invitation: Invitation,
+}
+
+/// A device asking to join, waiting for the user to approve it.
+pub struct Request {
+    pairing: Pairing,
+    device: TrustedDevice,
+    verified: bool,
+}
+
+/// A new device waiting to be let in by the one it's pairing with.
+pub struct Joining {
+    pairing: Pairing,
+    /// The signing key the host is expected to have, when pairing from an invitation.
+    host: Option<String>,
+}
+
+impl Invite {
+    /// Listens on `address` for devices to pair with `identity`.
+    pub fn new(identity: &Identity, address: impl ToSocketAddrs) -> Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let invitation = Invitation::new(reachable(listener.local_addr()?), identity);
+        Ok(Self {
+            listener,
+            invitation,
+        })
+    }
+
+    /// The invitation to show to the new device, as a link or a QR code.
+    pub fn invitation(&self) -> &Invitation {
+        &self.invitation
+    }
+
+    /// Waits for a device to ask to join.
+    pub fn accept(&self) -> Result<Request> {
+        let (stream, _) = self.listener.accept()?;
+        let mut pairing = Pairing::host(stream)?;
+        let request: JoinRequest = pairing.receive()?;
+        verify(
+            &request.device.signing_key,
+            &pairing.signed(b"joiner"),
+            &request.signature,
+        )?;
+        let verified = request.token.as_deref() == Some(self.invitation.token.as_str());
+        Ok(Request {
+            pairing,
+            device: request.device,
+            verified,
+        })
+    }
+}
+
+impl Request {
+    /// The device asking to join.
+    pub fn device(&self) -> &TrustedDevice {
+        &self.device
+    }
+
+    /// The code to compare with the one shown on the other device.
+    pub fn code(&self) -> &str {
+        &self.pairing.code
+    }
+
+    /// Whether the device presented the invitation, so there's no need to compare codes.
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+
+    /// Trusts the device in `config` and hands it the sync key and the trusted devices.
+    /// The configuration has to be written afterwards for the others to learn about it.
+    pub fn approve(
+        mut self,
+        identity: &Identity,
+        key: &str,
+        config: &mut Configuration,
+    ) -> Result<()> {
+        Cipher::new(key)?;
+        config.devices.trust(identity.device());
+        config.devices.trust(self.device.clone());
+        let welcome = Welcome {
+            key: key.trim().to_string(),
+            devices: config.devices.clone(),
+            host: identity.signing_key(),
+            signature: identity.sign(&self.pairing.signed(b"host")),
+        };
+        self.pairing.send(&welcome)
+    }
+}
+
+impl Joining {
+    /// Asks the device listening on `address` to pair, to be confirmed by comparing codes.
+    pub fn new(address: SocketAddr, identity: &Identity) -> Result<Self> {
+        Self::start(address, identity, None)
+    }
+
+    /// Asks the device that made `invitation` to pair.
+    pub fn with_invitation(invitation: &Invitation, identity: &Identity) -> Result<Self> {
+        Self::start(invitation.address, identity, Some(invitation))
+    }
+
+    fn start(
+        address: SocketAddr,
+        identity: &Identity,
+        invitation: Option<&Invitation>,
+    ) -> Result<Self> {
+        let mut pairing = Pairing::join(address)?;
+        let request = JoinRequest {
+            device: identity.device(),
+            signature: identity.sign(&pairing.signed(b"joiner")),
+            token: invitation.map(|invitation| invitation.token.clone()),
+        };
+        pairing.send(&request)?;
+        Ok(Self {
+            pairing,
+            host: invitation.map(|invitation| invitation.host.clone()),
+        })
+    }
+
+    /// The code to compare with the one shown on the other device.
+    pub fn code(&self) -> &str {
+        &self.pairing.code
+    }
+
+    /// Waits for the user on the other device to let this one in.
+    pub fn finish(mut self) -> Result<Joined> {
+        let welcome: Welcome = self
+            .pairing
+            .receive()
+            .map_err(|_| anyhow!("The other device didn't confirm the pairing."))?;
+        if self.host.as_ref().is_some_and(|host| *host != welcome.host) {
+            bail!("The device answering isn't the one that made the invitation.");
+        }
+        verify(
+            &welcome.host,
+            &self.pairing.signed(b"host"),
+            &welcome.signature,
+        )?;
+        if welcome.devices.find(&welcome.host).is_none() {
+            bail!("The other device doesn't trust itself.");
+        }
+        Cipher::new(&welcome.key)?;
+        Ok(Joined {
+            key: welcome.key,
+            devices: welcome.devices,
+        })
+    }
+}
+
+impl Pairing {
+    /// Pairs with a device that connected to this one.
+    fn host(mut stream: TcpStream) -> Result<Self> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        let commitment = Sha256::digest(public.as_bytes());
+        write_frame(&mut stream, &[PROTOCOL, &commitment].concat())?;
+        let joiner = public_key(&read_frame(&mut stream, MAX_FRAME)?)?;
+        write_frame(&mut stream, public.as_bytes())?;
+        let shared = secret.diffie_hellman(&joiner);
+        Self::new(stream, shared.as_bytes(), &public, &joiner, true)
+    }
+
+    /// Pairs with the device listening on `address`.
+    fn join(address: SocketAddr) -> Result<Self> {
+        let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(30))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        let hello = read_frame(&mut stream, MAX_FRAME)?;
+        let Some(commitment) = hello.strip_prefix(PROTOCOL) else {
+            bail!("The device doesn't speak the Symmetry pairing protocol.");
+        };
+        let commitment = commitment.to_vec();
+        write_frame(&mut stream, public.as_bytes())?;
+        let host = public_key(&read_frame(&mut stream, MAX_FRAME)?)?;
+        if Sha256::digest(host.as_bytes()).as_slice() != commitment {
+            bail!("The other device changed its key during pairing, someone may be interfering.");
+        }
+        let shared = secret.diffie_hellman(&host);
+        Self::new(stream, shared.as_bytes(), &host, &public, false)
+    }
+
+    fn new(
+        stream: TcpStream,
+        shared: &[u8],
+        host: &PublicKey,
+        joiner: &PublicKey,
+        hosting: bool,
+    ) -> Result<Self> {
+        let transcript = [host.as_bytes().as_slice(), joiner.as_bytes()].concat();
+        let digest = Sha256::digest([b"symmetry pairing code".as_slice(), &transcript].concat());
+        let number = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+        let hkdf = Hkdf::<Sha256>::new(Some(&transcript), shared);
+        let derive = |info: &[u8]| -> Result<Direction> {
+            let mut key = [0u8; 32];
+            hkdf.expand(info, &mut key)
+                .map_err(|_| anyhow!("Failed to derive the pairing keys."))?;
+            Ok(Direction::new(&key))
+        };
+        let (to_joiner, to_host) = (
+            derive(b"symmetry pairing host")?,
+            derive(b"symmetry pairing joiner")?,
+        );
+        let (sending, receiving) = if hosting {
+            (to_joiner, to_host)
+        } else {
+            (to_host, to_joiner)
+        };
+        Ok(Self {
+            stream,
+            code: format!("{:06}", number % 1_000_000),
+            transcript,
+            sending,
+            receiving,
+        })
+    }
+
+    /// What each side signs to prove it holds its signing key, tied to this pairing.
+    fn signed(&self, role: &[u8]) -> Vec<u8> {
+        [b"symmetry pairing ", role, b"\n", &self.transcript].concat()
+    }
+
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let ciphertext = self.sending.seal(ron::to_string(message)?.as_bytes())?;
+        write_frame(&mut self.stream, &ciphertext)
+    }
+
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let plaintext = self
+            .receiving
+            .open(&read_frame(&mut self.stream, MAX_FRAME)?)?;
+        Ok(ron::from_str(std::str::from_utf8(&plaintext)?)?)
+    }
+}
+
+fn public_key(data: &[u8]) -> Result<PublicKey> {
+    let bytes: [u8; 32] = data
+        .try_into()
+        .map_err(|_| anyhow!("The other device sent a malformed key."))?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// Replaces an unspecified listening address with the one other devices reach this one at.
+fn reachable(mut address: SocketAddr) -> SocketAddr {
+    if address.ip().is_unspecified() {
+        // Connecting a UDP socket sends nothing, it only picks the outgoing interface.
+        let local = UdpSocket::bind("0.0.0.0:0")
+            .and_then(|socket| socket.connect("192.0.2.1:9").map(|_| socket))
+            .and_then(|socket| socket.local_addr());
+        if let Ok(local) = local {
+            address.set_ip(local.ip());
+        }
+    }
+    address
+}
@@ -0,0 +1,260 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::trust::{
+    identity::{verify, Identity},
+    Devices,
+};
+
+use super::Frame;
+
+/// Opens every session, so peers speaking another protocol are turned away early.
+const PROTOCOL: &[u8] = b"SYMMETRY-LAN-2\n";
+const PUBLIC_KEY_LENGTH: usize = 32;
+/// Frames larger than this are refused, so a misbehaving peer can't exhaust memory.
+const MAX_FRAME: usize = 256 * 1024 * 1024;
+/// Frames before the peer proved it's trusted only hold keys, anyone on the network could
+/// send them.
+const HANDSHAKE_FRAME: usize = 4 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One of the devices isn't trusted by the other.
+#[derive(Debug)]
+pub struct Rejected;
+
+impl Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The peer isn't a trusted device, or doesn't trust this one."
+        )
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// An encrypted connection between two trusted devices.
+///
+/// Both sides contribute an ephemeral key, and the keys for each direction are derived from
+/// both, so recorded sessions can't be decrypted or replayed later. Each side then signs
+/// the exchanged keys with its identity, which the other checks against its trusted devices.
+pub struct Session {
+    stream: TcpStream,
+    sending: Direction,
+    receiving: Direction,
+    initiator: bool,
+    /// Whether the peer proved it's a trusted device, lifting the limit on frame sizes.
+    trusted: bool,
+    peer: String,
+    peer_id: String,
+}
+
+/// Encrypts or decrypts one direction of a connection.
+pub(crate) struct Direction {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl Session {
+    /// Connects to the device listening on `address`, if it's one of `devices`.
+    pub fn connect(address: SocketAddr, identity: &Identity, devices: &Devices) -> Result<Self> {
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        Self::handshake(stream, identity, devices, true)
+    }
+
+    /// Accepts a connection from another device, if it's one of `devices`.
+    pub fn accept(stream: TcpStream, identity: &Identity, devices: &Devices) -> Result<Self> {
+        Self::handshake(stream, identity, devices, false)
+    }
+
+    /// The name of the other device.
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    /// The identifier of the other device.
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    /// Whether this side opened the connection.
+    pub fn is_initiator(&self) -> bool {
+        self.initiator
+    }
+
+    pub fn send(&mut self, frame: &Frame) -> Result<()> {
+        let plaintext = ron::to_string(frame)?;
+        let ciphertext = self.sending.seal(plaintext.as_bytes())?;
+        write_frame(&mut self.stream, &ciphertext)
+    }
+
+    pub fn receive(&mut self) -> Result<Frame> {
+        let limit = if self.trusted {
+            MAX_FRAME
+        } else {
+            HANDSHAKE_FRAME
+        };
+        let ciphertext = read_frame(&mut self.stream, limit)?;
+        let plaintext = self.receiving.open(&ciphertext)?;
+        Ok(ron::from_str(std::str::from_utf8(&plaintext)?)?)
+    }
+
+    fn handshake(
+        mut stream: TcpStream,
+        identity: &Identity,
+        devices: &Devices,
+        initiator: bool,
+    ) -> Result<Self> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let ours = PublicKey::from(&secret);
+        let hello = [PROTOCOL, ours.as_bytes()].concat();
+        let theirs = if initiator {
+            write_frame(&mut stream, &hello)?;
+            read_frame(&mut stream, HANDSHAKE_FRAME)?
+        } else {
+            let theirs = read_frame(&mut stream, HANDSHAKE_FRAME)?;
+            write_frame(&mut stream, &hello)?;
+            theirs
+        };
+        let Some(theirs) = theirs.strip_prefix(PROTOCOL) else {
+            bail!("The peer doesn't speak the Symmetry protocol.");
+        };
+        let Ok(theirs) = <[u8; PUBLIC_KEY_LENGTH]>::try_from(theirs) else {
+            bail!("The peer sent a malformed key.");
+        };
+        let theirs = PublicKey::from(theirs);
+        let shared = secret.diffie_hellman(&theirs);
+        let transcript = if initiator {
+            [ours.as_bytes().as_slice(), theirs.as_bytes()].concat()
+        } else {
+            [theirs.as_bytes().as_slice(), ours.as_bytes()].concat()
+        };
+        let hkdf = Hkdf::<Sha256>::new(Some(&transcript), shared.as_bytes());
+        let derive = |info: &[u8]| -> Result<Direction> {
+            let mut key = [0u8; 32];
+            hkdf.expand(info, &mut key)
+                .map_err(|_| anyhow!("Failed to derive the session keys."))?;
+            Ok(Direction::new(&key))
+        };
+        let (initiator_keys, responder_keys) = (
+            derive(b"symmetry lan initiator")?,
+            derive(b"symmetry lan responder")?,
+        );
+        let (sending, receiving) = if initiator {
+            (initiator_keys, responder_keys)
+        } else {
+            (responder_keys, initiator_keys)
+        };
+        let mut session = Self {
+            stream,
+            sending,
+            receiving,
+            initiator,
+            trusted: false,
+            peer: String::new(),
+            peer_id: String::new(),
+        };
+        session.send(&Frame::Hello {
+            key: identity.signing_key(),
+            signature: identity.sign(&signed(&transcript, initiator)),
+        })?;
+        let Frame::Hello { key, signature } = session.receive()? else {
+            bail!("The peer didn't introduce itself.");
+        };
+        let peer = devices
+            .find(&key)
+            .filter(|_| verify(&key, &signed(&transcript, !initiator), &signature).is_ok());
+        // Both sides tell whether they trust the other, so a rejection is reported as such
+        // rather than as a dropped connection.
+        session.send(&Frame::Trusted(peer.is_some()))?;
+        let Frame::Trusted(trusted) = session.receive()? else {
+            bail!("The peer didn't say whether it trusts this device.");
+        };
+        match peer {
+            Some(peer) if trusted => {
+                session.trusted = true;
+                session.peer = peer.name.clone();
+                session.peer_id = peer.id();
+                Ok(session)
+            }
+            _ => Err(Rejected.into()),
+        }
+    }
+}
+
+/// What each side signs to prove who it is, tied to this session and its role in it.
+fn signed(transcript: &[u8], initiator: bool) -> Vec<u8> {
+    let role: &[u8] = if initiator {
+        b"initiator"
+    } else {
+        b"responder"
+    };
+    [b"symmetry lan ", role, b"\n", transcript].concat()
+}
+
+impl Direction {
+    pub(crate) fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            counter: 0,
+        }
+    }
+
+    /// Nonces count frames, so frames can't be dropped, reordered or replayed unnoticed.
+    fn nonce(&mut self) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter += 1;
+        *Nonce::from_slice(&nonce)
+    }
+
+    pub(crate) fn seal(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.nonce();
+        self.cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("Failed to encrypt a message for the peer."))
+    }
+
+    pub(crate) fn open(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.nonce();
+        self.cipher
+            .decrypt(&nonce, ciphertext)
+            .map_err(|_| anyhow!("Received a corrupted or forged message."))
+    }
+}
+
+/// Writes a length-prefixed frame.
+pub(crate) fn write_frame(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+    stream.write_all(&(data.len() as u32).to_be_bytes())?;
+    stream.write_all(data)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Reads a length-prefixed frame, refusing those longer than `limit`.
+pub(crate) fn read_frame(stream: &mut TcpStream, limit: usize) -> Result<Vec<u8>> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > limit {
+        bail!("The peer sent a frame of {length} bytes, more than allowed.");
+    }
+    let mut data = vec![0u8; length];
+    stream.read_exact(&mut data)?;
+    Ok(data)
+}
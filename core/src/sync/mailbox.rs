@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    secrets::cipher::Cipher,
+    trust::{
+        self,
+        identity::{device_id, verify, Identity},
+        Devices,
+    },
+};
+
+use super::document::{Actor, Delta};
+
+/// A place where devices that are never online together leave their state for each other,
+/// such as a shared folder or a relay.
+pub trait Mailbox {
+    /// Reads what the trusted devices other than `identity` left, keyed by device. Fails
+    /// when the mailbox can't be reached.
+    fn read(&mut self, identity: &Identity, devices: &Devices) -> Result<Vec<(Actor, Delta)>>;
+
+    /// Leaves `state` for the trusted devices other than `identity`.
+    fn write(&mut self, identity: &Identity, devices: &Devices, state: &Delta) -> Result<()>;
+}
+
+/// The state of a device as it leaves it in a mailbox, readable only by the devices it
+/// trusts.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Envelope {
+    /// The signing key of the device that sealed the envelope.
+    device: String,
+    /// The key the state is encrypted with, sealed for each reader, keyed by device.
+    keys: BTreeMap<String, String>,
+    /// The state of the device, encrypted.
+    state: String,
+    /// The signature of the device over the keys and the state.
+    signature: String,
+}
+
+impl Envelope {
+    /// Seals `state` for the trusted devices other than `identity`.
+    pub fn seal(identity: &Identity, devices: &Devices, state: &Delta) -> Result<Self> {
+        let id = identity.id();
+        let key = Cipher::generate_key();
+        let mut keys = BTreeMap::new();
+        for (device, trusted) in devices.trusted.iter().filter(|(device, _)| **device != id) {
+            keys.insert(device.clone(), trust::seal(&key, &trusted.agreement_key)?);
+        }
+        let state = Cipher::new(&key)?.encrypt_string(&ron::to_string(state)?)?;
+        let signature = identity.sign(signed(&keys, &state)?.as_bytes());
+        Ok(Self {
+            device: identity.signing_key(),
+            keys,
+            state,
+            signature,
+        })
+    }
+
+    /// The device that sealed the envelope, or claims to have.
+    pub fn writer(&self) -> Actor {
+        device_id(&self.device)
+    }
+
+    /// Opens the envelope, or returns `None` if it isn't meant for this device.
+    pub fn open(&self, identity: &Identity, devices: &Devices) -> Result<Option<Delta>> {
+        if devices.find(&self.device).is_none() {
+            return Ok(None);
+        }
+        verify(
+            &self.device,
+            signed(&self.keys, &self.state)?.as_bytes(),
+            &self.signature,
+        )?;
+        let Some(sealed) = self.keys.get(&identity.id()) else {
+            // Sealed before this device was trusted, it'll be readable once sealed again.
+            return Ok(None);
+        };
+        let key = trust::unseal(sealed, identity)?;
+        let state = Cipher::new(&key)?
+            .decrypt_string(&self.state)
+            .context("The state can't be decrypted")?;
+        Ok(Some(ron::from_str(&state)?))
+    }
+}
+
+/// What a device signs in its envelope.
+fn signed(keys: &BTreeMap<String, String>, state: &str) -> Result<String> {
+    Ok(ron::to_string(&(keys, state))?)
+}
@@ -1,3 +1,14 @@
+use crate::{configuration::changes::Field, sync::providers::config::git::ConflictStrategy};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Message {
     Update,
+    /// Updates, but only takes the given configuration fields from the remote changes.
+    Accept(Vec<Field>),
+    /// Publishes the local changes without bringing in remote ones.
+    Push,
+    /// Discards the local changes and goes back to the remote state.
+    Reset,
+    /// Updates, settling conflicts by keeping one side.
+    Resolve(ConflictStrategy),
 }
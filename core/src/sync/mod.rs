@@ -1,3 +1,143 @@
+pub mod document;
+pub mod folder;
+pub mod lan;
+pub mod listener;
+pub mod mailbox;
 pub mod message;
+pub mod outbox;
+pub mod overrides;
 pub mod providers;
+pub mod relay;
 pub mod status;
+pub mod store;
+
+use std::{
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    configuration::Configuration,
+    context::SymmetryContext,
+    desktop::Desktop,
+    files::Files,
+    secrets::Secrets,
+    trust::{self, identity::Identity},
+};
+
+use self::overrides::Overrides;
+
+/// Records the desktop settings and tracked files of the device of `context` into its
+/// synchronized directory, before local changes are shared. Fields this device overrides
+/// stay in its state.
+pub(crate) fn capture(context: &SymmetryContext) -> Result<()> {
+    let (path, state) = (context.config_path(), context.state_path());
+    if let Some(mut config) = Configuration::read_from(&path) {
+        let mut overrides = Overrides::load(&state);
+        let mut device = overrides.apply(&config);
+        if Desktop::capture(context, &mut device)? {
+            overrides.record(&device, &mut config);
+            overrides.save(&state)?;
+            config.write_to(&path)?;
+        }
+        Files::new(context).capture(&device)?;
+    }
+    Ok(())
+}
+
+/// Writes the desktop settings and tracked files from the synchronized directory of
+/// `context` back to its device. Returns what couldn't be applied, so a failure in one
+/// place doesn't hold back the rest. Fields this device overrides are taken from its state.
+pub(crate) fn apply(context: &SymmetryContext) -> Vec<String> {
+    let mut failed = vec![];
+    let Some(config) = Configuration::read_from(&context.config_path()) else {
+        return failed;
+    };
+    let state = context.state_path();
+    let config = Overrides::load(&state).apply(&config);
+    // Files render their own templates, the desktop settings are rendered here.
+    if let Err(err) = config
+        .rendered(context)
+        .and_then(|rendered| Desktop::apply(context, &rendered))
+    {
+        failed.push(format!("Desktop settings: {err}"));
+    }
+    let files = Files::new(context);
+    match files.deploy(&config) {
+        Ok(differences) if !differences.is_empty() => failed.push(format!(
+            "Files: {} differ from their synchronized version, review them on the Files page.",
+            differences.len()
+        )),
+        Ok(_) => {}
+        Err(err) => failed.push(format!("Files: {err}")),
+    }
+    // A device was revoked, the new sync key is needed before reading the secrets.
+    if !config.devices.keys.is_empty() {
+        if let Err(err) = receive_key(context, &config) {
+            failed.push(format!("Sync key: {err}"));
+        }
+    }
+    if !config.secrets.is_empty() {
+        if let Err(err) = Secrets::new(context).and_then(|secrets| secrets.import(&config)) {
+            failed.push(format!("Secrets: {err}"));
+        }
+    }
+    failed
+}
+
+/// Stores the sync key sealed for this device, if it differs from the current one.
+fn receive_key(context: &SymmetryContext, config: &Configuration) -> Result<()> {
+    let Some(key) = trust::receive_key(config, &Identity::load(&context.state_path())?)? else {
+        return Ok(());
+    };
+    if Secrets::key(context).ok().as_deref() != Some(key.as_str()) {
+        Secrets::set_key(context, config, &key)?;
+    }
+    Ok(())
+}
+
+/// Resolves `relative`, a path another device shared, in the synchronized directory
+/// `repo`. Refuses paths leading out of it or into its git metadata, which git would run.
+pub fn inside(repo: &Path, relative: &str) -> Result<PathBuf> {
+    let path = Path::new(relative);
+    let normal = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if relative.is_empty() || !normal || path.starts_with(".git") {
+        bail!("Refusing to write {relative}, outside of the synchronized directory.");
+    }
+    Ok(repo.join(path))
+}
+
+/// Formats a time in UTC as ISO 8601 without separators, e.g. `20240101T120000Z`, which
+/// sorts in order and is safe in file names.
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rest) = ((seconds / 86400) as i64, seconds % 86400);
+    // Converts days since the epoch to a civil date, after Howard Hinnant's algorithm.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
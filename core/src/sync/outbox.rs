@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+/// File in the state directory holding the outbox.
+pub const OUTBOX_FILE: &str = "outbox.ron";
+
+/// Local changes that haven't reached the remote yet, kept across restarts
+/// so they can be shown and retried while offline.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Outbox {
+    pub pending: Vec<Pending>,
+}
+
+/// A change waiting to be published.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Pending {
+    /// Identifies the change within the provider, e.g. a commit id.
+    pub id: String,
+    pub summary: String,
+    /// When the change was made, in seconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+impl Outbox {
+    pub fn path(state: &Path) -> PathBuf {
+        state.join(OUTBOX_FILE)
+    }
+
+    /// Loads the outbox kept in the state directory, empty if there is none.
+    pub fn load(state: &Path) -> Self {
+        std::fs::read_to_string(Self::path(state))
+            .ok()
+            .and_then(|data| ron::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the outbox in the state directory, removing the file once it is empty.
+    pub fn save(&self, state: &Path) -> Result<()> {
+        let path = Self::path(state);
+        if self.pending.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        std::fs::create_dir_all(state).context("Failed to create the state directory.")?;
+        let data = ron::ser::to_string_pretty(self, PrettyConfig::new())?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::{changes::Field, Configuration};
+
+/// File in the state directory holding the configuration fields this device keeps to itself.
+pub const OVERRIDES_FILE: &str = "overrides.ron";
+
+/// This device's values of the configuration fields whose remote changes were declined.
+/// They're applied on top of the synchronized configuration here, and never published.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Overrides {
+    pub fields: BTreeSet<Field>,
+    pub values: Configuration,
+}
+
+impl Overrides {
+    pub fn path(state: &Path) -> PathBuf {
+        state.join(OVERRIDES_FILE)
+    }
+
+    /// Loads the overrides kept in the state directory, empty if there are none.
+    pub fn load(state: &Path) -> Self {
+        std::fs::read_to_string(Self::path(state))
+            .ok()
+            .and_then(|data| ron::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the overrides in the state directory, removing the file once there are none.
+    pub fn save(&self, state: &Path) -> Result<()> {
+        let path = Self::path(state);
+        if self.fields.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        std::fs::create_dir_all(state).context("Failed to create the state directory.")?;
+        let data = ron::ser::to_string_pretty(self, PrettyConfig::new())?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Records the fields the user took from the remote and those they declined, keeping
+    /// the `local` value of the latter unless this device already overrode them.
+    pub fn decide(
+        state: &Path,
+        accepted: &[Field],
+        declined: &[Field],
+        local: &Configuration,
+    ) -> Result<()> {
+        let mut overrides = Self::load(state);
+        for field in accepted {
+            overrides.fields.remove(field);
+        }
+        for field in declined {
+            if overrides.fields.insert(*field) {
+                field.copy(local, &mut overrides.values);
+            }
+        }
+        overrides.save(state)
+    }
+
+    /// The configuration as this device uses it, `shared` with the overridden fields.
+    pub fn apply(&self, shared: &Configuration) -> Configuration {
+        let mut config = shared.clone();
+        for field in &self.fields {
+            field.copy(&self.values, &mut config);
+        }
+        config
+    }
+
+    /// Splits the configuration read from the device into `shared`, leaving out the
+    /// overridden fields, whose values are kept here instead.
+    pub fn record(&mut self, device: &Configuration, shared: &mut Configuration) {
+        for field in Field::ALL {
+            if self.fields.contains(&field) {
+                field.copy(device, &mut self.values);
+            } else {
+                field.copy(device, shared);
+            }
+        }
+    }
+}
@@ -1,6 +1,24 @@
 use serde::{Deserialize, Serialize};
 
+use crate::network::MeteredPolicy;
+
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct CrdtConfig {
     pub enabled: bool,
+    #[serde(default)]
+    pub metered: MeteredPolicy,
+    /// Port to accept sessions from other devices on, zero for the default one.
+    #[serde(default)]
+    pub port: u16,
+    /// Addresses of devices to reach directly, in addition to those found on the LAN.
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// A directory kept in sync by another tool to meet other devices through, empty for
+    /// none. It can use template variables, as it's often mounted in different places.
+    #[serde(default)]
+    pub folder: String,
+    /// A relay to meet other devices through, as `ws://host:port/account` or
+    /// `wss://host:port/account`, empty for none.
+    #[serde(default)]
+    pub relay: String,
 }
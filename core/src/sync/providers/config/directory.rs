@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DirectoryConfig {
+    pub enabled: bool,
+    /// The folder to write snapshots to, usually on a mounted disk. It can use template
+    /// variables, as disks are often mounted under the user's name.
+    #[serde(default)]
+    pub path: String,
+    /// How many snapshots to keep, zero to keep them all.
+    #[serde(default)]
+    pub keep: usize,
+    /// How many days to keep snapshots for, zero to keep them forever.
+    #[serde(default)]
+    pub days: u64,
+}
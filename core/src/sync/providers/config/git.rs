@@ -1,8 +1,31 @@
 use serde::{Deserialize, Serialize};
 
+use crate::network::MeteredPolicy;
+
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct GitConfig {
+    /// Remote URL, a `file://` URL or a plain path to a bare repository.
     pub url: String,
     pub username: String,
     pub enabled: bool,
+    #[serde(default)]
+    pub conflicts: ConflictStrategy,
+    /// Seconds during which successive changes are folded into one commit before it is
+    /// pushed. Zero commits and pushes every change right away.
+    #[serde(default)]
+    pub batch_seconds: u64,
+    #[serde(default)]
+    pub metered: MeteredPolicy,
+}
+
+/// What to do when both devices changed the same file in incompatible ways.
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Ask which version to keep.
+    #[default]
+    Ask,
+    /// Keep the version from the remote.
+    Theirs,
+    /// Keep the version from this device.
+    Ours,
 }
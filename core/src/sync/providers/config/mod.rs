@@ -1,12 +1,29 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
-use self::{crdt::CrdtConfig, git::GitConfig};
+use self::{
+    crdt::CrdtConfig, directory::DirectoryConfig, git::GitConfig, s3::S3Config,
+    webdav::WebDavConfig,
+};
 
 pub mod crdt;
+pub mod directory;
 pub mod git;
+pub mod s3;
+pub mod webdav;
 
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Services {
     pub git: GitConfig,
     pub crdt: CrdtConfig,
+    #[serde(default)]
+    pub webdav: WebDavConfig,
+    #[serde(default)]
+    pub s3: S3Config,
+    #[serde(default)]
+    pub directory: DirectoryConfig,
+    /// Settings of the backends registered by other crates, keyed by backend then setting.
+    #[serde(default)]
+    pub custom: BTreeMap<String, BTreeMap<String, String>>,
 }
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+use crate::network::MeteredPolicy;
+
+use super::git::ConflictStrategy;
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct S3Config {
+    pub enabled: bool,
+    /// Where the object storage is reached, e.g. `https://s3.eu-west-1.amazonaws.com` or
+    /// the address of a MinIO server.
+    #[serde(default)]
+    pub endpoint: String,
+    #[serde(default)]
+    pub bucket: String,
+    /// Keeps the objects under this prefix, so a bucket can be shared with other uses.
+    #[serde(default)]
+    pub prefix: String,
+    /// Region the bucket is in, empty for `us-east-1`, which MinIO uses by default.
+    #[serde(default)]
+    pub region: String,
+    #[serde(default)]
+    pub access_key: String,
+    #[serde(default)]
+    pub conflicts: ConflictStrategy,
+    #[serde(default)]
+    pub metered: MeteredPolicy,
+}
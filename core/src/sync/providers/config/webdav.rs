@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+use crate::network::MeteredPolicy;
+
+use super::git::ConflictStrategy;
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct WebDavConfig {
+    pub enabled: bool,
+    /// The collection to keep the files in, e.g. a folder of the Nextcloud WebDAV endpoint.
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub conflicts: ConflictStrategy,
+    #[serde(default)]
+    pub metered: MeteredPolicy,
+}
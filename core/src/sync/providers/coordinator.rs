@@ -0,0 +1,246 @@
+use std::{cell::Cell, time::Duration};
+
+use anyhow::Result;
+
+use crate::{
+    configuration::changes::Change,
+    network::MeteredPolicy,
+    sync::{message::Message, status::Status},
+    traits::synchronization::Synchronization,
+};
+
+use super::Provider;
+
+/// Runs several providers over the same synchronized directory as one, such as CRDT for
+/// realtime sync between devices and git for a durable history of every change.
+///
+/// Providers run in order, so those listed first should be the realtime ones: what they
+/// merge is then recorded by the others. Whatever a later provider brings in is handed
+/// back to the earlier ones, so they all end up with the same state.
+pub struct Coordinator {
+    providers: Vec<(String, Provider)>,
+    /// The provider waiting for the user to review changes or settle conflicts.
+    waiting: Cell<Option<usize>>,
+    /// What the other providers couldn't do meanwhile, by provider, reported once the
+    /// review is settled unless they run again by then.
+    deferred: Cell<Vec<(usize, String)>>,
+}
+
+impl Synchronization for Coordinator {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Status> {
+        self.run(|provider| provider.sync(), |_| true)
+    }
+
+    /// Hands the message to the provider that asked the user for it, or to every provider
+    /// when none did.
+    fn handle(&self, message: Message) -> Result<Status> {
+        let Some(index) = self.waiting.take() else {
+            return self.run(|provider| provider.handle(message.clone()), |_| true);
+        };
+        let status = self.providers[index].1.handle(message);
+        let realign = downloaded(&status);
+        let mut statuses = vec![(index, status)];
+        if realign {
+            for (other, (_, provider)) in self.providers.iter().enumerate() {
+                if other != index {
+                    statuses.push((other, provider.sync()));
+                }
+            }
+        }
+        self.combine(statuses)
+    }
+
+    fn preview(&self) -> Result<Vec<Change>> {
+        match self.waiting.get() {
+            Some(index) => self.providers[index].1.preview(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Allows syncs on metered links as soon as one of the providers does.
+    fn metered_policy(&self) -> MeteredPolicy {
+        let allowed = self
+            .providers
+            .iter()
+            .any(|(_, provider)| provider.metered_policy() == MeteredPolicy::Allow);
+        if allowed {
+            MeteredPolicy::Allow
+        } else {
+            MeteredPolicy::Defer
+        }
+    }
+
+    fn sync_metered(&self) -> Result<Status> {
+        self.run(
+            |provider| provider.sync_metered(),
+            |provider| provider.metered_policy() == MeteredPolicy::Allow,
+        )
+    }
+
+    /// Needs the network only when every provider does.
+    fn requires_network(&self) -> bool {
+        self.providers
+            .iter()
+            .all(|(_, provider)| provider.requires_network())
+    }
+
+    fn sync_offline(&self) -> Result<Status> {
+        self.run(
+            |provider| provider.sync_offline(),
+            |provider| !provider.requires_network(),
+        )
+    }
+
+    /// The earliest time one of the providers has changes due.
+    fn pending(&self) -> Option<Duration> {
+        self.providers
+            .iter()
+            .filter_map(|(_, provider)| provider.pending())
+            .min()
+    }
+}
+
+impl Coordinator {
+    /// Coordinates the providers given with the name to report them under.
+    pub fn new(providers: Vec<(String, Provider)>) -> Self {
+        Self {
+            providers,
+            waiting: Cell::new(None),
+            deferred: Cell::new(vec![]),
+        }
+    }
+
+    /// The names of the providers coordinated, in the order they run.
+    pub fn names(&self) -> Vec<&str> {
+        self.providers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Runs `action` on each included provider, then syncs the providers before one that
+    /// brought changes in so they receive them too.
+    fn run(
+        &self,
+        action: impl Fn(&Provider) -> Result<Status>,
+        included: impl Fn(&Provider) -> bool,
+    ) -> Result<Status> {
+        let mut statuses = vec![];
+        for (index, (_, provider)) in self.providers.iter().enumerate() {
+            if !included(provider) {
+                continue;
+            }
+            let status = action(provider);
+            if downloaded(&status) {
+                for (earlier, (_, provider)) in self.providers.iter().enumerate().take(index) {
+                    if included(provider) {
+                        statuses.push((earlier, provider.sync()));
+                    }
+                }
+            }
+            statuses.push((index, status));
+        }
+        self.combine(statuses)
+    }
+
+    /// Sums up what every provider reported. The first provider needing the user takes over,
+    /// what the others couldn't do is kept for when the review is settled. A provider that
+    /// failed while others went through is reported along with what it couldn't do.
+    fn combine(&self, statuses: Vec<(usize, Result<Status>)>) -> Result<Status> {
+        let mut best: Option<Status> = None;
+        let mut review: Option<Status> = None;
+        let mut failed = vec![];
+        let mut reached = false;
+        let mut auth_required = false;
+        let mut offline: Option<usize> = None;
+        let mut error = None;
+        let ran: Vec<usize> = statuses.iter().map(|(index, _)| *index).collect();
+        for (index, status) in statuses {
+            let name = &self.providers[index].0;
+            match status {
+                Ok(status @ (Status::NewChangesDetected { .. } | Status::Conflict { .. })) => {
+                    if review.is_none() {
+                        self.waiting.set(Some(index));
+                        review = Some(status);
+                    } else {
+                        // Asked for again by the next sync, once the first review is settled.
+                        failed.push((index, format!("{name}: changes waiting for review")));
+                    }
+                }
+                Ok(Status::RepoNotConfigured) => {}
+                Ok(Status::AuthRequired) => {
+                    auth_required = true;
+                    failed.push((index, format!("{name}: the credentials were rejected")));
+                }
+                Ok(Status::Offline { pending }) => {
+                    offline = Some(offline.unwrap_or_default().max(pending));
+                    failed.push((index, format!("{name}: offline, {pending} changes pending")));
+                }
+                Ok(Status::PartialSync { failed: items }) => {
+                    reached = true;
+                    failed.extend(
+                        items
+                            .into_iter()
+                            .map(|item| (index, format!("{name}: {item}"))),
+                    );
+                }
+                Ok(status) => {
+                    reached = true;
+                    if best.as_ref().map_or(0, rank) <= rank(&status) {
+                        best = Some(status);
+                    }
+                }
+                Err(err) => {
+                    failed.push((index, format!("{name}: {err}")));
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        if let Some(review) = review {
+            self.deferred.set(failed);
+            return Ok(review);
+        }
+        let deferred = self.deferred.take();
+        failed.extend(
+            deferred
+                .into_iter()
+                .filter(|(index, _)| !ran.contains(index)),
+        );
+        if !reached {
+            return match (auth_required, offline, error) {
+                (true, _, _) => Ok(Status::AuthRequired),
+                (_, Some(pending), _) => Ok(Status::Offline { pending }),
+                (_, _, Some(err)) => Err(err),
+                _ => Ok(Status::RepoNotConfigured),
+            };
+        }
+        if !failed.is_empty() {
+            let failed = failed.into_iter().map(|(_, item)| item).collect();
+            return Ok(Status::PartialSync { failed });
+        }
+        Ok(best.unwrap_or(Status::UpToDate))
+    }
+}
+
+/// Whether a provider may have brought changes from elsewhere into the synchronized
+/// directory. Partial syncs can follow a pull, and first syncs merge the remote files in.
+fn downloaded(status: &Result<Status>) -> bool {
+    matches!(
+        status,
+        Ok(Status::ChangesDownloaded { .. } | Status::PartialSync { .. } | Status::RepoConfigured)
+    )
+}
+
+/// How much a status tells about a sync that went through, to report the most telling one.
+fn rank(status: &Status) -> u8 {
+    match status {
+        Status::ChangesDownloaded { .. } => 4,
+        Status::ChangesUploaded { .. } => 3,
+        Status::ChangesCommitted { .. } => 2,
+        Status::RepoConfigured => 1,
+        _ => 0,
+    }
+}
@@ -1,12 +1,86 @@
-use anyhow::Result;
-use crdts;
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crdts::CvRDT;
+use log::warn;
+use walkdir::WalkDir;
 
 use crate::{
-    sync::{message::Message, status::Status},
-    traits::synchronization::Synchronization,
+    configuration::{changes::Field, Configuration, CONFIG_FILE},
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    sync::{
+        self,
+        document::Document,
+        folder::Folder,
+        lan::{
+            discovery,
+            session::{Rejected, Session},
+            Frame, Server, DEFAULT_PORT,
+        },
+        mailbox::Mailbox,
+        message::Message,
+        providers::{
+            config::Services,
+            registry::{parse, show, show_number, Kind, Setting, ENABLED, METERED},
+            Provider,
+        },
+        relay::client::{Connection, Subscription},
+        status::Status,
+    },
+    template::Variables,
+    traits::{backend::Backend, synchronization::Synchronization},
+    trust::{identity::Identity, Devices},
 };
+
+/// Where the document is kept, in the state directory.
+pub const DOCUMENT_FILE: &str = "crdt.ron";
+const CONFIG_KEY: &str = "config/";
+const FILE_KEY: &str = "files/";
+/// How long to look for other devices on the LAN before synchronizing.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Synchronizes with the trusted devices, directly on the LAN or through a shared folder or
+/// a relay, merging concurrent changes without conflicts.
 pub struct CrdtSync {
-    key: Option<String>,
+    replica: Replica,
+    port: u16,
+    peers: Vec<SocketAddr>,
+    discovery: bool,
+    folder: Option<Folder>,
+    relay: Option<String>,
+    metered: MeteredPolicy,
+}
+
+/// The document of this device and the directory it mirrors.
+#[derive(Clone)]
+struct Replica {
+    path: PathBuf,
+    context: SymmetryContext,
+    document: PathBuf,
+    /// The state directory, holding the identity of this device.
+    state: PathBuf,
+}
+
+/// What came out of synchronizing with the reachable devices.
+#[derive(Default)]
+struct Outcome {
+    reached: usize,
+    rejected: bool,
+    /// Whether this device joined the others, dropping its own document.
+    adopted: bool,
+    sent: usize,
+    received: BTreeSet<String>,
+    failed: Vec<String>,
+    pending: usize,
+    version: String,
 }
 
 impl Synchronization for CrdtSync {
@@ -14,18 +88,615 @@ impl Synchronization for CrdtSync {
     type Message = Message;
 
     fn sync(&self) -> Result<Self::Status> {
-        todo!()
+        let identity = Identity::load(&self.replica.state)?;
+        let devices = self.replica.devices();
+        if !devices.is_paired(&identity) {
+            return Ok(Status::RepoNotConfigured);
+        }
+        let sessions = self
+            .peers(&identity)
+            .into_iter()
+            .map(|peer| Session::connect(peer, &identity, &devices));
+        let mut folder = self.folder.clone();
+        let mut relay = self.relay.as_ref().and_then(|relay| {
+            Connection::open(relay, &identity, &devices)
+                .map_err(|err| warn!("{relay}: {err:#}"))
+                .ok()
+        });
+        let mut mailboxes: Vec<&mut dyn Mailbox> = vec![];
+        if let Some(folder) = &mut folder {
+            mailboxes.push(folder);
+        }
+        if let Some(relay) = &mut relay {
+            mailboxes.push(relay);
+        }
+        let outcome = self
+            .replica
+            .synchronize(&identity, sessions, &mut mailboxes)?;
+        Ok(outcome.status())
     }
 
-    fn handle(&self, message: Self::Message) -> Result<()> {
+    /// Changes merge on their own, so every request comes down to synchronizing.
+    fn handle(&self, message: Self::Message) -> Result<Self::Status> {
         match message {
-            Message::Update => todo!(),
+            Message::Update | Message::Accept(_) | Message::Push | Message::Resolve(_) => {
+                self.sync()
+            }
+            Message::Reset => self.reset(),
         }
     }
+
+    fn metered_policy(&self) -> MeteredPolicy {
+        self.metered
+    }
 }
 
 impl CrdtSync {
-    pub fn new() -> Self {
-        Self { key: None }
+    /// Opens the document of this device, synchronized with the configured peers.
+    pub fn new() -> Result<Self> {
+        Self::with_context(&SymmetryContext::current()?)
+    }
+
+    /// Opens the document of the given context, synchronized with its configured peers.
+    pub fn with_context(context: &SymmetryContext) -> Result<Self> {
+        let configuration = Configuration::load(context).unwrap_or_default();
+        let config = &configuration.service_config.crdt;
+        let mut sync = Self::open(context);
+        if !config.folder.is_empty() {
+            let folder = Variables::new(context, &configuration)
+                .render(&config.folder)
+                .context("The shared folder can't be resolved.")?;
+            sync.set_folder(Some(folder.into()));
+        }
+        if !config.relay.is_empty() {
+            sync.set_relay(Some(config.relay.clone()));
+        }
+        sync.set_port(config.port);
+        sync.set_metered_policy(config.metered);
+        sync.set_peers(
+            config
+                .peers
+                .iter()
+                .filter_map(|peer| resolve(peer))
+                .collect(),
+        );
+        Ok(sync)
+    }
+
+    /// Opens the document of the given context, without looking up its configured peers.
+    pub fn open(context: &SymmetryContext) -> Self {
+        let state = context.state_path();
+        Self {
+            replica: Replica {
+                path: context.repo_path(),
+                context: context.clone(),
+                document: state.join(DOCUMENT_FILE),
+                state,
+            },
+            port: DEFAULT_PORT,
+            peers: vec![],
+            discovery: true,
+            folder: None,
+            relay: None,
+            metered: MeteredPolicy::default(),
+        }
+    }
+
+    /// Sets the port to accept sessions on, zero meaning the default one.
+    pub fn set_port(&mut self, port: u16) {
+        self.port = if port == 0 { DEFAULT_PORT } else { port };
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sets devices to reach directly, in addition to those found on the LAN.
+    pub fn set_peers(&mut self, peers: Vec<SocketAddr>) {
+        self.peers = peers;
+    }
+
+    /// Whether to advertise this device and look for others over mDNS.
+    pub fn set_discovery(&mut self, enabled: bool) {
+        self.discovery = enabled;
+    }
+
+    /// Sets a directory kept in sync by another tool to meet other devices through.
+    pub fn set_folder(&mut self, folder: Option<PathBuf>) {
+        self.folder = folder.map(Folder::new);
+    }
+
+    /// Sets a relay to meet other devices through, as `ws://host:port/account` or
+    /// `wss://host:port/account`.
+    pub fn set_relay(&mut self, relay: Option<String>) {
+        self.relay = relay;
+    }
+
+    pub fn relay(&self) -> Option<&str> {
+        self.relay.as_deref()
+    }
+
+    pub fn set_metered_policy(&mut self, metered: MeteredPolicy) {
+        self.metered = metered;
+    }
+
+    /// Accepts sessions from other devices on `address` in the background, until the
+    /// returned server is dropped.
+    pub fn listen(&self, address: impl ToSocketAddrs) -> Result<Server> {
+        let identity = Identity::load(&self.replica.state)?;
+        let id = identity.id();
+        let replica = self.replica.clone();
+        let mut server = Server::bind(address, move |stream| {
+            // The trusted devices are read anew, so revocations take effect right away.
+            let session = Session::accept(stream, &identity, &replica.devices());
+            replica
+                .synchronize(&identity, [session], &mut [])
+                .map(|_| ())
+        })?;
+        if self.discovery {
+            if let Err(err) = server.advertise(&id) {
+                warn!("Other devices won't find this one on their own: {err:#}");
+            }
+        }
+        Ok(server)
+    }
+
+    /// Stays connected to the relay in the background, merging what the other devices
+    /// publish as soon as they do, until the returned subscription is dropped.
+    pub fn subscribe(&self) -> Result<Subscription> {
+        let Some(relay) = self.relay.clone() else {
+            bail!("No relay is configured.");
+        };
+        let identity = Identity::load(&self.replica.state)?;
+        let (replica, trusted) = (self.replica.clone(), self.replica.clone());
+        Ok(Subscription::start(
+            relay,
+            identity,
+            move || trusted.devices(),
+            move |identity, connection| {
+                let sessions = std::iter::empty();
+                replica
+                    .synchronize(identity, sessions, &mut [connection])
+                    .map(|_| ())
+            },
+        ))
+    }
+
+    /// Drops the local document and takes whatever the other devices have.
+    fn reset(&self) -> Result<Status> {
+        {
+            let _lock = lock(&self.replica.document)?;
+            if let Err(err) = std::fs::remove_file(&self.replica.document) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    return Err(err.into());
+                }
+            }
+        }
+        self.sync()
+    }
+
+    fn peers(&self, identity: &Identity) -> Vec<SocketAddr> {
+        let mut peers = self.peers.clone();
+        if self.discovery {
+            match discovery::browse(&identity.id(), DISCOVERY_TIMEOUT) {
+                Ok(found) => {
+                    peers.extend(found.into_iter().filter(|peer| !self.peers.contains(peer)))
+                }
+                Err(err) => warn!("{err:#}"),
+            }
+        }
+        peers
+    }
+}
+
+impl Replica {
+    /// The devices this one trusts, as listed in its configuration.
+    fn devices(&self) -> Devices {
+        Configuration::read_from(&self.path.join(CONFIG_FILE))
+            .map(|config| config.devices)
+            .unwrap_or_default()
+    }
+
+    /// Records local changes, exchanges changes over each session and applies what came in.
+    ///
+    /// The document is only locked while it's read or changed, never while waiting on
+    /// another device, which may be synchronizing with this one at the same time.
+    fn synchronize(
+        &self,
+        identity: &Identity,
+        sessions: impl IntoIterator<Item = Result<Session>>,
+        mailboxes: &mut [&mut dyn Mailbox],
+    ) -> Result<Outcome> {
+        let actor = identity.id();
+        let unsent = self.update(|document| {
+            self.capture(&actor, document)?;
+            Ok(document.pending(&actor))
+        })?;
+        let mut outcome = Outcome::default();
+        let mut exchanged = vec![];
+        for session in sessions {
+            let result = session.and_then(|mut session| {
+                self.exchange(&mut session, &mut outcome)?;
+                Ok(session)
+            });
+            match result {
+                Ok(session) => exchanged.push(session),
+                Err(err) => {
+                    outcome.rejected |= err.is::<Rejected>();
+                    warn!("{err:#}");
+                }
+            }
+        }
+        let mut reachable = vec![];
+        for mailbox in mailboxes.iter_mut() {
+            match self.gather(&mut **mailbox, identity, &mut outcome) {
+                Ok(()) => reachable.push(mailbox),
+                Err(err) => warn!("{err:#}"),
+            }
+        }
+        let state = self.update(|document| {
+            if !outcome.received.is_empty() {
+                outcome.failed = self.apply(document, &outcome.received)?;
+            }
+            // What only this device had is recorded again on top of what it adopted.
+            if outcome.adopted {
+                self.capture(&actor, document)?;
+            }
+            Ok(document.delta(&Default::default()))
+        })?;
+        // Leaving the same state again would only have the others read it for nothing.
+        if unsent > 0 || outcome.adopted || !outcome.received.is_empty() {
+            let devices = self.devices();
+            let mut written = false;
+            for mailbox in reachable {
+                match mailbox.write(identity, &devices, &state) {
+                    Ok(()) => written = true,
+                    Err(err) => warn!("{err:#}"),
+                }
+            }
+            if written {
+                self.update(|document| {
+                    document.mark_synced_to(state.clock.clone());
+                    Ok(())
+                })?;
+                outcome.sent += unsent;
+            }
+        }
+        let document = self.update(|document| {
+            if outcome.reached > 0 {
+                let mut devices: BTreeSet<String> = self.devices().trusted.into_keys().collect();
+                devices.remove(&actor);
+                document.compact(&devices);
+            }
+            Ok(document.clone())
+        })?;
+        // Waits for the other side to save too, so a finished sync is finished on both.
+        for mut session in exchanged {
+            let result = if session.is_initiator() {
+                session.receive().map(|_| ())
+            } else {
+                session.send(&Frame::Done)
+            };
+            if let Err(err) = result {
+                warn!("{err:#}");
+            }
+        }
+        outcome.pending = document.pending(&actor);
+        outcome.version = document.version();
+        Ok(outcome)
+    }
+
+    /// Loads the document, changes it and saves it, keeping it locked only meanwhile.
+    fn update<T>(&self, change: impl FnOnce(&mut Document) -> Result<T>) -> Result<T> {
+        let _lock = lock(&self.document)?;
+        let mut document = Document::load(&self.document)?;
+        let result = change(&mut document)?;
+        document.save(&self.document)?;
+        Ok(result)
+    }
+
+    /// Sends the changes the other device is missing and merges the ones this one is missing.
+    fn exchange(&self, session: &mut Session, outcome: &mut Outcome) -> Result<()> {
+        let (clock, joined) =
+            self.update(|document| Ok((document.clock().clone(), document.has_synced())))?;
+        session.send(&Frame::Clock { clock, joined })?;
+        let Frame::Clock { clock, joined } = session.receive()? else {
+            bail!("{} sent an unexpected message.", session.peer());
+        };
+        let ours = self.update(|document| {
+            // A device joining others takes what they have instead of overwriting it with
+            // its defaults. Between two new devices, the one that connected joins the other.
+            if !document.has_synced() && (joined || session.is_initiator()) {
+                *document = Document::new();
+                outcome.adopted = true;
+            }
+            Ok(document.delta(&clock))
+        })?;
+        let sent = ours.entries.len();
+        let mut seen = ours.clock.clone();
+        // The side that connected sends first, so large deltas never block both sides.
+        let theirs = if session.is_initiator() {
+            session.send(&Frame::Delta(ours))?;
+            session.receive()?
+        } else {
+            let theirs = session.receive()?;
+            session.send(&Frame::Delta(ours))?;
+            theirs
+        };
+        let Frame::Delta(theirs) = theirs else {
+            bail!("{} sent an unexpected message.", session.peer());
+        };
+        seen.merge(theirs.clock.clone());
+        self.update(|document| {
+            outcome.received.extend(document.merge(theirs));
+            document.mark_synced_to(seen.clone());
+            document.record_peer_clock(session.peer_id(), seen);
+            Ok(())
+        })?;
+        outcome.reached += 1;
+        outcome.sent += sent;
+        Ok(())
+    }
+
+    /// Merges what the other devices left in a mailbox.
+    fn gather(
+        &self,
+        mailbox: &mut dyn Mailbox,
+        identity: &Identity,
+        outcome: &mut Outcome,
+    ) -> Result<()> {
+        let states = mailbox.read(identity, &self.devices())?;
+        self.update(|document| {
+            // As over the LAN, a device joining others takes what they have.
+            if !document.has_synced() && !states.is_empty() {
+                *document = Document::new();
+                outcome.adopted = true;
+            }
+            for (peer, state) in states {
+                let clock = state.clock.clone();
+                outcome.received.extend(document.merge(state));
+                document.record_peer_clock(&peer, clock);
+            }
+            Ok(())
+        })?;
+        outcome.reached += 1;
+        Ok(())
+    }
+
+    /// Records the configuration and the synchronized files into the document.
+    fn capture(&self, actor: &str, document: &mut Document) -> Result<()> {
+        sync::capture(&self.context)?;
+        if let Some(config) = Configuration::read_from(&self.path.join(CONFIG_FILE)) {
+            for field in Field::ALL {
+                let key = format!("{CONFIG_KEY}{}", field.name());
+                document.set(actor, &key, Some(field.value(&config)?));
+            }
+        }
+        let mut present = BTreeSet::new();
+        let entries = WalkDir::new(&self.path)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git");
+        for entry in entries {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(&self.path)?;
+            if !entry.file_type().is_file()
+                || relative == Path::new(CONFIG_FILE)
+                || relative == Path::new(".gitignore")
+            {
+                continue;
+            }
+            let key = format!("{FILE_KEY}{}", relative.to_string_lossy());
+            let contents = STANDARD.encode(std::fs::read(entry.path())?);
+            document.set(actor, &key, Some(contents));
+            present.insert(key);
+        }
+        let removed: Vec<String> = document
+            .keys()
+            .filter(|key| key.starts_with(FILE_KEY) && !present.contains(*key))
+            .cloned()
+            .collect();
+        for key in removed {
+            document.set(actor, &key, None);
+        }
+        Ok(())
+    }
+
+    /// Writes the changed keys to the configuration and synchronized files, then applies
+    /// them to the device. Returns what couldn't be applied.
+    fn apply(&self, document: &Document, keys: &BTreeSet<String>) -> Result<Vec<String>> {
+        let path = self.path.join(CONFIG_FILE);
+        let mut config = Configuration::read_from(&path).unwrap_or_default();
+        let mut configured = false;
+        let mut failed = vec![];
+        for key in keys {
+            if let Some(name) = key.strip_prefix(CONFIG_KEY) {
+                // Fields from newer versions are left for those versions to apply.
+                let (Some(field), Some(value)) = (Field::from_name(name), document.get(key)) else {
+                    continue;
+                };
+                match field.set(&mut config, value) {
+                    Ok(()) => configured = true,
+                    Err(err) => failed.push(format!("{name}: {err}")),
+                }
+            } else if let Some(relative) = key.strip_prefix(FILE_KEY) {
+                if let Err(err) = self.write_file(relative, document.get(key)) {
+                    failed.push(format!("{relative}: {err}"));
+                }
+            }
+        }
+        if configured {
+            std::fs::create_dir_all(&self.path)?;
+            config.write_to(&path)?;
+        }
+        failed.extend(sync::apply(&self.context));
+        Ok(failed)
+    }
+
+    /// Writes a synchronized file, or removes it when `contents` is `None`.
+    fn write_file(&self, relative: &str, contents: Option<&str>) -> Result<()> {
+        let target = sync::inside(&self.path, relative)?;
+        match contents {
+            Some(contents) => {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&target, STANDARD.decode(contents)?)?;
+            }
+            None if target.exists() => std::fs::remove_file(&target)?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl Outcome {
+    fn status(self) -> Status {
+        if self.reached == 0 {
+            return if self.rejected {
+                Status::AuthRequired
+            } else {
+                Status::Offline {
+                    pending: self.pending,
+                }
+            };
+        }
+        if !self.failed.is_empty() {
+            Status::PartialSync {
+                failed: self.failed,
+            }
+        } else if !self.received.is_empty() {
+            Status::ChangesDownloaded {
+                commit: self.version,
+            }
+        } else if self.sent > 0 {
+            Status::ChangesUploaded {
+                commit: self.version,
+            }
+        } else {
+            Status::UpToDate
+        }
+    }
+}
+
+/// Registers [`CrdtSync`] with the [registry](super::registry).
+pub struct CrdtBackend;
+
+impl Backend for CrdtBackend {
+    fn name(&self) -> &'static str {
+        "crdt"
+    }
+
+    fn title(&self) -> &'static str {
+        "CRDT"
+    }
+
+    fn description(&self) -> &'static str {
+        "Allows multiple devices to collaborate without conflicts"
+    }
+
+    fn schema(&self) -> Vec<Setting> {
+        vec![
+            Setting::new("folder", "Shared folder", Kind::Text).with_placeholder(
+                "A folder synced by Syncthing, Nextcloud or a USB drive, e.g. {{home}}/Sync/symmetry",
+            ),
+            Setting::new("relay", "Relay", Kind::Text).with_placeholder(
+                "A relay for devices on other networks, e.g. wss://relay.example.org/family",
+            ),
+            Setting::new("peers", "Peers", Kind::Text)
+                .with_placeholder("Devices to reach directly, e.g. desktop.local, 10.0.0.2:47474"),
+            Setting::new(
+                "port",
+                "Port",
+                Kind::Number {
+                    min: 0,
+                    max: u16::MAX.into(),
+                },
+            )
+            .with_placeholder("Port to accept other devices on, empty for the default one"),
+            Setting::metered(),
+        ]
+    }
+
+    fn realtime(&self) -> bool {
+        true
+    }
+
+    fn get(&self, services: &Services, key: &str) -> Option<String> {
+        let config = &services.crdt;
+        match key {
+            ENABLED => show(&config.enabled),
+            "folder" => Some(config.folder.clone()),
+            "relay" => Some(config.relay.clone()),
+            "peers" => Some(config.peers.join(", ")),
+            "port" => show_number(&config.port),
+            METERED => show(&config.metered),
+            _ => None,
+        }
+    }
+
+    fn set(&self, services: &mut Services, key: &str, value: &str) -> Result<()> {
+        let config = &mut services.crdt;
+        let schema = self.schema();
+        match key {
+            ENABLED => config.enabled = value == "true",
+            "folder" => config.folder = value.trim().to_string(),
+            "relay" => config.relay = value.trim().to_string(),
+            "peers" => {
+                config.peers = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|peer| !peer.is_empty())
+                    .map(String::from)
+                    .collect()
+            }
+            "port" => config.port = parse(Setting::find(&schema, key)?, value)?,
+            METERED => config.metered = parse(Setting::find(&schema, key)?, value)?,
+            _ => bail!("There's no setting named {key}."),
+        }
+        Ok(())
+    }
+
+    fn open(&self, context: &SymmetryContext) -> Result<Provider> {
+        Ok(Box::new(CrdtSync::with_context(context)?))
+    }
+}
+
+/// Locks the document stored at `path` until the returned file is dropped, through a
+/// lock file next to it, so the server, the provider and other processes take turns.
+fn lock(path: &Path) -> Result<File> {
+    let parent = path.parent().context("Invalid document path.")?;
+    std::fs::create_dir_all(parent)?;
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Resolves a configured peer, given as `host` or `host:port`, IPv6 addresses as they are
+/// or in brackets.
+fn resolve(peer: &str) -> Option<SocketAddr> {
+    if let Ok(address) = peer.parse::<SocketAddr>() {
+        return Some(address);
+    }
+    let bare = peer.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = bare.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, DEFAULT_PORT));
+    }
+    let address = if peer.contains(':') {
+        peer.to_socket_addrs()
+    } else {
+        (peer, DEFAULT_PORT).to_socket_addrs()
+    };
+    match address {
+        Ok(mut addresses) => addresses.next(),
+        Err(err) => {
+            warn!("{peer}: {err}");
+            None
+        }
     }
 }
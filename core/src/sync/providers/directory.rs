@@ -0,0 +1,361 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use ron::ser::PrettyConfig;
+
+use crate::{
+    configuration::Configuration,
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    sync::{
+        self,
+        message::Message,
+        providers::{
+            config::Services,
+            registry::{parse, show, show_number, Kind, Setting, ENABLED},
+            Provider,
+        },
+        status::Status,
+        store::mirror::Manifest,
+    },
+    template::Variables,
+    traits::{backend::Backend, synchronization::Synchronization},
+};
+
+/// File in the state directory keeping the files of the last snapshot written.
+const STATE_FILE: &str = "directory.ron";
+/// Marks a snapshot still being written, so it's never listed or restored.
+const PARTIAL: &str = ".partial";
+
+/// Writes timestamped snapshots of the synchronized directory to a folder, such as one on
+/// a mounted disk, and restores them.
+///
+/// Nothing is merged and no other device is involved: a snapshot is written whenever the
+/// files changed since the last one, and the oldest are pruned as configured. Being the
+/// simplest provider, it's the one to read first when writing another.
+pub struct DirectorySync {
+    target: Option<PathBuf>,
+    path: PathBuf,
+    context: SymmetryContext,
+    state: PathBuf,
+    keep: usize,
+    days: u64,
+}
+
+impl Synchronization for DirectorySync {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Self::Status> {
+        let status = self.snapshot()?;
+        self.prune()?;
+        Ok(status)
+    }
+
+    /// Snapshots only ever leave the device when written and come back when restored, so
+    /// updating and resolving just sync.
+    fn handle(&self, message: Self::Message) -> Result<Self::Status> {
+        match message {
+            Message::Update | Message::Accept(_) | Message::Resolve(_) => self.sync(),
+            Message::Push => {
+                let Some(target) = &self.target else {
+                    return Ok(Status::RepoNotConfigured);
+                };
+                if !target.is_dir() {
+                    bail!("The folder {} isn't there.", target.display());
+                }
+                let commit = self.write(target, &self.scan()?)?;
+                self.prune()?;
+                Ok(Status::ChangesUploaded { commit })
+            }
+            Message::Reset => match self.snapshots()?.first() {
+                Some(latest) => self.restore(latest),
+                None => bail!("There's no snapshot to reset to."),
+            },
+        }
+    }
+
+    /// Writing to a local disk costs nothing on a metered link.
+    fn metered_policy(&self) -> MeteredPolicy {
+        MeteredPolicy::Allow
+    }
+
+    /// The folder is on a local or mounted disk.
+    fn requires_network(&self) -> bool {
+        false
+    }
+}
+
+impl DirectorySync {
+    /// Snapshots this device to the configured folder.
+    pub fn new() -> Result<Self> {
+        Self::with_context(&SymmetryContext::current()?)
+    }
+
+    /// Snapshots the given context to its configured folder.
+    pub fn with_context(context: &SymmetryContext) -> Result<Self> {
+        let configuration = Configuration::load(context).unwrap_or_default();
+        let config = &configuration.service_config.directory;
+        let mut sync = Self::open(context, None);
+        if !config.path.is_empty() {
+            let path = Variables::new(context, &configuration)
+                .render(&config.path)
+                .context("The snapshot folder can't be resolved.")?;
+            sync.target = Some(path.into());
+        }
+        sync.set_retention(config.keep, config.days);
+        Ok(sync)
+    }
+
+    /// Snapshots the given context to `target`, keeping every snapshot.
+    pub fn open(context: &SymmetryContext, target: Option<PathBuf>) -> Self {
+        Self {
+            target,
+            path: context.repo_path(),
+            context: context.clone(),
+            state: context.state_path(),
+            keep: 0,
+            days: 0,
+        }
+    }
+
+    /// Keeps at most `keep` snapshots, and none older than `days`, zero meaning no limit.
+    /// The latest snapshot is always kept.
+    pub fn set_retention(&mut self, keep: usize, days: u64) {
+        self.keep = keep;
+        self.days = days;
+    }
+
+    /// Lists the snapshots in the folder by name, newest first.
+    pub fn snapshots(&self) -> Result<Vec<String>> {
+        let Some(target) = &self.target else {
+            return Ok(vec![]);
+        };
+        let mut snapshots = vec![];
+        for entry in std::fs::read_dir(target)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_dir() && is_snapshot(&name) {
+                snapshots.push(name);
+            }
+        }
+        snapshots.sort_by(|a, b| b.cmp(a));
+        Ok(snapshots)
+    }
+
+    /// Brings the configuration and files back to how they were in the snapshot `name`,
+    /// after snapshotting them as they are so the restore can be undone.
+    pub fn restore(&self, name: &str) -> Result<Status> {
+        let Some(target) = &self.target else {
+            return Ok(Status::RepoNotConfigured);
+        };
+        let snapshot = target.join(name);
+        if !is_snapshot(name) || !snapshot.is_dir() {
+            bail!("There's no snapshot named {name}.");
+        }
+        self.snapshot()?;
+        let wanted = Manifest::scan(&snapshot)?;
+        let current = self.scan()?;
+        copy(&snapshot, &self.path, &wanted, &current)?;
+        for path in current.files.keys() {
+            if !wanted.files.contains_key(path) {
+                std::fs::remove_file(self.path.join(path))?;
+            }
+        }
+        self.prune()?;
+        let failed = sync::apply(&self.context);
+        if !failed.is_empty() {
+            return Ok(Status::PartialSync { failed });
+        }
+        Ok(Status::ChangesDownloaded {
+            commit: name.to_string(),
+        })
+    }
+
+    /// Removes the snapshots the retention policy no longer keeps, returning their names.
+    pub fn prune(&self) -> Result<Vec<String>> {
+        let Some(target) = self.target.as_ref().filter(|target| target.is_dir()) else {
+            return Ok(vec![]);
+        };
+        let age = Duration::from_secs(self.days.saturating_mul(24 * 60 * 60));
+        // Snapshot names sort by time, so older ones sort before this.
+        let cutoff = SystemTime::now()
+            .checked_sub(age)
+            .map(sync::timestamp)
+            .unwrap_or_default();
+        let mut pruned = vec![];
+        for (index, name) in self.snapshots()?.into_iter().enumerate().skip(1) {
+            let surplus = self.keep > 0 && index >= self.keep;
+            let expired = self.days > 0 && name < cutoff;
+            if surplus || expired {
+                std::fs::remove_dir_all(target.join(&name))?;
+                pruned.push(name);
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Writes a snapshot if the files changed since the latest one, without pruning.
+    fn snapshot(&self) -> Result<Status> {
+        let Some(target) = &self.target else {
+            return Ok(Status::RepoNotConfigured);
+        };
+        let local = self.scan()?;
+        // The disk isn't mounted, the changes wait for the next snapshot.
+        if !target.is_dir() {
+            let pending = local.distance(&self.last());
+            return Ok(Status::Offline { pending });
+        }
+        let Some(latest) = self.snapshots()?.into_iter().next() else {
+            self.write(target, &local)?;
+            return Ok(Status::RepoConfigured);
+        };
+        if Manifest::scan(&target.join(&latest))? == local {
+            return Ok(Status::UpToDate);
+        }
+        Ok(Status::ChangesUploaded {
+            commit: self.write(target, &local)?,
+        })
+    }
+
+    /// Records the device's settings and lists the synchronized files.
+    fn scan(&self) -> Result<Manifest> {
+        sync::capture(&self.context)?;
+        std::fs::create_dir_all(&self.path)?;
+        Manifest::scan(&self.path)
+    }
+
+    /// Writes the files of `manifest` as a new snapshot in `target`, returning its name.
+    fn write(&self, target: &Path, manifest: &Manifest) -> Result<String> {
+        let now = sync::timestamp(SystemTime::now());
+        let mut name = now.clone();
+        let mut count = 0;
+        while target.join(&name).exists() {
+            count += 1;
+            name = format!("{now}-{count}");
+        }
+        let partial = target.join(format!("{name}{PARTIAL}"));
+        if partial.exists() {
+            std::fs::remove_dir_all(&partial)?;
+        }
+        copy(&self.path, &partial, manifest, &Manifest::default())?;
+        std::fs::rename(&partial, target.join(&name))
+            .context("Failed to finish writing the snapshot.")?;
+        std::fs::create_dir_all(&self.state).context("Failed to create the state directory.")?;
+        std::fs::write(
+            self.state.join(STATE_FILE),
+            ron::ser::to_string_pretty(manifest, PrettyConfig::new())?,
+        )?;
+        Ok(name)
+    }
+
+    /// The files of the last snapshot written, even while the folder is out of reach.
+    fn last(&self) -> Manifest {
+        std::fs::read_to_string(self.state.join(STATE_FILE))
+            .ok()
+            .and_then(|data| ron::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Copies the files of `wanted` that differ from `current` from `source` to `target`.
+fn copy(source: &Path, target: &Path, wanted: &Manifest, current: &Manifest) -> Result<()> {
+    for (path, hash) in &wanted.files {
+        if current.files.get(path) == Some(hash) {
+            continue;
+        }
+        let file = target.join(path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(source.join(path), file)?;
+    }
+    Ok(())
+}
+
+/// Whether a folder in the target holds a finished snapshot, named after the time it was
+/// written.
+fn is_snapshot(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let mut components = Path::new(name).components();
+    let folder =
+        matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none();
+    folder
+        && bytes.len() >= 16
+        && bytes[..8].iter().all(u8::is_ascii_digit)
+        && bytes[8] == b'T'
+        && !name.ends_with(PARTIAL)
+}
+
+/// Registers [`DirectorySync`] with the [registry](super::registry).
+pub struct DirectoryBackend;
+
+impl Backend for DirectoryBackend {
+    fn name(&self) -> &'static str {
+        "directory"
+    }
+
+    fn title(&self) -> &'static str {
+        "Snapshots"
+    }
+
+    fn description(&self) -> &'static str {
+        "Keeps timestamped snapshots in a folder, such as one on an external disk"
+    }
+
+    fn schema(&self) -> Vec<Setting> {
+        vec![
+            Setting::new("path", "Folder", Kind::Text)
+                .with_placeholder("e.g. {{home}}/Backups/symmetry"),
+            Setting::new(
+                "keep",
+                "Snapshots to keep",
+                Kind::Number {
+                    min: 0,
+                    max: u32::MAX.into(),
+                },
+            )
+            .with_placeholder("Empty keeps them all"),
+            Setting::new(
+                "days",
+                "Days to keep them",
+                Kind::Number {
+                    min: 0,
+                    max: u32::MAX.into(),
+                },
+            )
+            .with_placeholder("Empty keeps them forever"),
+        ]
+    }
+
+    fn get(&self, services: &Services, key: &str) -> Option<String> {
+        let config = &services.directory;
+        match key {
+            ENABLED => show(&config.enabled),
+            "path" => Some(config.path.clone()),
+            "keep" => show_number(&config.keep),
+            "days" => show_number(&config.days),
+            _ => None,
+        }
+    }
+
+    fn set(&self, services: &mut Services, key: &str, value: &str) -> Result<()> {
+        let config = &mut services.directory;
+        let schema = self.schema();
+        match key {
+            ENABLED => config.enabled = value == "true",
+            "path" => config.path = value.trim().to_string(),
+            "keep" => config.keep = parse(Setting::find(&schema, key)?, value)?,
+            "days" => config.days = parse(Setting::find(&schema, key)?, value)?,
+            _ => bail!("There's no setting named {key}."),
+        }
+        Ok(())
+    }
+
+    fn open(&self, context: &SymmetryContext) -> Result<Provider> {
+        Ok(Box::new(DirectorySync::with_context(context)?))
+    }
+}
@@ -1,362 +1,1020 @@
-use anyhow::Result;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
 use git2::{
-    BranchType, FetchOptions, MergeOptions, PushOptions, Repository, Signature, StatusOptions,
+    build::CheckoutBuilder, BranchType, Commit, Cred, CredentialType, ErrorClass, ErrorCode,
+    FetchOptions, Index, IndexAddOption, IndexEntry, MergeOptions, Oid, PushOptions, Repository,
+    RepositoryInitOptions, Signature, Tree,
 };
 use git2_credentials::CredentialHandler;
-use native_dialog::{MessageDialog, MessageType};
 
 use crate::{
-    configuration::Configuration,
-    sync::{message::Message, status::Status},
-    traits::synchronization::Synchronization,
+    configuration::{
+        changes::{self, Change, Field},
+        Configuration, CONFIG_FILE,
+    },
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    secrets::{scanner, Secrets},
+    sync::{
+        self,
+        message::Message,
+        outbox::{Outbox, Pending},
+        overrides::Overrides,
+        providers::{
+            config::{git::ConflictStrategy, Services},
+            registry::{parse, show, show_number, Kind, Setting, ENABLED, METERED},
+            Provider,
+        },
+        status::Status,
+    },
+    traits::{backend::Backend, synchronization::Synchronization},
 };
 
-pub struct GitSync {
-    repo: Option<Repository>,
+/// Name of the keyring entry holding the token used to authenticate against the remote.
+pub const GIT_TOKEN: &str = "git.token";
+
+const BRANCH: &str = "main";
+const LOCAL_BRANCH: &str = "refs/heads/main";
+const REMOTE_BRANCH: &str = "refs/remotes/origin/main";
+
+const GITIGNORE_START: &str = "# BEGIN symmetry, managed automatically";
+const GITIGNORE_END: &str = "# END symmetry";
+/// Where Symmetry keeps what only concerns this device, never synchronized should it end up
+/// in the working tree, e.g. copied there along with an older install.
+const IGNORED: [&str; 2] = ["/state/", "/symmetry-backups/"];
+/// The longest batch window, a day.
+const MAX_BATCH_SECONDS: u64 = 24 * 60 * 60;
+/// Bits of an index entry's flags holding its merge stage, `GIT_INDEX_ENTRY_STAGEMASK` in
+/// libgit2. Cleared, the entry is a resolved one.
+const STAGE_MASK: u16 = 0x3000;
+
+/// What a failed fetch or push says about the remote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The remote can't be reached for now, changes wait in the outbox.
+    Unreachable,
+    /// The remote turned the credentials down.
+    Unauthorized,
+    Other,
 }
 
-impl Default for GitSync {
-    fn default() -> Self {
-        Self::new()
+impl Failure {
+    pub fn of(err: &git2::Error) -> Self {
+        match (err.class(), err.code()) {
+            (_, ErrorCode::Auth) => Self::Unauthorized,
+            // Waiting won't fix a certificate that doesn't check out.
+            (_, ErrorCode::Certificate) => Self::Other,
+            (ErrorClass::Net | ErrorClass::Ssh | ErrorClass::Ssl | ErrorClass::Http, _) => {
+                Self::Unreachable
+            }
+            // A remote on a drive that isn't mounted, other file system errors stand.
+            (ErrorClass::Os, ErrorCode::NotFound) => Self::Unreachable,
+            _ => Self::Other,
+        }
     }
 }
 
+pub struct GitSync {
+    repo: Option<Repository>,
+    path: PathBuf,
+    context: SymmetryContext,
+    state: PathBuf,
+    url: String,
+    conflicts: ConflictStrategy,
+    batch_window: Duration,
+    metered: MeteredPolicy,
+}
+
 impl Synchronization for GitSync {
     type Status = Status;
     type Message = Message;
 
     fn sync(&self) -> Result<Self::Status> {
-        if let Some(repo) = self.repo.as_ref() {
-            let mut options = StatusOptions::new();
-            options.include_untracked(true);
-            let branch = repo.find_branch("main", BranchType::Local);
+        let Some(repo) = self.repo.as_ref() else {
+            return Ok(Status::RepoNotConfigured);
+        };
+        if self.url.is_empty() {
+            return Ok(Status::RepoNotConfigured);
+        }
 
-            if branch.is_err() && !repo.statuses(Some(&mut options))?.is_empty() {
-                self.configure_remote()?;
+        self.capture()?;
+        self.configure_remote(repo)?;
+        if let Err(err) = self.fetch(repo) {
+            // Keep the changes made meanwhile, they are published once the remote is back.
+            if Self::target(repo, LOCAL_BRANCH).is_some() {
                 self.commit()?;
-                self.set_upstream_branch()?;
-                self.force_pull("main")?;
-                return Ok(Status::RepoConfigured);
             }
+            return self.connection_status(repo, err);
+        }
 
-            let status = if !repo.statuses(Some(&mut options))?.is_empty() {
-                self.pull()?;
-                self.commit()?;
-                self.push()?;
-                Status::ChangesUploaded
-            } else if self.updates_pending()? {
-                Status::NewChangesDetected
-            } else {
-                Status::UpToDate
-            };
+        let local = Self::target(repo, LOCAL_BRANCH);
+        let remote = Self::target(repo, REMOTE_BRANCH);
+
+        // First sync on this device, adopt the remote state if there is one.
+        if local.is_none() {
+            match remote {
+                Some(remote) => {
+                    repo.reference(LOCAL_BRANCH, remote, true, "Adopt remote branch")?;
+                    repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
+                    self.set_upstream_branch()?;
+                    let failed = self.apply();
+                    if !failed.is_empty() {
+                        return Ok(Status::PartialSync { failed });
+                    }
+                }
+                None => {
+                    if self.commit()? {
+                        if let Err(err) = self.push(repo) {
+                            return self.connection_status(repo, err);
+                        }
+                        self.set_upstream_branch()?;
+                    }
+                }
+            }
+            return Ok(Status::RepoConfigured);
+        }
 
-            Ok(status)
-        } else {
-            Ok(Status::RepoNotConfigured)
+        let committed = self.commit()?;
+        let batching = self.batch_open(repo);
+        if (committed || Self::is_ahead(repo)?) && batching {
+            return Ok(Status::ChangesCommitted {
+                commit: Self::head(repo),
+            });
+        }
+        if committed {
+            let conflicts = self.merge(repo, self.conflicts)?;
+            if !conflicts.is_empty() {
+                return self.conflict_status(repo, conflicts);
+            }
+            let failed = self.apply();
+            return self.publish(repo, failed);
+        }
+        if local == remote {
+            return Ok(Status::UpToDate);
+        }
+        if Self::is_ahead(repo)? {
+            return self.publish(repo, vec![]);
         }
+        if self.conflicts == ConflictStrategy::Ask {
+            let conflicts = Self::conflicts(repo)?;
+            if !conflicts.is_empty() {
+                return self.conflict_status(repo, conflicts);
+            }
+        }
+        Ok(Status::NewChangesDetected {
+            changes: self.preview()?,
+        })
     }
 
-    fn handle(&self, message: Self::Message) -> Result<()> {
+    fn handle(&self, message: Self::Message) -> Result<Self::Status> {
         match message {
-            Message::Update => self.pull(),
+            Message::Update => self.pull(self.conflicts, None),
+            Message::Accept(fields) => self.pull(self.conflicts, Some(&fields)),
+            Message::Resolve(strategy) => self.pull(strategy, None),
+            Message::Push => {
+                let Some(repo) = self.repo.as_ref() else {
+                    return Ok(Status::RepoNotConfigured);
+                };
+                self.capture()?;
+                self.configure_remote(repo)?;
+                self.commit()?;
+                if !Self::is_ahead(repo)? {
+                    return Ok(Status::UpToDate);
+                }
+                self.publish(repo, vec![])
+            }
+            Message::Reset => self.reset(),
+        }
+    }
+
+    fn metered_policy(&self) -> MeteredPolicy {
+        self.metered
+    }
+
+    /// Remotes on a local disk, such as a USB drive, are reachable offline.
+    fn requires_network(&self) -> bool {
+        local_remote(&self.url).is_none()
+    }
+
+    /// The time left before the open batch is pushed.
+    fn pending(&self) -> Option<Duration> {
+        let repo = self.repo.as_ref()?;
+        let head = repo.head().ok()?.peel_to_commit().ok()?;
+        self.batch_left(repo, &head)
+    }
+
+    /// Compares the configuration at the common ancestor with the remote one,
+    /// as of the last fetch.
+    fn preview(&self) -> Result<Vec<Change>> {
+        let Some(repo) = self.repo.as_ref() else {
+            return Ok(vec![]);
+        };
+        let (Some(local), Some(remote)) = (
+            Self::target(repo, LOCAL_BRANCH),
+            Self::target(repo, REMOTE_BRANCH),
+        ) else {
+            return Ok(vec![]);
+        };
+        let base = repo.merge_base(local, remote)?;
+        let read = |oid: Oid| -> Result<Option<Configuration>> {
+            let tree = repo.find_commit(oid)?.tree()?;
+            Ok(Self::configuration_in(repo, &tree))
+        };
+        match (read(base)?, read(remote)?) {
+            (Some(base), Some(remote)) => Ok(base.changes(&remote)),
+            _ => Ok(vec![]),
         }
     }
 }
 
 impl GitSync {
-    pub fn new() -> Self {
-        let path = Configuration::local_path().unwrap();
-        let repo = match Repository::open(&path) {
-            Ok(repo) => Some(repo),
-            Err(_) => match Repository::init(path) {
-                Ok(repo) => Some(repo),
-                Err(_) => None,
-            },
-        };
-        Self { repo }
+    /// Opens the sync repository of this device, synchronized with the configured remote.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_context(&SymmetryContext::current()?))
     }
 
-    fn configure_remote(&self) -> Result<()> {
-        if let Some(repo) = self.repo.as_ref() {
-            let config = Configuration::current().unwrap();
-            let url = config.service_config.git.url.as_str();
-            let callbacks = Self::callbacks()?;
-            let mut remote = repo.remote("origin", url)?;
-            remote.connect_auth(git2::Direction::Push, Some(callbacks), None)?;
+    /// Opens the sync repository of the given context, synchronized with its configured remote.
+    pub fn with_context(context: &SymmetryContext) -> Self {
+        let config = Configuration::load(context)
+            .map(|config| config.service_config.git)
+            .unwrap_or_default();
+        let mut sync = Self::open(context, config.url);
+        sync.set_conflict_strategy(config.conflicts);
+        sync.set_batch_window(Duration::from_secs(config.batch_seconds));
+        sync.set_metered_policy(config.metered);
+        sync
+    }
+
+    /// Opens the repository of the given context, initializing it if needed, and synchronizes
+    /// it with `url`. The remote can be a `file://` URL or a plain path, such as a USB drive
+    /// or a NAS mount.
+    pub fn open(context: &SymmetryContext, url: impl Into<String>) -> Self {
+        let path = context.repo_path();
+        let repo = Repository::open(&path)
+            .or_else(|_| {
+                Repository::init_opts(&path, RepositoryInitOptions::new().initial_head(BRANCH))
+            })
+            .ok();
+        // Repositories created before the branch was fixed may point to another unborn branch.
+        if let Some(repo) = repo.as_ref() {
+            if repo.head().is_err() {
+                let _ = repo.set_head(LOCAL_BRANCH);
+            }
+        }
+        Self {
+            repo,
+            path,
+            context: context.clone(),
+            state: context.state_path(),
+            url: url.into(),
+            conflicts: ConflictStrategy::default(),
+            batch_window: Duration::ZERO,
+            metered: MeteredPolicy::default(),
         }
-        Ok(())
     }
 
-    fn commit(&self) -> Result<()> {
-        if let Some(repo) = self.repo.as_ref() {
-            let author_name = "Symmetry";
-            let author_email = "symmetry@proton.me";
-            let commit_message = "Update configuration.";
+    pub fn set_conflict_strategy(&mut self, conflicts: ConflictStrategy) {
+        self.conflicts = conflicts;
+    }
 
-            // Create the commit
-            let signature = Signature::now(author_name, author_email)?;
+    /// Folds changes made within `window` of an unpublished commit into it, and holds off
+    /// pushing until the window has passed. A zero window commits and pushes every change.
+    pub fn set_batch_window(&mut self, window: Duration) {
+        self.batch_window = window;
+    }
 
-            // Add all files to the index
-            let mut index = repo.index()?;
-            index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
-            index.write()?;
+    pub fn set_metered_policy(&mut self, metered: MeteredPolicy) {
+        self.metered = metered;
+    }
+
+    /// Creates a bare repository for a remote on this machine, if it doesn't exist yet.
+    pub fn init_remote(url: &str) -> Result<()> {
+        let Some(path) = local_remote(url) else {
+            return Ok(());
+        };
+        let is_empty = match std::fs::read_dir(&path) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(_) => true,
+        };
+        // Don't create anything where a drive isn't mounted.
+        let is_mounted = path.parent().is_some_and(|parent| parent.is_dir());
+        if is_empty && is_mounted {
+            Repository::init_opts(
+                &path,
+                RepositoryInitOptions::new().bare(true).initial_head(BRANCH),
+            )?;
+        }
+        Ok(())
+    }
 
-            let tree_id = index.write_tree()?;
-            let tree = repo.find_tree(tree_id)?;
+    /// Stores the selected desktop settings and tracked files in the repository
+    /// so they get committed.
+    fn capture(&self) -> Result<()> {
+        sync::capture(&self.context)
+    }
 
-            if let Ok(head) = repo.head() {
-                let head_commit = head.peel_to_commit()?;
-                let parent_commit = head_commit.clone();
+    /// Writes the desktop settings and tracked files from the synchronized
+    /// repository back to the device, returning what couldn't be applied.
+    fn apply(&self) -> Vec<String> {
+        sync::apply(&self.context)
+    }
 
-                repo.commit(
+    /// Points `origin` at the configured URL, creating local bare repositories as needed.
+    fn configure_remote(&self, repo: &Repository) -> Result<()> {
+        let url = remote_url(&self.context, &self.url);
+        Self::init_remote(&url)?;
+        match repo.find_remote("origin") {
+            Ok(remote) if remote.url() == Some(url.as_str()) => {}
+            Ok(_) => repo.remote_set_url("origin", &url)?,
+            Err(_) => {
+                repo.remote("origin", &url)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits every change in the working tree, described from the configuration diff.
+    /// Returns `false` if there was nothing to commit. While a batch is open, changes are
+    /// folded into its unpublished commit instead.
+    fn commit(&self) -> Result<bool> {
+        let Some(repo) = self.repo.as_ref() else {
+            return Ok(false);
+        };
+        let signature = Signature::now("Symmetry", "symmetry@proton.me")?;
+        write_gitignore(&self.path)?;
+
+        // Stage every addition, modification and deletion.
+        let mut index = repo.index()?;
+        index.add_all(["*"].iter(), IndexAddOption::DEFAULT, None)?;
+        index.update_all(["*"].iter(), None)?;
+        if let Err(err) = Self::scan_for_secrets(repo, &index) {
+            index.read(true)?;
+            return Err(err);
+        }
+        index.write()?;
+
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        if head
+            .as_ref()
+            .is_some_and(|head| head.tree_id() == tree.id())
+        {
+            return Ok(false);
+        }
+
+        match head.filter(|head| self.is_batching(repo, head)) {
+            Some(head) => {
+                let parent = head.parent(0)?;
+                if parent.tree_id() == tree.id() {
+                    // The batch was reverted, drop its commit altogether.
+                    repo.reference(LOCAL_BRANCH, parent.id(), true, "Drop empty batch")?;
+                    self.update_outbox(repo)?;
+                    return Ok(true);
+                }
+                let message = Self::commit_message(repo, Some(&parent), &tree)?;
+                head.amend(
                     Some("HEAD"),
-                    &signature,
-                    &signature,
-                    commit_message,
-                    &tree,
-                    &[&parent_commit],
+                    None,
+                    Some(&signature),
+                    None,
+                    Some(&message),
+                    Some(&tree),
                 )?;
-            } else {
+            }
+            None => {
+                let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+                let message = Self::commit_message(repo, head.as_ref(), &tree)?;
+                let parents: Vec<&Commit> = head.iter().collect();
                 repo.commit(
                     Some("HEAD"),
                     &signature,
                     &signature,
-                    commit_message,
+                    &message,
                     &tree,
-                    &[],
+                    &parents,
                 )?;
             }
         }
+        self.update_outbox(repo)?;
+        Ok(true)
+    }
 
-        Ok(())
+    /// Whether the latest commit is still collecting changes before being pushed.
+    fn batch_open(&self, repo: &Repository) -> bool {
+        repo.head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .is_some_and(|head| self.is_batching(repo, &head))
     }
 
-    fn push(&self) -> Result<()> {
-        if let Some(repo) = self.repo.as_ref() {
-            let remote_callbacks: git2::RemoteCallbacks = Self::callbacks()?;
-            let mut push_options = PushOptions::new();
-            push_options.remote_callbacks(remote_callbacks);
-            let mut remote = repo.find_remote("origin")?;
-            remote.push(
-                &["refs/heads/main:refs/heads/main"],
-                Some(&mut push_options),
-            )?;
+    /// Summarizes the changes from `parent` to `tree`, listing the configuration fields that
+    /// changed, e.g. `color_scheme: Light → Dark; wallpaper changed`, and the other files.
+    fn commit_message(repo: &Repository, parent: Option<&Commit>, tree: &Tree) -> Result<String> {
+        let parent_tree = parent.map(|parent| parent.tree()).transpose()?;
+        let read = |tree: &Tree| Self::configuration_in(repo, tree);
+
+        let mut parts = vec![];
+        match (parent_tree.as_ref().and_then(read), read(tree)) {
+            (Some(old), Some(new)) => {
+                parts.extend(old.changes(&new).iter().map(ToString::to_string));
+            }
+            (None, Some(_)) => parts.push("Add configuration".to_string()),
+            _ => {}
         }
 
-        Ok(())
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(tree), None)?;
+        let files: Vec<String> = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+            .filter(|path| *path != Path::new(CONFIG_FILE))
+            .map(|path| path.display().to_string())
+            .collect();
+        match files.len() {
+            0 => {}
+            1..=3 => parts.push(format!("{} updated", files.join(", "))),
+            count => parts.push(format!("{count} files updated")),
+        }
+
+        let mut message = if parts.is_empty() {
+            "Update configuration.".to_string()
+        } else {
+            parts.join("; ")
+        };
+        if files.len() > 3 {
+            message.push_str("\n\n");
+            message.push_str(&files.join("\n"));
+        }
+        Ok(message)
     }
 
-    pub fn set_upstream_branch(&self) -> Result<()> {
-        if let Some(repo) = self.repo.as_ref() {
-            let mut branch = repo.find_branch("main", BranchType::Local)?;
+    /// Reads the configuration stored in a tree, if any.
+    fn configuration_in(repo: &Repository, tree: &Tree) -> Option<Configuration> {
+        let entry = tree.get_name(CONFIG_FILE)?;
+        let blob = repo.find_blob(entry.id()).ok()?;
+        ron::from_str(std::str::from_utf8(blob.content()).ok()?).ok()
+    }
 
-            Self::fetch(repo)?;
+    /// Whether `head` is an unpublished commit, started less than the batch window ago.
+    fn is_batching(&self, repo: &Repository, head: &Commit) -> bool {
+        self.batch_left(repo, head).is_some()
+    }
 
-            // Check if the branch already has an upstream branch
-            if branch.upstream().is_err() {
-                let upstream_name = "origin/main"; // Replace with the desired upstream branch name
+    /// The time left before the batch `head` started is closed, if it's still open.
+    fn batch_left(&self, repo: &Repository, head: &Commit) -> Option<Duration> {
+        if self.batch_window.is_zero() || head.parent_count() != 1 {
+            return None;
+        }
+        let published = Self::target(repo, REMOTE_BRANCH).is_some_and(|remote| {
+            remote == head.id() || repo.graph_descendant_of(remote, head.id()).unwrap_or(false)
+        });
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs() as i64)
+            .unwrap_or_default();
+        let left = self.batch_window.as_secs() as i64 - (now - head.author().when().seconds());
+        (!published && left > 0).then(|| Duration::from_secs(left as u64))
+    }
 
-                let upstream_branch = repo.find_branch(upstream_name, BranchType::Remote)?;
+    /// Refuses to commit staged files that look like they contain tokens or private keys.
+    fn scan_for_secrets(repo: &Repository, index: &Index) -> Result<()> {
+        let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+        let diff = repo.diff_tree_to_index(head.as_ref(), Some(index), None)?;
+        for delta in diff.deltas() {
+            let file = delta.new_file();
+            if file.id().is_zero() {
+                continue;
+            }
+            let blob = repo.find_blob(file.id())?;
+            if let Some(kind) = scanner::scan(blob.content()) {
+                bail!(
+                    "Refusing to commit {}, it looks like it contains a {kind}. Mark it as secret instead.",
+                    file.path().unwrap_or(Path::new("")).display()
+                );
+            }
+        }
+        Ok(())
+    }
 
-                branch.set_upstream(upstream_branch.name()?)?;
+    fn push(&self, repo: &Repository) -> Result<()> {
+        let mut remote_callbacks = self.callbacks()?;
+        remote_callbacks.push_update_reference(|reference, status| match status {
+            Some(status) => Err(git2::Error::from_str(&format!(
+                "Failed to push {reference}: {status}"
+            ))),
+            None => Ok(()),
+        });
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(remote_callbacks);
+        let mut remote = repo.find_remote("origin")?;
+        remote.push(
+            &[format!("{LOCAL_BRANCH}:{LOCAL_BRANCH}")],
+            Some(&mut push_options),
+        )?;
+        self.update_outbox(repo)?;
+        Ok(())
+    }
 
-                println!("Upstream branch set successfully.");
-            } else {
-                println!("Upstream branch is already set.");
+    /// Records the local commits the remote doesn't have yet in the outbox.
+    fn update_outbox(&self, repo: &Repository) -> Result<Outbox> {
+        let mut outbox = Outbox::default();
+        if let Some(local) = Self::target(repo, LOCAL_BRANCH) {
+            let mut walk = repo.revwalk()?;
+            walk.push(local)?;
+            if let Some(remote) = Self::target(repo, REMOTE_BRANCH) {
+                walk.hide(remote)?;
+            }
+            for oid in walk {
+                let commit = repo.find_commit(oid?)?;
+                outbox.pending.push(Pending {
+                    id: commit.id().to_string(),
+                    summary: commit.summary().unwrap_or_default().to_string(),
+                    created_at: commit.time().seconds(),
+                });
             }
         }
+        outbox.save(&self.state)?;
+        Ok(outbox)
+    }
 
+    pub fn set_upstream_branch(&self) -> Result<()> {
+        if let Some(repo) = self.repo.as_ref() {
+            let mut branch = repo.find_branch(BRANCH, BranchType::Local)?;
+            if branch.upstream().is_err() {
+                branch.set_upstream(Some(&format!("origin/{BRANCH}")))?;
+            }
+        }
         Ok(())
     }
 
-    fn fetch(repo: &Repository) -> Result<(), anyhow::Error> {
-        let mut remote = repo.find_remote("origin")?;
+    fn fetch(&self, repo: &Repository) -> Result<()> {
         let mut fetch_options = FetchOptions::new();
-        remote.fetch(&["main"], Some(&mut fetch_options), None)?;
+        fetch_options.remote_callbacks(self.callbacks()?);
+        let mut remote = repo.find_remote("origin")?;
+        remote.fetch::<&str>(&[], Some(&mut fetch_options), None)?;
         Ok(())
     }
 
-    fn force_pull(&self, branch_name: &str) -> Result<()> {
-        if let Some(repo) = self.repo.as_ref() {
-            let upstream = repo.find_branch("origin/main", BranchType::Remote)?;
-            let refname = format!("refs/heads/{}", branch_name);
-            let mut reference = repo.find_reference(&refname)?;
-            let remote_oid = upstream
-                .get()
-                .target()
-                .ok_or_else(|| git2::Error::from_str("Remote branch has no target (commit)"))?;
+    /// Downloads the remote changes and merges them into the local branch, settling
+    /// conflicts with `strategy`, then publishes the merge so other devices can fast-forward
+    /// to it. With `fields`, only those configuration fields are taken from the remote
+    /// changes, this device keeps its values of the others without publishing them.
+    fn pull(&self, strategy: ConflictStrategy, fields: Option<&[Field]>) -> Result<Status> {
+        let Some(repo) = self.repo.as_ref() else {
+            return Ok(Status::RepoNotConfigured);
+        };
+        self.configure_remote(repo)?;
+        if let Err(err) = self.fetch(repo) {
+            return self.connection_status(repo, err);
+        }
 
-            reference.set_target(remote_oid, "Fast-forward")?;
-            repo.set_head(&refname)?;
-            repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
-            println!("Pull completed successfully.");
+        let path = self.path.join(CONFIG_FILE);
+        let local = Configuration::read_from(&path);
+        let rejected: Vec<Field> = match fields {
+            Some(fields) => self
+                .preview()?
+                .into_iter()
+                .map(|change| change.field)
+                .filter(|field| !fields.contains(field))
+                .collect(),
+            None => vec![],
+        };
+
+        let conflicts = self.merge(repo, strategy)?;
+        if !conflicts.is_empty() {
+            return self.conflict_status(repo, conflicts);
         }
-        Ok(())
+        if let (Some(fields), Some(local)) = (fields, local) {
+            Overrides::decide(&self.state, fields, &rejected, &local)?;
+        }
+
+        let failed = self.apply();
+        if Self::is_ahead(repo)? {
+            if let Err(err) = self.push(repo) {
+                return self.connection_status(repo, err);
+            }
+        }
+        if !failed.is_empty() {
+            return Ok(Status::PartialSync { failed });
+        }
+        Ok(Status::ChangesDownloaded {
+            commit: Self::head(repo),
+        })
     }
 
-    pub fn pull(&self) -> Result<()> {
-        if let Some(repo) = self.repo.as_ref() {
-            let branch_name = "main";
-            let local_branch = repo.find_branch(branch_name, BranchType::Local)?;
-            let upstream = repo.find_branch("origin/main", BranchType::Remote)?;
+    /// Drops every local change, including unpublished commits, and checks out the remote state.
+    fn reset(&self) -> Result<Status> {
+        let Some(repo) = self.repo.as_ref() else {
+            return Ok(Status::RepoNotConfigured);
+        };
+        self.configure_remote(repo)?;
+        if let Err(err) = self.fetch(repo) {
+            return self.connection_status(repo, err);
+        }
+        let Some(remote) = Self::target(repo, REMOTE_BRANCH) else {
+            bail!("The remote has nothing to reset to.");
+        };
+        repo.reference(LOCAL_BRANCH, remote, true, "Reset to remote branch")?;
+        repo.checkout_head(Some(
+            CheckoutBuilder::default().force().remove_untracked(true),
+        ))?;
+        let failed = self.apply();
+        if !failed.is_empty() {
+            return Ok(Status::PartialSync { failed });
+        }
+        Ok(Status::ChangesDownloaded {
+            commit: remote.to_string(),
+        })
+    }
 
-            let remote_callbacks = Self::callbacks()?;
+    /// Pushes the local branch, reporting the published commit.
+    /// Items that couldn't be applied beforehand turn it into a partial sync.
+    fn publish(&self, repo: &Repository, failed: Vec<String>) -> Result<Status> {
+        if let Err(err) = self.push(repo) {
+            return self.connection_status(repo, err);
+        }
+        if !failed.is_empty() {
+            return Ok(Status::PartialSync { failed });
+        }
+        Ok(Status::ChangesUploaded {
+            commit: Self::head(repo),
+        })
+    }
 
-            let mut fetch_options = git2::FetchOptions::new();
-            fetch_options.remote_callbacks(remote_callbacks);
+    /// Merges the fetched remote branch into the local one. Conflicts the configuration
+    /// merge can't settle are resolved with `strategy`, unless it is to ask, in which case
+    /// nothing is merged and the conflicting paths are returned.
+    fn merge(&self, repo: &Repository, strategy: ConflictStrategy) -> Result<Vec<String>> {
+        let (Some(local_oid), Some(remote_oid)) = (
+            Self::target(repo, LOCAL_BRANCH),
+            Self::target(repo, REMOTE_BRANCH),
+        ) else {
+            return Ok(vec![]);
+        };
+        if local_oid == remote_oid {
+            return Ok(vec![]);
+        }
 
-            let mut remote = repo.find_remote("origin")?;
-            remote.fetch(
-                &[&upstream.name()?.unwrap()],
-                Some(&mut fetch_options),
-                None,
-            )?;
+        let remote_annotated_commit = repo.find_annotated_commit(remote_oid)?;
+        let (analysis, _) = repo.merge_analysis(&[&remote_annotated_commit])?;
+        if analysis.is_up_to_date() {
+            return Ok(vec![]);
+        }
+        if analysis.is_fast_forward() {
+            let mut reference = repo.find_reference(LOCAL_BRANCH)?;
+            reference.set_target(remote_oid, "Fast-forward")?;
+            repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
+            return Ok(vec![]);
+        }
 
-            let local_oid = local_branch
-                .get()
-                .target()
-                .ok_or_else(|| git2::Error::from_str("Local branch has no target (commit)"))?;
-
-            let remote_oid = upstream
-                .get()
-                .target()
-                .ok_or_else(|| git2::Error::from_str("Remote branch has no target (commit)"))?;
-
-            if local_oid != remote_oid {
-                let local_commit = repo.find_commit(local_oid)?;
-                let remote_commit = repo.find_commit(remote_oid)?;
-
-                let remote_annotated_commit =
-                    repo.reference_to_annotated_commit(&upstream.into_reference())?;
-
-                let mut merge_options = MergeOptions::new();
-                merge_options.fail_on_conflict(false);
-
-                let analysis = repo.merge_analysis(&[&remote_annotated_commit])?;
-                if analysis.0.is_up_to_date() {
-                    println!("Already up to date, no changes to pull.");
-                } else if analysis.0.is_fast_forward() {
-                    let refname = format!("refs/heads/{}", branch_name);
-                    let mut reference = repo.find_reference(&refname)?;
-                    reference.set_target(remote_oid, "Fast-forward")?;
-                    repo.set_head(&refname)?;
-                    repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
-                    println!("Pull completed successfully.");
-                } else {
-                    let index =
-                        repo.merge_commits(&local_commit, &remote_commit, Some(&merge_options))?;
-                    if index.has_conflicts() {
-                        let yes = MessageDialog::new()
-                        .set_type(MessageType::Info)
-                        .set_title("Conflict")
-                        .set_text("There are two conflicting configuration files, which one would you like to keep?")
-                        .set_labels("Latest", "Current")
-                        .show_confirm()
-                        .unwrap();
-                        if yes {
-                            // Perform the merge with the "theirs" strategy
-                            let reference = repo.find_reference("FETCH_HEAD")?; // TODO: How do I make this work?
-                            let fetch_commit = repo.reference_to_annotated_commit(&reference)?;
-                            let mut merge_options = MergeOptions::new();
-                            repo.merge(&[&fetch_commit], Some(&mut merge_options), None)?;
-
-                            // Commit the merge result
-                            let signature = repo.signature()?;
-                            let tree_oid = repo.index()?.write_tree()?;
-                            let tree = repo.find_tree(tree_oid)?;
-                            let parent_commit = repo.find_commit(repo.head()?.target().unwrap())?;
-                            let message = "Merge changes from remote branch";
-                            repo.commit(
-                                Some("HEAD"),
-                                &signature,
-                                &signature,
-                                message,
-                                &tree,
-                                &[&parent_commit],
-                            )?;
-
-                            println!("Merge completed successfully.");
-                        } else {
-                            // Perform the merge with the "ours" strategy
-                            let reference = repo.find_reference("FETCH_HEAD")?;
-                            let fetch_commit = repo.reference_to_annotated_commit(&reference)?;
-                            let mut merge_options = MergeOptions::new();
-                            repo.merge(&[&fetch_commit], Some(&mut merge_options), None)?;
-
-                            // Perform "ours" operation
-                            let mut checkout_options = git2::build::CheckoutBuilder::new();
-                            checkout_options.force();
-
-                            repo.checkout_head(Some(&mut checkout_options))?;
-                            repo.index()?.write()?;
-
-                            // Commit the merge result
-                            let signature = repo.signature()?;
-                            let tree_oid = repo.index()?.write_tree()?;
-                            let tree = repo.find_tree(tree_oid)?;
-                            let parent_commit = repo.find_commit(repo.head()?.target().unwrap())?;
-                            let message = "Merge changes from remote branch with ours strategy";
-                            repo.commit(
-                                Some("HEAD"),
-                                &signature,
-                                &signature,
-                                message,
-                                &tree,
-                                &[&parent_commit],
-                            )?;
-
-                            println!("Merge completed successfully with ours strategy.");
-                        }
-                    }
-                }
-            } else {
-                println!("Already up to date, no changes to pull.");
+        let local_commit = repo.find_commit(local_oid)?;
+        let remote_commit = repo.find_commit(remote_oid)?;
+        let mut index = Self::merge_index(repo, &local_commit, &remote_commit)?;
+        if index.has_conflicts() {
+            match strategy {
+                ConflictStrategy::Ask => return Self::conflict_paths(&index),
+                ConflictStrategy::Theirs => Self::resolve_conflicts(&mut index, true)?,
+                ConflictStrategy::Ours => Self::resolve_conflicts(&mut index, false)?,
             }
         }
+        Self::commit_merge(repo, &mut index, &local_commit, &remote_commit)?;
+        Ok(vec![])
+    }
 
+    /// Merges two commits in memory, merging the configuration field by field.
+    fn merge_index(repo: &Repository, local: &Commit, remote: &Commit) -> Result<Index> {
+        let mut merge_options = MergeOptions::new();
+        merge_options.fail_on_conflict(false);
+        let mut index = repo.merge_commits(local, remote, Some(&merge_options))?;
+        if index.has_conflicts() {
+            Self::merge_configuration(repo, &mut index)?;
+        }
+        Ok(index)
+    }
+
+    /// The paths that would conflict when merging the remote branch, without merging it.
+    fn conflicts(repo: &Repository) -> Result<Vec<String>> {
+        let (Some(local), Some(remote)) = (
+            Self::target(repo, LOCAL_BRANCH),
+            Self::target(repo, REMOTE_BRANCH),
+        ) else {
+            return Ok(vec![]);
+        };
+        let index = Self::merge_index(repo, &repo.find_commit(local)?, &repo.find_commit(remote)?)?;
+        Self::conflict_paths(&index)
+    }
+
+    fn conflict_paths(index: &Index) -> Result<Vec<String>> {
+        let mut paths = vec![];
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let path = [&conflict.our, &conflict.their, &conflict.ancestor]
+                .into_iter()
+                .flatten()
+                .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+                .next();
+            paths.extend(path);
+        }
+        Ok(paths)
+    }
+
+    /// Describes a conflict, including the configuration fields both sides changed differently.
+    fn conflict_status(&self, repo: &Repository, paths: Vec<String>) -> Result<Status> {
+        let mut fields = vec![];
+        if let (Some(local), Some(remote)) = (
+            Self::target(repo, LOCAL_BRANCH),
+            Self::target(repo, REMOTE_BRANCH),
+        ) {
+            let read = |oid: Oid| -> Result<Option<Configuration>> {
+                Ok(Self::configuration_in(
+                    repo,
+                    &repo.find_commit(oid)?.tree()?,
+                ))
+            };
+            let base = repo.merge_base(local, remote)?;
+            if let (Some(base), Some(local), Some(remote)) =
+                (read(base)?, read(local)?, read(remote)?)
+            {
+                fields = changes::conflicts(&base, &local, &remote);
+            }
+        }
+        Ok(Status::Conflict { fields, paths })
+    }
+
+    /// Resolves every conflict by keeping the remote side, or the local one.
+    fn resolve_conflicts(index: &mut Index, theirs: bool) -> Result<()> {
+        let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+        for conflict in conflicts {
+            let Some(path) = [&conflict.our, &conflict.their, &conflict.ancestor]
+                .into_iter()
+                .flatten()
+                .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+                .next()
+            else {
+                continue;
+            };
+            let chosen = if theirs { conflict.their } else { conflict.our };
+            index.remove_path(Path::new(&path))?;
+            // A missing side means the file was deleted there.
+            if let Some(mut entry) = chosen {
+                entry.flags &= !STAGE_MASK;
+                index.add(&entry)?;
+            }
+        }
         Ok(())
     }
 
-    fn updates_pending(&self) -> Result<bool> {
-        if let Some(repo) = self.repo.as_ref() {
-            let local_branch = repo.find_branch("main", BranchType::Local)?;
-            let remote_branch = repo.find_branch("origin/main", BranchType::Remote)?;
+    /// Resolves a conflicting configuration file by merging it field by field.
+    /// Returns `false` if any conflict remains, leaving the index untouched.
+    fn merge_configuration(repo: &Repository, index: &mut Index) -> Result<bool> {
+        let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+        let mut resolved = vec![];
+        for conflict in conflicts {
+            let (Some(ancestor), Some(ours), Some(theirs)) =
+                (conflict.ancestor, conflict.our, conflict.their)
+            else {
+                return Ok(false);
+            };
+            if ours.path != CONFIG_FILE.as_bytes() {
+                return Ok(false);
+            }
+            let read = |entry: &IndexEntry| -> Result<Configuration> {
+                let blob = repo.find_blob(entry.id)?;
+                Ok(ron::from_str(std::str::from_utf8(blob.content())?)?)
+            };
+            let Some(merged) =
+                Configuration::merge(&read(&ancestor)?, &read(&ours)?, &read(&theirs)?)
+            else {
+                return Ok(false);
+            };
+            resolved.push((ours, merged.to_ron()?));
+        }
 
-            let remote_callbacks = Self::callbacks()?;
+        for (mut entry, contents) in resolved {
+            // The merge index isn't backed by the repository, so store the blob first.
+            entry.id = repo.blob(contents.as_bytes())?;
+            entry.file_size = contents.len() as u32;
+            // Clear the stage bits so the entry replaces the conflict.
+            entry.flags &= !STAGE_MASK;
+            index.remove_path(Path::new(CONFIG_FILE))?;
+            index.add(&entry)?;
+        }
+        Ok(true)
+    }
 
-            let mut fetch_options = git2::FetchOptions::new();
-            fetch_options.remote_callbacks(remote_callbacks);
+    fn commit_merge(
+        repo: &Repository,
+        index: &mut Index,
+        local_commit: &Commit,
+        remote_commit: &Commit,
+    ) -> Result<()> {
+        let signature = Signature::now("Symmetry", "symmetry@proton.me")?;
+        let tree_id = index.write_tree_to(repo)?;
+        let tree = repo.find_tree(tree_id)?;
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Merge changes from remote branch",
+            &tree,
+            &[local_commit, remote_commit],
+        )?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+        Ok(())
+    }
 
-            let mut remote = repo.find_remote("origin")?;
-            remote.fetch(
-                &[&remote_branch.name()?.unwrap()],
-                Some(&mut fetch_options),
-                None,
-            )?;
+    /// The commit a reference points to, if it exists.
+    fn target(repo: &Repository, reference: &str) -> Option<Oid> {
+        repo.find_reference(reference).ok()?.target()
+    }
 
-            let local_oid = local_branch
-                .get()
-                .target()
-                .ok_or_else(|| git2::Error::from_str("Local branch has no target (commit)"))?;
+    /// The commit the local branch points to.
+    fn head(repo: &Repository) -> String {
+        Self::target(repo, LOCAL_BRANCH)
+            .map(|oid| oid.to_string())
+            .unwrap_or_default()
+    }
 
-            let remote_oid = remote_branch
-                .get()
-                .target()
-                .ok_or_else(|| git2::Error::from_str("Remote branch has no target (commit)"))?;
+    /// Reports network and authentication failures as a status, other errors are returned.
+    fn connection_status(&self, repo: &Repository, err: anyhow::Error) -> Result<Status> {
+        match err.downcast_ref::<git2::Error>().map(Failure::of) {
+            Some(Failure::Unauthorized) => Ok(Status::AuthRequired),
+            Some(Failure::Unreachable) => Ok(Status::Offline {
+                pending: self.update_outbox(repo)?.len(),
+            }),
+            Some(Failure::Other) | None => Err(err),
+        }
+    }
 
-            Ok(local_oid != remote_oid)
-        } else {
-            Ok(false)
+    /// Whether the local branch has commits the remote doesn't.
+    fn is_ahead(repo: &Repository) -> Result<bool> {
+        match (
+            Self::target(repo, LOCAL_BRANCH),
+            Self::target(repo, REMOTE_BRANCH),
+        ) {
+            (Some(local), Some(remote)) => {
+                Ok(local != remote && repo.graph_descendant_of(local, remote)?)
+            }
+            (Some(_), None) => Ok(true),
+            _ => Ok(false),
         }
     }
 
-    fn callbacks<'a>() -> Result<git2::RemoteCallbacks<'a>, anyhow::Error> {
+    fn callbacks<'a>(&self) -> Result<git2::RemoteCallbacks<'a>, anyhow::Error> {
         let git_config = git2::Config::open_default()?;
         let mut credential_handler = CredentialHandler::new(git_config);
+        let mut token = Secrets::get(&self.context, GIT_TOKEN).ok().flatten();
+        let token_username = Configuration::read_from(&self.path.join(CONFIG_FILE))
+            .map(|config| config.service_config.git.username)
+            .unwrap_or_default();
         let mut remote_callbacks = git2::RemoteCallbacks::new();
         remote_callbacks.credentials(move |url, username, allowed| {
+            // Try the token stored in the keyring once before falling back.
+            if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                if let Some(token) = token.take() {
+                    let username = username.unwrap_or(token_username.as_str());
+                    return Cred::userpass_plaintext(username, &token);
+                }
+            }
             credential_handler.try_next_credential(url, username, allowed)
         });
         Ok(remote_callbacks)
     }
 }
+
+/// Registers [`GitSync`] with the [registry](super::registry).
+pub struct GitBackend;
+
+impl Backend for GitBackend {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn title(&self) -> &'static str {
+        "Git"
+    }
+
+    fn description(&self) -> &'static str {
+        "Free and open source distributed version control system"
+    }
+
+    fn schema(&self) -> Vec<Setting> {
+        vec![
+            Setting::new("url", "Url", Kind::Text).with_placeholder("Paste the Git repo URL here."),
+            Setting::new("username", "Username", Kind::Text)
+                .with_placeholder("The user the token belongs to, if the host needs one."),
+            Setting::new(GIT_TOKEN, "Token", Kind::Secret)
+                .with_placeholder("Stored in the system keyring, never committed."),
+            Setting::new(
+                "conflicts",
+                "Conflicts",
+                Kind::Choice(&["Ask", "Theirs", "Ours"]),
+            ),
+            Setting::new(
+                "batch_seconds",
+                "Batch window",
+                Kind::Number {
+                    min: 0,
+                    max: MAX_BATCH_SECONDS,
+                },
+            )
+            .with_placeholder(
+                "Seconds to fold successive changes into one commit, empty for none.",
+            ),
+            Setting::metered(),
+        ]
+    }
+
+    fn get(&self, services: &Services, key: &str) -> Option<String> {
+        let config = &services.git;
+        match key {
+            ENABLED => show(&config.enabled),
+            "url" => Some(config.url.clone()),
+            "username" => Some(config.username.clone()),
+            "conflicts" => show(&config.conflicts),
+            "batch_seconds" => show_number(&config.batch_seconds),
+            METERED => show(&config.metered),
+            _ => None,
+        }
+    }
+
+    fn set(&self, services: &mut Services, key: &str, value: &str) -> Result<()> {
+        let config = &mut services.git;
+        let schema = self.schema();
+        match key {
+            ENABLED => config.enabled = value == "true",
+            "url" => config.url = value.trim().to_string(),
+            "username" => config.username = value.trim().to_string(),
+            "conflicts" => config.conflicts = parse(Setting::find(&schema, key)?, value)?,
+            "batch_seconds" => config.batch_seconds = parse(Setting::find(&schema, key)?, value)?,
+            METERED => config.metered = parse(Setting::find(&schema, key)?, value)?,
+            _ => bail!("There's no setting named {key}."),
+        }
+        Ok(())
+    }
+
+    fn open(&self, context: &SymmetryContext) -> Result<Provider> {
+        Ok(Box::new(GitSync::with_context(context)))
+    }
+}
+
+/// Writes the ignore rules Symmetry relies on to `.gitignore`, keeping any other rules.
+fn write_gitignore(repo: &Path) -> Result<()> {
+    let path = repo.join(".gitignore");
+    let current = std::fs::read_to_string(&path).unwrap_or_default();
+    let mut contents = String::new();
+    let mut managed = false;
+    for line in current.lines() {
+        match line {
+            GITIGNORE_START => managed = true,
+            GITIGNORE_END => managed = false,
+            _ if !managed => {
+                contents.push_str(line);
+                contents.push('\n');
+            }
+            _ => {}
+        }
+    }
+    contents.push_str(GITIGNORE_START);
+    contents.push('\n');
+    for rule in IGNORED {
+        contents.push_str(rule);
+        contents.push('\n');
+    }
+    contents.push_str(GITIGNORE_END);
+    contents.push('\n');
+    if contents != current {
+        std::fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// The location of a remote on this machine, for `file://` URLs and plain paths.
+fn local_remote(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Some(PathBuf::from(path));
+    }
+    // Other schemes and scp-like `user@host:path` remotes are network remotes.
+    let is_scp_like = url
+        .find(':')
+        .is_some_and(|colon| !url[..colon].contains('/'));
+    if url.contains("://") || is_scp_like {
+        return None;
+    }
+    Some(PathBuf::from(url))
+}
+
+/// The URL handed to git, with a leading `~` of plain paths expanded to the home
+/// directory of `context`.
+fn remote_url(context: &SymmetryContext, url: &str) -> String {
+    match (url.strip_prefix('~'), context.home_dir().ok()) {
+        (Some(relative), Some(home)) => home
+            .join(relative.trim_start_matches('/'))
+            .to_string_lossy()
+            .to_string(),
+        _ => url.to_string(),
+    }
+}
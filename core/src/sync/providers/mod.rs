@@ -1,3 +1,51 @@
 pub mod config;
+pub mod coordinator;
 pub mod crdt;
+pub mod directory;
 pub mod git;
+pub mod registry;
+pub mod s3;
+pub mod webdav;
+
+use anyhow::Result;
+
+use crate::{
+    configuration::Configuration, context::SymmetryContext,
+    traits::synchronization::Synchronization,
+};
+
+use self::coordinator::Coordinator;
+
+use super::{message::Message, status::Status};
+
+/// A sync provider, as driven by the app and the daemon.
+pub type Provider = Box<dyn Synchronization<Status = Status, Message = Message>>;
+
+/// Opens the providers of the backends enabled in the configuration of `context`,
+/// coordinated when there are several. Configurations without any enabled use their
+/// active service. Returns `None` without a configuration.
+pub fn open(context: &SymmetryContext) -> Result<Option<Provider>> {
+    let Some(configuration) = Configuration::load(context) else {
+        return Ok(None);
+    };
+    let mut backends: Vec<_> = registry::backends()
+        .into_iter()
+        .filter(|backend| backend.enabled(&configuration.service_config))
+        .collect();
+    if backends.is_empty() {
+        backends.extend(registry::find(configuration.active_service.backend()));
+    }
+    let mut providers: Vec<(String, Provider)> = vec![];
+    for backend in backends {
+        providers.push((backend.title().to_string(), backend.open(context)?));
+    }
+    if providers.len() == 1 {
+        return Ok(providers.pop().map(|(_, provider)| provider));
+    }
+    Ok(Some(Box::new(Coordinator::new(providers))))
+}
+
+/// Opens the providers enabled in the current configuration.
+pub fn current() -> Result<Option<Provider>> {
+    open(&SymmetryContext::current()?)
+}
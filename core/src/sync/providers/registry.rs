@@ -0,0 +1,152 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::traits::backend::Backend;
+
+use super::{
+    crdt::CrdtBackend, directory::DirectoryBackend, git::GitBackend, s3::S3Backend,
+    webdav::WebDavBackend,
+};
+
+/// Key under which every backend records whether it is enabled.
+pub const ENABLED: &str = "enabled";
+/// Key of the setting deciding whether a backend synchronizes on metered links.
+pub const METERED: &str = "metered";
+
+static BACKENDS: Lazy<RwLock<Vec<Arc<dyn Backend>>>> = Lazy::new(|| {
+    RwLock::new(vec![
+        Arc::new(CrdtBackend),
+        Arc::new(GitBackend),
+        Arc::new(WebDavBackend),
+        Arc::new(S3Backend),
+        Arc::new(DirectoryBackend),
+    ])
+});
+
+/// A setting of a backend, for the Services page to ask for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    /// Identifies the setting within its backend.
+    pub key: &'static str,
+    pub label: &'static str,
+    /// Shown while the field is empty, usually with an example.
+    pub placeholder: &'static str,
+    pub kind: Kind,
+}
+
+/// What a setting holds, deciding how it's edited and checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Text,
+    /// A whole number within the bounds, empty standing for zero.
+    Number {
+        min: u64,
+        max: u64,
+    },
+    Toggle,
+    /// One of the listed values.
+    Choice(&'static [&'static str]),
+    /// Kept with the secrets under the key of the setting, and never shown back.
+    Secret,
+}
+
+impl Setting {
+    pub fn new(key: &'static str, label: &'static str, kind: Kind) -> Self {
+        Self {
+            key,
+            label,
+            placeholder: "",
+            kind,
+        }
+    }
+
+    pub fn with_placeholder(mut self, placeholder: &'static str) -> Self {
+        self.placeholder = placeholder;
+        self
+    }
+
+    /// Whether the backend synchronizes while the device is on a metered link, kept under
+    /// [`METERED`] by the backends that can tell.
+    pub fn metered() -> Self {
+        Self::new(METERED, "Metered links", Kind::Choice(&["Defer", "Allow"]))
+    }
+
+    /// Looks up the setting with the given key in a schema.
+    pub fn find<'a>(schema: &'a [Setting], key: &str) -> Result<&'a Setting> {
+        schema
+            .iter()
+            .find(|setting| setting.key == key)
+            .ok_or_else(|| anyhow!("There's no setting named {key}."))
+    }
+
+    /// Fails with a message for the user if `value` doesn't suit the setting.
+    pub fn check(&self, value: &str) -> Result<()> {
+        let valid = match self.kind {
+            Kind::Text | Kind::Secret => true,
+            Kind::Number { min, max } => {
+                let number = match value {
+                    "" => Ok(0),
+                    value => value.parse::<u64>(),
+                };
+                if !number.is_ok_and(|number| (min..=max).contains(&number)) {
+                    bail!("{} takes a whole number from {min} to {max}.", self.label);
+                }
+                true
+            }
+            Kind::Toggle => value == "true" || value == "false",
+            Kind::Choice(choices) => choices.contains(&value),
+        };
+        if !valid {
+            bail!("{value} isn't a valid {}.", self.label.to_lowercase());
+        }
+        Ok(())
+    }
+}
+
+/// Makes a backend available to the app and the daemon, replacing any registered under the
+/// same name. Each of them needs it registered before opening providers.
+pub fn register(backend: impl Backend + 'static) {
+    let mut backends = BACKENDS.write().unwrap_or_else(|err| err.into_inner());
+    backends.retain(|registered| registered.name() != backend.name());
+    backends.push(Arc::new(backend));
+}
+
+/// The registered backends, realtime ones first, in the order their providers should run.
+pub fn backends() -> Vec<Arc<dyn Backend>> {
+    let mut backends = BACKENDS
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    backends.sort_by_key(|backend| !backend.realtime());
+    backends
+}
+
+pub fn find(name: &str) -> Option<Arc<dyn Backend>> {
+    backends()
+        .into_iter()
+        .find(|backend| backend.name() == name)
+}
+
+/// Writes a number, toggle or choice of the built-in configurations as text.
+pub(crate) fn show<T: Serialize>(value: &T) -> Option<String> {
+    ron::to_string(value).ok()
+}
+
+/// Writes a number of the built-in configurations as text, leaving zero empty as it's read
+/// back.
+pub(crate) fn show_number<T: Serialize + Default + PartialEq>(value: &T) -> Option<String> {
+    if *value == T::default() {
+        return Some(String::new());
+    }
+    show(value)
+}
+
+/// Reads a number, toggle or choice of the built-in configurations back from text.
+pub(crate) fn parse<T: DeserializeOwned>(setting: &Setting, value: &str) -> Result<T> {
+    setting.check(value)?;
+    let value = if value.is_empty() { "0" } else { value };
+    Ok(ron::from_str(value)?)
+}
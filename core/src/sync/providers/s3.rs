@@ -0,0 +1,210 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    configuration::{changes::Change, Configuration},
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    secrets::Secrets,
+    sync::{
+        message::Message,
+        providers::{
+            config::{s3::S3Config, Services},
+            registry::{parse, show, Kind, Setting, ENABLED, METERED},
+            Provider,
+        },
+        status::Status,
+        store::{
+            mirror::Mirror,
+            s3::{Credentials, S3},
+            Version,
+        },
+    },
+    traits::{backend::Backend, synchronization::Synchronization},
+};
+
+/// Name of the keyring entry holding the secret key matching the access key.
+pub const S3_SECRET_KEY: &str = "s3.secret_key";
+/// Region used when none is configured, the one MinIO answers for by default.
+pub const DEFAULT_REGION: &str = "us-east-1";
+/// File in the state directory keeping what was last agreed with the bucket.
+const STATE_FILE: &str = "s3";
+
+/// Synchronizes the configuration and tracked files through a bucket of an S3-compatible
+/// object storage, such as AWS S3 or MinIO.
+///
+/// With versioning enabled on the bucket, every state published is kept and can be
+/// restored.
+pub struct S3Sync {
+    mirror: Option<Mirror<S3>>,
+}
+
+impl Synchronization for S3Sync {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Self::Status> {
+        match &self.mirror {
+            Some(mirror) => mirror.sync(),
+            None => Ok(Status::RepoNotConfigured),
+        }
+    }
+
+    fn handle(&self, message: Self::Message) -> Result<Self::Status> {
+        match &self.mirror {
+            Some(mirror) => mirror.handle(message),
+            None => Ok(Status::RepoNotConfigured),
+        }
+    }
+
+    fn preview(&self) -> Result<Vec<Change>> {
+        match &self.mirror {
+            Some(mirror) => mirror.preview(),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn metered_policy(&self) -> MeteredPolicy {
+        self.mirror
+            .as_ref()
+            .map(|mirror| mirror.metered_policy())
+            .unwrap_or_default()
+    }
+}
+
+impl S3Sync {
+    /// Synchronizes this device with the configured bucket.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_context(&SymmetryContext::current()?))
+    }
+
+    /// Synchronizes the given context with its configured bucket.
+    pub fn with_context(context: &SymmetryContext) -> Self {
+        let config = Configuration::load(context)
+            .map(|config| config.service_config.s3)
+            .unwrap_or_default();
+        if config.endpoint.is_empty() || config.bucket.is_empty() {
+            return Self { mirror: None };
+        }
+        let credentials = Credentials {
+            access_key: config.access_key.clone(),
+            secret_key: Secrets::get(context, S3_SECRET_KEY)
+                .ok()
+                .flatten()
+                .unwrap_or_default(),
+        };
+        let mut sync = Self::open(context, &config, credentials);
+        if let Some(mirror) = sync.mirror.as_mut() {
+            mirror.set_conflict_strategy(config.conflicts);
+            mirror.set_metered_policy(config.metered);
+        }
+        sync
+    }
+
+    /// Synchronizes the given context with the bucket described by `config`.
+    pub fn open(context: &SymmetryContext, config: &S3Config, credentials: Credentials) -> Self {
+        let region = match config.region.as_str() {
+            "" => DEFAULT_REGION,
+            region => region,
+        };
+        let store = S3::new(
+            &config.endpoint,
+            &config.bucket,
+            &config.prefix,
+            region,
+            credentials,
+        );
+        Self {
+            mirror: Some(Mirror::new(store, context, STATE_FILE)),
+        }
+    }
+
+    /// Lists the states the bucket kept, newest first. Empty unless versioning is enabled
+    /// on the bucket.
+    pub fn history(&self) -> Result<Vec<Version>> {
+        match &self.mirror {
+            Some(mirror) => mirror.history(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Brings back a state listed by [`S3Sync::history`], for every device.
+    pub fn restore(&self, version: &str) -> Result<Status> {
+        match &self.mirror {
+            Some(mirror) => mirror.restore(version),
+            None => Ok(Status::RepoNotConfigured),
+        }
+    }
+}
+
+/// Registers [`S3Sync`] with the [registry](super::registry).
+pub struct S3Backend;
+
+impl Backend for S3Backend {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn title(&self) -> &'static str {
+        "S3"
+    }
+
+    fn description(&self) -> &'static str {
+        "Keeps everything in a bucket of AWS S3, MinIO or any S3-compatible storage"
+    }
+
+    fn schema(&self) -> Vec<Setting> {
+        vec![
+            Setting::new("endpoint", "Endpoint", Kind::Text)
+                .with_placeholder("e.g. https://s3.eu-west-1.amazonaws.com"),
+            Setting::new("bucket", "Bucket", Kind::Text),
+            Setting::new("prefix", "Prefix", Kind::Text)
+                .with_placeholder("e.g. symmetry, leave empty to use the whole bucket"),
+            Setting::new("region", "Region", Kind::Text).with_placeholder(DEFAULT_REGION),
+            Setting::new("access_key", "Access key", Kind::Text),
+            Setting::new(S3_SECRET_KEY, "Secret key", Kind::Secret)
+                .with_placeholder("Stored in the system keyring."),
+            Setting::new(
+                "conflicts",
+                "Conflicts",
+                Kind::Choice(&["Ask", "Theirs", "Ours"]),
+            ),
+            Setting::metered(),
+        ]
+    }
+
+    fn get(&self, services: &Services, key: &str) -> Option<String> {
+        let config = &services.s3;
+        match key {
+            ENABLED => show(&config.enabled),
+            "endpoint" => Some(config.endpoint.clone()),
+            "bucket" => Some(config.bucket.clone()),
+            "prefix" => Some(config.prefix.clone()),
+            "region" => Some(config.region.clone()),
+            "access_key" => Some(config.access_key.clone()),
+            "conflicts" => show(&config.conflicts),
+            METERED => show(&config.metered),
+            _ => None,
+        }
+    }
+
+    fn set(&self, services: &mut Services, key: &str, value: &str) -> Result<()> {
+        let config = &mut services.s3;
+        let schema = self.schema();
+        match key {
+            ENABLED => config.enabled = value == "true",
+            "endpoint" => config.endpoint = value.trim().to_string(),
+            "bucket" => config.bucket = value.trim().to_string(),
+            "prefix" => config.prefix = value.trim().to_string(),
+            "region" => config.region = value.trim().to_string(),
+            "access_key" => config.access_key = value.trim().to_string(),
+            "conflicts" => config.conflicts = parse(Setting::find(&schema, key)?, value)?,
+            METERED => config.metered = parse(Setting::find(&schema, key)?, value)?,
+            _ => bail!("There's no setting named {key}."),
+        }
+        Ok(())
+    }
+
+    fn open(&self, context: &SymmetryContext) -> Result<Provider> {
+        Ok(Box::new(S3Sync::with_context(context)))
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    configuration::{changes::Change, Configuration},
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    secrets::Secrets,
+    sync::{
+        message::Message,
+        providers::{
+            config::Services,
+            registry::{parse, show, Kind, Setting, ENABLED, METERED},
+            Provider,
+        },
+        status::Status,
+        store::{mirror::Mirror, webdav::WebDav},
+    },
+    traits::{backend::Backend, synchronization::Synchronization},
+};
+
+/// Name of the keyring entry holding the password used to authenticate against the server.
+pub const WEBDAV_PASSWORD: &str = "webdav.password";
+/// File in the state directory keeping what was last agreed with the server.
+const STATE_FILE: &str = "webdav";
+
+/// Synchronizes the configuration and tracked files through a WebDAV server, such as
+/// Nextcloud or ownCloud, without needing git.
+pub struct WebDavSync {
+    mirror: Option<Mirror<WebDav>>,
+}
+
+impl Synchronization for WebDavSync {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Self::Status> {
+        match &self.mirror {
+            Some(mirror) => mirror.sync(),
+            None => Ok(Status::RepoNotConfigured),
+        }
+    }
+
+    fn handle(&self, message: Self::Message) -> Result<Self::Status> {
+        match &self.mirror {
+            Some(mirror) => mirror.handle(message),
+            None => Ok(Status::RepoNotConfigured),
+        }
+    }
+
+    fn preview(&self) -> Result<Vec<Change>> {
+        match &self.mirror {
+            Some(mirror) => mirror.preview(),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn metered_policy(&self) -> MeteredPolicy {
+        self.mirror
+            .as_ref()
+            .map(|mirror| mirror.metered_policy())
+            .unwrap_or_default()
+    }
+}
+
+impl WebDavSync {
+    /// Synchronizes this device with the configured server.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_context(&SymmetryContext::current()?))
+    }
+
+    /// Synchronizes the given context with its configured server.
+    pub fn with_context(context: &SymmetryContext) -> Self {
+        let config = Configuration::load(context)
+            .map(|config| config.service_config.webdav)
+            .unwrap_or_default();
+        if config.url.is_empty() {
+            return Self { mirror: None };
+        }
+        let password = Secrets::get(context, WEBDAV_PASSWORD).ok().flatten();
+        let mut sync = Self::open(context, &config.url, &config.username, password.as_deref());
+        if let Some(mirror) = sync.mirror.as_mut() {
+            mirror.set_conflict_strategy(config.conflicts);
+            mirror.set_metered_policy(config.metered);
+        }
+        sync
+    }
+
+    /// Synchronizes the given context with the collection at `url`.
+    pub fn open(
+        context: &SymmetryContext,
+        url: &str,
+        username: &str,
+        password: Option<&str>,
+    ) -> Self {
+        let store = WebDav::new(url, username, password);
+        Self {
+            mirror: Some(Mirror::new(store, context, STATE_FILE)),
+        }
+    }
+}
+
+/// Registers [`WebDavSync`] with the [registry](super::registry).
+pub struct WebDavBackend;
+
+impl Backend for WebDavBackend {
+    fn name(&self) -> &'static str {
+        "webdav"
+    }
+
+    fn title(&self) -> &'static str {
+        "WebDAV"
+    }
+
+    fn description(&self) -> &'static str {
+        "Keeps everything in a folder of Nextcloud, ownCloud or any WebDAV server"
+    }
+
+    fn schema(&self) -> Vec<Setting> {
+        vec![
+            Setting::new("url", "Url", Kind::Text).with_placeholder(
+                "e.g. https://cloud.example.org/remote.php/dav/files/me/symmetry",
+            ),
+            Setting::new("username", "Username", Kind::Text),
+            Setting::new(WEBDAV_PASSWORD, "Password", Kind::Secret)
+                .with_placeholder("An app password, stored in the system keyring."),
+            Setting::new(
+                "conflicts",
+                "Conflicts",
+                Kind::Choice(&["Ask", "Theirs", "Ours"]),
+            ),
+            Setting::metered(),
+        ]
+    }
+
+    fn get(&self, services: &Services, key: &str) -> Option<String> {
+        let config = &services.webdav;
+        match key {
+            ENABLED => show(&config.enabled),
+            "url" => Some(config.url.clone()),
+            "username" => Some(config.username.clone()),
+            "conflicts" => show(&config.conflicts),
+            METERED => show(&config.metered),
+            _ => None,
+        }
+    }
+
+    fn set(&self, services: &mut Services, key: &str, value: &str) -> Result<()> {
+        let config = &mut services.webdav;
+        let schema = self.schema();
+        match key {
+            ENABLED => config.enabled = value == "true",
+            "url" => config.url = value.trim().to_string(),
+            "username" => config.username = value.trim().to_string(),
+            "conflicts" => config.conflicts = parse(Setting::find(&schema, key)?, value)?,
+            METERED => config.metered = parse(Setting::find(&schema, key)?, value)?,
+            _ => bail!("There's no setting named {key}."),
+        }
+        Ok(())
+    }
+
+    fn open(&self, context: &SymmetryContext) -> Result<Provider> {
+        Ok(Box::new(WebDavSync::with_context(context)))
+    }
+}
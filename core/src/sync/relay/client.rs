@@ -0,0 +1,287 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+use rustls::{pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use tungstenite::{http::Uri, WebSocket};
+
+use crate::{
+    sync::{
+        document::{Actor, Delta},
+        mailbox::{Envelope, Mailbox},
+    },
+    trust::{identity::Identity, Devices},
+};
+
+use super::{poll, receive, send, signed, validate_account, Frame};
+
+/// How long to wait for the relay to answer.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// How often a subscription checks whether it should stop.
+const POLL: Duration = Duration::from_millis(200);
+/// How long a subscription waits before connecting again after losing the relay.
+const RETRY: Duration = Duration::from_secs(30);
+
+/// A connection to a relay, on behalf of a device of the account.
+pub struct Connection {
+    socket: WebSocket<Stream>,
+    /// The sealed states received and not read yet, with the device that left them.
+    received: Vec<(Actor, String)>,
+}
+
+/// The stream to a relay, encrypted for `wss://` addresses.
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+/// Stays connected to a relay in the background, handing over what the other devices
+/// publish as soon as they do, until dropped.
+pub struct Subscription {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Connection {
+    /// Connects to the relay at `url`, given as `ws://host:port/account` or
+    /// `wss://host:port/account`, and receives the states the other devices of the account
+    /// left there. Tells the relay which `devices` to let into the account.
+    ///
+    /// The states are sealed end to end already, `wss://` also hides who they're for.
+    pub fn open(url: &str, identity: &Identity, devices: &Devices) -> Result<Self> {
+        let uri: Uri = url.parse()?;
+        let (tls, port) = match uri.scheme_str() {
+            Some("ws") => (false, 80),
+            Some("wss") => (true, 443),
+            _ => bail!("Relays are reached at ws:// or wss:// addresses."),
+        };
+        let account = uri.path().trim_matches('/');
+        validate_account(account)?;
+        let host = uri
+            .host()
+            .ok_or_else(|| anyhow!("The relay address has no host."))?;
+        let address = (host, uri.port_u16().unwrap_or(port))
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("{host} can't be resolved."))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let stream = if tls {
+            Stream::tls(host, stream)?
+        } else {
+            Stream::Plain(stream)
+        };
+        let (mut socket, _) = tungstenite::client(url, stream).map_err(|err| anyhow!("{err}"))?;
+        let challenge = match receive(&mut socket)? {
+            Frame::Challenge(challenge) => challenge,
+            Frame::Refused(reason) => bail!("The relay refused the connection: {reason}"),
+            _ => bail!("The relay sent an unexpected message."),
+        };
+        let trusted: Vec<String> = devices
+            .trusted
+            .values()
+            .map(|device| device.signing_key.clone())
+            .collect();
+        let hello = Frame::Hello {
+            account: account.to_string(),
+            key: identity.signing_key(),
+            signature: identity.sign(&signed(account, &challenge, &trusted)),
+            trusted,
+        };
+        send(&mut socket, &hello)?;
+        let mut connection = Self {
+            socket,
+            received: vec![],
+        };
+        loop {
+            match receive(&mut connection.socket)? {
+                Frame::State { device, envelope } => connection.received.push((device, envelope)),
+                Frame::Ready => return Ok(connection),
+                Frame::Refused(reason) => bail!("The relay refused the connection: {reason}"),
+                _ => bail!("The relay sent an unexpected message."),
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for another device to publish its state. Returns whether there
+    /// are states left to read.
+    pub fn wait(&mut self, timeout: Duration) -> Result<bool> {
+        if self.received.is_empty() {
+            self.socket
+                .get_ref()
+                .tcp()
+                .set_read_timeout(Some(timeout))?;
+            let frame = poll(&mut self.socket);
+            self.socket
+                .get_ref()
+                .tcp()
+                .set_read_timeout(Some(TIMEOUT))?;
+            match frame? {
+                Some(Frame::State { device, envelope }) => self.received.push((device, envelope)),
+                Some(_) => bail!("The relay sent an unexpected message."),
+                None => {}
+            }
+        }
+        Ok(!self.received.is_empty())
+    }
+
+    /// Opens the state `device` left, or returns `None` if it isn't meant for this device.
+    fn unseal(
+        device: &str,
+        envelope: &str,
+        identity: &Identity,
+        devices: &Devices,
+    ) -> Result<Option<Delta>> {
+        let envelope: Envelope = ron::from_str(envelope)?;
+        let Some(state) = envelope.open(identity, devices)? else {
+            return Ok(None);
+        };
+        if envelope.writer() != device {
+            bail!("The state was sealed by another device than the one that left it.");
+        }
+        Ok(Some(state))
+    }
+}
+
+impl Mailbox for Connection {
+    /// Only hands over the states received since the last read.
+    fn read(&mut self, identity: &Identity, devices: &Devices) -> Result<Vec<(Actor, Delta)>> {
+        let mut states = vec![];
+        for (device, envelope) in std::mem::take(&mut self.received) {
+            match Self::unseal(&device, &envelope, identity, devices) {
+                Ok(Some(state)) => states.push((device, state)),
+                Ok(None) => {}
+                Err(err) => warn!("{device}: {err:#}"),
+            }
+        }
+        Ok(states)
+    }
+
+    /// Returns once the relay stored the state, so the others find it from then on.
+    fn write(&mut self, identity: &Identity, devices: &Devices, state: &Delta) -> Result<()> {
+        let envelope = Envelope::seal(identity, devices, state)?;
+        send(
+            &mut self.socket,
+            &Frame::Publish(ron::to_string(&envelope)?),
+        )?;
+        loop {
+            match receive(&mut self.socket)? {
+                Frame::Stored => return Ok(()),
+                Frame::State { device, envelope } => self.received.push((device, envelope)),
+                Frame::Refused(reason) => bail!("The relay refused the state: {reason}"),
+                _ => bail!("The relay sent an unexpected message."),
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.socket.close(None);
+        let _ = self.socket.flush();
+    }
+}
+
+impl Stream {
+    /// Encrypts `stream` to `host`, checking its certificate against the well-known roots.
+    fn tls(host: &str, stream: TcpStream) -> Result<Self> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(host.to_string())?;
+        let connection = ClientConnection::new(Arc::new(config), name)?;
+        Ok(Self::Tls(Box::new(StreamOwned::new(connection, stream))))
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Self::Plain(stream) => stream,
+            Self::Tls(stream) => stream.get_ref(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.read(buf),
+            Self::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.write(buf),
+            Self::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush(),
+            Self::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Subscription {
+    /// Connects to the relay at `url` on behalf of `identity`, calling `handler` whenever
+    /// states are waiting to be read. Connects again whenever the relay is lost, letting
+    /// the `devices` trusted by then into the account.
+    pub fn start<F, D>(url: String, identity: Identity, devices: D, mut handler: F) -> Self
+    where
+        F: FnMut(&Identity, &mut Connection) -> Result<()> + Send + 'static,
+        D: Fn() -> Devices + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                let connection = Connection::open(&url, &identity, &devices());
+                let result = connection.and_then(|mut connection| {
+                    while !stopped.load(Ordering::Relaxed) {
+                        if connection.wait(POLL)? {
+                            handler(&identity, &mut connection)?;
+                        }
+                    }
+                    Ok(())
+                });
+                if let Err(err) = result {
+                    warn!("{url}: {err:#}");
+                }
+                // Waits in steps, so dropping the subscription doesn't.
+                let retry = Instant::now() + RETRY;
+                while !stopped.load(Ordering::Relaxed) && Instant::now() < retry {
+                    std::thread::sleep(POLL);
+                }
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,99 @@
+pub mod client;
+pub mod server;
+
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, WebSocket};
+
+use super::document::Actor;
+
+/// The port the relay listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 47475;
+/// The largest sealed state the relay stores.
+pub const MAX_ENVELOPE: usize = 16 * 1024 * 1024;
+/// The most devices an account holds states of.
+pub const MAX_DEVICES: usize = 64;
+
+/// A message exchanged between a device and the relay.
+///
+/// The relay only ever handles sealed envelopes, it has no key to open them with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Frame {
+    /// Sent by the relay as a device connects, for it to sign.
+    Challenge(String),
+    /// Names the account of the device, and proves it holds the signing key it presents.
+    /// Lists the signing keys of the devices it trusts, the only ones the relay lets into
+    /// the account from then on.
+    Hello {
+        account: String,
+        key: String,
+        trusted: Vec<String>,
+        signature: String,
+    },
+    /// The sealed state a device of the account left, stored or just published.
+    State { device: Actor, envelope: String },
+    /// Sent by the relay once it handed over every stored state.
+    Ready,
+    /// Leaves the sealed state of the device for the others.
+    Publish(String),
+    /// Sent by the relay once it stored what the device published.
+    Stored,
+    /// Why the relay turned the device away.
+    Refused(String),
+}
+
+/// Checks that `account` is a name the relay can store states under.
+pub fn validate_account(account: &str) -> Result<()> {
+    let valid = !account.is_empty()
+        && account.len() <= 64
+        && account
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!("Relay accounts are made of up to 64 letters, digits, dashes and underscores.");
+    }
+    Ok(())
+}
+
+/// What a device signs to prove who it is, tied to the account, the challenge and the
+/// devices it trusts.
+fn signed(account: &str, challenge: &str, trusted: &[String]) -> Vec<u8> {
+    format!(
+        "symmetry relay\n{account}\n{challenge}\n{}",
+        trusted.join("\n")
+    )
+    .into_bytes()
+}
+
+fn send<S: Read + Write>(socket: &mut WebSocket<S>, frame: &Frame) -> Result<()> {
+    socket.send(Message::Text(ron::to_string(frame)?))?;
+    Ok(())
+}
+
+/// Waits for the next frame, skipping the control messages the socket answers by itself.
+fn receive<S: Read + Write>(socket: &mut WebSocket<S>) -> Result<Frame> {
+    loop {
+        match socket.read()? {
+            Message::Text(text) => return Ok(ron::from_str(&text)?),
+            Message::Close(_) => return Err(anyhow!("The connection was closed.")),
+            _ => {}
+        }
+    }
+}
+
+/// Like [`receive`], but returns `None` once the read timeout of the socket elapses.
+fn poll<S: Read + Write>(socket: &mut WebSocket<S>) -> Result<Option<Frame>> {
+    match receive(socket) {
+        Ok(frame) => Ok(Some(frame)),
+        Err(err) => match err.downcast_ref::<tungstenite::Error>() {
+            Some(tungstenite::Error::Io(io))
+                if matches!(io.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                Ok(None)
+            }
+            _ => Err(err),
+        },
+    }
+}
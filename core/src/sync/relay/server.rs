@@ -0,0 +1,259 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{ErrorKind, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use log::warn;
+use tungstenite::protocol::WebSocketConfig;
+
+use crate::{
+    sync::{document::Actor, listener::Listener},
+    trust::identity::{device_id, verify},
+};
+
+use super::{poll, receive, send, signed, validate_account, Frame, MAX_DEVICES, MAX_ENVELOPE};
+
+/// How long to wait for a device to answer the challenge.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often connections look for states to forward.
+const POLL: Duration = Duration::from_millis(200);
+/// The largest message a device sends before it's let into its account.
+const HANDSHAKE_MESSAGE: usize = 64 * 1024;
+/// Room around a sealed state for the frame carrying it.
+const FRAME_OVERHEAD: usize = 4 * 1024;
+/// How many devices can be connected at once, over every account.
+const MAX_CONNECTIONS: usize = 256;
+/// File in the directory of an account listing the signing keys of its devices.
+const TRUSTED: &str = "trusted";
+
+/// The devices connected to each account, to forward published states to.
+type Accounts = Arc<Mutex<Connected>>;
+type Connected = HashMap<String, Vec<(Actor, Sender<Frame>)>>;
+
+/// Keeps the sealed state of each device, per account, and forwards it to the other devices
+/// of the account that are connected, until dropped.
+///
+/// A device proves it holds its signing key and can only replace its own state. The first
+/// device to reach an account claims it, after that only the devices the connected ones
+/// trust are let in. Devices still ignore the states of devices they don't trust.
+///
+/// Connections aren't encrypted, the states are sealed end to end already. Put the relay
+/// behind a proxy terminating TLS for devices to reach it at a `wss://` address.
+pub struct Server {
+    listener: Listener,
+}
+
+impl Server {
+    /// Listens on `address`, storing the states under `storage`.
+    pub fn bind(address: impl ToSocketAddrs, storage: impl Into<PathBuf>) -> Result<Self> {
+        let storage = storage.into();
+        std::fs::create_dir_all(&storage)?;
+        let accounts = Accounts::default();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let listener = Listener::bind(address, move |stream, stopped| {
+            // Turned away by closing the connection, before spending a thread on it.
+            if connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::Relaxed);
+                return;
+            }
+            let (storage, accounts, stopped, connections) = (
+                storage.clone(),
+                accounts.clone(),
+                stopped.clone(),
+                connections.clone(),
+            );
+            // Devices stay connected to hear from the others, so each gets a thread.
+            std::thread::spawn(move || {
+                if let Err(err) = serve(stream, &storage, &accounts, &stopped) {
+                    warn!("{err:#}");
+                }
+                connections.fetch_sub(1, Ordering::Relaxed);
+            });
+        })?;
+        Ok(Self { listener })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.listener.address()
+    }
+}
+
+/// Authenticates a device, hands it the stored states of its account, then stores and
+/// forwards what it publishes until it leaves.
+fn serve(stream: TcpStream, storage: &Path, accounts: &Accounts, stop: &AtomicBool) -> Result<()> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let config = WebSocketConfig {
+        max_message_size: Some(HANDSHAKE_MESSAGE),
+        max_frame_size: Some(HANDSHAKE_MESSAGE),
+        ..Default::default()
+    };
+    let mut socket =
+        tungstenite::accept_with_config(stream, Some(config)).map_err(|err| anyhow!("{err}"))?;
+    let mut challenge = [0u8; 32];
+    OsRng.fill_bytes(&mut challenge);
+    let challenge = STANDARD.encode(challenge);
+    send(&mut socket, &Frame::Challenge(challenge.clone()))?;
+    let Frame::Hello {
+        account,
+        key,
+        trusted,
+        signature,
+    } = receive(&mut socket)?
+    else {
+        bail!("The device didn't introduce itself.");
+    };
+    if let Err(err) = validate_account(&account) {
+        return send(&mut socket, &Frame::Refused(err.to_string()));
+    }
+    if verify(&key, &signed(&account, &challenge, &trusted), &signature).is_err() {
+        let reason = "The signature doesn't match the key.".to_string();
+        return send(&mut socket, &Frame::Refused(reason));
+    }
+    let device = device_id(&key);
+    let directory = storage.join(&account);
+    let admitted = {
+        // Devices joining at once take turns, so none misses what another trusts.
+        let _turn = lock(accounts);
+        admit(&directory, &key, &trusted)
+    };
+    if let Err(err) = admitted {
+        return send(&mut socket, &Frame::Refused(err.to_string()));
+    }
+    socket.set_config(|config| {
+        config.max_message_size = Some(MAX_ENVELOPE + FRAME_OVERHEAD);
+        config.max_frame_size = Some(MAX_ENVELOPE + FRAME_OVERHEAD);
+    });
+    for (other, envelope) in stored(&directory)? {
+        if other != device {
+            send(
+                &mut socket,
+                &Frame::State {
+                    device: other,
+                    envelope,
+                },
+            )?;
+        }
+    }
+    send(&mut socket, &Frame::Ready)?;
+
+    let (sender, forwarded) = mpsc::channel();
+    lock(accounts)
+        .entry(account.clone())
+        .or_default()
+        .push((device.clone(), sender));
+    socket.get_mut().set_read_timeout(Some(POLL))?;
+    while !stop.load(Ordering::Relaxed) {
+        match poll(&mut socket) {
+            Ok(Some(Frame::Publish(envelope))) if envelope.len() > MAX_ENVELOPE => {
+                let reason = format!("States are kept up to {MAX_ENVELOPE} bytes.");
+                return send(&mut socket, &Frame::Refused(reason));
+            }
+            Ok(Some(Frame::Publish(envelope))) => {
+                store(&directory, &device, &envelope)?;
+                send(&mut socket, &Frame::Stored)?;
+                let frame = Frame::State {
+                    device: device.clone(),
+                    envelope,
+                };
+                if let Some(connected) = lock(accounts).get_mut(&account) {
+                    // Connections that ended dropped their receiver, and are forgotten here.
+                    connected.retain(|(other, sender)| {
+                        *other == device || sender.send(frame.clone()).is_ok()
+                    });
+                }
+            }
+            Ok(Some(_)) => bail!("The device sent an unexpected message."),
+            Ok(None) => {}
+            // The device went away.
+            Err(_) => break,
+        }
+        for frame in forwarded.try_iter() {
+            send(&mut socket, &frame)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lets the device holding `key` into the account kept in `directory` if the devices there
+/// trust it, or if it's the first to reach the account. Then records the devices it
+/// trusts as the only ones let in, forgetting the states of the others.
+fn admit(directory: &Path, key: &str, trusted: &[String]) -> Result<()> {
+    let path = directory.join(TRUSTED);
+    let known = match std::fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    if !known.is_empty() && !known.lines().any(|other| other == key) {
+        bail!("This device isn't trusted by the devices of the account.");
+    }
+    let mut keys: BTreeSet<&str> = trusted.iter().map(String::as_str).collect();
+    keys.insert(key);
+    if keys.len() > MAX_DEVICES {
+        bail!("Accounts hold up to {MAX_DEVICES} devices.");
+    }
+    if keys
+        .iter()
+        .any(|key| key.is_empty() || key.contains(char::is_whitespace))
+    {
+        bail!("The trusted devices aren't valid signing keys.");
+    }
+    let devices: BTreeSet<String> = keys.iter().map(|key| device_id(key)).collect();
+    for (device, _) in stored(directory)? {
+        if !devices.contains(&device) {
+            std::fs::remove_file(directory.join(format!("{device}.ron")))?;
+        }
+    }
+    let keys: Vec<&str> = keys.into_iter().collect();
+    replace(directory, TRUSTED, &keys.join("\n"))
+}
+
+/// Reads the states stored for an account, keyed by device.
+fn stored(directory: &Path) -> Result<Vec<(Actor, String)>> {
+    let mut states = vec![];
+    if !directory.is_dir() {
+        return Ok(states);
+    }
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("ron") {
+            continue;
+        }
+        if let Some(device) = path.file_stem().and_then(|stem| stem.to_str()) {
+            states.push((device.to_string(), std::fs::read_to_string(&path)?));
+        }
+    }
+    Ok(states)
+}
+
+/// Replaces the state of `device`.
+fn store(directory: &Path, device: &str, envelope: &str) -> Result<()> {
+    replace(directory, &format!("{device}.ron"), envelope)
+}
+
+/// Replaces the file `name`, written aside and moved into place so it's never read half
+/// written.
+fn replace(directory: &Path, name: &str, contents: &str) -> Result<()> {
+    std::fs::create_dir_all(directory)?;
+    let temporary = directory.join(format!(".{name}.tmp"));
+    let mut file = std::fs::File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, directory.join(name))?;
+    Ok(())
+}
+
+fn lock(accounts: &Accounts) -> MutexGuard<'_, Connected> {
+    accounts.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -1,7 +1,40 @@
+use crate::configuration::changes::{Change, Field};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Status {
     UpToDate,
-    ChangesUploaded,
-    NewChangesDetected,
+    /// Local changes were published, up to the given commit.
+    ChangesUploaded {
+        commit: String,
+    },
+    /// Changes were committed but are held back until the batch window passes.
+    ChangesCommitted {
+        commit: String,
+    },
+    /// The remote has changes this device doesn't, touching these configuration fields.
+    NewChangesDetected {
+        changes: Vec<Change>,
+    },
+    /// Remote changes were brought into this device, up to the given commit.
+    ChangesDownloaded {
+        commit: String,
+    },
+    /// Both sides changed the same settings or files differently and nothing was merged.
+    Conflict {
+        fields: Vec<Field>,
+        paths: Vec<String>,
+    },
+    /// Synchronization went through, but these items couldn't be applied to the device,
+    /// or these providers couldn't be reached while others were.
+    PartialSync {
+        failed: Vec<String>,
+    },
+    /// The remote can't be reached, local changes are committed and wait in the outbox.
+    Offline {
+        pending: usize,
+    },
+    /// The remote rejected the credentials.
+    AuthRequired,
     RepoNotConfigured,
     RepoConfigured,
 }
@@ -0,0 +1,576 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::{
+    configuration::{
+        changes::{self, Change, Field},
+        Configuration, CONFIG_FILE,
+    },
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    sync::{
+        self, message::Message, overrides::Overrides, providers::config::git::ConflictStrategy,
+        status::Status,
+    },
+    traits::synchronization::Synchronization,
+};
+
+use super::{Condition, Failure, Store, Version, Written};
+
+/// The object listing the synchronized files, only replaced if unchanged since read.
+pub const MANIFEST: &str = "manifest.ron";
+/// Where the contents of the files are kept, named by their hash so they never change.
+const BLOBS: &str = "blobs";
+/// How many times to start over when another device replaces the manifest first.
+const ATTEMPTS: usize = 5;
+
+/// The synchronized files, as the hash of their contents by path.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Manifest {
+    pub files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Identifies the state of the files, the same on every device holding them.
+    pub fn version(&self) -> String {
+        hash(ron::to_string(self).unwrap_or_default().as_bytes())[..12].to_string()
+    }
+
+    /// Lists the files under `path`, leaving out those of git.
+    pub fn scan(path: &Path) -> Result<Manifest> {
+        let mut files = BTreeMap::new();
+        let entries = WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git");
+        for entry in entries {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(path)?;
+            if !entry.file_type().is_file() || relative == Path::new(".gitignore") {
+                continue;
+            }
+            let parts: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            files.insert(parts.join("/"), hash(&std::fs::read(entry.path())?));
+        }
+        Ok(Manifest { files })
+    }
+
+    /// How many files differ from `other`.
+    pub fn distance(&self, other: &Manifest) -> usize {
+        let paths: BTreeSet<&String> = self.files.keys().chain(other.files.keys()).collect();
+        paths
+            .into_iter()
+            .filter(|path| self.files.get(*path) != other.files.get(*path))
+            .count()
+    }
+}
+
+/// What was last agreed with the remote, kept in the state directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Base {
+    location: String,
+    tag: String,
+    manifest: Manifest,
+}
+
+/// Keeps the synchronized directory of a device in a [`Store`], merging the changes made
+/// on either side since they last agreed.
+///
+/// Files are uploaded under the hash of their contents, then the manifest listing them is
+/// replaced only if no other device replaced it meanwhile. A device losing that race
+/// merges what the other one wrote and tries again.
+pub struct Mirror<S: Store> {
+    store: S,
+    path: PathBuf,
+    context: SymmetryContext,
+    state: PathBuf,
+    /// File in the state directory keeping what was agreed with the store.
+    base: PathBuf,
+    conflicts: ConflictStrategy,
+    metered: MeteredPolicy,
+}
+
+impl<S: Store> Synchronization for Mirror<S> {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Self::Status> {
+        self.attempt(Self::step)
+    }
+
+    fn handle(&self, message: Self::Message) -> Result<Self::Status> {
+        match message {
+            Message::Update => self.attempt(|mirror| mirror.pull(mirror.conflicts, None)),
+            Message::Accept(fields) => {
+                self.attempt(|mirror| mirror.pull(mirror.conflicts, Some(&fields)))
+            }
+            Message::Resolve(strategy) => self.attempt(|mirror| mirror.pull(strategy, None)),
+            Message::Push => self.attempt(Self::push),
+            Message::Reset => self.attempt(Self::reset),
+        }
+    }
+
+    /// Compares the configuration last agreed on with the one in the store.
+    fn preview(&self) -> Result<Vec<Change>> {
+        let (Some(base), Some((remote, _))) = (self.base(), self.remote()?) else {
+            return Ok(vec![]);
+        };
+        self.changes(&base.manifest, &remote)
+    }
+
+    fn metered_policy(&self) -> MeteredPolicy {
+        self.metered
+    }
+}
+
+impl<S: Store> Mirror<S> {
+    /// Mirrors the synchronized directory of `context` in `store`, keeping what was agreed
+    /// with it in the state file `name`.
+    pub fn new(store: S, context: &SymmetryContext, name: &str) -> Self {
+        let state = context.state_path();
+        Self {
+            store,
+            path: context.repo_path(),
+            context: context.clone(),
+            base: state.join(format!("{name}.ron")),
+            state,
+            conflicts: ConflictStrategy::default(),
+            metered: MeteredPolicy::default(),
+        }
+    }
+
+    pub fn set_conflict_strategy(&mut self, conflicts: ConflictStrategy) {
+        self.conflicts = conflicts;
+    }
+
+    pub fn set_metered_policy(&mut self, metered: MeteredPolicy) {
+        self.metered = metered;
+    }
+
+    /// Lists the states of the files the store kept, newest first.
+    pub fn history(&self) -> Result<Vec<Version>> {
+        self.store.versions(MANIFEST)
+    }
+
+    /// Brings the files back to a state listed by [`Mirror::history`] and publishes it as
+    /// the newest one, so other devices take it too.
+    pub fn restore(&self, version: &str) -> Result<Status> {
+        self.attempt(|mirror| mirror.revert(version))
+    }
+
+    /// Runs `step` until another device doesn't replace the manifest in the meantime.
+    fn attempt(&self, step: impl Fn(&Self) -> Result<Option<Status>>) -> Result<Status> {
+        for _ in 0..ATTEMPTS {
+            match step(self) {
+                Ok(Some(status)) => return Ok(status),
+                Ok(None) => continue,
+                Err(err) => return self.failure(err),
+            }
+        }
+        bail!("Other devices kept changing the remote, try again later.")
+    }
+
+    fn step(&self) -> Result<Option<Status>> {
+        sync::capture(&self.context)?;
+        let local = self.scan()?;
+        let base = self.base();
+        let Some((remote, tag)) = self.remote()? else {
+            let status = match base {
+                Some(_) => Status::ChangesUploaded {
+                    commit: local.version(),
+                },
+                None => Status::RepoConfigured,
+            };
+            return self.publish(&local, &Manifest::default(), Condition::Absent, status);
+        };
+        let Some(base) = base else {
+            // First sync on this device, merged as if both started empty so the files only
+            // found here are kept.
+            let merged = self.merge(
+                &Manifest::default(),
+                &local,
+                &remote,
+                tag,
+                self.conflicts,
+                None,
+            )?;
+            return Ok(merged.map(|status| match status {
+                Status::ChangesDownloaded { .. } => Status::RepoConfigured,
+                status => status,
+            }));
+        };
+        if tag == base.tag {
+            if local == base.manifest {
+                return Ok(Some(Status::UpToDate));
+            }
+            let status = Status::ChangesUploaded {
+                commit: local.version(),
+            };
+            return self.publish(&local, &remote, Condition::Matches(tag), status);
+        }
+        if local == base.manifest {
+            return Ok(Some(Status::NewChangesDetected {
+                changes: self.changes(&base.manifest, &remote)?,
+            }));
+        }
+        self.merge(&base.manifest, &local, &remote, tag, self.conflicts, None)
+    }
+
+    /// Brings the remote changes in, settling conflicts with `strategy`, and publishes the
+    /// result. With `fields`, only those configuration fields are taken from the remote
+    /// changes, this device keeps its values of the others without publishing them.
+    fn pull(&self, strategy: ConflictStrategy, fields: Option<&[Field]>) -> Result<Option<Status>> {
+        sync::capture(&self.context)?;
+        let local = self.scan()?;
+        match (self.base(), self.remote()?) {
+            (Some(base), Some((remote, tag))) if tag != base.tag => {
+                self.merge(&base.manifest, &local, &remote, tag, strategy, fields)
+            }
+            (None, Some((remote, tag))) => {
+                self.merge(&Manifest::default(), &local, &remote, tag, strategy, fields)
+            }
+            _ => self.step(),
+        }
+    }
+
+    /// Publishes the local files over whatever the remote holds.
+    fn push(&self) -> Result<Option<Status>> {
+        sync::capture(&self.context)?;
+        let local = self.scan()?;
+        let status = Status::ChangesUploaded {
+            commit: local.version(),
+        };
+        match self.remote()? {
+            Some((remote, tag)) => self.publish(&local, &remote, Condition::Matches(tag), status),
+            None => self.publish(&local, &Manifest::default(), Condition::Absent, status),
+        }
+    }
+
+    /// Drops every local change and takes the files in the remote.
+    fn reset(&self) -> Result<Option<Status>> {
+        let Some((remote, tag)) = self.remote()? else {
+            bail!("The remote has nothing to reset to.");
+        };
+        self.download(&remote, &self.scan()?)?;
+        self.agree(&tag, &self.scan()?)?;
+        Ok(Some(self.applied(Status::ChangesDownloaded {
+            commit: remote.version(),
+        })))
+    }
+
+    fn revert(&self, version: &str) -> Result<Option<Status>> {
+        let Some(object) = self.store.get_version(MANIFEST, version)? else {
+            bail!("The remote doesn't keep version {version}.");
+        };
+        let target: Manifest = ron::from_str(std::str::from_utf8(&object.data)?)
+            .context("The manifest in the remote is damaged.")?;
+        sync::capture(&self.context)?;
+        self.download(&target, &self.scan()?)?;
+        let status = Status::ChangesDownloaded {
+            commit: target.version(),
+        };
+        let published = match self.remote()? {
+            Some((remote, tag)) => self.publish(&target, &remote, Condition::Matches(tag), status),
+            None => self.publish(&target, &Manifest::default(), Condition::Absent, status),
+        }?;
+        Ok(published.map(|status| self.applied(status)))
+    }
+
+    /// Merges the remote files into the local ones against what both last agreed on, the
+    /// configuration field by field. Publishes the result unless it's what the remote
+    /// already holds.
+    fn merge(
+        &self,
+        base: &Manifest,
+        local: &Manifest,
+        remote: &Manifest,
+        tag: String,
+        strategy: ConflictStrategy,
+        fields: Option<&[Field]>,
+    ) -> Result<Option<Status>> {
+        let config_path = self.path.join(CONFIG_FILE);
+        let local_config = Configuration::read_from(&config_path);
+        let mut target = local.clone();
+        let mut merged = None;
+        let mut conflicts = vec![];
+        let paths: BTreeSet<&String> = base
+            .files
+            .keys()
+            .chain(local.files.keys())
+            .chain(remote.files.keys())
+            .collect();
+        for path in paths {
+            let (ours, theirs) = (local.files.get(path), remote.files.get(path));
+            if ours == theirs || theirs == base.files.get(path) {
+                continue;
+            }
+            if ours == base.files.get(path) {
+                take(&mut target, remote, path);
+                continue;
+            }
+            if path == CONFIG_FILE {
+                if let Some(config) = self.merge_configuration(base, remote)? {
+                    merged = Some(config);
+                    continue;
+                }
+            }
+            conflicts.push(path.clone());
+        }
+        if !conflicts.is_empty() {
+            match strategy {
+                ConflictStrategy::Ask => {
+                    return Ok(Some(self.conflict_status(base, remote, conflicts)?));
+                }
+                ConflictStrategy::Theirs => {
+                    for path in &conflicts {
+                        take(&mut target, remote, path);
+                    }
+                }
+                ConflictStrategy::Ours => {}
+            }
+        }
+
+        self.download(&target, local)?;
+        if let Some(config) = merged {
+            config.write_to(&config_path)?;
+        }
+        if let (Some(fields), Some(local_config)) = (fields, local_config) {
+            let rejected: Vec<Field> = self
+                .changes(base, remote)?
+                .into_iter()
+                .map(|change| change.field)
+                .filter(|field| !fields.contains(field))
+                .collect();
+            Overrides::decide(&self.state, fields, &rejected, &local_config)?;
+        }
+
+        let result = self.scan()?;
+        let status = Status::ChangesDownloaded {
+            commit: result.version(),
+        };
+        if result == *remote {
+            self.agree(&tag, &result)?;
+            return Ok(Some(self.applied(status)));
+        }
+        let published = self.publish(&result, remote, Condition::Matches(tag), status)?;
+        Ok(published.map(|status| self.applied(status)))
+    }
+
+    /// Merges the configuration in the remote with the local one, if they don't conflict.
+    fn merge_configuration(
+        &self,
+        base: &Manifest,
+        remote: &Manifest,
+    ) -> Result<Option<Configuration>> {
+        let local = Configuration::read_from(&self.path.join(CONFIG_FILE));
+        match (
+            self.base_configuration(base)?,
+            local,
+            self.configuration(remote)?,
+        ) {
+            (Some(base), Some(local), Some(remote)) => {
+                Ok(Configuration::merge(&base, &local, &remote))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Describes a conflict, including the configuration fields both sides changed differently.
+    fn conflict_status(
+        &self,
+        base: &Manifest,
+        remote: &Manifest,
+        paths: Vec<String>,
+    ) -> Result<Status> {
+        let local = Configuration::read_from(&self.path.join(CONFIG_FILE));
+        let fields = match (
+            self.base_configuration(base)?,
+            local,
+            self.configuration(remote)?,
+        ) {
+            (Some(base), Some(local), Some(remote)) => changes::conflicts(&base, &local, &remote),
+            _ => vec![],
+        };
+        Ok(Status::Conflict { fields, paths })
+    }
+
+    /// Lists the configuration changes from the files of `base` to those of `remote`.
+    fn changes(&self, base: &Manifest, remote: &Manifest) -> Result<Vec<Change>> {
+        match (self.configuration(base)?, self.configuration(remote)?) {
+            (Some(base), Some(remote)) => Ok(base.changes(&remote)),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Uploads the files `manifest` lists that `known` doesn't, then replaces the manifest
+    /// in the store provided it meets `condition`. Returns `None` if another device
+    /// replaced it first.
+    fn publish(
+        &self,
+        manifest: &Manifest,
+        known: &Manifest,
+        condition: Condition,
+        status: Status,
+    ) -> Result<Option<Status>> {
+        let uploaded: BTreeSet<&String> = known.files.values().collect();
+        for (path, hash) in &manifest.files {
+            if uploaded.contains(hash) {
+                continue;
+            }
+            let data = std::fs::read(self.inside(path)?)?;
+            // Another device may have uploaded the same contents, which is just as good.
+            self.store
+                .put(&format!("{BLOBS}/{hash}"), &data, Condition::Absent)?;
+        }
+        let data = ron::ser::to_string_pretty(manifest, PrettyConfig::new())?;
+        match self.store.put(MANIFEST, data.as_bytes(), condition)? {
+            Written::Stored(tag) => {
+                self.agree(&tag, manifest)?;
+                Ok(Some(status))
+            }
+            Written::Changed => Ok(None),
+        }
+    }
+
+    /// Writes the files of `target` that differ from `current` into the synchronized
+    /// directory, and removes those it doesn't list.
+    fn download(&self, target: &Manifest, current: &Manifest) -> Result<()> {
+        for (path, hash) in &target.files {
+            if current.files.get(path) == Some(hash) {
+                continue;
+            }
+            let file = self.inside(path)?;
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(file, self.blob(hash)?)?;
+        }
+        for path in current.files.keys() {
+            if target.files.contains_key(path) {
+                continue;
+            }
+            match std::fs::remove_file(self.inside(path)?) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports the store being out of reach or refusing the credentials as such.
+    fn failure(&self, err: anyhow::Error) -> Result<Status> {
+        match err.downcast_ref::<Failure>() {
+            Some(Failure::Unauthorized) => Ok(Status::AuthRequired),
+            Some(Failure::Unreachable(_)) => {
+                let base = self.base().unwrap_or_default();
+                let pending = self.scan()?.distance(&base.manifest);
+                Ok(Status::Offline { pending })
+            }
+            None => Err(err),
+        }
+    }
+
+    /// Writes the desktop settings and tracked files back to the device, reporting what
+    /// couldn't be applied.
+    fn applied(&self, status: Status) -> Status {
+        let failed = sync::apply(&self.context);
+        if !failed.is_empty() {
+            return Status::PartialSync { failed };
+        }
+        status
+    }
+
+    /// Lists the synchronized files on this device.
+    fn scan(&self) -> Result<Manifest> {
+        std::fs::create_dir_all(&self.path)?;
+        Manifest::scan(&self.path)
+    }
+
+    /// Reads the manifest in the store and its tag.
+    fn remote(&self) -> Result<Option<(Manifest, String)>> {
+        let Some(object) = self.store.get(MANIFEST)? else {
+            return Ok(None);
+        };
+        let manifest = ron::from_str(std::str::from_utf8(&object.data)?)
+            .context("The manifest in the remote is damaged.")?;
+        Ok(Some((manifest, object.tag)))
+    }
+
+    /// Reads the contents with the given hash from the store, checking they match it.
+    fn blob(&self, hash: &str) -> Result<Vec<u8>> {
+        let Some(object) = self.store.get(&format!("{BLOBS}/{hash}"))? else {
+            bail!("The remote is missing the contents of {hash}.");
+        };
+        if self::hash(&object.data) != hash {
+            bail!("The contents of {hash} in the remote are damaged.");
+        }
+        Ok(object.data)
+    }
+
+    /// Reads the configuration listed in `manifest` from the store.
+    fn configuration(&self, manifest: &Manifest) -> Result<Option<Configuration>> {
+        let Some(hash) = manifest.files.get(CONFIG_FILE) else {
+            return Ok(None);
+        };
+        let data = self.blob(hash)?;
+        Ok(ron::from_str(std::str::from_utf8(&data)?).ok())
+    }
+
+    /// Reads the configuration `base` lists, the default one if it lists none as happens
+    /// before a device first syncs.
+    fn base_configuration(&self, base: &Manifest) -> Result<Option<Configuration>> {
+        Ok(Some(self.configuration(base)?.unwrap_or_default()))
+    }
+
+    /// What was last agreed with this store, if anything.
+    fn base(&self) -> Option<Base> {
+        let data = std::fs::read_to_string(&self.base).ok()?;
+        let base: Base = ron::from_str(&data).ok()?;
+        (base.location == self.store.location()).then_some(base)
+    }
+
+    fn agree(&self, tag: &str, manifest: &Manifest) -> Result<()> {
+        let base = Base {
+            location: self.store.location(),
+            tag: tag.to_string(),
+            manifest: manifest.clone(),
+        };
+        std::fs::create_dir_all(&self.state).context("Failed to create the state directory.")?;
+        std::fs::write(
+            &self.base,
+            ron::ser::to_string_pretty(&base, PrettyConfig::new())?,
+        )?;
+        Ok(())
+    }
+
+    /// Resolves a path from a manifest in the synchronized directory.
+    fn inside(&self, path: &str) -> Result<PathBuf> {
+        sync::inside(&self.path, path)
+    }
+}
+
+/// Makes `target` hold the version of `path` in `source`, or not hold it at all.
+fn take(target: &mut Manifest, source: &Manifest, path: &str) {
+    match source.files.get(path) {
+        Some(hash) => target.files.insert(path.to_string(), hash.clone()),
+        None => target.files.remove(path),
+    };
+}
+
+fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,83 @@
+pub mod mirror;
+pub mod s3;
+pub mod webdav;
+
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::Result;
+
+/// A remote keeping whole objects by path, each with a tag that changes whenever it's
+/// written, such as a WebDAV server or object storage.
+pub trait Store {
+    /// Identifies the remote, so what was agreed with another one isn't mixed up with it.
+    fn location(&self) -> String;
+
+    /// Reads an object and its tag, `None` if there's none at `path`.
+    fn get(&self, path: &str) -> Result<Option<Object>>;
+
+    /// Writes an object, provided what's at `path` still meets `condition`.
+    fn put(&self, path: &str, data: &[u8], condition: Condition) -> Result<Written>;
+
+    /// Lists the versions the remote kept of the object at `path`, newest first. Remotes
+    /// that don't keep older versions list none.
+    fn versions(&self, _path: &str) -> Result<Vec<Version>> {
+        Ok(vec![])
+    }
+
+    /// Reads a version of an object listed by [`Store::versions`].
+    fn get_version(&self, _path: &str, _version: &str) -> Result<Option<Object>> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Object {
+    pub data: Vec<u8>,
+    pub tag: String,
+}
+
+/// A version of an object kept by the remote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub id: String,
+    /// When it was written, as the remote reports it.
+    pub modified: String,
+    /// Whether it's the current version.
+    pub latest: bool,
+}
+
+/// What a write expects to replace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// Nothing, the object is new.
+    Absent,
+    /// The version with this tag.
+    Matches(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Written {
+    /// The object was written and now has this tag.
+    Stored(String),
+    /// Someone else wrote it first, nothing was written.
+    Changed,
+}
+
+/// Why a remote couldn't be used, for the provider to report the right status.
+#[derive(Debug)]
+pub enum Failure {
+    /// The remote rejected the credentials.
+    Unauthorized,
+    Unreachable(String),
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Unauthorized => write!(f, "The remote rejected the credentials."),
+            Failure::Unreachable(reason) => write!(f, "The remote can't be reached: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
@@ -0,0 +1,315 @@
+use std::{
+    io::Read,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use ureq::{Agent, AgentBuilder, Request};
+
+use crate::sync;
+
+use super::{Condition, Failure, Object, Store, Version, Written};
+
+/// How long to wait for the endpoint before considering it unreachable.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Characters left as is in the paths and queries of signed requests.
+const UNRESERVED: &[u8] = b"-_.~";
+
+static VERSION: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<Version>(.*?)</Version>").unwrap());
+
+/// Credentials of an S3 user, or of a MinIO one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// A bucket of an S3-compatible object storage, such as AWS S3 or MinIO, with the objects
+/// kept under a prefix.
+///
+/// Writes are made conditional with `If-Match` and `If-None-Match` on the ETags of the
+/// objects. With versioning enabled on the bucket, the older versions of each object are
+/// kept and can be read back.
+pub struct S3 {
+    endpoint: String,
+    bucket: String,
+    prefix: String,
+    region: String,
+    credentials: Credentials,
+    agent: Agent,
+}
+
+/// What a request is signed with, besides the credentials.
+pub struct Signed<'a> {
+    pub method: &'a str,
+    /// The path of the request, not encoded yet.
+    pub path: &'a str,
+    pub query: &'a [(&'a str, &'a str)],
+    /// The headers signed, other than those of the signature.
+    pub headers: &'a [(&'a str, &'a str)],
+    pub payload: &'a [u8],
+}
+
+impl S3 {
+    /// Uses the objects under `prefix` in `bucket`, reached at `endpoint` by path so any
+    /// S3-compatible storage works, e.g. `https://s3.eu-west-1.amazonaws.com` or
+    /// `http://localhost:9000`.
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        prefix: &str,
+        region: &str,
+        credentials: Credentials,
+    ) -> Self {
+        let prefix = prefix.trim_matches('/');
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket: bucket.to_string(),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{prefix}/")
+            },
+            region: region.to_string(),
+            credentials,
+            agent: AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Signs a request for the object at `path`, or for the bucket itself when empty, to
+    /// send with `payload`.
+    fn request(&self, method: &str, path: &str, query: &[(&str, &str)], payload: &[u8]) -> Request {
+        let path = match path {
+            "" => format!("/{}", self.bucket),
+            path => format!("/{}/{}{path}", self.bucket, self.prefix),
+        };
+        let hash = hex(&Sha256::digest(payload));
+        let date = sync::timestamp(SystemTime::now());
+        let headers = [
+            ("host", host(&self.endpoint)),
+            ("x-amz-content-sha256", hash.as_str()),
+            ("x-amz-date", date.as_str()),
+        ];
+        let authorization = authorization(
+            &self.credentials,
+            &self.region,
+            &Signed {
+                method,
+                path: &path,
+                query,
+                headers: &headers,
+                payload,
+            },
+        );
+        let mut url = format!("{}{}", self.endpoint, encode(&path, false));
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&canonical_query(query));
+        }
+        self.agent
+            .request(method, &url)
+            .set("x-amz-content-sha256", &hash)
+            .set("x-amz-date", &date)
+            .set("Authorization", &authorization)
+    }
+
+    /// Reads an object, or a version of it, `None` if there's no such object.
+    fn read(&self, path: &str, query: &[(&str, &str)]) -> Result<Option<Object>> {
+        let response = match self.request("GET", path, query, &[]).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(err) => return Err(failure(err)),
+        };
+        let Some(tag) = response.header("ETag").map(String::from) else {
+            bail!("The object storage doesn't hand out ETags.");
+        };
+        let mut data = vec![];
+        response.into_reader().read_to_end(&mut data)?;
+        Ok(Some(Object { data, tag }))
+    }
+}
+
+impl Store for S3 {
+    fn location(&self) -> String {
+        format!("{}/{}/{}", self.endpoint, self.bucket, self.prefix)
+    }
+
+    fn get(&self, path: &str) -> Result<Option<Object>> {
+        self.read(path, &[])
+    }
+
+    fn put(&self, path: &str, data: &[u8], condition: Condition) -> Result<Written> {
+        let request = self.request("PUT", path, &[], data);
+        let request = match &condition {
+            Condition::Absent => request.set("If-None-Match", "*"),
+            Condition::Matches(tag) => request.set("If-Match", tag),
+        };
+        match request.send_bytes(data) {
+            Ok(response) => match response.header("ETag") {
+                Some(tag) => Ok(Written::Stored(tag.to_string())),
+                None => bail!("The object storage doesn't hand out ETags."),
+            },
+            // A concurrent write with a condition on the same object can also end in 409.
+            Err(ureq::Error::Status(409 | 412, _)) => Ok(Written::Changed),
+            Err(err) => Err(failure(err)),
+        }
+    }
+
+    fn versions(&self, path: &str) -> Result<Vec<Version>> {
+        let key = format!("{}{path}", self.prefix);
+        let mut versions = vec![];
+        // Listings stop after a page of versions, the next one picking up at the markers.
+        let mut markers: Option<(String, String)> = None;
+        loop {
+            let mut query = vec![("prefix", key.as_str()), ("versions", "")];
+            if let Some((key_marker, version_marker)) = &markers {
+                query.push(("key-marker", key_marker));
+                query.push(("version-id-marker", version_marker));
+            }
+            let response = self
+                .request("GET", "", &query, &[])
+                .call()
+                .map_err(failure)?;
+            let listing = response.into_string()?;
+            versions.extend(
+                VERSION
+                    .captures_iter(&listing)
+                    .map(|version| version[1].to_string())
+                    .filter(|version| element(version, "Key").as_deref() == Some(key.as_str()))
+                    .filter_map(|version| {
+                        Some(Version {
+                            id: element(&version, "VersionId")?,
+                            modified: element(&version, "LastModified")?,
+                            latest: element(&version, "IsLatest").as_deref() == Some("true"),
+                        })
+                    }),
+            );
+            if element(&listing, "IsTruncated").as_deref() != Some("true") {
+                break;
+            }
+            let next =
+                element(&listing, "NextKeyMarker").zip(element(&listing, "NextVersionIdMarker"));
+            if next.is_none() || next == markers {
+                bail!("The object storage didn't say where the list of versions goes on.");
+            }
+            markers = next;
+        }
+        versions.sort_by(|a, b| b.modified.cmp(&a.modified));
+        Ok(versions)
+    }
+
+    fn get_version(&self, path: &str, version: &str) -> Result<Option<Object>> {
+        self.read(path, &[("versionId", version)])
+    }
+}
+
+/// Signs a request with AWS Signature Version 4, returning its `Authorization` header.
+pub fn authorization(credentials: &Credentials, region: &str, request: &Signed) -> String {
+    let date = request
+        .headers
+        .iter()
+        .find(|(name, _)| *name == "x-amz-date")
+        .map(|(_, value)| *value)
+        .unwrap_or_default();
+    let day = &date[..date.len().min(8)];
+    let scope = format!("{day}/{region}/s3/aws4_request");
+    let mut headers: Vec<(String, &str)> = request
+        .headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim()))
+        .collect();
+    headers.sort();
+    let signed_headers: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+    let signed_headers = signed_headers.join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{value}\n"))
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{}",
+        request.method,
+        encode(request.path, false),
+        canonical_query(request.query),
+        hex(&Sha256::digest(request.payload)),
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{date}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [day, region, "s3", "aws4_request"].into_iter().fold(
+        format!("AWS4{}", credentials.secret_key).into_bytes(),
+        |key, part| mac(&key, part.as_bytes()),
+    );
+    let signature = hex(&mac(&key, string_to_sign.as_bytes()));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+        credentials.access_key
+    )
+}
+
+/// Tells rejected credentials and unreachable endpoints apart from other errors.
+fn failure(err: ureq::Error) -> anyhow::Error {
+    match err {
+        ureq::Error::Status(401 | 403, _) => Failure::Unauthorized.into(),
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let reason = element(&body, "Message").unwrap_or(body);
+            anyhow!("The object storage answered {code}: {reason}")
+        }
+        ureq::Error::Transport(transport) => Failure::Unreachable(transport.to_string()).into(),
+    }
+}
+
+/// The text of the first `name` element in an XML document.
+fn element(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{name}>"))?;
+    Some(xml[start..end].to_string())
+}
+
+/// The host of an endpoint, with its port, as sent in the `Host` header.
+fn host(endpoint: &str) -> &str {
+    let host = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    host.split('/').next().unwrap_or_default()
+}
+
+fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut query: Vec<String> = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name, true), encode(value, true)))
+        .collect();
+    query.sort();
+    query.join("&")
+}
+
+/// Percent-encodes everything but unreserved characters, and slashes unless `slashes`.
+fn encode(text: &str, slashes: bool) -> String {
+    text.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric()
+                || UNRESERVED.contains(&byte)
+                || byte == b'/' && !slashes
+            {
+                (byte as char).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
+
+fn mac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,150 @@
+use std::{io::Read, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use ureq::{Agent, AgentBuilder, Request, Response};
+
+use super::{Condition, Failure, Object, Store, Written};
+
+/// How long to wait for the server before considering it unreachable.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// What's encoded in each segment of a path: everything but the unreserved characters.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A collection on a WebDAV server, such as a folder in Nextcloud or ownCloud.
+///
+/// Writes are made conditional with `If-Match` and `If-None-Match` on the ETags the server
+/// hands out, so a device never overwrites what it hasn't seen.
+pub struct WebDav {
+    url: String,
+    authorization: Option<String>,
+    agent: Agent,
+}
+
+impl WebDav {
+    /// Uses the collection at `url`, authenticating with basic credentials when given a
+    /// password, usually an app password.
+    pub fn new(url: &str, username: &str, password: Option<&str>) -> Self {
+        let authorization = password.map(|password| {
+            format!(
+                "Basic {}",
+                STANDARD.encode(format!("{username}:{password}"))
+            )
+        });
+        Self {
+            url: format!("{}/", url.trim_end_matches('/')),
+            authorization,
+            agent: AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> Request {
+        let path: Vec<String> = path
+            .split('/')
+            .map(|segment| utf8_percent_encode(segment, SEGMENT).to_string())
+            .collect();
+        let url = format!("{}{}", self.url, path.join("/"));
+        let request = self.agent.request(method, &url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+
+    /// Creates the collection and those leading to `path` within it, those already there
+    /// are left as is.
+    fn create_collections(&self, path: &str) -> Result<()> {
+        let parts: Vec<&str> = path.split('/').collect();
+        let mut collection = String::new();
+        let mut collections = vec![collection.clone()];
+        for part in &parts[..parts.len() - 1] {
+            collection.push_str(part);
+            collection.push('/');
+            collections.push(collection.clone());
+        }
+        for collection in collections {
+            match self.request("MKCOL", &collection).call() {
+                Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+                Err(err) => return Err(failure(err)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the tag of what's at `path`, for servers that don't return it on writes.
+    fn tag(&self, path: &str) -> Result<String> {
+        let response = self.request("HEAD", path).call().map_err(failure)?;
+        etag(&response).ok_or_else(|| anyhow!("The WebDAV server doesn't hand out ETags."))
+    }
+
+    /// Writes an object, `None` if the collection holding it is missing.
+    fn write(&self, path: &str, data: &[u8], condition: &Condition) -> Result<Option<Written>> {
+        let request = self.request("PUT", path);
+        let request = match condition {
+            Condition::Absent => request.set("If-None-Match", "*"),
+            Condition::Matches(tag) => request.set("If-Match", tag),
+        };
+        match request.send_bytes(data) {
+            Ok(response) => match etag(&response) {
+                Some(tag) => Ok(Some(Written::Stored(tag))),
+                None => Ok(Some(Written::Stored(self.tag(path)?))),
+            },
+            Err(ureq::Error::Status(412, _)) => Ok(Some(Written::Changed)),
+            Err(ureq::Error::Status(409, _)) => Ok(None),
+            Err(err) => Err(failure(err)),
+        }
+    }
+}
+
+impl Store for WebDav {
+    fn location(&self) -> String {
+        self.url.clone()
+    }
+
+    fn get(&self, path: &str) -> Result<Option<Object>> {
+        let response = match self.request("GET", path).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(err) => return Err(failure(err)),
+        };
+        let Some(tag) = etag(&response) else {
+            bail!("The WebDAV server doesn't hand out ETags.");
+        };
+        let mut data = vec![];
+        response.into_reader().read_to_end(&mut data)?;
+        Ok(Some(Object { data, tag }))
+    }
+
+    fn put(&self, path: &str, data: &[u8], condition: Condition) -> Result<Written> {
+        if let Some(written) = self.write(path, data, &condition)? {
+            return Ok(written);
+        }
+        // The collection holding the object doesn't exist yet.
+        self.create_collections(path)?;
+        self.write(path, data, &condition)?
+            .ok_or_else(|| anyhow!("The WebDAV server can't hold {path}."))
+    }
+}
+
+fn etag(response: &Response) -> Option<String> {
+    response.header("ETag").map(String::from)
+}
+
+/// Tells rejected credentials and unreachable servers apart from other errors.
+fn failure(err: ureq::Error) -> anyhow::Error {
+    match err {
+        ureq::Error::Status(401 | 403, _) => Failure::Unauthorized.into(),
+        ureq::Error::Status(code, response) => {
+            anyhow!(
+                "The WebDAV server answered {code} {}",
+                response.status_text()
+            )
+        }
+        ureq::Error::Transport(transport) => Failure::Unreachable(transport.to_string()).into(),
+    }
+}
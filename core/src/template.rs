@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+
+use crate::{configuration::Configuration, context::SymmetryContext, desktop::Entries};
+
+/// Values available to templates, such as `{{home}}` or `{{device.name}}`.
+///
+/// Templates support variable substitution and conditionals:
+/// ```text
+/// {{#if os == "linux"}}editor = nvim{{else}}editor = code{{/if}}
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Variables(BTreeMap<String, String>);
+
+/// A conditional block being rendered.
+struct Block {
+    /// Whether the enclosing block is being rendered.
+    parent: bool,
+    condition: bool,
+    in_else: bool,
+}
+
+impl Block {
+    fn active(&self) -> bool {
+        self.parent && (self.condition != self.in_else)
+    }
+}
+
+impl Variables {
+    /// Collects the variables of the device of `context`, including the custom
+    /// variables defined for it in the configuration as `device.<name>`.
+    pub fn new(context: &SymmetryContext, config: &Configuration) -> Self {
+        let device = context.device();
+        let mut variables = BTreeMap::new();
+        if let Ok(home) = context.home_dir() {
+            variables.insert("home".into(), home.to_string_lossy().to_string());
+        }
+        variables.insert(
+            "hostname".into(),
+            gethostname::gethostname().to_string_lossy().to_string(),
+        );
+        variables.insert("os".into(), std::env::consts::OS.into());
+        variables.insert(
+            "desktop".into(),
+            std::env::var("XDG_CURRENT_DESKTOP")
+                .unwrap_or_default()
+                .to_lowercase(),
+        );
+        variables.insert("device.name".into(), device.name.clone());
+        if let Some(custom) = config.variables.get(&device.name) {
+            for (name, value) in custom {
+                variables.insert(format!("device.{name}"), value.clone());
+            }
+        }
+        Self(variables)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.0.get(name)
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(name.into(), value.into());
+    }
+
+    /// Renders a template, failing on unknown variables or unbalanced blocks.
+    pub fn render(&self, template: &str) -> Result<String> {
+        let mut output = String::new();
+        let mut blocks: Vec<Block> = vec![];
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            let active = blocks.last().map(Block::active).unwrap_or(true);
+            if active {
+                output.push_str(&rest[..start]);
+            }
+            let end = rest[start..].find("}}").context("Unclosed template tag.")? + start;
+            let tag = rest[start + 2..end].trim();
+            rest = &rest[end + 2..];
+
+            if let Some(condition) = tag.strip_prefix("#if ") {
+                blocks.push(Block {
+                    parent: active,
+                    condition: self.evaluate(condition)?,
+                    in_else: false,
+                });
+            } else if tag == "else" {
+                let block = blocks
+                    .last_mut()
+                    .context("`else` outside of an `if` block.")?;
+                block.in_else = true;
+            } else if tag == "/if" {
+                blocks.pop().context("`/if` without a matching `if`.")?;
+            } else if active {
+                let value = self
+                    .get(tag)
+                    .with_context(|| format!("Unknown template variable `{tag}`."))?;
+                output.push_str(value);
+            }
+        }
+
+        if !blocks.is_empty() {
+            bail!("Unclosed `if` block.");
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Puts the templates in `stored` back wherever `read` holds what they render to, so
+    /// settings read back after being applied don't replace their templates.
+    pub fn keep_templates(&self, read: &mut Entries, stored: &Entries) {
+        for (key, value) in read.iter_mut() {
+            if let Some(template) = stored.get(key) {
+                if self
+                    .render(template)
+                    .is_ok_and(|rendered| rendered == *value)
+                {
+                    value.clone_from(template);
+                }
+            }
+        }
+    }
+
+    /// Evaluates `name == "value"`, `name != "value"`, or `name`, which is true
+    /// when the variable is set and not empty.
+    fn evaluate(&self, condition: &str) -> Result<bool> {
+        let parse = |operator: &str| -> Option<(String, String)> {
+            let (name, value) = condition.split_once(operator)?;
+            Some((name.trim().into(), value.trim().trim_matches('"').into()))
+        };
+        if let Some((name, value)) = parse("==") {
+            Ok(self.get(&name).is_some_and(|current| *current == value))
+        } else if let Some((name, value)) = parse("!=") {
+            Ok(self
+                .get(&name)
+                .map(|current| *current != value)
+                .unwrap_or(true))
+        } else {
+            Ok(self
+                .get(condition.trim())
+                .is_some_and(|value| !value.is_empty()))
+        }
+    }
+}
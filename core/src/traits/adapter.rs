@@ -0,0 +1,11 @@
+use anyhow::Result;
+
+use crate::desktop::Entries;
+
+pub trait Adapter {
+    /// Reads the entries this adapter is responsible for from the desktop.
+    fn read(&self) -> Result<Entries>;
+
+    /// Writes the given entries back to the desktop, leaving unrelated settings untouched.
+    fn write(&self, entries: &Entries) -> Result<()>;
+}
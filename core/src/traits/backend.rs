@@ -0,0 +1,64 @@
+use anyhow::Result;
+
+use crate::{
+    context::SymmetryContext,
+    sync::providers::{
+        config::Services,
+        registry::{Setting, ENABLED},
+        Provider,
+    },
+};
+
+/// A sync backend, as registered with the [registry](crate::sync::providers::registry).
+///
+/// Settings are exchanged as text so the Services page can edit any backend the same way.
+/// Backends from other crates keep theirs in [`Services::custom`] through the default
+/// accessors, the built-in ones map them onto their own configuration.
+pub trait Backend: Send + Sync {
+    /// Identifies the backend in the configuration, so it must never change.
+    fn name(&self) -> &'static str;
+
+    /// The name of the backend, as shown to the user.
+    fn title(&self) -> &'static str;
+
+    /// What the backend is good for, in a sentence.
+    fn description(&self) -> &'static str;
+
+    /// The settings the Services page asks for, in order.
+    fn schema(&self) -> Vec<Setting>;
+
+    /// Whether the backend syncs devices as changes happen, so it runs before those that
+    /// keep a history.
+    fn realtime(&self) -> bool {
+        false
+    }
+
+    /// Reads a setting from the configuration.
+    fn get(&self, services: &Services, key: &str) -> Option<String> {
+        services.custom.get(self.name())?.get(key).cloned()
+    }
+
+    /// Stores a setting in the configuration, failing if the value doesn't suit it.
+    fn set(&self, services: &mut Services, key: &str, value: &str) -> Result<()> {
+        if key != ENABLED {
+            Setting::find(&self.schema(), key)?.check(value)?;
+        }
+        services
+            .custom
+            .entry(self.name().to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Opens the provider for the given context, configured from it.
+    fn open(&self, context: &SymmetryContext) -> Result<Provider>;
+
+    fn enabled(&self, services: &Services) -> bool {
+        self.get(services, ENABLED).as_deref() == Some("true")
+    }
+
+    fn set_enabled(&self, services: &mut Services, enabled: bool) -> Result<()> {
+        self.set(services, ENABLED, &enabled.to_string())
+    }
+}
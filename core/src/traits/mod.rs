@@ -1 +1,3 @@
+pub mod adapter;
+pub mod backend;
 pub mod synchronization;
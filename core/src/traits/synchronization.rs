@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use anyhow::Result;
 
+use crate::{configuration::changes::Change, network::MeteredPolicy};
+
 pub trait Synchronization {
     /// The status of the synchronization.
     type Status;
@@ -10,6 +14,40 @@ pub trait Synchronization {
     /// Afterwards it returns a status defined by the user.
     fn sync(&self) -> Result<Self::Status>;
 
-    /// A way to manage internal logic.
-    fn handle(&self, message: Self::Message) -> Result<()>;
+    /// A way to manage internal logic, returning the resulting status.
+    fn handle(&self, message: Self::Message) -> Result<Self::Status>;
+
+    /// Lists the configuration changes that updating would bring in, so they can be reviewed.
+    fn preview(&self) -> Result<Vec<Change>> {
+        Ok(vec![])
+    }
+
+    /// Whether background syncs may run while the device is on a metered link.
+    fn metered_policy(&self) -> MeteredPolicy {
+        MeteredPolicy::default()
+    }
+
+    /// Synchronizes what may run while the device is on a metered link, as allowed by
+    /// [`Synchronization::metered_policy`].
+    fn sync_metered(&self) -> Result<Self::Status> {
+        self.sync()
+    }
+
+    /// Whether syncing needs the network. Providers that only reach local disks, such as
+    /// a folder or a USB drive, keep running while the device is offline.
+    fn requires_network(&self) -> bool {
+        true
+    }
+
+    /// Synchronizes what may run while the device is offline, as allowed by
+    /// [`Synchronization::requires_network`].
+    fn sync_offline(&self) -> Result<Self::Status> {
+        self.sync()
+    }
+
+    /// How long until changes held back, such as a batch of commits, are due to be
+    /// published by the next sync. `None` when nothing is held back.
+    fn pending(&self) -> Option<Duration> {
+        None
+    }
 }
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::device::Device;
+
+use super::TrustedDevice;
+
+/// Where the keys of this device are kept, in the state directory.
+pub const IDENTITY_FILE: &str = "identity.ron";
+
+/// The keys of this device, which never leave it.
+///
+/// The signing key proves who the device is to the others, the agreement key lets them
+/// seal things only this device can open.
+pub struct Identity {
+    signing: SigningKey,
+    agreement: StaticSecret,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    signing: String,
+    agreement: String,
+}
+
+impl Identity {
+    /// Loads the identity kept in `state`, generating one on first use.
+    pub fn load(state: &Path) -> Result<Self> {
+        let path = state.join(IDENTITY_FILE);
+        if let Ok(data) = std::fs::read_to_string(&path) {
+            let stored: Stored = ron::from_str(&data)
+                .with_context(|| format!("The identity in {} is corrupted.", path.display()))?;
+            return Ok(Self {
+                signing: SigningKey::from_bytes(&decode(&stored.signing)?),
+                agreement: StaticSecret::from(decode(&stored.agreement)?),
+            });
+        }
+        let identity = Self::generate();
+        let stored = Stored {
+            signing: STANDARD.encode(identity.signing.to_bytes()),
+            agreement: STANDARD.encode(identity.agreement.to_bytes()),
+        };
+        std::fs::create_dir_all(state)?;
+        write_private(&path, ron::to_string(&stored)?.as_bytes())?;
+        Ok(identity)
+    }
+
+    fn generate() -> Self {
+        let mut signing = [0u8; 32];
+        let mut agreement = [0u8; 32];
+        OsRng.fill_bytes(&mut signing);
+        OsRng.fill_bytes(&mut agreement);
+        Self {
+            signing: SigningKey::from_bytes(&signing),
+            agreement: StaticSecret::from(agreement),
+        }
+    }
+
+    /// A short identifier derived from the signing key.
+    pub fn id(&self) -> String {
+        device_id(&self.signing_key())
+    }
+
+    /// The public signing key, base64 encoded.
+    pub fn signing_key(&self) -> String {
+        STANDARD.encode(self.signing.verifying_key().as_bytes())
+    }
+
+    /// The public agreement key, base64 encoded.
+    pub fn agreement_key(&self) -> String {
+        STANDARD.encode(PublicKey::from(&self.agreement).as_bytes())
+    }
+
+    /// Describes this device, so others can trust it.
+    pub fn device(&self) -> TrustedDevice {
+        TrustedDevice {
+            name: Device::current().name,
+            signing_key: self.signing_key(),
+            agreement_key: self.agreement_key(),
+        }
+    }
+
+    /// Signs `message`, returning the base64 encoded signature.
+    pub fn sign(&self, message: &[u8]) -> String {
+        STANDARD.encode(self.signing.sign(message).to_bytes())
+    }
+
+    pub(crate) fn agreement(&self) -> &StaticSecret {
+        &self.agreement
+    }
+}
+
+/// The identifier of the device holding the base64 encoded `signing_key`.
+pub fn device_id(signing_key: &str) -> String {
+    Sha256::digest(signing_key.as_bytes())[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Checks that `signature` was made over `message` by the holder of `signing_key`.
+pub fn verify(signing_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let key = VerifyingKey::from_bytes(&decode(signing_key)?)?;
+    let signature = Signature::from_bytes(
+        &STANDARD
+            .decode(signature)?
+            .try_into()
+            .map_err(|_| anyhow!("The signature is malformed."))?,
+    );
+    key.verify(message, &signature)
+        .map_err(|_| anyhow!("The signature doesn't match."))
+}
+
+pub(crate) fn decode(key: &str) -> Result<[u8; 32]> {
+    STANDARD
+        .decode(key.trim())?
+        .try_into()
+        .map_err(|_| anyhow!("The key must be 32 bytes long."))
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)?;
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::net::SocketAddr;
+
+use anyhow::{anyhow, bail, Result};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use qrcode::{render::unicode::Dense1x2, QrCode};
+
+use super::identity::Identity;
+
+const SCHEME: &str = "symmetry://pair?";
+
+/// What a new device needs to pair with one offering to: where to reach it, the key it
+/// signs with and a one-time token.
+///
+/// It's shown as a link or a QR code. A device presenting the token saw the invitation,
+/// so there are no codes to compare, and it can tell the host apart from an impostor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invitation {
+    pub address: SocketAddr,
+    /// The signing key of the inviting device, base64 encoded.
+    pub host: String,
+    pub token: String,
+}
+
+impl Invitation {
+    /// Invites devices to pair with `identity`, listening on `address`.
+    pub fn new(address: SocketAddr, identity: &Identity) -> Self {
+        let mut token = [0u8; 16];
+        OsRng.fill_bytes(&mut token);
+        Self {
+            address,
+            host: identity.signing_key(),
+            token: URL_SAFE_NO_PAD.encode(token),
+        }
+    }
+
+    /// Formats the invitation as a `symmetry://pair` link.
+    pub fn link(&self) -> String {
+        let host = URL_SAFE_NO_PAD.encode(STANDARD.decode(&self.host).unwrap_or_default());
+        format!(
+            "{SCHEME}address={}&host={host}&token={}",
+            self.address, self.token
+        )
+    }
+
+    /// Reads an invitation from a link made by [`Invitation::link`].
+    pub fn parse(link: &str) -> Result<Self> {
+        let Some(query) = link.trim().strip_prefix(SCHEME) else {
+            bail!("This isn't a Symmetry pairing link.");
+        };
+        let (mut address, mut host, mut token) = (None, None, None);
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some(("address", value)) => address = Some(value.parse()?),
+                Some(("host", value)) => {
+                    host = Some(STANDARD.encode(URL_SAFE_NO_PAD.decode(value)?))
+                }
+                Some(("token", value)) => token = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        let missing = || anyhow!("The pairing link is incomplete.");
+        Ok(Self {
+            address: address.ok_or_else(missing)?,
+            host: host.ok_or_else(missing)?,
+            token: token.ok_or_else(missing)?,
+        })
+    }
+
+    /// Draws the link as a QR code out of text, to be shown in a monospace font.
+    pub fn qr_code(&self) -> Result<String> {
+        let code = QrCode::new(self.link())?;
+        Ok(code.render::<Dense1x2>().quiet_zone(true).build())
+    }
+}
@@ -0,0 +1,141 @@
+pub mod identity;
+pub mod invitation;
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::{configuration::Configuration, secrets::cipher::Cipher};
+
+use self::identity::{decode, device_id, Identity};
+
+/// The devices allowed to synchronize with each other, kept in the synchronized
+/// configuration so every device knows about the others.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Devices {
+    /// Trusted devices, keyed by their identifier.
+    #[serde(default)]
+    pub trusted: BTreeMap<String, TrustedDevice>,
+    /// The sync key that replaced a revoked one, sealed for each remaining device.
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>,
+}
+
+/// The public keys of a device allowed to synchronize.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TrustedDevice {
+    pub name: String,
+    pub signing_key: String,
+    pub agreement_key: String,
+}
+
+impl Devices {
+    /// Finds the trusted device holding `signing_key`.
+    pub fn find(&self, signing_key: &str) -> Option<&TrustedDevice> {
+        self.trusted
+            .get(&device_id(signing_key))
+            .filter(|device| device.signing_key == signing_key)
+    }
+
+    /// Allows `device` to synchronize.
+    pub fn trust(&mut self, device: TrustedDevice) {
+        self.trusted.insert(device.id(), device);
+    }
+
+    /// Whether another device than `identity` is trusted, meaning this one was paired.
+    pub fn is_paired(&self, identity: &Identity) -> bool {
+        let id = identity.id();
+        self.trusted.contains_key(&id) && self.trusted.keys().any(|other| *other != id)
+    }
+}
+
+impl TrustedDevice {
+    pub fn id(&self) -> String {
+        device_id(&self.signing_key)
+    }
+}
+
+/// Stops trusting the device `id` and replaces the sync key it knows.
+///
+/// The secrets are encrypted again with a new key, which is sealed for each remaining
+/// device so they pick it up on their next sync. Returns the new key, to be stored on
+/// this device.
+pub fn revoke(config: &mut Configuration, id: &str, key: &str) -> Result<String> {
+    if config.devices.trusted.remove(id).is_none() {
+        bail!("The device {id} isn't trusted.");
+    }
+    let new_key = Cipher::generate_key();
+    let (old, new) = (Cipher::new(key)?, Cipher::new(&new_key)?);
+    for ciphertext in config.secrets.values_mut() {
+        *ciphertext = new.encrypt_string(&old.decrypt_string(ciphertext)?)?;
+    }
+    config.devices.keys.clear();
+    for (id, device) in &config.devices.trusted {
+        let sealed = seal(&new_key, &device.agreement_key)?;
+        config.devices.keys.insert(id.clone(), sealed);
+    }
+    Ok(new_key)
+}
+
+/// Returns the sync key sealed for `identity` after a revocation, if there is one.
+pub fn receive_key(config: &Configuration, identity: &Identity) -> Result<Option<String>> {
+    let Some(sealed) = config.devices.keys.get(&identity.id()) else {
+        return Ok(None);
+    };
+    let key = unseal(sealed, identity)
+        .map_err(|_| anyhow!("The sync key wasn't sealed for this device."))?;
+    Cipher::new(&key)?;
+    Ok(Some(key))
+}
+
+/// Opens a key sealed with [`seal`] for `identity`.
+pub(crate) fn unseal(sealed: &str, identity: &Identity) -> Result<String> {
+    let data = STANDARD.decode(sealed)?;
+    if data.len() < 32 {
+        bail!("The sealed key is truncated.");
+    }
+    let (ephemeral, ciphertext) = data.split_at(32);
+    let ephemeral: [u8; 32] = ephemeral.try_into()?;
+    let ephemeral = PublicKey::from(ephemeral);
+    let recipient = PublicKey::from(identity.agreement());
+    let shared = identity.agreement().diffie_hellman(&ephemeral);
+    let key = sealing_cipher(shared.as_bytes(), &ephemeral, &recipient)?
+        .decrypt(&Nonce::default(), ciphertext)
+        .map_err(|_| anyhow!("The key wasn't sealed for this device."))?;
+    Ok(String::from_utf8(key)?)
+}
+
+/// Encrypts `key` so only the holder of the agreement key `recipient` can read it.
+pub(crate) fn seal(key: &str, recipient: &str) -> Result<String> {
+    let recipient = PublicKey::from(decode(recipient)?);
+    let secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral = PublicKey::from(&secret);
+    let shared = secret.diffie_hellman(&recipient);
+    // Each ephemeral key is used once, so the nonce never repeats for a key.
+    let ciphertext = sealing_cipher(shared.as_bytes(), &ephemeral, &recipient)?
+        .encrypt(&Nonce::default(), key.as_bytes())
+        .map_err(|_| anyhow!("Failed to seal the key."))?;
+    Ok(STANDARD.encode([ephemeral.as_bytes().as_slice(), &ciphertext].concat()))
+}
+
+fn sealing_cipher(
+    shared: &[u8],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<ChaCha20Poly1305> {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(b"symmetry sealed key", &mut key)
+        .map_err(|_| anyhow!("Failed to derive the sealing key."))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
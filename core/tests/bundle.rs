@@ -0,0 +1,190 @@
+//! Exports bundles from a simulated device and imports them into others.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use symmetry_core::{
+    color_scheme::ColorScheme,
+    configuration::{
+        bundle::{Bundle, Mode},
+        Configuration, CONFIG_FILE,
+    },
+    trust::identity::Identity,
+};
+use tempfile::TempDir;
+
+/// A bundle as written to disk, to alter and sign again.
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    contents: String,
+    signature: String,
+}
+
+/// A device without any sync provider, moving its configuration in bundles.
+type Device = common::Device<()>;
+
+impl Device {
+    fn new(root: &Path, name: &str, config: Configuration) -> Self {
+        Self::set_up(root, name, Some(config), |_| ())
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        let file = self.path.join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, contents).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        std::fs::read_to_string(self.path.join(path)).unwrap()
+    }
+}
+
+/// A laptop with a wallpaper and synchronized notes, exported to a bundle.
+fn exported(root: &TempDir) -> (Device, PathBuf) {
+    let wallpaper = root.path().join("pictures/a.png");
+    std::fs::create_dir_all(wallpaper.parent().unwrap()).unwrap();
+    std::fs::write(&wallpaper, "image").unwrap();
+    let config = Configuration {
+        color_scheme: ColorScheme::Dark,
+        wallpaper: wallpaper.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    let laptop = Device::new(root.path(), "laptop", config);
+    laptop.write("notes/todo.txt", "theirs");
+    laptop.write("notes/ideas.txt", "more");
+    let file = root.path().join("laptop.symmetry");
+    Bundle::export(&laptop.context, &file).unwrap();
+    (laptop, file)
+}
+
+#[test]
+fn replacing_takes_everything_in_the_bundle() {
+    let root = TempDir::new().unwrap();
+    let (laptop, file) = exported(&root);
+    let wallpaper = PathBuf::from(&laptop.config().wallpaper);
+    std::fs::remove_file(&wallpaper).unwrap();
+    let desktop = Device::new(root.path(), "desktop", Configuration::default());
+    desktop.write("old.txt", "gone");
+
+    let bundle = Bundle::open(&file).unwrap();
+    assert_eq!(
+        bundle.signer.signing_key,
+        Identity::load(&laptop.context.state_path())
+            .unwrap()
+            .signing_key()
+    );
+    bundle.import(&desktop.context, Mode::Replace).unwrap();
+    assert_eq!(desktop.config().color_scheme, ColorScheme::Dark);
+    assert_eq!(desktop.read("notes/todo.txt"), "theirs");
+    assert!(!desktop.path.join("old.txt").exists());
+    // The image lands in the app directory rather than where the bundle says.
+    assert!(!wallpaper.exists());
+    let placed = PathBuf::from(desktop.config().wallpaper);
+    assert!(placed.starts_with(desktop.context.local_path()));
+    assert_eq!(std::fs::read_to_string(placed).unwrap(), "image");
+}
+
+#[test]
+fn merging_keeps_what_the_device_already_has() {
+    let root = TempDir::new().unwrap();
+    let (laptop, file) = exported(&root);
+    let config = Configuration {
+        color_scheme: ColorScheme::Light,
+        ..Default::default()
+    };
+    let desktop = Device::new(root.path(), "desktop", config);
+    desktop.write("notes/todo.txt", "mine");
+
+    Bundle::open(&file)
+        .unwrap()
+        .import(&desktop.context, Mode::Merge)
+        .unwrap();
+    assert_eq!(desktop.config().color_scheme, ColorScheme::Light);
+    // The laptop still has its wallpaper where the setting points.
+    assert_eq!(desktop.config().wallpaper, laptop.config().wallpaper);
+    assert_eq!(desktop.read("notes/todo.txt"), "mine");
+    assert_eq!(desktop.read("notes/ideas.txt"), "more");
+}
+
+#[test]
+fn altered_bundles_are_refused() {
+    let root = TempDir::new().unwrap();
+    let (_laptop, file) = exported(&root);
+    let data = std::fs::read_to_string(&file).unwrap();
+    std::fs::write(&file, data.replacen("(signer:", "( signer:", 1)).unwrap();
+
+    let err = Bundle::open(&file).unwrap_err();
+    assert!(err.to_string().contains("altered"), "{err}");
+}
+
+#[test]
+fn paired_devices_only_take_bundles_from_trusted_ones() {
+    let root = TempDir::new().unwrap();
+    let (laptop, file) = exported(&root);
+    let desktop = Device::new(root.path(), "desktop", Configuration::default());
+    let phone = Identity::load(&root.path().join("phone")).unwrap();
+    let mut config = desktop.config();
+    config.devices.trust(
+        Identity::load(&desktop.context.state_path())
+            .unwrap()
+            .device(),
+    );
+    config.devices.trust(phone.device());
+    config.write_to(&desktop.path.join(CONFIG_FILE)).unwrap();
+
+    let bundle = Bundle::open(&file).unwrap();
+    assert!(bundle.import(&desktop.context, Mode::Merge).is_err());
+    assert_eq!(desktop.config(), config);
+
+    config.devices.trust(bundle.signer.clone());
+    config.write_to(&desktop.path.join(CONFIG_FILE)).unwrap();
+    bundle.import(&desktop.context, Mode::Merge).unwrap();
+    assert_eq!(desktop.config().wallpaper, laptop.config().wallpaper);
+}
+
+#[test]
+fn importing_never_trusts_other_devices() {
+    let root = TempDir::new().unwrap();
+    let (laptop, file) = exported(&root);
+    let phone = Identity::load(&root.path().join("phone")).unwrap();
+    let mut config = laptop.config();
+    config.devices.trust(phone.device());
+    config.write_to(&laptop.path.join(CONFIG_FILE)).unwrap();
+    Bundle::export(&laptop.context, &file).unwrap();
+    let desktop = Device::new(root.path(), "desktop", Configuration::default());
+
+    for mode in [Mode::Merge, Mode::Replace] {
+        let bundle = Bundle::open(&file).unwrap();
+        assert!(bundle
+            .configuration
+            .devices
+            .find(&phone.signing_key())
+            .is_some());
+        bundle.import(&desktop.context, mode).unwrap();
+        assert_eq!(desktop.config().devices, Configuration::default().devices);
+    }
+}
+
+#[test]
+fn bundles_never_write_into_git() {
+    let root = TempDir::new().unwrap();
+    let (laptop, file) = exported(&root);
+    let archive: Archive = ron::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    let contents = archive
+        .contents
+        .replacen("files:{", "files:{\".git/config\":\"aGk=\",", 1);
+    let identity = Identity::load(&laptop.context.state_path()).unwrap();
+    let archive = Archive {
+        signature: identity.sign(contents.as_bytes()),
+        contents,
+    };
+    std::fs::write(&file, ron::to_string(&archive).unwrap()).unwrap();
+    let desktop = Device::new(root.path(), "desktop", Configuration::default());
+
+    let bundle = Bundle::open(&file).unwrap();
+    assert!(bundle.import(&desktop.context, Mode::Replace).is_err());
+    assert!(!desktop.path.join(".git/config").exists());
+    assert!(!desktop.path.join("notes/todo.txt").exists());
+}
@@ -0,0 +1,135 @@
+//! Stand-ins shared by the integration tests.
+
+// Each test crate only uses some of these.
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use symmetry_core::{
+    configuration::{Configuration, CONFIG_FILE},
+    context::SymmetryContext,
+    sync::{message::Message, status::Status},
+    traits::synchronization::Synchronization,
+    trust::identity::Identity,
+};
+use tiny_http::{Header, Request, Server};
+
+/// A simulated device with a context of its own, synchronizing through `sync`.
+pub struct Device<S> {
+    pub context: SymmetryContext,
+    pub path: PathBuf,
+    pub state: PathBuf,
+    pub sync: S,
+}
+
+impl<S> Device<S> {
+    /// Sets up the device `name` under `root`, holding `config` when given, with what
+    /// `open` makes of its context to synchronize. Its desktop settings and home are kept
+    /// there too.
+    pub fn set_up(
+        root: &Path,
+        name: &str,
+        config: Option<Configuration>,
+        open: impl FnOnce(&SymmetryContext) -> S,
+    ) -> Self {
+        let data_dir = root.join(name);
+        let context = SymmetryContext::new(&data_dir)
+            .with_config_dir(data_dir.join("config"))
+            .with_home_dir(data_dir.join("home"));
+        if let Some(config) = config {
+            config.init_in(&context).unwrap();
+        }
+        Self {
+            path: context.repo_path(),
+            state: context.state_path(),
+            sync: open(&context),
+            context,
+        }
+    }
+
+    pub fn identity(&self) -> Identity {
+        Identity::load(&self.state).unwrap()
+    }
+
+    pub fn config(&self) -> Configuration {
+        Configuration::read_from(&self.path.join(CONFIG_FILE)).unwrap()
+    }
+
+    pub fn edit(&self, edit: impl FnOnce(&mut Configuration)) {
+        let mut config = self.config();
+        edit(&mut config);
+        config.write_to(&self.path.join(CONFIG_FILE)).unwrap();
+    }
+}
+
+impl<S: Synchronization<Status = Status, Message = Message>> Device<S> {
+    pub fn sync(&self) -> Status {
+        self.sync.sync().unwrap()
+    }
+}
+
+/// Answers HTTP requests on a local port, one at a time, over state kept in memory.
+pub struct HttpServer<T> {
+    server: Arc<Server>,
+    pub state: Arc<Mutex<T>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> HttpServer<T> {
+    pub fn start(state: T, answer: fn(Request, &mut T)) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let state = Arc::new(Mutex::new(state));
+        let (listener, shared) = (server.clone(), state.clone());
+        let thread = std::thread::spawn(move || {
+            for request in listener.incoming_requests() {
+                answer(request, &mut shared.lock().unwrap());
+            }
+        });
+        Self {
+            server,
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    /// The address and port the server listens on.
+    pub fn address(&self) -> String {
+        self.server.server_addr().to_ip().unwrap().to_string()
+    }
+}
+
+impl<T> Drop for HttpServer<T> {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The value of a request header, if it was sent.
+pub fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.to_string())
+}
+
+/// Whether a write with `If-Match` or `If-None-Match` goes through, given the ETag of
+/// what's stored.
+pub fn allowed(current: Option<&str>, if_match: Option<&str>, if_none_match: Option<&str>) -> bool {
+    match (if_match, if_none_match) {
+        (Some(tag), _) => current == Some(tag),
+        (_, Some(_)) => current.is_none(),
+        _ => true,
+    }
+}
+
+pub fn etag(tag: &str) -> Header {
+    Header::from_bytes("ETag", tag).unwrap()
+}
@@ -0,0 +1,74 @@
+//! Points instances of Symmetry at their own data directories.
+
+use symmetry_core::{
+    configuration::{Configuration, CONFIG_FILE},
+    context::SymmetryContext,
+    files::tracked_file::{DeployMode, TrackedFile},
+};
+use tempfile::TempDir;
+
+#[test]
+fn configurations_are_kept_per_context() {
+    let root = TempDir::new().unwrap();
+    let first = SymmetryContext::new(root.path().join("first"));
+    let second = SymmetryContext::new(root.path().join("second"));
+
+    let config = Configuration {
+        wallpaper: "first.png".into(),
+        ..Default::default()
+    };
+    config.init_in(&first).unwrap();
+
+    assert!(first.config_path().is_file());
+    assert_eq!(Configuration::load(&first), Some(config));
+    assert_eq!(Configuration::load(&second), None);
+}
+
+#[test]
+fn environment_overrides_the_data_directory() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::from_env(Some(root.path().into())).unwrap();
+
+    assert_eq!(context.data_dir(), root.path());
+    assert_eq!(context.local_path(), root.path().join("symmetry"));
+    assert_eq!(context.repo_path(), root.path().join("symmetry/repo"));
+    assert!(context.backup_path().starts_with(root.path()));
+    // Left empty, the user's data directory is used as when it's unset.
+    assert_eq!(
+        SymmetryContext::from_env(Some("".into())).unwrap(),
+        SymmetryContext::from_env(None).unwrap()
+    );
+}
+
+#[test]
+fn existing_installs_are_migrated() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::new(root.path());
+    let old_repo = context.local_path();
+    std::fs::create_dir_all(old_repo.join(".git")).unwrap();
+    std::fs::create_dir_all(root.path().join("symmetry-backups")).unwrap();
+    std::fs::write(root.path().join("symmetry-backups/original"), "original").unwrap();
+
+    let mut config = Configuration::default();
+    let mut file = TrackedFile::new(root.path().join("home/.vimrc").to_string_lossy());
+    file.mode = DeployMode::Symlink;
+    config.files.push(file);
+    config.write_to(&old_repo.join(CONFIG_FILE)).unwrap();
+    std::fs::create_dir_all(root.path().join("home")).unwrap();
+    let source = root.path().join("home/.vimrc");
+    let repo_path = config.files[0].repo_path.clone();
+    std::fs::create_dir_all(old_repo.join(&repo_path).parent().unwrap()).unwrap();
+    std::fs::write(old_repo.join(&repo_path), "set number").unwrap();
+    std::os::unix::fs::symlink(old_repo.join(&repo_path), &source).unwrap();
+
+    context.migrate().unwrap();
+
+    assert!(context.repo_path().join(".git").is_dir());
+    assert_eq!(Configuration::load(&context), Some(config));
+    assert!(context.backup_path().join("original").is_file());
+    assert_eq!(
+        std::fs::read_link(&source).unwrap(),
+        context.repo_path().join(&repo_path)
+    );
+    assert_eq!(std::fs::read_to_string(&source).unwrap(), "set number");
+}
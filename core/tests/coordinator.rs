@@ -0,0 +1,333 @@
+//! Runs several providers together: scripted ones for how their statuses combine, and git
+//! alongside CRDT over simulated devices for how they keep each other up to date.
+
+mod common;
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use anyhow::Result;
+use symmetry_core::{
+    color_scheme::ColorScheme,
+    configuration::Configuration,
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    sync::{
+        message::Message,
+        providers::{coordinator::Coordinator, crdt::CrdtSync, git::GitSync, Provider},
+        status::Status,
+    },
+    traits::synchronization::Synchronization,
+};
+use tempfile::TempDir;
+
+/// Returns the scripted statuses in order, counting syncs and recording messages.
+#[derive(Clone, Default)]
+struct Scripted {
+    statuses: Rc<RefCell<VecDeque<Status>>>,
+    syncs: Rc<RefCell<usize>>,
+    messages: Rc<RefCell<Vec<Message>>>,
+    metered: MeteredPolicy,
+    /// Whether the provider only reaches local disks.
+    local: bool,
+}
+
+impl Scripted {
+    fn new(statuses: impl IntoIterator<Item = Status>) -> Self {
+        Self {
+            statuses: Rc::new(RefCell::new(statuses.into_iter().collect())),
+            ..Default::default()
+        }
+    }
+
+    fn syncs(&self) -> usize {
+        *self.syncs.borrow()
+    }
+}
+
+impl Synchronization for Scripted {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Status> {
+        *self.syncs.borrow_mut() += 1;
+        Ok(self
+            .statuses
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(Status::UpToDate))
+    }
+
+    fn handle(&self, message: Message) -> Result<Status> {
+        self.messages.borrow_mut().push(message);
+        Ok(Status::ChangesDownloaded {
+            commit: "abc".into(),
+        })
+    }
+
+    fn metered_policy(&self) -> MeteredPolicy {
+        self.metered
+    }
+
+    fn requires_network(&self) -> bool {
+        !self.local
+    }
+}
+
+fn coordinator(realtime: &Scripted, history: &Scripted) -> Coordinator {
+    Coordinator::new(vec![
+        ("CRDT".into(), Box::new(realtime.clone()) as Provider),
+        ("Git".into(), Box::new(history.clone()) as Provider),
+    ])
+}
+
+fn downloaded() -> Status {
+    Status::ChangesDownloaded {
+        commit: "abc".into(),
+    }
+}
+
+fn uploaded() -> Status {
+    Status::ChangesUploaded {
+        commit: "def".into(),
+    }
+}
+
+#[test]
+fn the_most_telling_status_is_reported() {
+    let realtime = Scripted::new([downloaded()]);
+    let history = Scripted::new([uploaded()]);
+
+    assert_eq!(
+        coordinator(&realtime, &history).sync().unwrap(),
+        downloaded()
+    );
+}
+
+#[test]
+fn an_unreachable_provider_is_reported_alongside_the_others() {
+    let realtime = Scripted::new([uploaded()]);
+    let history = Scripted::new([Status::Offline { pending: 2 }]);
+
+    assert_eq!(
+        coordinator(&realtime, &history).sync().unwrap(),
+        Status::PartialSync {
+            failed: vec!["Git: offline, 2 changes pending".into()],
+        }
+    );
+}
+
+#[test]
+fn all_providers_unreachable_means_offline() {
+    let realtime = Scripted::new([Status::Offline { pending: 1 }]);
+    let history = Scripted::new([Status::Offline { pending: 3 }]);
+
+    assert_eq!(
+        coordinator(&realtime, &history).sync().unwrap(),
+        Status::Offline { pending: 3 }
+    );
+}
+
+#[test]
+fn changes_brought_in_later_reach_the_earlier_providers() {
+    let realtime = Scripted::new([uploaded()]);
+    let history = Scripted::new([downloaded()]);
+
+    coordinator(&realtime, &history).sync().unwrap();
+    assert_eq!(realtime.syncs(), 2);
+    assert_eq!(history.syncs(), 1);
+}
+
+#[test]
+fn partial_syncs_reach_the_earlier_providers() {
+    let realtime = Scripted::new([uploaded()]);
+    let history = Scripted::new([Status::PartialSync {
+        failed: vec!["Files: permission denied".into()],
+    }]);
+
+    coordinator(&realtime, &history).sync().unwrap();
+    assert_eq!(realtime.syncs(), 2);
+}
+
+#[test]
+fn failures_are_reported_once_the_review_is_settled() {
+    let realtime = Scripted::new([
+        Status::Offline { pending: 1 },
+        Status::Offline { pending: 1 },
+    ]);
+    let history = Scripted::new([Status::NewChangesDetected { changes: vec![] }]);
+    let coordinator = coordinator(&realtime, &history);
+
+    assert!(matches!(
+        coordinator.sync().unwrap(),
+        Status::NewChangesDetected { .. }
+    ));
+    assert_eq!(
+        coordinator.handle(Message::Update).unwrap(),
+        Status::PartialSync {
+            failed: vec!["CRDT: offline, 1 changes pending".into()],
+        }
+    );
+}
+
+#[test]
+fn reviews_go_to_the_provider_that_asked() {
+    let realtime = Scripted::new([]);
+    let history = Scripted::new([Status::NewChangesDetected { changes: vec![] }]);
+    let coordinator = coordinator(&realtime, &history);
+
+    assert!(matches!(
+        coordinator.sync().unwrap(),
+        Status::NewChangesDetected { .. }
+    ));
+    assert_eq!(coordinator.handle(Message::Update).unwrap(), downloaded());
+    assert_eq!(*history.messages.borrow(), [Message::Update]);
+    assert!(realtime.messages.borrow().is_empty());
+    // The realtime provider syncs again to hand the accepted changes around.
+    assert_eq!(realtime.syncs(), 2);
+}
+
+#[test]
+fn metered_links_only_run_the_providers_allowing_them() {
+    let realtime = Scripted {
+        metered: MeteredPolicy::Allow,
+        ..Default::default()
+    };
+    let history = Scripted::default();
+    let coordinator = coordinator(&realtime, &history);
+
+    assert_eq!(coordinator.metered_policy(), MeteredPolicy::Allow);
+    coordinator.sync_metered().unwrap();
+    assert_eq!((realtime.syncs(), history.syncs()), (1, 0));
+}
+
+#[test]
+fn offline_only_the_local_providers_run() {
+    let realtime = Scripted::default();
+    let history = Scripted {
+        local: true,
+        ..Default::default()
+    };
+    let coordinator = coordinator(&realtime, &history);
+
+    assert!(!coordinator.requires_network());
+    coordinator.sync_offline().unwrap();
+    assert_eq!((realtime.syncs(), history.syncs()), (0, 1));
+}
+
+type Device = common::Device<Accepting>;
+
+impl Device {
+    /// A device synchronizing through the shared folder, and keeping history in the git
+    /// remote when given one.
+    fn new(root: &Path, name: &str, git: Option<&Path>, config: Configuration) -> Self {
+        Self::set_up(root, name, Some(config), |context| {
+            let mut crdt = CrdtSync::open(context);
+            crdt.set_discovery(false);
+            crdt.set_folder(Some(root.join("shared")));
+            let provider: Provider = match git {
+                Some(remote) => Box::new(Coordinator::new(vec![
+                    ("CRDT".into(), Box::new(crdt) as Provider),
+                    (
+                        "Git".into(),
+                        Box::new(GitSync::open(context, remote.to_string_lossy())),
+                    ),
+                ])),
+                None => Box::new(crdt),
+            };
+            Accepting(provider)
+        })
+    }
+}
+
+/// Accepts whatever the remote brought in on every sync, as the daemon would.
+struct Accepting(Provider);
+
+impl Synchronization for Accepting {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Status> {
+        match self.0.sync()? {
+            Status::NewChangesDetected { .. } => self.0.handle(Message::Update),
+            status => Ok(status),
+        }
+    }
+
+    fn handle(&self, message: Message) -> Result<Status> {
+        self.0.handle(message)
+    }
+}
+
+/// A laptop keeping history in git and syncing in realtime with a desktop that only uses
+/// the shared folder, and a git remote to inspect the history with.
+fn devices(root: &TempDir) -> (Device, Device, GitSync, PathBuf) {
+    std::fs::create_dir_all(root.path().join("shared")).unwrap();
+    let remote = root.path().join("symmetry.git");
+    let config = Configuration {
+        color_scheme: ColorScheme::Dark,
+        wallpaper: "a.png".into(),
+        ..Default::default()
+    };
+    let laptop = Device::new(root.path(), "laptop", Some(&remote), config);
+    let desktop = Device::new(root.path(), "desktop", None, Configuration::default());
+    for device in [&laptop, &desktop] {
+        device.edit(|config| {
+            config.devices.trust(laptop.identity().device());
+            config.devices.trust(desktop.identity().device());
+        });
+    }
+    laptop.sync();
+    desktop.sync();
+    let backup = GitSync::open(
+        &SymmetryContext::new(root.path().join("backup")),
+        remote.to_string_lossy(),
+    );
+    (laptop, desktop, backup, root.path().join("backup"))
+}
+
+/// The wallpaper as last recorded in the git history.
+fn recorded_wallpaper(backup: &GitSync, path: &Path) -> String {
+    if let Ok(Status::NewChangesDetected { .. }) = backup.sync() {
+        backup.handle(Message::Update).unwrap();
+    }
+    let config = SymmetryContext::new(path).config_path();
+    Configuration::read_from(&config).unwrap().wallpaper
+}
+
+#[test]
+fn realtime_changes_are_recorded_in_the_history() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop, backup, backup_path) = devices(&root);
+    assert_eq!(desktop.config().wallpaper, "a.png");
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    desktop.sync();
+    assert!(matches!(laptop.sync(), Status::ChangesDownloaded { .. }));
+
+    assert_eq!(laptop.config().wallpaper, "b.png");
+    assert_eq!(recorded_wallpaper(&backup, &backup_path), "b.png");
+}
+
+#[test]
+fn changes_from_the_history_reach_realtime_devices() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop, backup, backup_path) = devices(&root);
+    recorded_wallpaper(&backup, &backup_path);
+
+    // Another device only keeping to git publishes a change.
+    let config = SymmetryContext::new(&backup_path).config_path();
+    let mut edited = Configuration::read_from(&config).unwrap();
+    edited.wallpaper = "c.png".into();
+    edited.write_to(&config).unwrap();
+    backup.sync().unwrap();
+
+    assert!(matches!(laptop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(laptop.config().wallpaper, "c.png");
+    desktop.sync();
+    assert_eq!(desktop.config().wallpaper, "c.png");
+}
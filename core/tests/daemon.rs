@@ -0,0 +1,286 @@
+//! Drives the background daemon with a scripted provider and a fake NetworkManager bus.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use symmetry_core::{
+    daemon::{backoff::Backoff, Daemon, Provider},
+    network::{Bus, Connectivity, MeteredPolicy, NetworkManager, NetworkMonitor},
+    sync::{message::Message, status::Status},
+    traits::synchronization::Synchronization,
+};
+
+/// NetworkManager properties, shared so tests can change them while the daemon runs.
+#[derive(Clone, Default)]
+struct FakeBus {
+    properties: Rc<RefCell<HashMap<String, u32>>>,
+    /// Whether the properties changed since the last wait.
+    changed: Rc<Cell<bool>>,
+}
+
+impl FakeBus {
+    fn set(&self, connectivity: u32, metered: u32) {
+        let mut properties = self.properties.borrow_mut();
+        properties.insert("Connectivity".into(), connectivity);
+        properties.insert("Metered".into(), metered);
+        self.changed.set(true);
+    }
+}
+
+impl Bus for FakeBus {
+    fn property(&self, name: &str) -> Result<u32> {
+        self.properties
+            .borrow()
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("No such property: {name}"))
+    }
+
+    fn wait(&self, timeout: Duration) -> Result<bool> {
+        if !self.changed.replace(false) {
+            std::thread::sleep(timeout);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+const FULL: u32 = 4;
+const NONE: u32 = 1;
+const METERED: u32 = 1;
+const UNMETERED: u32 = 2;
+
+/// Returns the scripted statuses in order and records the messages it receives.
+#[derive(Clone, Default)]
+struct Scripted {
+    statuses: Rc<RefCell<VecDeque<Status>>>,
+    messages: Rc<RefCell<Vec<Message>>>,
+    metered: MeteredPolicy,
+    /// Whether the provider only reaches local disks.
+    local: bool,
+    pending: Option<Duration>,
+}
+
+impl Synchronization for Scripted {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Status> {
+        Ok(self
+            .statuses
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(Status::UpToDate))
+    }
+
+    fn handle(&self, message: Message) -> Result<Status> {
+        self.messages.borrow_mut().push(message);
+        Ok(Status::ChangesDownloaded {
+            commit: "abc".into(),
+        })
+    }
+
+    fn metered_policy(&self) -> MeteredPolicy {
+        self.metered
+    }
+
+    fn requires_network(&self) -> bool {
+        !self.local
+    }
+
+    fn pending(&self) -> Option<Duration> {
+        self.pending
+    }
+}
+
+fn daemon(scripted: &Scripted) -> Daemon<impl FnMut() -> Result<Option<Provider>>> {
+    let scripted = scripted.clone();
+    let mut daemon = Daemon::new(
+        move || Ok(Some(Box::new(scripted.clone()) as Provider)),
+        Duration::from_secs(300),
+    );
+    daemon.set_backoff(Backoff::new(
+        Duration::from_secs(10),
+        Duration::from_secs(60),
+    ));
+    daemon
+}
+
+#[test]
+fn backoff_doubles_up_to_the_cap() {
+    let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60));
+    let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+    assert_eq!(delays, [10, 20, 40, 60, 60]);
+    backoff.reset();
+    assert_eq!(backoff.next_delay(), Duration::from_secs(10));
+}
+
+#[test]
+fn offline_syncs_are_retried_with_backoff() {
+    let scripted = Scripted::default();
+    scripted.statuses.borrow_mut().extend([
+        Status::Offline { pending: 1 },
+        Status::Offline { pending: 2 },
+        Status::ChangesUploaded {
+            commit: "abc".into(),
+        },
+        Status::Offline { pending: 1 },
+    ]);
+    let mut daemon = daemon(&scripted);
+
+    let delays: Vec<u64> = (0..4).map(|_| daemon.tick().1.as_secs()).collect();
+    assert_eq!(delays, [10, 20, 300, 10]);
+}
+
+#[test]
+fn held_back_changes_are_synced_when_due() {
+    let mut scripted = Scripted {
+        pending: Some(Duration::from_secs(42)),
+        ..Default::default()
+    };
+    scripted
+        .statuses
+        .borrow_mut()
+        .push_back(Status::ChangesCommitted {
+            commit: "abc".into(),
+        });
+    assert_eq!(daemon(&scripted).tick().1, Duration::from_secs(42));
+
+    // Never later than the interval.
+    scripted.pending = Some(Duration::from_secs(3600));
+    assert_eq!(daemon(&scripted).tick().1, Duration::from_secs(300));
+}
+
+#[test]
+fn remote_changes_are_left_for_review() {
+    let scripted = Scripted::default();
+    scripted
+        .statuses
+        .borrow_mut()
+        .push_back(Status::NewChangesDetected { changes: vec![] });
+    let mut daemon = daemon(&scripted);
+
+    let (status, _) = daemon.tick();
+    assert!(matches!(status, Some(Status::NewChangesDetected { .. })));
+    assert!(scripted.messages.borrow().is_empty());
+}
+
+#[test]
+fn network_manager_states_are_mapped() {
+    let bus = FakeBus::default();
+    let monitor = NetworkManager::new(bus.clone());
+    bus.set(FULL, UNMETERED);
+    assert_eq!(monitor.connectivity().unwrap(), Connectivity::Unmetered);
+    bus.set(FULL, METERED);
+    assert_eq!(monitor.connectivity().unwrap(), Connectivity::Metered);
+    // A captive portal isn't usable yet.
+    bus.set(2, UNMETERED);
+    assert_eq!(monitor.connectivity().unwrap(), Connectivity::Offline);
+    bus.set(NONE, UNMETERED);
+    assert_eq!(monitor.connectivity().unwrap(), Connectivity::Offline);
+}
+
+#[test]
+fn syncs_wait_for_the_network() {
+    let scripted = Scripted::default();
+    scripted
+        .statuses
+        .borrow_mut()
+        .push_back(Status::ChangesUploaded {
+            commit: "abc".into(),
+        });
+    let bus = FakeBus::default();
+    bus.set(NONE, UNMETERED);
+    let mut daemon = daemon(&scripted);
+    daemon.set_monitor(Box::new(NetworkManager::new(bus.clone())));
+
+    assert_eq!(daemon.tick(), (None, Duration::from_secs(300)));
+    assert_eq!(scripted.statuses.borrow().len(), 1);
+    assert!(!daemon.network_changed());
+
+    bus.set(FULL, UNMETERED);
+    assert!(daemon.network_changed());
+    let (status, _) = daemon.tick();
+    assert!(matches!(status, Some(Status::ChangesUploaded { .. })));
+}
+
+#[test]
+fn local_providers_sync_offline() {
+    let bus = FakeBus::default();
+    bus.set(NONE, UNMETERED);
+    let local = Scripted {
+        local: true,
+        ..Default::default()
+    };
+    let mut daemon = daemon(&local);
+    daemon.set_monitor(Box::new(NetworkManager::new(bus.clone())));
+
+    assert_eq!(daemon.tick().0, Some(Status::UpToDate));
+}
+
+#[test]
+fn metered_links_follow_the_provider_policy() {
+    let bus = FakeBus::default();
+    bus.set(FULL, METERED);
+
+    let deferred = Scripted::default();
+    let mut waiting = daemon(&deferred);
+    waiting.set_monitor(Box::new(NetworkManager::new(bus.clone())));
+    assert_eq!(waiting.tick().0, None);
+
+    let allowed = Scripted {
+        metered: MeteredPolicy::Allow,
+        ..Default::default()
+    };
+    let mut syncing = daemon(&allowed);
+    syncing.set_monitor(Box::new(NetworkManager::new(bus.clone())));
+    assert_eq!(syncing.tick().0, Some(Status::UpToDate));
+
+    // Moving to an unmetered link lets the deferred sync run right away.
+    bus.set(FULL, UNMETERED);
+    assert!(waiting.network_changed());
+    assert_eq!(waiting.tick().0, Some(Status::UpToDate));
+}
+
+#[test]
+fn reconnecting_resets_the_backoff() {
+    let scripted = Scripted::default();
+    scripted.statuses.borrow_mut().extend([
+        Status::Offline { pending: 1 },
+        Status::Offline { pending: 1 },
+    ]);
+    let bus = FakeBus::default();
+    bus.set(FULL, UNMETERED);
+    let mut daemon = daemon(&scripted);
+    daemon.set_monitor(Box::new(NetworkManager::new(bus.clone())));
+
+    assert_eq!(daemon.tick().1, Duration::from_secs(10));
+    bus.set(NONE, UNMETERED);
+    assert!(!daemon.network_changed());
+    bus.set(FULL, UNMETERED);
+    assert!(daemon.network_changed());
+    assert_eq!(daemon.tick().1, Duration::from_secs(10));
+}
+
+#[test]
+fn waiting_ends_when_the_network_comes_back() {
+    let bus = FakeBus::default();
+    bus.set(NONE, UNMETERED);
+    let mut daemon = daemon(&Scripted::default());
+    daemon.set_monitor(Box::new(NetworkManager::new(bus.clone())));
+    assert_eq!(daemon.tick().0, None);
+
+    let start = Instant::now();
+    daemon.wait(Duration::from_millis(200));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    bus.set(FULL, UNMETERED);
+    let start = Instant::now();
+    daemon.wait(Duration::from_secs(300));
+    assert!(start.elapsed() < Duration::from_secs(60));
+}
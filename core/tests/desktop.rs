@@ -0,0 +1,224 @@
+//! Reads and writes desktop settings in directories standing in for the real ones.
+
+use std::collections::BTreeSet;
+
+use symmetry_core::{
+    configuration::Configuration,
+    context::SymmetryContext,
+    desktop::{
+        cosmic::{Cosmic, CosmicComponent},
+        kde::Kde,
+        merge::{merge, Merge},
+        Desktop, Entries,
+    },
+    template::Variables,
+    traits::adapter::Adapter,
+};
+use tempfile::TempDir;
+
+fn cosmic(root: &TempDir) -> Cosmic {
+    let components = BTreeSet::from([CosmicComponent::Panel, CosmicComponent::Workspaces]);
+    Cosmic::with_root(root.path().join("cosmic"), components)
+}
+
+#[test]
+fn cosmic_settings_round_trip() {
+    let root = TempDir::new().unwrap();
+    let laptop = TempDir::new().unwrap();
+    let entries = Entries::from([
+        ("com.system76.CosmicPanel/v1/size".into(), "M".into()),
+        (
+            "com.system76.CosmicComp/v1/workspaces".into(),
+            "(amount: 4)".into(),
+        ),
+        ("com.system76.CosmicComp/v1/xkb_config".into(), "us".into()),
+        (
+            "com.system76.CosmicTheme.Mode/v1/is_dark".into(),
+            "true".into(),
+        ),
+    ]);
+
+    cosmic(&root).write(&entries).unwrap();
+    let read = cosmic(&root).read().unwrap();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read["com.system76.CosmicPanel/v1/size"], "M");
+    assert_eq!(read["com.system76.CosmicComp/v1/workspaces"], "(amount: 4)");
+
+    cosmic(&laptop).write(&read).unwrap();
+    assert_eq!(cosmic(&laptop).read().unwrap(), read);
+}
+
+#[test]
+fn cosmic_entries_never_lead_out_of_its_directory() {
+    let root = TempDir::new().unwrap();
+    let entries = Entries::from([
+        (
+            "com.system76.CosmicPanel/v1/../../../.bashrc".into(),
+            "evil".into(),
+        ),
+        (
+            "com.system76.CosmicPanel/../../.profile".into(),
+            "evil".into(),
+        ),
+        (
+            "com.system76.CosmicPanel/v1//etc/passwd".into(),
+            "evil".into(),
+        ),
+    ]);
+
+    cosmic(&root).write(&entries).unwrap();
+    assert!(!root.path().join(".bashrc").exists());
+    assert!(!root.path().join(".profile").exists());
+    assert!(cosmic(&root).read().unwrap().is_empty());
+}
+
+#[test]
+fn kde_keys_are_written_in_place() {
+    let root = TempDir::new().unwrap();
+    std::fs::write(
+        root.path().join("kdeglobals"),
+        "# Written by Plasma\n[General]\nColorScheme=BreezeLight\nfont=Noto Sans\n\n[KDE]\nSingleClick=false\n",
+    )
+    .unwrap();
+    let kde = Kde::with_root(root.path().to_path_buf());
+
+    let mut entries = kde.read().unwrap();
+    assert_eq!(entries["kdeglobals/General/ColorScheme"], "BreezeLight");
+    assert_eq!(entries["kdeglobals/KDE/SingleClick"], "false");
+    entries.insert("kdeglobals/General/ColorScheme".into(), "BreezeDark".into());
+    entries.insert("kdeglobals/Icons/Theme".into(), "breeze-dark".into());
+    entries.insert(
+        "kwinrc/Windows/FocusPolicy".into(),
+        "FocusFollowsMouse".into(),
+    );
+    kde.write(&entries).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(root.path().join("kdeglobals")).unwrap(),
+        "# Written by Plasma\n[General]\nColorScheme=BreezeDark\nfont=Noto Sans\n\n[KDE]\nSingleClick=false\n\n[Icons]\nTheme=breeze-dark\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(root.path().join("kwinrc")).unwrap(),
+        "[Windows]\nFocusPolicy=FocusFollowsMouse\n"
+    );
+    assert_eq!(kde.read().unwrap(), entries);
+}
+
+#[test]
+fn kde_keys_missing_from_the_entries_are_kept() {
+    let root = TempDir::new().unwrap();
+    std::fs::write(
+        root.path().join("kdeglobals"),
+        "[General]\nColorScheme=BreezeLight\nfont=Noto Sans\n",
+    )
+    .unwrap();
+    let kde = Kde::with_root(root.path().to_path_buf());
+
+    let mut entries = Entries::new();
+    entries.insert("kdeglobals/General/ColorScheme".into(), "BreezeDark".into());
+    kde.write(&entries).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(root.path().join("kdeglobals")).unwrap(),
+        "[General]\nColorScheme=BreezeDark\nfont=Noto Sans\n"
+    );
+}
+
+#[test]
+fn merges_keep_the_side_that_changed_each_key() {
+    let entries = |pairs: &[(&str, &str)]| -> Entries {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+    let base = entries(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")]);
+    let local = entries(&[("a", "2"), ("b", "1"), ("c", "2"), ("e", "1")]);
+    let remote = entries(&[("a", "1"), ("b", "2"), ("c", "3"), ("d", "1")]);
+
+    assert_eq!(
+        merge(&base, &local, &remote),
+        Merge {
+            entries: entries(&[("a", "2"), ("b", "2"), ("c", "3"), ("e", "1")]),
+            conflicts: vec!["c".into()],
+        }
+    );
+}
+
+#[test]
+fn templated_settings_are_rendered_for_this_device() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::new(root.path())
+        .with_home_dir("/home/ada")
+        .with_device("laptop");
+    let mut config = Configuration {
+        wallpaper: "{{home}}/wallpapers/{{device.name}}.png".into(),
+        ..Default::default()
+    };
+    let entries = &mut config.desktop.cosmic.entries;
+    entries.insert(
+        "com.system76.CosmicPanel/v1/name".into(),
+        "{{device.name}}".into(),
+    );
+    entries.insert("com.system76.CosmicPanel/v1/size".into(), "M".into());
+    config
+        .desktop
+        .kde
+        .entries
+        .insert("kdeglobals/General/Name".into(), "{{device.name}}".into());
+
+    let rendered = config.rendered(&context).unwrap();
+    assert_eq!(rendered.wallpaper, "/home/ada/wallpapers/laptop.png");
+    let entries = &rendered.desktop.cosmic.entries;
+    assert_eq!(entries["com.system76.CosmicPanel/v1/name"], "laptop");
+    assert_eq!(entries["com.system76.CosmicPanel/v1/size"], "M");
+    assert_eq!(
+        rendered.desktop.kde.entries["kdeglobals/General/Name"],
+        "laptop"
+    );
+    assert_eq!(config.wallpaper, "{{home}}/wallpapers/{{device.name}}.png");
+}
+
+#[test]
+fn settings_read_back_keep_their_templates() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::new(root.path())
+        .with_home_dir("/home/ada")
+        .with_device("laptop");
+    let variables = Variables::new(&context, &Configuration::default());
+    let stored = Entries::from([
+        ("kdeglobals/General/Name".into(), "{{device.name}}".into()),
+        ("kdeglobals/General/Path".into(), "{{home}}/bin".into()),
+    ]);
+    let mut read = Entries::from([
+        ("kdeglobals/General/Name".into(), "laptop".into()),
+        ("kdeglobals/General/Path".into(), "/opt/bin".into()),
+    ]);
+
+    variables.keep_templates(&mut read, &stored);
+    assert_eq!(read["kdeglobals/General/Name"], "{{device.name}}");
+    assert_eq!(read["kdeglobals/General/Path"], "/opt/bin");
+}
+
+#[test]
+fn desktop_settings_stay_in_the_config_directory_of_the_context() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::new(root.path()).with_config_dir(root.path().join("config"));
+    let mut config = Configuration::default();
+    config.desktop.kde.enabled = true;
+    config.desktop.kde.entries.insert(
+        "kwinrc/Windows/FocusPolicy".into(),
+        "FocusFollowsMouse".into(),
+    );
+
+    std::fs::create_dir_all(root.path().join("config")).unwrap();
+    Desktop::apply(&context, &config).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(root.path().join("config/kwinrc")).unwrap(),
+        "[Windows]\nFocusPolicy=FocusFollowsMouse\n"
+    );
+    let mut captured = Configuration::default();
+    captured.desktop.kde.enabled = true;
+    assert!(Desktop::capture(&context, &mut captured).unwrap());
+    assert_eq!(captured.desktop.kde.entries, config.desktop.kde.entries);
+}
@@ -0,0 +1,131 @@
+//! Snapshots a simulated device to a folder standing in for a mounted disk.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use symmetry_core::{
+    configuration::{Configuration, CONFIG_FILE},
+    sync::{message::Message, providers::directory::DirectorySync, status::Status},
+    traits::synchronization::Synchronization,
+};
+use tempfile::TempDir;
+
+type Device = common::Device<DirectorySync>;
+
+impl Device {
+    /// A device holding the configuration, with a disk to snapshot it to.
+    fn new(root: &Path) -> Self {
+        let config = Configuration {
+            wallpaper: "a.png".into(),
+            ..Default::default()
+        };
+        let disk = root.join("disk");
+        std::fs::create_dir_all(&disk).unwrap();
+        Self::set_up(root, "laptop", Some(config), |context| {
+            DirectorySync::open(context, Some(disk))
+        })
+    }
+
+    /// The folder standing in for the disk, next to the device.
+    fn disk(&self) -> PathBuf {
+        self.context.data_dir().with_file_name("disk")
+    }
+
+    fn snapshot(&self, name: &str) -> Configuration {
+        Configuration::read_from(&self.disk().join(name).join(CONFIG_FILE)).unwrap()
+    }
+}
+
+#[test]
+fn snapshots_are_written_when_files_change() {
+    let root = TempDir::new().unwrap();
+    let device = Device::new(root.path());
+
+    assert_eq!(device.sync(), Status::RepoConfigured);
+    assert_eq!(device.sync(), Status::UpToDate);
+
+    device.edit(|config| config.wallpaper = "b.png".into());
+    let Status::ChangesUploaded { commit } = device.sync() else {
+        panic!("No snapshot was written for the change.");
+    };
+    let snapshots = device.sync.snapshots().unwrap();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0], commit);
+    assert_eq!(device.snapshot(&snapshots[0]).wallpaper, "b.png");
+    assert_eq!(device.snapshot(&snapshots[1]).wallpaper, "a.png");
+
+    assert!(matches!(
+        device.sync.handle(Message::Push).unwrap(),
+        Status::ChangesUploaded { .. }
+    ));
+    assert_eq!(device.sync.snapshots().unwrap().len(), 3);
+}
+
+#[test]
+fn old_snapshots_are_pruned_but_never_the_latest() {
+    let root = TempDir::new().unwrap();
+    let mut device = Device::new(root.path());
+    device.sync.set_retention(2, 30);
+    std::fs::create_dir_all(device.disk().join("20000101T000000Z")).unwrap();
+    std::fs::create_dir_all(device.disk().join("photos")).unwrap();
+
+    device.sync();
+    assert!(!device.disk().join("20000101T000000Z").exists());
+    for wallpaper in ["b.png", "c.png"] {
+        device.edit(|config| config.wallpaper = wallpaper.into());
+        device.sync();
+    }
+    let snapshots = device.sync.snapshots().unwrap();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(device.snapshot(&snapshots[0]).wallpaper, "c.png");
+    assert!(device.disk().join("photos").exists());
+
+    device.sync.set_retention(1, 0);
+    assert_eq!(device.sync.prune().unwrap(), [snapshots[1].clone()]);
+    assert_eq!(device.sync.snapshots().unwrap(), [snapshots[0].clone()]);
+
+    // Keeping them for longer than time can tell keeps them all.
+    device.sync.set_retention(0, u64::MAX);
+    assert!(device.sync.prune().unwrap().is_empty());
+}
+
+#[test]
+fn restoring_brings_back_a_snapshot_and_keeps_the_current_state() {
+    let root = TempDir::new().unwrap();
+    let device = Device::new(root.path());
+    device.sync();
+    let first = device.sync.snapshots().unwrap()[0].clone();
+
+    device.edit(|config| config.wallpaper = "b.png".into());
+    std::fs::create_dir_all(device.path.join("notes")).unwrap();
+    std::fs::write(device.path.join("notes/todo.txt"), "sync").unwrap();
+    device.sync();
+    device.edit(|config| config.wallpaper = "c.png".into());
+
+    assert_eq!(
+        device.sync.restore(&first).unwrap(),
+        Status::ChangesDownloaded {
+            commit: first.clone()
+        }
+    );
+    assert_eq!(device.config().wallpaper, "a.png");
+    assert!(!device.path.join("notes/todo.txt").exists());
+    let snapshots = device.sync.snapshots().unwrap();
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!(device.snapshot(&snapshots[0]).wallpaper, "c.png");
+
+    assert!(device.sync.restore("photos").is_err());
+    assert!(device.sync.restore(&format!("{first}/../{first}")).is_err());
+}
+
+#[test]
+fn an_unmounted_disk_leaves_changes_pending() {
+    let root = TempDir::new().unwrap();
+    let device = Device::new(root.path());
+    device.sync();
+    std::fs::rename(device.disk(), root.path().join("unmounted")).unwrap();
+
+    device.edit(|config| config.wallpaper = "b.png".into());
+    assert_eq!(device.sync(), Status::Offline { pending: 1 });
+}
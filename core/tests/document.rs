@@ -0,0 +1,151 @@
+//! Persists the CRDT document, survives crashes while saving it, and compacts it.
+
+use std::collections::BTreeSet;
+
+use symmetry_core::sync::document::Document;
+use tempfile::TempDir;
+
+fn document(entries: &[(&str, &str)]) -> Document {
+    let mut document = Document::new();
+    for (key, value) in entries {
+        document.set("laptop", key, Some(value.to_string()));
+    }
+    document
+}
+
+fn devices(names: &[&str]) -> BTreeSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn documents_survive_a_restart() {
+    let root = TempDir::new().unwrap();
+    let path = root.path().join("state/crdt.ron");
+    let mut saved = document(&[("config/wallpaper", "a.png")]);
+    saved.mark_synced_to(saved.clock().clone());
+    saved.save(&path).unwrap();
+
+    let loaded = Document::load(&path).unwrap();
+    assert_eq!(loaded, saved);
+    assert_eq!(loaded.version(), saved.version());
+    assert!(loaded.has_synced());
+}
+
+#[test]
+fn missing_documents_start_empty() {
+    let root = TempDir::new().unwrap();
+
+    assert!(Document::load(&root.path().join("crdt.ron"))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn a_crash_while_writing_leaves_the_document_intact() {
+    let root = TempDir::new().unwrap();
+    let path = root.path().join("crdt.ron");
+    let saved = document(&[("config/wallpaper", "a.png")]);
+    saved.save(&path).unwrap();
+    // The process died halfway through writing the next version.
+    std::fs::write(root.path().join("crdt.ron.tmp"), "(clock:(dots:{\"lap").unwrap();
+
+    assert_eq!(Document::load(&path).unwrap(), saved);
+    let next = document(&[("config/wallpaper", "b.png")]);
+    next.save(&path).unwrap();
+    assert_eq!(Document::load(&path).unwrap(), next);
+}
+
+#[test]
+fn a_crash_while_replacing_keeps_the_latest_version() {
+    let root = TempDir::new().unwrap();
+    let path = root.path().join("crdt.ron");
+    let first = document(&[("config/wallpaper", "a.png")]);
+    first.save(&path).unwrap();
+    let second = document(&[("config/wallpaper", "b.png")]);
+    second.save(&path).unwrap();
+    assert_eq!(
+        Document::load(&root.path().join("crdt.ron.bak")).unwrap(),
+        first
+    );
+    // The process died with the next version written out, before moving it into place.
+    document(&[("config/wallpaper", "c.png")])
+        .save(&root.path().join("crdt.ron.tmp"))
+        .unwrap();
+
+    assert_eq!(Document::load(&path).unwrap(), second);
+}
+
+#[test]
+fn corrupted_documents_fall_back_to_the_previous_version() {
+    let root = TempDir::new().unwrap();
+    let path = root.path().join("crdt.ron");
+    let first = document(&[("config/wallpaper", "a.png")]);
+    first.save(&path).unwrap();
+    document(&[("config/wallpaper", "b.png")])
+        .save(&path)
+        .unwrap();
+    std::fs::write(&path, "(clock:(dots:{").unwrap();
+
+    assert_eq!(Document::load(&path).unwrap(), first);
+}
+
+#[test]
+fn corrupted_documents_without_a_previous_version_are_not_dropped() {
+    let root = TempDir::new().unwrap();
+    let path = root.path().join("crdt.ron");
+    std::fs::write(&path, "(clock:(dots:{").unwrap();
+
+    assert!(Document::load(&path).is_err());
+}
+
+#[test]
+fn compaction_drops_removals_every_device_has_seen() {
+    let mut laptop = document(&[("files/a", "1"), ("files/b", "2")]);
+    laptop.set("laptop", "files/a", None);
+    laptop.record_peer_clock("desktop", laptop.clock().clone());
+    laptop.set("laptop", "files/b", None);
+
+    // The tablet never synchronized with the laptop, so it may miss both removals.
+    assert_eq!(laptop.compact(&devices(&["desktop", "tablet"])), 0);
+    assert_eq!(laptop.len(), 2);
+    // The desktop only saw the first removal.
+    assert_eq!(laptop.compact(&devices(&["desktop"])), 1);
+    assert_eq!(laptop.len(), 1);
+    laptop.record_peer_clock("desktop", laptop.clock().clone());
+    assert_eq!(laptop.compact(&devices(&["desktop"])), 1);
+    assert_eq!(laptop.len(), 0);
+}
+
+#[test]
+fn compacted_removals_stay_removed() {
+    let mut laptop = document(&[("files/a", "1")]);
+    let mut desktop = Document::new();
+    desktop.merge(laptop.delta(desktop.clock()));
+    let stale = desktop.delta(&Default::default());
+
+    laptop.set("laptop", "files/a", None);
+    laptop.record_peer_clock("desktop", laptop.clock().clone());
+    laptop.compact(&devices(&["desktop"]));
+    // A device that missed the removal sends the old value again.
+    assert!(laptop.merge(stale).is_empty());
+    assert_eq!(laptop.get("files/a"), None);
+    assert_eq!(laptop.len(), 0);
+}
+
+#[test]
+fn compaction_forgets_devices_no_longer_synchronized_with() {
+    let mut laptop = document(&[("files/a", "1")]);
+    laptop.set("laptop", "files/a", None);
+    laptop.record_peer_clock("desktop", laptop.clock().clone());
+
+    // Once the tablet is gone, only the desktop has to see the removal.
+    assert_eq!(laptop.compact(&devices(&["desktop"])), 1);
+    let root = TempDir::new().unwrap();
+    let path = root.path().join("crdt.ron");
+    laptop.save(&path).unwrap();
+    let size = std::fs::metadata(&path).unwrap().len();
+    laptop.record_peer_clock("tablet", laptop.clock().clone());
+    laptop.compact(&devices(&["desktop"]));
+    laptop.save(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+}
@@ -0,0 +1,180 @@
+//! Moves tracked files between a simulated device and its synchronized directory.
+
+use symmetry_core::{
+    configuration::Configuration,
+    context::SymmetryContext,
+    files::{
+        tracked_file::{DeployMode, TrackedFile},
+        Files,
+    },
+};
+use tempfile::TempDir;
+
+fn tracked(source: &str, repo_path: &str) -> Configuration {
+    Configuration {
+        files: vec![TrackedFile {
+            source: source.into(),
+            repo_path: repo_path.into(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn tracked_files_are_captured_into_the_repository() {
+    let root = TempDir::new().unwrap();
+    let source = root.path().join("home/.bashrc");
+    std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+    std::fs::write(&source, "alias ll='ls -l'").unwrap();
+    let context = SymmetryContext::new(root.path());
+    let files = Files::new(&context);
+
+    let config = tracked(&source.to_string_lossy(), "files/.bashrc");
+    files.capture(&config).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(context.repo_path().join("files/.bashrc")).unwrap(),
+        "alias ll='ls -l'"
+    );
+}
+
+#[test]
+fn home_is_the_one_of_the_context() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::new(root.path()).with_home_dir(root.path().join("home"));
+    std::fs::create_dir_all(context.repo_path().join("files")).unwrap();
+    std::fs::write(
+        context.repo_path().join("files/.bashrc"),
+        "alias ll='ls -l'",
+    )
+    .unwrap();
+
+    let config = tracked("~/.bashrc", "files/.bashrc");
+    Files::new(&context).deploy(&config).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(root.path().join("home/.bashrc")).unwrap(),
+        "alias ll='ls -l'"
+    );
+}
+
+#[test]
+fn repository_paths_never_lead_out_of_it() {
+    let root = TempDir::new().unwrap();
+    let source = root.path().join("home/.bashrc");
+    std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+    std::fs::write(&source, "alias ll='ls -l'").unwrap();
+    let context = SymmetryContext::new(root.path());
+    let files = Files::new(&context);
+
+    for repo_path in ["../escaped", "/tmp/escaped", ".git/hooks/post-commit"] {
+        let config = tracked(&source.to_string_lossy(), repo_path);
+        assert!(files.capture(&config).is_err(), "{repo_path}");
+        assert!(files.deploy(&config).is_err(), "{repo_path}");
+    }
+    assert!(!context.local_path().join("escaped").exists());
+    assert!(!context.repo_path().join(".git").exists());
+}
+
+#[test]
+fn differing_device_files_are_held_until_accepted() {
+    let root = TempDir::new().unwrap();
+    let source = root.path().join("home/.bashrc");
+    std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+    std::fs::write(&source, "alias ls='ls -a'").unwrap();
+    let context = SymmetryContext::new(root.path());
+    std::fs::create_dir_all(context.repo_path().join("files")).unwrap();
+    std::fs::write(
+        context.repo_path().join("files/.bashrc"),
+        "alias ll='ls -l'",
+    )
+    .unwrap();
+    let files = Files::new(&context);
+
+    let mut config = tracked(&source.to_string_lossy(), "files/.bashrc");
+    let differences = files.deploy(&config).unwrap();
+    assert_eq!(differences.len(), 1);
+    assert_eq!(differences[0].source, source);
+    assert_eq!(
+        differences[0].diff,
+        "+ alias ll='ls -l'\n- alias ls='ls -a'"
+    );
+    assert_eq!(
+        std::fs::read_to_string(&source).unwrap(),
+        "alias ls='ls -a'"
+    );
+
+    // The held file isn't published before the user decides.
+    files.capture(&config).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(context.repo_path().join("files/.bashrc")).unwrap(),
+        "alias ll='ls -l'"
+    );
+    assert_eq!(files.differences(&config).unwrap(), differences);
+
+    files.accept(&config, &source).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&source).unwrap(),
+        "alias ll='ls -l'"
+    );
+    assert!(files.differences(&config).unwrap().is_empty());
+    assert!(files.deploy(&config).unwrap().is_empty());
+
+    files.restore(&mut config).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&source).unwrap(),
+        "alias ls='ls -a'"
+    );
+}
+
+#[test]
+fn kept_device_files_replace_the_synchronized_version() {
+    let root = TempDir::new().unwrap();
+    let source = root.path().join("home/.bashrc");
+    std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+    std::fs::write(&source, "alias ls='ls -a'").unwrap();
+    let context = SymmetryContext::new(root.path());
+    let target = context.repo_path().join("files/.bashrc");
+    std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+    std::fs::write(&target, "alias ll='ls -l'").unwrap();
+    let files = Files::new(&context);
+
+    let config = tracked(&source.to_string_lossy(), "files/.bashrc");
+    assert_eq!(files.deploy(&config).unwrap().len(), 1);
+
+    files.keep(&config, &source).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&target).unwrap(),
+        "alias ls='ls -a'"
+    );
+    assert!(files.differences(&config).unwrap().is_empty());
+    assert!(files.deploy(&config).unwrap().is_empty());
+    assert_eq!(
+        std::fs::read_to_string(&source).unwrap(),
+        "alias ls='ls -a'"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn restored_files_are_left_alone_by_the_next_sync() {
+    let root = TempDir::new().unwrap();
+    let source = root.path().join("home/.bashrc");
+    std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+    std::fs::write(&source, "alias ll='ls -l'").unwrap();
+    let context = SymmetryContext::new(root.path());
+    let files = Files::new(&context);
+
+    let mut config = tracked(&source.to_string_lossy(), "files/.bashrc");
+    config.files[0].mode = DeployMode::Symlink;
+    files.capture(&config).unwrap();
+    assert!(source.symlink_metadata().unwrap().is_symlink());
+
+    files.restore(&mut config).unwrap();
+    files.capture(&config).unwrap();
+    files.deploy(&config).unwrap();
+    assert!(!source.symlink_metadata().unwrap().is_symlink());
+    assert_eq!(
+        std::fs::read_to_string(&source).unwrap(),
+        "alias ll='ls -l'"
+    );
+}
@@ -0,0 +1,171 @@
+//! Synchronizes simulated devices through a shared folder, as Syncthing or a USB drive would
+//! carry it between them.
+
+mod common;
+
+use std::path::Path;
+
+use symmetry_core::{
+    color_scheme::ColorScheme,
+    configuration::Configuration,
+    context::SymmetryContext,
+    sync::{providers::crdt::CrdtSync, status::Status},
+};
+use tempfile::TempDir;
+
+type Device = common::Device<CrdtSync>;
+
+impl Device {
+    fn new(root: &Path, name: &str, config: Configuration) -> Self {
+        Self::set_up(root, name, Some(config), |context| {
+            let mut sync = CrdtSync::open(context);
+            sync.set_discovery(false);
+            sync.set_folder(Some(root.join("shared")));
+            sync
+        })
+    }
+}
+
+/// Makes every device trust all the others, as pairing them would.
+fn trust(devices: &[&Device]) {
+    for device in devices {
+        device.edit(|config| {
+            for other in devices {
+                config.devices.trust(other.identity().device());
+            }
+        });
+    }
+}
+
+/// A device holding the configuration and a new one, both using the shared folder.
+fn devices(root: &TempDir) -> (Device, Device) {
+    std::fs::create_dir_all(root.path().join("shared")).unwrap();
+    let config = Configuration {
+        color_scheme: ColorScheme::Dark,
+        wallpaper: "a.png".into(),
+        ..Default::default()
+    };
+    let laptop = Device::new(root.path(), "laptop", config);
+    let desktop = Device::new(root.path(), "desktop", Configuration::default());
+    trust(&[&laptop, &desktop]);
+    laptop.sync();
+    (laptop, desktop)
+}
+
+fn shared_files(root: &TempDir) -> Vec<String> {
+    let mut files: Vec<String> = std::fs::read_dir(root.path().join("shared"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn new_devices_adopt_the_configuration_from_the_folder() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = devices(&root);
+
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config(), laptop.config());
+    assert_eq!(desktop.sync(), Status::UpToDate);
+}
+
+#[test]
+fn changes_flow_both_ways_through_the_folder() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = devices(&root);
+    desktop.sync();
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    std::fs::write(desktop.path.join("notes.txt"), "sync").unwrap();
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(laptop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(laptop.config().wallpaper, "b.png");
+    assert_eq!(
+        std::fs::read_to_string(laptop.path.join("notes.txt")).unwrap(),
+        "sync"
+    );
+
+    laptop.edit(|config| config.color_scheme = ColorScheme::Light);
+    std::fs::remove_file(laptop.path.join("notes.txt")).unwrap();
+    laptop.sync();
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config().color_scheme, ColorScheme::Light);
+    assert!(!desktop.path.join("notes.txt").exists());
+}
+
+#[test]
+fn each_device_only_writes_its_own_file() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = devices(&root);
+    desktop.sync();
+    let laptop_file = root
+        .path()
+        .join(format!("shared/{}.ron", laptop.identity().id()));
+    let written = std::fs::read(&laptop_file).unwrap();
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    desktop.sync();
+
+    assert_eq!(std::fs::read(&laptop_file).unwrap(), written);
+    let mut expected = vec![
+        format!("{}.ron", laptop.identity().id()),
+        format!("{}.ron", desktop.identity().id()),
+    ];
+    expected.sort();
+    assert_eq!(shared_files(&root), expected);
+}
+
+#[test]
+fn the_folder_never_holds_plaintext() {
+    let root = TempDir::new().unwrap();
+    let (_laptop, desktop) = devices(&root);
+    desktop.edit(|config| config.wallpaper = "very-secret-wallpaper.png".into());
+    desktop.sync();
+    desktop.sync();
+
+    for name in shared_files(&root) {
+        let data = std::fs::read_to_string(root.path().join("shared").join(name)).unwrap();
+        assert!(!data.contains("very-secret-wallpaper"));
+    }
+}
+
+#[test]
+fn files_from_untrusted_devices_are_ignored() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = devices(&root);
+    let stranger = Device::new(root.path(), "stranger", Configuration::default());
+    stranger.edit(|config| config.wallpaper = "evil.png".into());
+    // The stranger trusts the others, but they don't trust it back.
+    trust(&[&stranger, &laptop, &desktop]);
+    laptop.edit(|config| {
+        config.devices.trusted.remove(&stranger.identity().id());
+    });
+    stranger.sync();
+
+    laptop.sync();
+    assert_eq!(laptop.config().wallpaper, "a.png");
+}
+
+#[test]
+fn a_missing_folder_leaves_changes_pending() {
+    let root = TempDir::new().unwrap();
+    let (_laptop, desktop) = devices(&root);
+    desktop.sync();
+    std::fs::remove_dir_all(root.path().join("shared")).unwrap();
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    assert_eq!(desktop.sync(), Status::Offline { pending: 1 });
+}
+
+#[test]
+fn a_folder_that_cant_be_resolved_is_reported() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::new(root.path().join("laptop"));
+    let mut config = Configuration::default();
+    config.service_config.crdt.folder = "{{ unclosed".into();
+    config.init_in(&context).unwrap();
+
+    assert!(CrdtSync::with_context(&context).is_err());
+}
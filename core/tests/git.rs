@@ -0,0 +1,492 @@
+//! Synchronizes two simulated devices through a local bare repository.
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use git2::{ErrorClass, ErrorCode, Repository};
+use symmetry_core::{
+    color_scheme::ColorScheme,
+    configuration::{changes::Field, Configuration, CONFIG_FILE},
+    context::SymmetryContext,
+    sync::{
+        message::Message,
+        outbox::Outbox,
+        overrides::Overrides,
+        providers::config::git::ConflictStrategy,
+        providers::git::{Failure, GitSync},
+        status::Status,
+    },
+    traits::synchronization::Synchronization,
+};
+use tempfile::TempDir;
+
+type Device = common::Device<GitSync>;
+
+impl Device {
+    fn new(root: &Path, name: &str, url: &str, config: Option<Configuration>) -> Self {
+        Self::set_up(root, name, config, |context| GitSync::open(context, url))
+    }
+
+    fn update(&self) -> Status {
+        self.sync.handle(Message::Update).unwrap()
+    }
+
+    /// The message of the latest commit and how many commits lead to it.
+    fn head(&self) -> (String, usize) {
+        let repo = Repository::open(&self.path).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let mut walk = repo.revwalk().unwrap();
+        walk.push(head.id()).unwrap();
+        (head.message().unwrap().to_string(), walk.count())
+    }
+}
+
+fn configuration(wallpaper: &str) -> Configuration {
+    Configuration {
+        color_scheme: ColorScheme::Dark,
+        wallpaper: wallpaper.into(),
+        ..Default::default()
+    }
+}
+
+/// Two devices sharing a remote that doesn't exist yet, as on a freshly mounted drive.
+fn devices(root: &TempDir) -> (Device, Device, PathBuf) {
+    std::fs::create_dir_all(root.path().join("usb")).unwrap();
+    let remote = root.path().join("usb/symmetry.git");
+    let url = remote.to_string_lossy().to_string();
+    let laptop = Device::new(root.path(), "laptop", &url, Some(configuration("a.png")));
+    let desktop = Device::new(root.path(), "desktop", &url, Some(configuration("b.png")));
+    (laptop, desktop, remote)
+}
+
+/// Connects both devices, the laptop publishes first and the desktop adopts its state.
+fn connected(root: &TempDir) -> (Device, Device) {
+    let (laptop, desktop, _) = devices(root);
+    assert!(matches!(laptop.sync(), Status::RepoConfigured));
+    assert!(matches!(desktop.sync(), Status::RepoConfigured));
+    (laptop, desktop)
+}
+
+#[test]
+fn first_sync_initializes_a_bare_remote() {
+    let root = TempDir::new().unwrap();
+    let (laptop, _, remote) = devices(&root);
+
+    assert!(matches!(laptop.sync(), Status::RepoConfigured));
+
+    let remote = Repository::open_bare(remote).unwrap();
+    let head = remote.find_reference("refs/heads/main").unwrap();
+    let tree = head.peel_to_tree().unwrap();
+    assert!(tree.get_name(CONFIG_FILE).is_some());
+    assert!(matches!(laptop.sync(), Status::UpToDate));
+}
+
+#[test]
+fn file_urls_are_local_remotes() {
+    let root = TempDir::new().unwrap();
+    std::fs::create_dir_all(root.path().join("nas")).unwrap();
+    let url = format!("file://{}", root.path().join("nas/symmetry.git").display());
+    let laptop = Device::new(root.path(), "laptop", &url, Some(configuration("a.png")));
+
+    assert!(matches!(laptop.sync(), Status::RepoConfigured));
+    assert!(Repository::open_bare(root.path().join("nas/symmetry.git")).is_ok());
+}
+
+#[test]
+fn home_relative_remotes_are_in_the_home_of_the_context() {
+    let root = TempDir::new().unwrap();
+    std::fs::create_dir_all(root.path().join("laptop/home/usb")).unwrap();
+    let laptop = Device::new(
+        root.path(),
+        "laptop",
+        "~/usb/symmetry.git",
+        Some(configuration("a.png")),
+    );
+
+    assert!(matches!(laptop.sync(), Status::RepoConfigured));
+    assert!(Repository::open_bare(root.path().join("laptop/home/usb/symmetry.git")).is_ok());
+}
+
+#[test]
+fn only_remote_hosts_need_the_network() {
+    let root = TempDir::new().unwrap();
+    let context = SymmetryContext::new(root.path());
+    let needs_network = |url: &str| GitSync::open(&context, url).requires_network();
+
+    assert!(needs_network("https://example.com/me/symmetry.git"));
+    assert!(needs_network("git@example.com:me/symmetry.git"));
+    assert!(!needs_network("file:///mnt/usb/symmetry.git"));
+    assert!(!needs_network("/mnt/usb/symmetry.git"));
+}
+
+#[test]
+fn new_device_adopts_the_remote_configuration() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    assert_eq!(desktop.config(), laptop.config());
+    assert_eq!(desktop.config().wallpaper, "a.png");
+    assert!(matches!(desktop.sync(), Status::UpToDate));
+}
+
+#[test]
+fn remote_changes_fast_forward() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::NewChangesDetected { .. }));
+
+    assert!(matches!(desktop.update(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config().wallpaper, "c.png");
+    assert!(matches!(desktop.sync(), Status::UpToDate));
+}
+
+#[test]
+fn remote_changes_can_be_previewed() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    laptop.edit(|config| {
+        config.color_scheme = ColorScheme::Light;
+        config.wallpaper = "c.png".into();
+    });
+    desktop.edit(|config| {
+        config
+            .variables
+            .insert("desktop".into(), Default::default());
+    });
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(laptop.sync(), Status::NewChangesDetected { .. }));
+
+    let changes = laptop.sync.preview().unwrap();
+    let fields: Vec<Field> = changes.iter().map(|change| change.field).collect();
+    assert_eq!(fields, [Field::Variables]);
+
+    let changes = desktop.sync.preview().unwrap();
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn selected_remote_changes_are_accepted() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    laptop.edit(|config| {
+        config.color_scheme = ColorScheme::Light;
+        config.wallpaper = "c.png".into();
+    });
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::NewChangesDetected { .. }));
+    let fields: Vec<Field> = desktop
+        .sync
+        .preview()
+        .unwrap()
+        .iter()
+        .map(|change| change.field)
+        .collect();
+    assert_eq!(fields, [Field::ColorScheme, Field::Wallpaper]);
+
+    let status = desktop
+        .sync
+        .handle(Message::Accept(vec![Field::Wallpaper]))
+        .unwrap();
+    assert!(matches!(status, Status::ChangesDownloaded { .. }));
+    let config = desktop.config();
+    assert_eq!(config, laptop.config());
+    let used = Overrides::load(&desktop.state).apply(&config);
+    assert_eq!(used.wallpaper, "c.png");
+    assert_eq!(used.color_scheme, ColorScheme::Dark);
+
+    // The declined color scheme stays on the desktop, the laptop keeps its own.
+    assert!(matches!(desktop.sync(), Status::UpToDate));
+    assert!(matches!(laptop.sync(), Status::UpToDate));
+    assert_eq!(laptop.config().color_scheme, ColorScheme::Light);
+
+    // Accepting it later drops the override.
+    laptop.edit(|config| config.color_scheme = ColorScheme::Dark);
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::NewChangesDetected { .. }));
+    desktop
+        .sync
+        .handle(Message::Accept(vec![Field::ColorScheme]))
+        .unwrap();
+    assert!(Overrides::load(&desktop.state).fields.is_empty());
+}
+
+#[test]
+fn divergent_changes_are_merged() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    desktop.edit(|config| config.color_scheme = ColorScheme::Light);
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+
+    let merged = desktop.config();
+    assert_eq!(merged.wallpaper, "c.png");
+    assert_eq!(merged.color_scheme, ColorScheme::Light);
+
+    assert!(matches!(laptop.sync(), Status::NewChangesDetected { .. }));
+    laptop.update();
+    assert_eq!(laptop.config(), merged);
+}
+
+#[test]
+fn divergent_files_are_merged() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    std::fs::write(laptop.path.join("laptop.txt"), "laptop").unwrap();
+    std::fs::write(desktop.path.join("desktop.txt"), "desktop").unwrap();
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+    laptop.update();
+
+    for device in [&laptop, &desktop] {
+        assert!(device.path.join("laptop.txt").is_file());
+        assert!(device.path.join("desktop.txt").is_file());
+    }
+}
+
+#[test]
+fn unchanged_trees_are_not_committed() {
+    let root = TempDir::new().unwrap();
+    let (laptop, _) = connected(&root);
+    let head = laptop.head();
+
+    assert!(matches!(laptop.sync(), Status::UpToDate));
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    laptop.edit(|config| config.wallpaper = "a.png".into());
+    assert!(matches!(laptop.sync(), Status::UpToDate));
+    assert_eq!(laptop.head(), head);
+}
+
+#[test]
+fn commit_messages_describe_the_changes() {
+    let root = TempDir::new().unwrap();
+    let (laptop, _) = connected(&root);
+
+    laptop.edit(|config| {
+        config.color_scheme = ColorScheme::Light;
+        config.wallpaper = "c.png".into();
+    });
+    std::fs::write(laptop.path.join("notes.txt"), "notes").unwrap();
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+
+    let (message, _) = laptop.head();
+    assert_eq!(
+        message,
+        "color_scheme: Dark → Light; wallpaper changed; notes.txt updated"
+    );
+}
+
+#[test]
+fn rapid_changes_are_batched() {
+    let root = TempDir::new().unwrap();
+    let (mut laptop, desktop) = connected(&root);
+    let (_, commits) = laptop.head();
+    laptop.sync.set_batch_window(Duration::from_secs(3600));
+
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    assert!(matches!(laptop.sync(), Status::ChangesCommitted { .. }));
+    laptop.edit(|config| config.color_scheme = ColorScheme::Light);
+    assert!(matches!(laptop.sync(), Status::ChangesCommitted { .. }));
+    assert!(matches!(desktop.sync(), Status::UpToDate));
+
+    let (message, batched) = laptop.head();
+    assert_eq!(batched, commits + 1);
+    assert_eq!(message, "color_scheme: Dark → Light; wallpaper changed");
+    let due = laptop.sync.pending().unwrap();
+    assert!(due > Duration::from_secs(3500) && due <= Duration::from_secs(3600));
+
+    laptop.sync.set_batch_window(Duration::ZERO);
+    assert_eq!(laptop.sync.pending(), None);
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::NewChangesDetected { .. }));
+}
+
+#[test]
+fn ignored_files_are_not_synchronized() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    std::fs::create_dir_all(laptop.path.join("state")).unwrap();
+    std::fs::write(laptop.path.join("state/document.ron"), "state").unwrap();
+    std::fs::create_dir_all(laptop.path.join("symmetry-backups")).unwrap();
+    std::fs::write(laptop.path.join("symmetry-backups/.vimrc"), "original").unwrap();
+    assert!(matches!(laptop.sync(), Status::UpToDate));
+
+    assert!(desktop.path.join(".gitignore").is_file());
+    assert!(!desktop.path.join("state").exists());
+    assert!(!desktop.path.join("symmetry-backups").exists());
+
+    // Anything else is the user's, even if it looks like a tool left it behind.
+    std::fs::write(laptop.path.join("notes.log"), "log").unwrap();
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    desktop.sync();
+    desktop.update();
+    assert!(desktop.path.join("notes.log").is_file());
+}
+
+fn conflicting(root: &TempDir, strategy: ConflictStrategy) -> Device {
+    let (laptop, mut desktop) = connected(root);
+    desktop.sync.set_conflict_strategy(strategy);
+
+    laptop.edit(|config| config.wallpaper = "laptop.png".into());
+    desktop.edit(|config| config.wallpaper = "desktop.png".into());
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+
+    laptop.update();
+    assert_eq!(laptop.config(), desktop.config());
+    desktop
+}
+
+#[test]
+fn conflicts_keep_the_remote_version() {
+    let root = TempDir::new().unwrap();
+    let desktop = conflicting(&root, ConflictStrategy::Theirs);
+    assert_eq!(desktop.config().wallpaper, "laptop.png");
+}
+
+#[test]
+fn conflicts_keep_the_local_version() {
+    let root = TempDir::new().unwrap();
+    let desktop = conflicting(&root, ConflictStrategy::Ours);
+    assert_eq!(desktop.config().wallpaper, "desktop.png");
+}
+
+#[test]
+fn conflicts_are_reported_when_asking() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop) = connected(&root);
+
+    laptop.edit(|config| config.wallpaper = "laptop.png".into());
+    desktop.edit(|config| config.wallpaper = "desktop.png".into());
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+
+    let conflict = Status::Conflict {
+        fields: vec![Field::Wallpaper],
+        paths: vec![CONFIG_FILE.into()],
+    };
+    assert_eq!(desktop.sync(), conflict);
+    assert_eq!(desktop.sync(), conflict);
+    assert_eq!(desktop.config().wallpaper, "desktop.png");
+
+    let status = desktop
+        .sync
+        .handle(Message::Resolve(ConflictStrategy::Theirs))
+        .unwrap();
+    assert!(matches!(status, Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config().wallpaper, "laptop.png");
+    assert!(matches!(desktop.sync(), Status::UpToDate));
+}
+
+#[test]
+fn unreachable_remotes_keep_local_changes() {
+    let root = TempDir::new().unwrap();
+    let (laptop, _) = connected(&root);
+    let url = root.path().join("unmounted/symmetry.git");
+    let offline = GitSync::open(
+        &SymmetryContext::new(root.path().join("laptop")),
+        url.to_string_lossy(),
+    );
+
+    let state = SymmetryContext::new(root.path().join("laptop")).state_path();
+
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    assert_eq!(offline.sync().unwrap(), Status::Offline { pending: 1 });
+    laptop.edit(|config| config.color_scheme = ColorScheme::Light);
+    assert_eq!(offline.sync().unwrap(), Status::Offline { pending: 2 });
+    assert!(!url.exists());
+
+    let outbox = Outbox::load(&state);
+    let summaries: Vec<&str> = outbox
+        .pending
+        .iter()
+        .map(|pending| pending.summary.as_str())
+        .collect();
+    assert_eq!(
+        summaries,
+        ["color_scheme: Dark → Light", "wallpaper changed"]
+    );
+
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(Outbox::load(&state).is_empty());
+    assert!(!Outbox::path(&state).exists());
+}
+
+#[test]
+fn reset_discards_local_changes() {
+    let root = TempDir::new().unwrap();
+    let (mut laptop, _) = connected(&root);
+    laptop.sync.set_batch_window(Duration::from_secs(3600));
+
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    assert!(matches!(laptop.sync(), Status::ChangesCommitted { .. }));
+    std::fs::write(laptop.path.join("scratch.txt"), "scratch").unwrap();
+
+    let status = laptop.sync.handle(Message::Reset).unwrap();
+    assert!(matches!(status, Status::ChangesDownloaded { .. }));
+    assert_eq!(laptop.config().wallpaper, "a.png");
+    assert!(!laptop.path.join("scratch.txt").exists());
+}
+
+#[test]
+fn push_publishes_without_pulling() {
+    let root = TempDir::new().unwrap();
+    let (mut laptop, desktop) = connected(&root);
+    laptop.sync.set_batch_window(Duration::from_secs(3600));
+
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    assert!(matches!(laptop.sync(), Status::ChangesCommitted { .. }));
+    let status = laptop.sync.handle(Message::Push).unwrap();
+    assert!(matches!(status, Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::NewChangesDetected { .. }));
+    assert_eq!(laptop.sync.handle(Message::Push).unwrap(), Status::UpToDate);
+}
+
+#[test]
+fn failures_are_told_apart_by_their_code() {
+    let failure = |code, class| Failure::of(&git2::Error::new(code, class, "failed"));
+    assert_eq!(
+        failure(ErrorCode::Auth, ErrorClass::Http),
+        Failure::Unauthorized
+    );
+    assert_eq!(
+        failure(ErrorCode::GenericError, ErrorClass::Net),
+        Failure::Unreachable
+    );
+    assert_eq!(
+        failure(ErrorCode::Certificate, ErrorClass::Ssl),
+        Failure::Other
+    );
+    assert_eq!(
+        failure(ErrorCode::NotFound, ErrorClass::Os),
+        Failure::Unreachable
+    );
+    assert_eq!(
+        failure(ErrorCode::GenericError, ErrorClass::Os),
+        Failure::Other
+    );
+}
+
+#[test]
+fn broken_local_remotes_are_not_offline() {
+    let root = TempDir::new().unwrap();
+    let (laptop, _) = connected(&root);
+    let url = root.path().join("symmetry.git");
+    std::fs::write(&url, "not a repository").unwrap();
+    let broken = GitSync::open(&laptop.context, url.to_string_lossy());
+
+    laptop.edit(|config| config.wallpaper = "c.png".into());
+    assert!(broken.sync().is_err());
+}
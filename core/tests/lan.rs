@@ -0,0 +1,336 @@
+//! Synchronizes simulated devices directly with each other over localhost.
+
+mod common;
+
+use std::{path::Path, time::Duration};
+
+use symmetry_core::{
+    color_scheme::ColorScheme,
+    configuration::Configuration,
+    context::SymmetryContext,
+    secrets::cipher::Cipher,
+    sync::{
+        document::Document,
+        lan::{
+            pairing::{Invite, Joining},
+            Server,
+        },
+        providers::crdt::{CrdtSync, DOCUMENT_FILE},
+        status::Status,
+    },
+    traits::synchronization::Synchronization,
+    trust::invitation::Invitation,
+};
+use tempfile::TempDir;
+
+type Device = common::Device<CrdtSync>;
+
+impl Device {
+    fn new(root: &Path, name: &str, config: Configuration) -> Self {
+        Self::set_up(root, name, Some(config), |context| {
+            let mut sync = CrdtSync::open(context);
+            sync.set_discovery(false);
+            sync
+        })
+    }
+
+    fn document(&self) -> Document {
+        Document::load(&self.state.join(DOCUMENT_FILE)).unwrap()
+    }
+
+    fn listen(&self) -> Server {
+        self.sync.listen("127.0.0.1:0").unwrap()
+    }
+
+    fn connect(&mut self, servers: &[&Server]) {
+        let peers = servers.iter().map(|server| server.address()).collect();
+        self.sync.set_peers(peers);
+    }
+}
+
+/// Makes every device trust all the others, as pairing them would.
+fn trust(devices: &[&Device]) {
+    for device in devices {
+        device.edit(|config| {
+            for other in devices {
+                config.devices.trust(other.identity().device());
+            }
+        });
+    }
+}
+
+fn configuration(wallpaper: &str) -> Configuration {
+    Configuration {
+        color_scheme: ColorScheme::Dark,
+        wallpaper: wallpaper.into(),
+        ..Default::default()
+    }
+}
+
+/// A device that already holds the configuration, and a new one connecting to it.
+fn devices(root: &TempDir) -> (Device, Device, Server) {
+    let laptop = Device::new(root.path(), "laptop", configuration("a.png"));
+    let mut desktop = Device::new(root.path(), "desktop", Configuration::default());
+    trust(&[&laptop, &desktop]);
+    // The laptop records its configuration before anyone connects.
+    assert!(matches!(laptop.sync(), Status::Offline { .. }));
+    let server = laptop.listen();
+    desktop.connect(&[&server]);
+    (laptop, desktop, server)
+}
+
+#[test]
+fn new_devices_adopt_the_configuration() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop, _server) = devices(&root);
+
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config(), laptop.config());
+    assert_eq!(desktop.sync(), Status::UpToDate);
+}
+
+#[test]
+fn changes_flow_both_ways() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop, _server) = devices(&root);
+    desktop.sync();
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    std::fs::create_dir_all(desktop.path.join("notes")).unwrap();
+    std::fs::write(desktop.path.join("notes/todo.txt"), "sync").unwrap();
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+    assert_eq!(laptop.config().wallpaper, "b.png");
+    assert_eq!(
+        std::fs::read_to_string(laptop.path.join("notes/todo.txt")).unwrap(),
+        "sync"
+    );
+
+    laptop.edit(|config| config.color_scheme = ColorScheme::Light);
+    std::fs::remove_file(laptop.path.join("notes/todo.txt")).unwrap();
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config().color_scheme, ColorScheme::Light);
+    assert!(!desktop.path.join("notes/todo.txt").exists());
+}
+
+#[test]
+fn concurrent_edits_converge() {
+    let root = TempDir::new().unwrap();
+    let (laptop, mut desktop, laptop_server) = devices(&root);
+    let mut tablet = Device::new(root.path(), "tablet", Configuration::default());
+    trust(&[&laptop, &desktop, &tablet]);
+    laptop.sync();
+    desktop.sync();
+    let desktop_server = desktop.listen();
+    tablet.connect(&[&laptop_server, &desktop_server]);
+    tablet.sync();
+
+    // Every device edits while apart, two of them the same field.
+    laptop.edit(|config| config.color_scheme = ColorScheme::Light);
+    desktop.edit(|config| config.wallpaper = "desktop.png".into());
+    tablet.edit(|config| config.wallpaper = "tablet.png".into());
+    desktop.connect(&[]);
+    desktop.sync();
+    // Concurrent writes to the same field go to the latest one.
+    std::thread::sleep(Duration::from_millis(10));
+    tablet.sync();
+    desktop.connect(&[&laptop_server]);
+    desktop.sync();
+    tablet.sync();
+
+    let config = laptop.config();
+    assert_eq!(config.color_scheme, ColorScheme::Light);
+    assert_eq!(config.wallpaper, "tablet.png");
+    assert_eq!(desktop.config(), config);
+    assert_eq!(tablet.config(), config);
+}
+
+#[test]
+fn removals_are_compacted_once_every_device_has_them() {
+    let root = TempDir::new().unwrap();
+    let (laptop, desktop, _server) = devices(&root);
+    desktop.sync();
+    let entries = desktop.document().len();
+
+    std::fs::write(desktop.path.join("notes.txt"), "sync").unwrap();
+    desktop.sync();
+    assert_eq!(desktop.document().len(), entries + 1);
+    std::fs::remove_file(desktop.path.join("notes.txt")).unwrap();
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+
+    assert!(!laptop.path.join("notes.txt").exists());
+    assert_eq!(desktop.document().len(), entries);
+    assert_eq!(laptop.document().len(), entries);
+}
+
+#[test]
+fn restarted_devices_pick_up_where_they_left_off() {
+    let root = TempDir::new().unwrap();
+    let (_laptop, desktop, server) = devices(&root);
+    desktop.sync();
+    let version = desktop.document().version();
+
+    let mut restarted = CrdtSync::open(&SymmetryContext::new(root.path().join("desktop")));
+    restarted.set_discovery(false);
+    restarted.set_peers(vec![server.address()]);
+    assert_eq!(restarted.sync().unwrap(), Status::UpToDate);
+    assert_eq!(desktop.document().version(), version);
+}
+
+#[test]
+fn untrusted_devices_are_rejected() {
+    let root = TempDir::new().unwrap();
+    let (laptop, _, server) = devices(&root);
+    let mut stranger = Device::new(root.path(), "stranger", configuration("evil.png"));
+    // The stranger trusting the laptop isn't enough.
+    stranger.edit(|config| config.devices.trust(laptop.identity().device()));
+    trust(&[&stranger]);
+    stranger.connect(&[&server]);
+
+    assert_eq!(stranger.sync(), Status::AuthRequired);
+    assert_eq!(laptop.config().wallpaper, "a.png");
+}
+
+#[test]
+fn revoked_devices_are_rejected() {
+    let root = TempDir::new().unwrap();
+    let (laptop, mut desktop, laptop_server) = devices(&root);
+    let mut tablet = Device::new(root.path(), "tablet", Configuration::default());
+    trust(&[&laptop, &desktop, &tablet]);
+    laptop.sync();
+    desktop.sync();
+    let desktop_server = desktop.listen();
+    tablet.connect(&[&laptop_server]);
+    tablet.sync();
+
+    let id = tablet.identity().id();
+    laptop.edit(|config| {
+        config.devices.trusted.remove(&id);
+    });
+    desktop.connect(&[&laptop_server]);
+    desktop.sync();
+    assert!(!desktop.config().devices.trusted.contains_key(&id));
+
+    tablet.connect(&[&laptop_server, &desktop_server]);
+    assert_eq!(tablet.sync(), Status::AuthRequired);
+}
+
+#[test]
+fn unreachable_devices_leave_changes_pending() {
+    let root = TempDir::new().unwrap();
+    let (_laptop, desktop, server) = devices(&root);
+    desktop.sync();
+    drop(server);
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    assert_eq!(desktop.sync(), Status::Offline { pending: 1 });
+}
+
+#[test]
+fn pairing_from_an_invitation_trusts_the_new_device() {
+    let root = TempDir::new().unwrap();
+    let laptop = Device::new(root.path(), "laptop", configuration("a.png"));
+    let mut desktop = Device::new(root.path(), "desktop", Configuration::default());
+    let key = Cipher::generate_key();
+    let invite = Invite::new(&laptop.identity(), "127.0.0.1:0").unwrap();
+    let link = invite.invitation().link();
+
+    let host = std::thread::spawn({
+        let (key, identity, mut config) = (key.clone(), laptop.identity(), laptop.config());
+        move || {
+            let request = invite.accept().unwrap();
+            assert!(request.is_verified());
+            request.approve(&identity, &key, &mut config).unwrap();
+            config
+        }
+    });
+    let invitation = Invitation::parse(&link).unwrap();
+    let joined = Joining::with_invitation(&invitation, &desktop.identity())
+        .unwrap()
+        .finish()
+        .unwrap();
+    let config = host.join().unwrap();
+    laptop.edit(|current| *current = config);
+    desktop.edit(|config| config.devices = joined.devices.clone());
+
+    assert_eq!(joined.key, key);
+    assert!(joined
+        .devices
+        .find(&desktop.identity().signing_key())
+        .is_some());
+    let server = laptop.listen();
+    desktop.connect(&[&server]);
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config(), laptop.config());
+}
+
+#[test]
+fn pairing_by_address_shows_the_same_code() {
+    let root = TempDir::new().unwrap();
+    let laptop = Device::new(root.path(), "laptop", Configuration::default());
+    let desktop = Device::new(root.path(), "desktop", Configuration::default());
+    let invite = Invite::new(&laptop.identity(), "127.0.0.1:0").unwrap();
+    let address = invite.invitation().address;
+
+    let host = std::thread::spawn(move || invite.accept().unwrap());
+    let joining = Joining::new(address, &desktop.identity()).unwrap();
+    let request = host.join().unwrap();
+
+    assert!(!request.is_verified());
+    assert_eq!(request.code().len(), 6);
+    assert_eq!(request.code(), joining.code());
+    assert_eq!(request.device().name, desktop.identity().device().name);
+    // Declining hands over nothing.
+    drop(request);
+    assert!(joining.finish().is_err());
+}
+
+#[test]
+fn devices_syncing_each_other_at_once_both_finish() {
+    let root = TempDir::new().unwrap();
+    let (laptop, mut desktop, laptop_server) = devices(&root);
+    desktop.sync();
+    let desktop_server = desktop.listen();
+    let mut laptop = laptop;
+    laptop.connect(&[&desktop_server]);
+    desktop.connect(&[&laptop_server]);
+
+    laptop.edit(|config| config.color_scheme = ColorScheme::Light);
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    let started = std::time::Instant::now();
+    let (ours, theirs) = std::thread::scope(|scope| {
+        let ours = scope.spawn(|| laptop.sync.sync());
+        let theirs = scope.spawn(|| desktop.sync.sync());
+        (ours.join().unwrap(), theirs.join().unwrap())
+    });
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(!matches!(ours.unwrap(), Status::Offline { .. }));
+    assert!(!matches!(theirs.unwrap(), Status::Offline { .. }));
+    assert_eq!(laptop.config().wallpaper, "b.png");
+    assert_eq!(desktop.config().color_scheme, ColorScheme::Light);
+}
+
+#[test]
+fn strangers_cant_send_large_frames() {
+    use std::io::{Read, Write};
+
+    let root = TempDir::new().unwrap();
+    let (_laptop, _desktop, server) = devices(&root);
+    let mut stream = std::net::TcpStream::connect(server.address()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    // Announces a frame of 1 MiB before saying who it is.
+    stream.write_all(&(1024u32 * 1024).to_be_bytes()).unwrap();
+    let mut buffer = [0u8; 64];
+    match stream.read(&mut buffer) {
+        Ok(read) => assert_eq!(read, 0),
+        Err(err) => assert!(
+            !matches!(
+                err.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ),
+            "{err}"
+        ),
+    }
+}
@@ -0,0 +1,195 @@
+//! Registers backends the way other crates would, and edits the built-in ones through the
+//! same text settings the Services page uses.
+
+use anyhow::Result;
+use symmetry_core::{
+    configuration::{repository_type::Service, Configuration},
+    context::SymmetryContext,
+    network::MeteredPolicy,
+    sync::{
+        message::Message,
+        providers::{
+            self,
+            config::{git::ConflictStrategy, Services},
+            registry::{self, Kind, Setting},
+            Provider,
+        },
+        status::Status,
+    },
+    traits::{backend::Backend, synchronization::Synchronization},
+};
+use tempfile::TempDir;
+
+/// Reports what it was configured with, as a backend from another crate would.
+struct Echo(String);
+
+impl Synchronization for Echo {
+    type Status = Status;
+    type Message = Message;
+
+    fn sync(&self) -> Result<Status> {
+        Ok(Status::ChangesUploaded {
+            commit: self.0.clone(),
+        })
+    }
+
+    fn handle(&self, _: Message) -> Result<Status> {
+        self.sync()
+    }
+}
+
+struct EchoBackend;
+
+impl Backend for EchoBackend {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn title(&self) -> &'static str {
+        "Echo"
+    }
+
+    fn description(&self) -> &'static str {
+        "Repeats what it was told"
+    }
+
+    fn schema(&self) -> Vec<Setting> {
+        vec![
+            Setting::new("word", "Word", Kind::Text),
+            Setting::new("times", "Times", Kind::Number { min: 1, max: 3 }),
+        ]
+    }
+
+    fn open(&self, context: &SymmetryContext) -> Result<Provider> {
+        let config = Configuration::load(context).unwrap_or_default();
+        Ok(Box::new(Echo(
+            self.get(&config.service_config, "word").unwrap_or_default(),
+        )))
+    }
+}
+
+fn context(root: &TempDir, services: Services) -> SymmetryContext {
+    let context = SymmetryContext::new(root.path());
+    let config = Configuration {
+        service_config: services,
+        ..Default::default()
+    };
+    config.init_in(&context).unwrap();
+    context
+}
+
+#[test]
+fn built_in_backends_are_registered_realtime_first() {
+    let names: Vec<_> = registry::backends()
+        .iter()
+        .map(|backend| backend.name())
+        .filter(|name| ["crdt", "git"].contains(name))
+        .collect();
+    assert_eq!(names, ["crdt", "git"]);
+    assert!(registry::find("crdt").unwrap().realtime());
+}
+
+#[test]
+fn built_in_settings_are_edited_as_text() {
+    let git = registry::find("git").unwrap();
+    let mut services = Services::default();
+
+    git.set(&mut services, "url", " file:///srv/symmetry.git ")
+        .unwrap();
+    git.set(&mut services, "conflicts", "Theirs").unwrap();
+    git.set(&mut services, "batch_seconds", "30").unwrap();
+    git.set(&mut services, "metered", "Allow").unwrap();
+    git.set_enabled(&mut services, true).unwrap();
+
+    assert_eq!(services.git.url, "file:///srv/symmetry.git");
+    assert_eq!(services.git.conflicts, ConflictStrategy::Theirs);
+    assert_eq!(services.git.batch_seconds, 30);
+    assert_eq!(services.git.metered, MeteredPolicy::Allow);
+    assert!(git.enabled(&services));
+    assert_eq!(git.get(&services, "conflicts").unwrap(), "Theirs");
+    assert_eq!(git.get(&services, "batch_seconds").unwrap(), "30");
+    assert!(services.custom.is_empty());
+}
+
+#[test]
+fn values_that_dont_suit_a_setting_are_refused() {
+    let crdt = registry::find("crdt").unwrap();
+    let mut services = Services::default();
+
+    assert!(crdt.set(&mut services, "port", "many").is_err());
+    assert!(crdt.set(&mut services, "port", "65536").is_err());
+    assert!(crdt.set(&mut services, "metered", "Sometimes").is_err());
+    assert!(crdt.set(&mut services, "colour", "blue").is_err());
+    assert_eq!(services, Services::default());
+
+    crdt.set(&mut services, "peers", "desktop.local, 10.0.0.2:47474,")
+        .unwrap();
+    assert_eq!(services.crdt.peers, ["desktop.local", "10.0.0.2:47474"]);
+}
+
+#[test]
+fn unset_numbers_read_back_empty() {
+    let mut services = Services::default();
+    let (crdt, git) = (
+        registry::find("crdt").unwrap(),
+        registry::find("git").unwrap(),
+    );
+    assert_eq!(crdt.get(&services, "port").unwrap(), "");
+    assert_eq!(git.get(&services, "batch_seconds").unwrap(), "");
+
+    crdt.set(&mut services, "port", "47474").unwrap();
+    git.set(&mut services, "batch_seconds", "").unwrap();
+    assert_eq!(crdt.get(&services, "port").unwrap(), "47474");
+    assert_eq!(services.git.batch_seconds, 0);
+    assert!(git.set(&mut services, "batch_seconds", "86401").is_err());
+}
+
+#[test]
+fn every_backend_offers_the_same_metered_setting() {
+    for name in ["crdt", "git", "webdav", "s3"] {
+        let schema = registry::find(name).unwrap().schema();
+        let metered = Setting::find(&schema, registry::METERED).unwrap();
+        assert_eq!(*metered, Setting::metered());
+    }
+}
+
+#[test]
+fn other_crates_can_add_backends() {
+    registry::register(EchoBackend);
+    let echo = registry::find("echo").unwrap();
+    let mut services = Services::default();
+    echo.set(&mut services, "word", "hello").unwrap();
+    assert!(echo.set(&mut services, "times", "twice").is_err());
+    assert!(echo.set(&mut services, "times", "").is_err());
+    assert!(echo.set(&mut services, "times", "4").is_err());
+    echo.set_enabled(&mut services, true).unwrap();
+    let root = TempDir::new().unwrap();
+    let context = context(&root, services);
+
+    // The settings are kept in the configuration along with the built-in ones.
+    let config = Configuration::load(&context).unwrap();
+    assert_eq!(config.service_config.custom["echo"]["word"], "hello");
+    let provider = providers::open(&context).unwrap().unwrap();
+    assert_eq!(
+        provider.sync().unwrap(),
+        Status::ChangesUploaded {
+            commit: "hello".into()
+        }
+    );
+}
+
+#[test]
+fn configurations_without_custom_settings_still_load() {
+    let root = TempDir::new().unwrap();
+    let context = context(&root, Services::default());
+    let path = context.config_path();
+    let written = std::fs::read_to_string(&path).unwrap();
+    let (before, after) = written.split_once("custom:").unwrap();
+    let after = &after[after.find('}').unwrap() + 1..];
+    let after = after.trim_start().trim_start_matches(',');
+    std::fs::write(&path, format!("{before}{after}")).unwrap();
+
+    let config = Configuration::read_from(&path).unwrap();
+    assert_eq!(config.service_config, Services::default());
+    assert_eq!(config.active_service, Service::Git);
+}
@@ -0,0 +1,291 @@
+//! Synchronizes simulated devices through a relay running locally, as they would through
+//! a relay on the internet.
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use symmetry_core::{
+    color_scheme::ColorScheme,
+    configuration::Configuration,
+    sync::{
+        providers::crdt::CrdtSync,
+        relay::{server::Server, Frame, MAX_ENVELOPE},
+        status::Status,
+    },
+    trust::identity::Identity,
+};
+use tempfile::TempDir;
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+type Device = common::Device<CrdtSync>;
+
+impl Device {
+    fn new(root: &Path, name: &str, relay: &Server, config: Configuration) -> Self {
+        Self::set_up(root, name, Some(config), |context| {
+            let mut sync = CrdtSync::open(context);
+            sync.set_discovery(false);
+            sync.set_relay(Some(format!("ws://{}/family", relay.address())));
+            sync
+        })
+    }
+}
+
+/// Makes every device trust all the others, as pairing them would.
+fn trust(devices: &[&Device]) {
+    for device in devices {
+        device.edit(|config| {
+            for other in devices {
+                config.devices.trust(other.identity().device());
+            }
+        });
+    }
+}
+
+/// A relay, a device holding the configuration and a new one, both using the relay.
+fn devices(root: &TempDir) -> (Server, Device, Device) {
+    let relay = Server::bind("127.0.0.1:0", root.path().join("relay")).unwrap();
+    let config = Configuration {
+        color_scheme: ColorScheme::Dark,
+        wallpaper: "a.png".into(),
+        ..Default::default()
+    };
+    let laptop = Device::new(root.path(), "laptop", &relay, config);
+    let desktop = Device::new(root.path(), "desktop", &relay, Configuration::default());
+    trust(&[&laptop, &desktop]);
+    laptop.sync();
+    (relay, laptop, desktop)
+}
+
+/// The states the relay keeps, one for each device of an account.
+fn relayed_states(root: &TempDir) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(root.path().join("relay"))
+        .into_iter()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "ron"))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Waits for `condition` to hold, as a subscription merges in the background.
+fn eventually(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+fn receive(socket: &mut WebSocket<impl Read + Write>) -> Frame {
+    match socket.read().unwrap() {
+        Message::Text(text) => ron::from_str(&text).unwrap(),
+        message => panic!("Unexpected message: {message:?}"),
+    }
+}
+
+fn send(socket: &mut WebSocket<impl Read + Write>, frame: &Frame) {
+    socket
+        .send(Message::Text(ron::to_string(frame).unwrap()))
+        .unwrap();
+}
+
+/// Connects to the family account as `identity`, trusting no one else, and returns the
+/// first answer of the relay.
+fn join(
+    relay: &Server,
+    identity: &Identity,
+) -> (WebSocket<MaybeTlsStream<std::net::TcpStream>>, Frame) {
+    let url = format!("ws://{}/family", relay.address());
+    let (mut socket, _) = tungstenite::connect(url).unwrap();
+    let Frame::Challenge(challenge) = receive(&mut socket) else {
+        panic!("The relay didn't challenge the device.");
+    };
+    let signed = format!("symmetry relay\nfamily\n{challenge}\n");
+    let hello = Frame::Hello {
+        account: "family".into(),
+        key: identity.signing_key(),
+        trusted: vec![],
+        signature: identity.sign(signed.as_bytes()),
+    };
+    send(&mut socket, &hello);
+    let answer = receive(&mut socket);
+    (socket, answer)
+}
+
+#[test]
+fn new_devices_adopt_the_configuration_from_the_relay() {
+    let root = TempDir::new().unwrap();
+    let (_relay, laptop, desktop) = devices(&root);
+
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config(), laptop.config());
+    assert_eq!(desktop.sync(), Status::UpToDate);
+}
+
+#[test]
+fn changes_flow_both_ways_through_the_relay() {
+    let root = TempDir::new().unwrap();
+    let (_relay, laptop, desktop) = devices(&root);
+    desktop.sync();
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    std::fs::write(desktop.path.join("notes.txt"), "sync").unwrap();
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(laptop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(laptop.config().wallpaper, "b.png");
+    assert_eq!(
+        std::fs::read_to_string(laptop.path.join("notes.txt")).unwrap(),
+        "sync"
+    );
+
+    laptop.edit(|config| config.color_scheme = ColorScheme::Light);
+    laptop.sync();
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config().color_scheme, ColorScheme::Light);
+}
+
+#[test]
+fn the_relay_never_holds_plaintext() {
+    let root = TempDir::new().unwrap();
+    let (_relay, _laptop, desktop) = devices(&root);
+    desktop.edit(|config| config.wallpaper = "very-secret-wallpaper.png".into());
+    desktop.sync();
+
+    let files = relayed_states(&root);
+    assert_eq!(files.len(), 2);
+    for file in files {
+        let data = std::fs::read_to_string(file).unwrap();
+        assert!(!data.contains("very-secret-wallpaper"));
+    }
+}
+
+#[test]
+fn subscribed_devices_receive_changes_as_they_are_published() {
+    let root = TempDir::new().unwrap();
+    let (_relay, laptop, desktop) = devices(&root);
+    desktop.sync();
+    let _subscription = desktop.sync.subscribe().unwrap();
+
+    laptop.edit(|config| config.wallpaper = "b.png".into());
+    laptop.sync();
+
+    assert!(eventually(|| desktop.config().wallpaper == "b.png"));
+}
+
+#[test]
+fn states_from_untrusted_devices_are_ignored() {
+    let root = TempDir::new().unwrap();
+    let (relay, laptop, desktop) = devices(&root);
+    let stranger = Device::new(root.path(), "stranger", &relay, Configuration::default());
+    stranger.edit(|config| config.wallpaper = "evil.png".into());
+    // The stranger trusts the others, but they don't trust it back.
+    trust(&[&stranger, &laptop, &desktop]);
+    laptop.edit(|config| {
+        config.devices.trusted.remove(&stranger.identity().id());
+    });
+    stranger.sync();
+
+    laptop.sync();
+    assert_eq!(laptop.config().wallpaper, "a.png");
+}
+
+#[test]
+fn accounts_are_kept_apart() {
+    let root = TempDir::new().unwrap();
+    let (relay, _laptop, mut desktop) = devices(&root);
+    desktop
+        .sync
+        .set_relay(Some(format!("ws://{}/neighbours", relay.address())));
+
+    desktop.sync();
+    assert_eq!(desktop.config().wallpaper, "");
+    let neighbours = relayed_states(&root)
+        .into_iter()
+        .filter(|state| state.starts_with(root.path().join("relay/neighbours")));
+    assert_eq!(neighbours.count(), 1);
+}
+
+#[test]
+fn devices_must_prove_who_they_are() {
+    let root = TempDir::new().unwrap();
+    let (relay, laptop, _desktop) = devices(&root);
+    let url = format!("ws://{}/family", relay.address());
+    let (mut socket, _) = tungstenite::connect(url).unwrap();
+    let Frame::Challenge(_) = receive(&mut socket) else {
+        panic!("The relay didn't challenge the device.");
+    };
+
+    // Presents the key of the laptop without holding it.
+    let hello = Frame::Hello {
+        account: "family".into(),
+        key: laptop.identity().signing_key(),
+        trusted: vec![],
+        signature: Identity::load(&root.path().join("impostor"))
+            .unwrap()
+            .sign(b"symmetry relay\nfamily\n"),
+    };
+    send(&mut socket, &hello);
+    assert!(matches!(receive(&mut socket), Frame::Refused(_)));
+}
+
+#[test]
+fn only_trusted_devices_join_an_account() {
+    let root = TempDir::new().unwrap();
+    let (relay, laptop, _desktop) = devices(&root);
+    let stranger = Identity::load(&root.path().join("stranger")).unwrap();
+
+    assert!(matches!(join(&relay, &stranger).1, Frame::Refused(_)));
+    assert!(matches!(
+        join(&relay, &laptop.identity()).1,
+        Frame::State { .. } | Frame::Ready
+    ));
+}
+
+#[test]
+fn revoked_devices_are_let_out_of_the_account() {
+    let root = TempDir::new().unwrap();
+    let (relay, laptop, desktop) = devices(&root);
+    let tablet = Device::new(root.path(), "tablet", &relay, Configuration::default());
+    trust(&[&laptop, &desktop, &tablet]);
+    desktop.sync();
+    assert_eq!(relayed_states(&root).len(), 2);
+
+    let revoked = desktop.identity();
+    laptop.edit(|config| {
+        config.devices.trusted.remove(&revoked.id());
+    });
+    laptop.sync();
+    assert!(matches!(join(&relay, &revoked).1, Frame::Refused(_)));
+    assert_eq!(relayed_states(&root).len(), 1);
+}
+
+#[test]
+fn large_states_are_refused() {
+    let root = TempDir::new().unwrap();
+    let (relay, laptop, _desktop) = devices(&root);
+    let (mut socket, mut answer) = join(&relay, &laptop.identity());
+    while !matches!(answer, Frame::Ready) {
+        answer = receive(&mut socket);
+    }
+
+    send(&mut socket, &Frame::Publish("a".repeat(MAX_ENVELOPE + 1)));
+    assert!(matches!(receive(&mut socket), Frame::Refused(_)));
+}
+
+#[test]
+fn an_unreachable_relay_leaves_changes_pending() {
+    let root = TempDir::new().unwrap();
+    let (relay, _laptop, desktop) = devices(&root);
+    desktop.sync();
+    drop(relay);
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    assert_eq!(desktop.sync(), Status::Offline { pending: 1 });
+}
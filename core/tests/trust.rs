@@ -0,0 +1,110 @@
+//! Trusts devices, revokes them and hands the new sync key to those remaining.
+
+use std::net::SocketAddr;
+
+use symmetry_core::{
+    configuration::{changes::Field, Configuration},
+    secrets::cipher::Cipher,
+    trust::{self, identity::Identity, invitation::Invitation},
+};
+use tempfile::TempDir;
+
+/// Identities of devices that trust each other, and a configuration listing them.
+fn devices(root: &TempDir, names: &[&str]) -> (Vec<Identity>, Configuration) {
+    let identities: Vec<Identity> = names
+        .iter()
+        .map(|name| Identity::load(&root.path().join(name)).unwrap())
+        .collect();
+    let mut config = Configuration::default();
+    for identity in &identities {
+        config.devices.trust(identity.device());
+    }
+    (identities, config)
+}
+
+#[test]
+fn identities_are_kept_between_runs() {
+    let root = TempDir::new().unwrap();
+    let identity = Identity::load(root.path()).unwrap();
+    let again = Identity::load(root.path()).unwrap();
+
+    assert_eq!(identity.id(), again.id());
+    assert_eq!(identity.device(), again.device());
+    assert_eq!(identity.id(), identity.device().id());
+}
+
+#[test]
+fn devices_are_paired_once_another_is_trusted() {
+    let root = TempDir::new().unwrap();
+    let (identities, config) = devices(&root, &["laptop", "desktop"]);
+    let stranger = Identity::load(&root.path().join("stranger")).unwrap();
+
+    assert!(config.devices.is_paired(&identities[0]));
+    assert!(!config.devices.is_paired(&stranger));
+    assert!(config.devices.find(&stranger.signing_key()).is_none());
+    let (alone, config) = devices(&root, &["tablet"]);
+    assert!(!config.devices.is_paired(&alone[0]));
+}
+
+#[test]
+fn revoking_a_device_rekeys_the_others() {
+    let root = TempDir::new().unwrap();
+    let (identities, mut config) = devices(&root, &["laptop", "desktop", "tablet"]);
+    let key = Cipher::generate_key();
+    let secret = Cipher::new(&key)
+        .unwrap()
+        .encrypt_string("hunter2")
+        .unwrap();
+    config.secrets.insert("token".into(), secret);
+    let before = config.clone();
+
+    let tablet = identities[2].id();
+    let new_key = trust::revoke(&mut config, &tablet, &key).unwrap();
+
+    assert_ne!(new_key, key);
+    assert!(!config.devices.trusted.contains_key(&tablet));
+    for identity in &identities[..2] {
+        let received = trust::receive_key(&config, identity).unwrap();
+        assert_eq!(received.as_deref(), Some(new_key.as_str()));
+    }
+    assert_eq!(trust::receive_key(&config, &identities[2]).unwrap(), None);
+    let cipher = Cipher::new(&new_key).unwrap();
+    assert_eq!(
+        cipher.decrypt_string(&config.secrets["token"]).unwrap(),
+        "hunter2"
+    );
+    assert!(Cipher::new(&key)
+        .unwrap()
+        .decrypt_string(&config.secrets["token"])
+        .is_err());
+    let change = before
+        .changes(&config)
+        .into_iter()
+        .find(|change| change.field == Field::Devices)
+        .unwrap();
+    assert!(change.summary.contains("removed"));
+}
+
+#[test]
+fn revoking_an_unknown_device_fails() {
+    let root = TempDir::new().unwrap();
+    let (_, mut config) = devices(&root, &["laptop"]);
+    let key = Cipher::generate_key();
+
+    assert!(trust::revoke(&mut config, "unknown", &key).is_err());
+    assert!(config.devices.keys.is_empty());
+}
+
+#[test]
+fn invitations_survive_their_link() {
+    let root = TempDir::new().unwrap();
+    let identity = Identity::load(root.path()).unwrap();
+    let address: SocketAddr = "192.168.1.20:47475".parse().unwrap();
+    let invitation = Invitation::new(address, &identity);
+
+    let link = invitation.link();
+    assert!(link.starts_with("symmetry://pair?"));
+    assert_eq!(Invitation::parse(&link).unwrap(), invitation);
+    assert!(!invitation.qr_code().unwrap().is_empty());
+    assert!(Invitation::parse("https://example.com").is_err());
+}
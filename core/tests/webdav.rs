@@ -0,0 +1,373 @@
+//! Synchronizes simulated devices through a WebDAV stand-in, answering the way Nextcloud
+//! does to the requests the provider makes.
+
+mod common;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use symmetry_core::{
+    color_scheme::ColorScheme,
+    configuration::{changes::Field, Configuration, CONFIG_FILE},
+    context::SymmetryContext,
+    sync::{
+        message::Message,
+        overrides::Overrides,
+        providers::{config::git::ConflictStrategy, webdav::WebDavSync},
+        status::Status,
+        store::{
+            mirror::{Manifest, MANIFEST},
+            webdav::WebDav,
+            Condition, Store, Written,
+        },
+    },
+    traits::synchronization::Synchronization,
+};
+use tempfile::TempDir;
+use tiny_http::{Request, Response};
+
+use common::{allowed, etag, header, HttpServer};
+
+const USERNAME: &str = "me";
+const PASSWORD: &str = "app-password";
+
+#[derive(Default)]
+struct Files {
+    objects: HashMap<String, (Vec<u8>, String)>,
+    collections: BTreeSet<String>,
+    writes: usize,
+}
+
+/// Keeps objects in memory under `/dav/`, handing out a new ETag on every write.
+struct WebDavServer {
+    http: HttpServer<Files>,
+}
+
+impl WebDavServer {
+    fn start() -> Self {
+        let mut files = Files::default();
+        files.collections.insert("/dav/".into());
+        Self {
+            http: HttpServer::start(files, answer),
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("http://{}/dav/symmetry", self.http.address())
+    }
+
+    fn store(&self, password: &str) -> WebDav {
+        WebDav::new(&self.url(), USERNAME, Some(password))
+    }
+}
+
+fn answer(mut request: Request, files: &mut Files) {
+    let expected = format!(
+        "Basic {}",
+        STANDARD.encode(format!("{USERNAME}:{PASSWORD}"))
+    );
+    if header(&request, "Authorization").as_deref() != Some(expected.as_str()) {
+        let _ = request.respond(Response::empty(401));
+        return;
+    }
+    let (if_match, if_none_match) = (
+        header(&request, "If-Match"),
+        header(&request, "If-None-Match"),
+    );
+    let path = request.url().to_string();
+    let parent = match path.trim_end_matches('/').rsplit_once('/') {
+        Some((parent, _)) => format!("{parent}/"),
+        None => "/".into(),
+    };
+    let response = match request.method().as_str() {
+        "GET" | "HEAD" => match files.objects.get(&path) {
+            Some((data, tag)) => Response::from_data(data.clone()).with_header(etag(tag)),
+            None => Response::from_data(vec![]).with_status_code(404),
+        },
+        "MKCOL" if files.collections.contains(&path) => {
+            Response::from_data(vec![]).with_status_code(405)
+        }
+        "MKCOL" if files.collections.contains(&parent) => {
+            files.collections.insert(path);
+            Response::from_data(vec![]).with_status_code(201)
+        }
+        "PUT" if !files.collections.contains(&parent) => {
+            Response::from_data(vec![]).with_status_code(409)
+        }
+        "PUT" => {
+            let current = files.objects.get(&path).map(|(_, tag)| tag.as_str());
+            if allowed(current, if_match.as_deref(), if_none_match.as_deref()) {
+                let mut data = vec![];
+                request.as_reader().read_to_end(&mut data).unwrap();
+                files.writes += 1;
+                let tag = format!("\"{}\"", files.writes);
+                files.objects.insert(path, (data, tag.clone()));
+                Response::from_data(vec![])
+                    .with_status_code(201)
+                    .with_header(etag(&tag))
+            } else {
+                Response::from_data(vec![]).with_status_code(412)
+            }
+        }
+        _ => Response::from_data(vec![]).with_status_code(405),
+    };
+    let _ = request.respond(response);
+}
+
+type Device = common::Device<WebDavSync>;
+
+impl Device {
+    fn new(root: &Path, name: &str, server: &WebDavServer, config: Configuration) -> Self {
+        Self::with_password(root, name, server, config, PASSWORD)
+    }
+
+    fn with_password(
+        root: &Path,
+        name: &str,
+        server: &WebDavServer,
+        config: Configuration,
+        password: &str,
+    ) -> Self {
+        Self::set_up(root, name, Some(config), |context| {
+            WebDavSync::open(context, &server.url(), USERNAME, Some(password))
+        })
+    }
+}
+
+/// A server, a device holding the configuration and a new one, both synced once.
+fn devices(root: &TempDir) -> (WebDavServer, Device, Device) {
+    let server = WebDavServer::start();
+    let config = Configuration {
+        color_scheme: ColorScheme::Dark,
+        wallpaper: "a.png".into(),
+        ..Default::default()
+    };
+    let laptop = Device::new(root.path(), "laptop", &server, config);
+    let desktop = Device::new(root.path(), "desktop", &server, Configuration::default());
+    assert_eq!(laptop.sync(), Status::RepoConfigured);
+    assert_eq!(desktop.sync(), Status::RepoConfigured);
+    (server, laptop, desktop)
+}
+
+#[test]
+fn new_devices_adopt_the_configuration_on_the_server() {
+    let root = TempDir::new().unwrap();
+    let (server, laptop, desktop) = devices(&root);
+
+    assert_eq!(desktop.config(), laptop.config());
+    assert_eq!(desktop.sync(), Status::UpToDate);
+    assert!(server
+        .http
+        .state
+        .lock()
+        .unwrap()
+        .objects
+        .contains_key(&format!("/dav/symmetry/{MANIFEST}")));
+}
+
+#[test]
+fn changes_flow_both_ways_through_the_server() {
+    let root = TempDir::new().unwrap();
+    let (_server, laptop, desktop) = devices(&root);
+
+    desktop.edit(|config| config.wallpaper = "b.png".into());
+    std::fs::create_dir_all(desktop.path.join("notes")).unwrap();
+    std::fs::write(desktop.path.join("notes/todo.txt"), "sync").unwrap();
+    assert!(matches!(desktop.sync(), Status::ChangesUploaded { .. }));
+
+    let Status::NewChangesDetected { changes } = laptop.sync() else {
+        panic!("The laptop didn't notice the changes.");
+    };
+    assert_eq!(changes[0].field, Field::Wallpaper);
+    assert!(matches!(
+        laptop.sync.handle(Message::Update).unwrap(),
+        Status::ChangesDownloaded { .. }
+    ));
+    assert_eq!(laptop.config().wallpaper, "b.png");
+    assert_eq!(
+        std::fs::read_to_string(laptop.path.join("notes/todo.txt")).unwrap(),
+        "sync"
+    );
+
+    std::fs::remove_file(laptop.path.join("notes/todo.txt")).unwrap();
+    laptop.sync();
+    desktop.sync();
+    desktop.sync.handle(Message::Update).unwrap();
+    assert!(!desktop.path.join("notes/todo.txt").exists());
+}
+
+#[test]
+fn changes_to_different_settings_are_merged() {
+    let root = TempDir::new().unwrap();
+    let (_server, laptop, desktop) = devices(&root);
+
+    laptop.edit(|config| config.wallpaper = "b.png".into());
+    desktop.edit(|config| config.color_scheme = ColorScheme::Light);
+    laptop.sync();
+    assert!(matches!(desktop.sync(), Status::ChangesDownloaded { .. }));
+    assert_eq!(desktop.config().wallpaper, "b.png");
+    assert_eq!(desktop.config().color_scheme, ColorScheme::Light);
+
+    laptop.sync();
+    laptop.sync.handle(Message::Update).unwrap();
+    assert_eq!(laptop.config(), desktop.config());
+}
+
+#[test]
+fn declined_changes_stay_on_the_device() {
+    let root = TempDir::new().unwrap();
+    let (_server, laptop, desktop) = devices(&root);
+
+    laptop.edit(|config| {
+        config.color_scheme = ColorScheme::Light;
+        config.wallpaper = "c.png".into();
+    });
+    assert!(matches!(laptop.sync(), Status::ChangesUploaded { .. }));
+    assert!(matches!(desktop.sync(), Status::NewChangesDetected { .. }));
+    let status = desktop
+        .sync
+        .handle(Message::Accept(vec![Field::Wallpaper]))
+        .unwrap();
+    assert!(matches!(status, Status::ChangesDownloaded { .. }));
+
+    assert_eq!(desktop.config(), laptop.config());
+    let used = Overrides::load(&desktop.state).apply(&desktop.config());
+    assert_eq!(used.wallpaper, "c.png");
+    assert_eq!(used.color_scheme, ColorScheme::Dark);
+    assert_eq!(desktop.sync(), Status::UpToDate);
+    assert_eq!(laptop.sync(), Status::UpToDate);
+}
+
+#[test]
+fn conflicting_changes_are_reported_and_resolved() {
+    let root = TempDir::new().unwrap();
+    let (_server, laptop, desktop) = devices(&root);
+
+    laptop.edit(|config| config.wallpaper = "b.png".into());
+    desktop.edit(|config| config.wallpaper = "c.png".into());
+    laptop.sync();
+    assert_eq!(
+        desktop.sync(),
+        Status::Conflict {
+            fields: vec![Field::Wallpaper],
+            paths: vec![CONFIG_FILE.into()],
+        }
+    );
+    assert_eq!(desktop.config().wallpaper, "c.png");
+
+    desktop
+        .sync
+        .handle(Message::Resolve(ConflictStrategy::Theirs))
+        .unwrap();
+    assert_eq!(desktop.config().wallpaper, "b.png");
+}
+
+#[test]
+fn writes_never_replace_what_they_havent_seen() {
+    let server = WebDavServer::start();
+    let store = server.store(PASSWORD);
+
+    let Written::Stored(first) = store.put("notes.txt", b"one", Condition::Absent).unwrap() else {
+        panic!("The first write didn't go through.");
+    };
+    assert_eq!(
+        store.put("notes.txt", b"two", Condition::Absent).unwrap(),
+        Written::Changed
+    );
+    store
+        .put("notes.txt", b"two", Condition::Matches(first.clone()))
+        .unwrap();
+    assert_eq!(
+        store
+            .put("notes.txt", b"three", Condition::Matches(first))
+            .unwrap(),
+        Written::Changed
+    );
+    assert_eq!(store.get("notes.txt").unwrap().unwrap().data, b"two");
+}
+
+#[test]
+fn paths_are_encoded() {
+    let server = WebDavServer::start();
+    let store = server.store(PASSWORD);
+
+    for (path, data) in [("notes #1.txt", b"one"), ("notes #2.txt", b"two")] {
+        assert!(matches!(
+            store.put(path, data, Condition::Absent).unwrap(),
+            Written::Stored(_)
+        ));
+    }
+    assert_eq!(store.get("notes #1.txt").unwrap().unwrap().data, b"one");
+    assert_eq!(store.get("notes #2.txt").unwrap().unwrap().data, b"two");
+}
+
+#[test]
+fn a_wrong_password_asks_for_credentials() {
+    let root = TempDir::new().unwrap();
+    let server = WebDavServer::start();
+    let device = Device::with_password(
+        root.path(),
+        "laptop",
+        &server,
+        Configuration::default(),
+        "wrong",
+    );
+
+    assert_eq!(device.sync(), Status::AuthRequired);
+}
+
+#[test]
+fn an_unreachable_server_leaves_changes_pending() {
+    let root = TempDir::new().unwrap();
+    let (server, laptop, _desktop) = devices(&root);
+    let url = server.url();
+    drop(server);
+
+    laptop.edit(|config| config.wallpaper = "b.png".into());
+    // Providers are opened again on every round, with new connections.
+    let context = SymmetryContext::new(root.path().join("laptop"));
+    let sync = WebDavSync::open(&context, &url, USERNAME, Some(PASSWORD));
+    assert_eq!(sync.sync().unwrap(), Status::Offline { pending: 1 });
+}
+
+#[test]
+fn new_devices_keep_the_files_only_they_have() {
+    let root = TempDir::new().unwrap();
+    let server = WebDavServer::start();
+    let laptop = Device::new(root.path(), "laptop", &server, Configuration::default());
+    let desktop = Device::new(root.path(), "desktop", &server, Configuration::default());
+    assert_eq!(laptop.sync(), Status::RepoConfigured);
+    std::fs::write(desktop.path.join("local.txt"), "mine").unwrap();
+
+    assert_eq!(desktop.sync(), Status::RepoConfigured);
+    assert_eq!(
+        std::fs::read_to_string(desktop.path.join("local.txt")).unwrap(),
+        "mine"
+    );
+    laptop.sync.handle(Message::Update).unwrap();
+    assert!(laptop.path.join("local.txt").exists());
+}
+
+#[test]
+fn manifests_never_write_into_git() {
+    let root = TempDir::new().unwrap();
+    let server = WebDavServer::start();
+    let manifest = Manifest {
+        files: [(".git/config".to_string(), "0".repeat(64))].into(),
+    };
+    server
+        .store(PASSWORD)
+        .put(
+            MANIFEST,
+            ron::to_string(&manifest).unwrap().as_bytes(),
+            Condition::Absent,
+        )
+        .unwrap();
+    let device = Device::new(root.path(), "laptop", &server, Configuration::default());
+
+    assert!(device.sync.sync().is_err());
+    assert!(!device.path.join(".git/config").exists());
+}
@@ -1,29 +1,26 @@
 use std::sync::atomic::{AtomicU32, Ordering};
 
 use crate::components::header_bar::header;
-use crate::pages::{desktop, services, settings, Page};
+use crate::pages::{conflicts, desktop, devices, files, review, services, settings, Page};
 use cosmic::iced::Application;
 use cosmic::iced_winit::widget::horizontal_space;
 use cosmic::iced_winit::window::{self, close, drag, minimize, toggle_maximize};
-use cosmic::iced_winit::{column, row, subscription, Command};
+use cosmic::iced_winit::{row, subscription, Command};
 use cosmic::theme::ThemeType;
 use cosmic::widget::segmented_button::{self, Entity, SingleSelectModel};
 use cosmic::widget::{nav_bar, text, IconSource};
 use cosmic::{iced, Element, Theme};
 use iced::Length;
-use symmetry_core::configuration::repository_type::Service;
 use symmetry_core::configuration::Configuration;
+use symmetry_core::context::SymmetryContext;
 use symmetry_core::sync;
-use symmetry_core::sync::providers::crdt::CrdtSync;
-use symmetry_core::sync::providers::git::GitSync;
-use symmetry_core::traits::synchronization::Synchronization;
+use symmetry_core::sync::providers::{self, Provider};
+use symmetry_core::sync::status::Status;
+use url::Url;
 
 static WINDOW_WIDTH: AtomicU32 = AtomicU32::new(1000);
 const BREAK_POINT: u32 = 700;
 
-type SyncProvider =
-    Box<dyn Synchronization<Status = sync::status::Status, Message = sync::message::Message>>;
-
 pub struct Symmetry {
     theme: Theme,
     nav_bar: SingleSelectModel,
@@ -31,43 +28,49 @@ pub struct Symmetry {
     page: Page,
     error: String,
     show_warning: bool,
+    notice: Option<String>,
     welcome: crate::pages::welcome::State,
     desktop: crate::pages::desktop::State,
+    files: crate::pages::files::State,
     services: crate::pages::services::State,
+    devices: crate::pages::devices::State,
     settings: crate::pages::settings::State,
-    sync: Option<SyncProvider>,
+    review: Option<review::State>,
+    conflicts: Option<conflicts::State>,
+    sync: Option<Provider>,
 }
 
 impl Default for Symmetry {
     fn default() -> Self {
-        let sync = refresh_sync_provider();
+        let (sync, error) = match refresh_sync_provider() {
+            Ok(sync) => (sync, String::new()),
+            Err(err) => (None, err.to_string()),
+        };
 
         Self {
             theme: Default::default(),
             nav_bar: Default::default(),
             nav_id_to_page: Default::default(),
             page: Default::default(),
-            error: Default::default(),
-            show_warning: Default::default(),
+            show_warning: !error.is_empty(),
+            error,
+            notice: None,
             welcome: Default::default(),
             desktop: Default::default(),
+            files: Default::default(),
             services: Default::default(),
+            devices: Default::default(),
             settings: Default::default(),
+            review: None,
+            conflicts: None,
             sync,
         }
     }
 }
 
-pub fn refresh_sync_provider() -> Option<SyncProvider> {
-    let sync: Option<SyncProvider> = if let Some(configuration) = Configuration::current() {
-        match configuration.active_service {
-            Service::Git => Some(Box::new(GitSync::new())),
-            Service::Crdt => Some(Box::new(CrdtSync::new())),
-        }
-    } else {
-        None
-    };
-    sync
+/// Opens the enabled sync providers, running together when there are several.
+pub fn refresh_sync_provider() -> anyhow::Result<Option<Provider>> {
+    providers::current()
 }
 
 impl Symmetry {
@@ -95,6 +98,78 @@ impl Symmetry {
         self.show_warning = !self.show_warning
     }
 
+    /// Returns the content of a page.
+    fn page_view(&self, page: Page) -> Element<Message> {
+        match page {
+            Page::Welcome => self.welcome.view(),
+            Page::Desktop => self.desktop.view(self).map(Message::Desktop),
+            Page::Files => self.files.view(self).map(Message::Files),
+            Page::Services => self.services.view(self).map(Message::Services),
+            Page::Devices => self.devices.view(self).map(Message::Devices),
+            Page::Settings => self.settings.view(self).map(Message::Settings),
+        }
+    }
+
+    /// Sends a message to the sync provider and reports the outcome.
+    fn handle(&mut self, message: sync::message::Message) {
+        let Some(sync) = self.sync.as_ref() else {
+            return;
+        };
+        match sync.handle(message) {
+            Ok(status) => self.report(status),
+            Err(err) => {
+                self.update(Message::Error(format!(
+                    "An error ocurred while trying to synchronize: {}.",
+                    err
+                )));
+            }
+        }
+        self.files.refresh();
+    }
+
+    /// Tells the user how synchronization went, asking them to review changes or
+    /// conflicts when needed.
+    fn report(&mut self, status: Status) {
+        let message: String = match status {
+            Status::RepoConfigured => "Repo configured successfully".into(),
+            Status::UpToDate => "Already up to date".into(),
+            Status::ChangesUploaded { commit } => {
+                format!("Successfully synchronized up to {}", short(&commit))
+            }
+            Status::ChangesCommitted { .. } => {
+                "Changes saved, they will be uploaded shortly".into()
+            }
+            Status::ChangesDownloaded { commit } => {
+                format!("Latest changes downloaded up to {}", short(&commit))
+            }
+            Status::NewChangesDetected { changes } if !changes.is_empty() => {
+                self.review = Some(review::State::new(changes));
+                return;
+            }
+            Status::NewChangesDetected { .. } => {
+                return self.handle(sync::message::Message::Update);
+            }
+            Status::Conflict { fields, paths } => {
+                self.conflicts = Some(conflicts::State::new(fields, paths));
+                return;
+            }
+            Status::PartialSync { failed } => {
+                format!(
+                    "Synchronized, but some items couldn't be applied: {}",
+                    failed.join("; ")
+                )
+            }
+            Status::Offline { pending } => format!(
+                "The remote can't be reached, {pending} pending changes will be uploaded once it's back"
+            ),
+            Status::AuthRequired => {
+                "The remote rejected the credentials, check the token on the services page or the trusted devices".into()
+            }
+            Status::RepoNotConfigured => "The repository has not been configured".into(),
+        };
+        self.update(Message::Error(message));
+    }
+
     fn is_condensed(&self) -> bool {
         WINDOW_WIDTH.load(Ordering::Relaxed) < BREAK_POINT
     }
@@ -104,11 +179,18 @@ impl Symmetry {
 pub enum Message {
     CondensedViewToggle,
     Desktop(desktop::Message),
+    Files(files::Message),
     Services(services::Message),
+    Devices(devices::Message),
     Settings(settings::Message),
+    Review(review::Message),
+    Conflicts(conflicts::Message),
     HandlePickedFile(Vec<String>),
     NavBar(Entity),
     Error(String),
+    /// Reports something that went as expected, unlike [`Message::Error`].
+    Notice(String),
+    DismissNotice,
     SwitchColorScheme,
     ToggleWarning,
     Maximize,
@@ -142,12 +224,24 @@ impl Application for Symmetry {
         model.theme = Theme::light();
 
         if let Some(config) = config {
-            model.desktop = desktop::State::new(config.wallpaper, Some(config.color_scheme));
+            let wallpaper_preview = SymmetryContext::current()
+                .and_then(|context| config.rendered(&context))
+                .map(|rendered| rendered.wallpaper)
+                .unwrap_or_else(|_| config.wallpaper.clone());
+            model.desktop = desktop::State::new(
+                config.wallpaper,
+                wallpaper_preview,
+                Some(config.color_scheme),
+                config.desktop.cosmic.components,
+                config.desktop.kde.enabled,
+            );
         }
 
         model.insert_page(Page::Welcome).activate();
         model.insert_page(Page::Desktop);
+        model.insert_page(Page::Files);
         model.insert_page(Page::Services);
+        model.insert_page(Page::Devices);
         model.insert_page(Page::Settings);
 
         (model, Command::none())
@@ -187,11 +281,10 @@ impl Application for Symmetry {
         let nav_bar: Element<_> = nav_bar(&self.nav_bar, Message::NavBar)
             .max_width(200)
             .into();
-        let page: Element<_> = match self.page {
-            Page::Welcome => self.welcome.view(),
-            Page::Desktop => self.desktop.view(&self).map(Message::Desktop),
-            Page::Services => self.services.view(&self).map(Message::Services),
-            Page::Settings => self.settings.view(&self).map(Message::Settings),
+        let page: Element<_> = match (&self.conflicts, &self.review) {
+            (Some(conflicts), _) => conflicts.view().map(Message::Conflicts),
+            (None, Some(review)) => review.view().map(Message::Review),
+            (None, None) => self.page_view(self.page),
         };
 
         let mut widgets: Vec<Element<_>> = vec![
@@ -209,12 +302,22 @@ impl Application for Symmetry {
             .height(Length::Fill)
             .padding(20);
 
+        let mut rows: Vec<Element<_>> = vec![header];
         if self.show_warning {
             let warning = cosmic::widget::warning(&self.error).on_close(Message::ToggleWarning);
-            column![header, warning, content].into()
-        } else {
-            column![header, content].into()
+            rows.push(warning.into());
+        }
+        if let Some(notice) = &self.notice {
+            let notice = row![
+                text(notice),
+                horizontal_space(Length::Fill),
+                cosmic::iced::widget::button(text("Dismiss")).on_press(Message::DismissNotice),
+            ]
+            .padding([10, 20]);
+            rows.push(notice.into());
         }
+        rows.push(content.into());
+        cosmic::iced::widget::column(rows).into()
     }
 
     fn update(&mut self, message: Self::Message) -> cosmic::iced::Command<Self::Message> {
@@ -252,6 +355,15 @@ impl Application for Symmetry {
                 }
                 None => (),
             },
+            Message::Files(message) => match self.files.update(message) {
+                Some(files::Output::Message(msg)) => {
+                    self.update(Message::Error(msg));
+                }
+                Some(files::Output::Error(error)) => {
+                    self.update(Message::Error(error));
+                }
+                None => (),
+            },
             Message::Services(message) => match self.services.update(message) {
                 Some(services::Output::Error(error)) => {
                     self.update(Message::Error(error));
@@ -261,10 +373,97 @@ impl Application for Symmetry {
                 }
                 None => (),
             },
+            Message::Devices(message) => match self.devices.update(message) {
+                Some(devices::Output::Command(command)) => return command.map(Message::Devices),
+                Some(devices::Output::Message(msg)) => {
+                    self.update(Message::Error(msg));
+                }
+                Some(devices::Output::Error(error)) => {
+                    self.update(Message::Error(error));
+                }
+                Some(devices::Output::Sync) => {
+                    self.update(Message::Sync);
+                }
+                None => (),
+            },
             Message::Settings(message) => match self.settings.update(message) {
                 Some(settings::Output::ChangeTheme(theme)) => self.theme = theme,
+                Some(settings::Output::Message(msg)) => {
+                    self.update(Message::Notice(msg));
+                }
+                Some(settings::Output::Error(error)) => {
+                    self.update(Message::Error(error));
+                }
+                Some(settings::Output::SaveFilePicker(request)) => {
+                    return Command::perform(request.send(), |response| {
+                        let uris = response
+                            .map_err(|err| err.to_string())
+                            .and_then(|request| request.response().map_err(|err| err.to_string()))
+                            .map(|files| files.uris().iter().map(ToString::to_string).collect());
+                        chosen(uris, settings::Message::ExportTo)
+                    });
+                }
+                Some(settings::Output::OpenFilePicker(request)) => {
+                    return Command::perform(request.send(), |response| {
+                        let uris = response
+                            .map_err(|err| err.to_string())
+                            .and_then(|request| request.response().map_err(|err| err.to_string()))
+                            .map(|files| files.uris().iter().map(ToString::to_string).collect());
+                        chosen(uris, settings::Message::ImportFrom)
+                    });
+                }
+                Some(settings::Output::Imported(msg)) => {
+                    self.update(Message::Sync);
+                    self.update(Message::Notice(msg));
+                }
                 None => (),
             },
+            Message::Review(message) => {
+                let output = self
+                    .review
+                    .as_mut()
+                    .and_then(|review| review.update(message));
+                match output {
+                    Some(review::Output::AcceptAll) => {
+                        self.review = None;
+                        self.handle(sync::message::Message::Update);
+                    }
+                    Some(review::Output::Accept(fields)) => {
+                        self.review = None;
+                        self.handle(sync::message::Message::Accept(fields));
+                    }
+                    Some(review::Output::Postpone) => {
+                        self.review = None;
+                        self.update(Message::Error(
+                            "Changes postponed until the next sync".into(),
+                        ));
+                    }
+                    None => (),
+                }
+            }
+            Message::Conflicts(message) => {
+                let output = self
+                    .conflicts
+                    .as_mut()
+                    .and_then(|conflicts| conflicts.update(message));
+                match output {
+                    Some(conflicts::Output::Resolve(strategy)) => {
+                        self.conflicts = None;
+                        self.handle(sync::message::Message::Resolve(strategy));
+                    }
+                    Some(conflicts::Output::Reset) => {
+                        self.conflicts = None;
+                        self.handle(sync::message::Message::Reset);
+                    }
+                    Some(conflicts::Output::Postpone) => {
+                        self.conflicts = None;
+                        self.update(Message::Error(
+                            "Conflicts postponed until the next sync".into(),
+                        ));
+                    }
+                    None => (),
+                }
+            }
             Message::SwitchColorScheme => {
                 self.theme = match self.theme.theme_type {
                     ThemeType::Dark => Theme::light(),
@@ -274,11 +473,15 @@ impl Application for Symmetry {
                 }
             }
             Message::HandlePickedFile(files) => {
-                let file = files.first().unwrap().replace("file://", "");
+                let Some(file) = files.first().and_then(|uri| file_path(uri)) else {
+                    return Command::none();
+                };
                 self.desktop
                     .update(desktop::Message::WallpaperChanged(file.clone()));
                 self.update(Message::Desktop(desktop::Message::WallpaperChanged(file)));
             }
+            Message::Notice(notice) => self.notice = Some(notice),
+            Message::DismissNotice => self.notice = None,
             Message::Error(error) => {
                 self.error = error;
                 if !self.show_warning {
@@ -287,45 +490,19 @@ impl Application for Symmetry {
             }
             Message::CondensedViewToggle => {}
             Message::Sync => {
-                self.sync = refresh_sync_provider();
+                self.sync = match refresh_sync_provider() {
+                    Ok(sync) => sync,
+                    Err(err) => return self.update(Message::Error(err.to_string())),
+                };
                 if let Some(sync) = self.sync.as_ref() {
                     match sync.sync() {
-                        Ok(status) => match status {
-                            sync::status::Status::RepoConfigured => {
-                                self.update(Message::Error("Repo configured successfully".into()));
-                            }
-                            sync::status::Status::UpToDate => {
-                                self.update(Message::Error("Already up to date".into()));
-                            }
-                            sync::status::Status::ChangesUploaded => {
-                                self.update(Message::Error("Successfully synchronized".into()));
-                            }
-                            sync::status::Status::NewChangesDetected => {
-                                // todo!("Check if there are conflicts, if not, pull changes");
-                                match sync.handle(sync::message::Message::Update) {
-                                    Ok(_) => {
-                                        self.update(Message::Error(
-                                            "Latest changes downloaded".into(),
-                                        ));
-                                    }
-                                    Err(err) => {
-                                        self.update(Message::Error(
-                                            format!("An error ocurred while trying to get the latest changes: {}.", err)
-                                        ));
-                                    }
-                                }
-                            }
-                            sync::status::Status::RepoNotConfigured => {
-                                self.update(Message::Error(
-                                    "The repository has not been configured".into(),
-                                ));
-                            }
-                        },
+                        Ok(status) => self.report(status),
                         Err(err) => {
                             self.update(Message::Error(err.to_string()));
                         }
                     }
                 }
+                self.files.refresh();
             }
         }
         Command::none()
@@ -335,3 +512,29 @@ impl Application for Symmetry {
         self.theme.clone()
     }
 }
+
+/// Turns the first file chosen in a file picker into a message for the settings page.
+fn chosen(uris: Result<Vec<String>, String>, message: fn(String) -> settings::Message) -> Message {
+    let uri = match uris {
+        Ok(uris) => uris.into_iter().next(),
+        Err(err) => return Message::Error(err),
+    };
+    let Some(uri) = uri else {
+        return Message::Error("No file was chosen.".into());
+    };
+    match file_path(&uri) {
+        Some(path) => Message::Settings(message(path)),
+        None => Message::Error(format!("{uri} isn't a local file.")),
+    }
+}
+
+/// The local path a `file://` URI points to, decoding spaces and other escaped characters.
+fn file_path(uri: &str) -> Option<String> {
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+    Some(path.to_string_lossy().to_string())
+}
+
+/// Abbreviates a commit id the way git does.
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
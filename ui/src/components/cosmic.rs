@@ -0,0 +1,29 @@
+use std::collections::BTreeSet;
+
+use cosmic::{
+    iced_winit::{row, widget::horizontal_space, Length},
+    widget::{
+        settings::{item, view_section},
+        toggler,
+    },
+    Element,
+};
+use symmetry_core::desktop::cosmic::CosmicComponent;
+
+use crate::pages::desktop::Message;
+
+pub(crate) fn cosmic_section<'a>(components: &BTreeSet<CosmicComponent>) -> Element<'a, Message> {
+    let mut section = view_section("COSMIC");
+    for component in CosmicComponent::ALL {
+        section = section.add(item(
+            component.title(),
+            row![
+                horizontal_space(Length::Fill),
+                toggler(None, components.contains(&component), move |state| {
+                    Message::CosmicComponentToggled(component, state)
+                })
+            ],
+        ));
+    }
+    section.into()
+}
@@ -0,0 +1,26 @@
+use cosmic::{
+    iced_winit::{row, widget::horizontal_space, Length},
+    widget::{
+        settings::{item, view_section},
+        toggler,
+    },
+    Element,
+};
+
+use crate::pages::desktop::Message;
+
+pub(crate) fn kde_section<'a>(enabled: bool) -> Element<'a, Message> {
+    view_section("KDE Plasma")
+        .add(item(
+            "Status",
+            row![
+                horizontal_space(Length::Fill),
+                toggler(
+                    Some("Synchronize kdeglobals, kwinrc and kcminputrc".into()),
+                    enabled,
+                    Message::KdeToggled
+                )
+            ],
+        ))
+        .into()
+}
@@ -1,3 +1,5 @@
 pub(crate) mod appearance;
+pub(crate) mod cosmic;
 pub(crate) mod header_bar;
+pub(crate) mod kde;
 pub(crate) mod wallpaper;
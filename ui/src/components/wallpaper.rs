@@ -18,7 +18,7 @@ use crate::pages::desktop::Message;
 
 static INPUT_ID: Lazy<text_input::Id> = Lazy::new(text_input::Id::unique);
 
-pub(crate) fn wallpaper_section<'a>(wallpaper: String) -> Element<'a, Message> {
+pub(crate) fn wallpaper_section<'a>(wallpaper: String, preview: String) -> Element<'a, Message> {
     let wallpaper_entry: Element<Message> = text_input(
         "Paste the wallpaper path or URL here.",
         &wallpaper,
@@ -44,7 +44,7 @@ pub(crate) fn wallpaper_section<'a>(wallpaper: String) -> Element<'a, Message> {
         ))
         .add(item(
             "Preview",
-            Image::new(&wallpaper).width(Length::FillPortion(20)),
+            Image::new(&preview).width(Length::FillPortion(20)),
         ))
         .into()
 }
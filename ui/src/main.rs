@@ -3,15 +3,38 @@ use cosmic::{
     iced::{Application, Settings},
     settings,
 };
+use symmetry_core::context::SymmetryContext;
 
 mod app;
 mod components;
 mod pages;
 
 fn main() -> cosmic::iced::Result {
+    if let Some(data_dir) = data_dir_arg() {
+        SymmetryContext::set_current(SymmetryContext::new(data_dir))
+            .expect("The data directory is only set once.");
+    }
+    if let Err(err) = SymmetryContext::current().and_then(|context| context.migrate()) {
+        eprintln!("The data couldn't be moved to the current layout: {err:#}");
+        std::process::exit(1);
+    }
     Symmetry::run(iced_settings())
 }
 
+/// Reads `--data-dir <path>` or `--data-dir=<path>` from the command line.
+fn data_dir_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--data-dir" {
+            return args.next();
+        }
+        if let Some(data_dir) = arg.strip_prefix("--data-dir=") {
+            return Some(data_dir.into());
+        }
+    }
+    None
+}
+
 pub fn iced_settings() -> Settings<()> {
     settings::set_default_icon_theme("Adwaita");
     let mut settings = settings();
@@ -0,0 +1,71 @@
+use cosmic::iced::widget::{button, row, text};
+use cosmic::iced_winit::widget::horizontal_space;
+use cosmic::iced_winit::Length;
+use cosmic::widget::scrollable;
+use cosmic::widget::settings::{item, view_column, view_section};
+use cosmic::Element;
+use symmetry_core::configuration::changes::Field;
+use symmetry_core::sync::providers::config::git::ConflictStrategy;
+
+/// Conflicting changes waiting for the user to pick a side, shown in place of the current page.
+pub struct State {
+    fields: Vec<Field>,
+    paths: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    KeepLocal,
+    KeepRemote,
+    Reset,
+    Postpone,
+}
+
+pub enum Output {
+    Resolve(ConflictStrategy),
+    Reset,
+    Postpone,
+}
+
+impl State {
+    pub fn new(fields: Vec<Field>, paths: Vec<String>) -> Self {
+        Self { fields, paths }
+    }
+
+    pub fn view(&self) -> Element<Message> {
+        let mut conflicts = view_section("Conflicts");
+        for field in &self.fields {
+            conflicts = conflicts.add(item(field.name(), text("Changed on both devices")));
+        }
+        for path in &self.paths {
+            conflicts = conflicts.add(item(path.as_str(), text("Changed on both devices")));
+        }
+
+        let review = view_column(vec![
+            row!(text("Resolve conflicts").size(30), horizontal_space(Length::Fill)).into(),
+            text("This device and another one changed the same things, choose which version to keep.")
+                .size(16)
+                .into(),
+            conflicts.into(),
+            row![
+                horizontal_space(Length::Fill),
+                button(text("Postpone")).on_press(Message::Postpone),
+                button(text("Discard local changes")).on_press(Message::Reset),
+                button(text("Keep this device's")).on_press(Message::KeepLocal),
+                button(text("Keep the other device's")).on_press(Message::KeepRemote),
+            ]
+            .spacing(10)
+            .into(),
+        ]);
+        scrollable(review).into()
+    }
+
+    pub fn update(&mut self, message: Message) -> Option<Output> {
+        match message {
+            Message::KeepLocal => Some(Output::Resolve(ConflictStrategy::Ours)),
+            Message::KeepRemote => Some(Output::Resolve(ConflictStrategy::Theirs)),
+            Message::Reset => Some(Output::Reset),
+            Message::Postpone => Some(Output::Postpone),
+        }
+    }
+}
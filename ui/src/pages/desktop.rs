@@ -1,6 +1,10 @@
+use std::collections::BTreeSet;
+
 use super::Page;
 use crate::app::Symmetry;
 use crate::components::appearance::appearance_section;
+use crate::components::cosmic::cosmic_section;
+use crate::components::kde::kde_section;
 use crate::components::wallpaper::wallpaper_section;
 use ashpd::desktop::file_chooser::OpenFileRequest;
 use ashpd::WindowIdentifier;
@@ -10,17 +14,24 @@ use cosmic::widget::settings::view_column;
 use cosmic::Element;
 use symmetry_core::color_scheme::ColorScheme;
 use symmetry_core::configuration::Configuration;
+use symmetry_core::context::SymmetryContext;
+use symmetry_core::desktop::cosmic::CosmicComponent;
 
 #[derive(Debug, Default)]
 pub struct State {
     wallpaper: String,
+    wallpaper_preview: String,
     selected_color_scheme: Option<ColorScheme>,
+    cosmic_components: BTreeSet<CosmicComponent>,
+    kde_enabled: bool,
 }
 
 #[derive(Clone, Debug)]
 pub enum Message {
     WallpaperChanged(String),
     ColorSchemeChanged(ColorScheme),
+    CosmicComponentToggled(CosmicComponent, bool),
+    KdeToggled(bool),
     OpenFilePicker,
 }
 
@@ -31,23 +42,36 @@ pub enum Output {
 }
 
 impl State {
-    pub fn new(wallpaper: String, selected_color_scheme: Option<ColorScheme>) -> Self {
+    pub fn new(
+        wallpaper: String,
+        wallpaper_preview: String,
+        selected_color_scheme: Option<ColorScheme>,
+        cosmic_components: BTreeSet<CosmicComponent>,
+        kde_enabled: bool,
+    ) -> Self {
         Self {
             wallpaper,
+            wallpaper_preview,
             selected_color_scheme,
+            cosmic_components,
+            kde_enabled,
         }
     }
 
     pub fn view<'a>(&'a self, app: &'a Symmetry) -> Element<'a, Message> {
-        let wallpaper = wallpaper_section(self.wallpaper.clone());
+        let wallpaper = wallpaper_section(self.wallpaper.clone(), self.wallpaper_preview.clone());
         let appearance = appearance_section(self.selected_color_scheme);
+        let cosmic = cosmic_section(&self.cosmic_components);
+        let kde = kde_section(self.kde_enabled);
         let desktop: Element<'a, Message> = view_column(vec![
             app.page_title(Page::Desktop),
             text("The desktop preferences section allows you to customize and personalize your desktop environment to suit your unique preferences and workflow.")
                 .size(16)
                 .into(),
             wallpaper,
-            appearance
+            appearance,
+            cosmic,
+            kde
         ]).into();
         scrollable(desktop).into()
     }
@@ -59,6 +83,10 @@ impl State {
                 let config = Configuration::current();
                 if let Some(mut config) = config {
                     config.wallpaper = path;
+                    self.wallpaper_preview = SymmetryContext::current()
+                        .and_then(|context| config.rendered(&context))
+                        .map(|rendered| rendered.wallpaper)
+                        .unwrap_or_else(|_| config.wallpaper.clone());
                     return match config.write() {
                         Ok(_) => Some(Output::Message("Wallpaper path updated".to_string())),
                         Err(err) => Some(Output::Error(err.to_string())),
@@ -78,6 +106,34 @@ impl State {
                 }
                 None
             }
+            Message::CosmicComponentToggled(component, state) => {
+                if state {
+                    self.cosmic_components.insert(component);
+                } else {
+                    self.cosmic_components.remove(&component);
+                }
+                let config = Configuration::current();
+                if let Some(mut config) = config {
+                    config.desktop.cosmic.components = self.cosmic_components.clone();
+                    return match config.write() {
+                        Ok(_) => Some(Output::Message("COSMIC components updated".into())),
+                        Err(err) => Some(Output::Error(err.to_string())),
+                    };
+                }
+                None
+            }
+            Message::KdeToggled(enabled) => {
+                self.kde_enabled = enabled;
+                let config = Configuration::current();
+                if let Some(mut config) = config {
+                    config.desktop.kde.enabled = enabled;
+                    return match config.write() {
+                        Ok(_) => Some(Output::Message("KDE Plasma sync updated".into())),
+                        Err(err) => Some(Output::Error(err.to_string())),
+                    };
+                }
+                None
+            }
             Message::OpenFilePicker => {
                 let request = OpenFileRequest::default()
                     .directory(false)
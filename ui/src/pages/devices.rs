@@ -0,0 +1,358 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use crate::app::Symmetry;
+use cosmic::iced::futures::channel::oneshot;
+use cosmic::iced::widget::{button, row, text, text_input};
+use cosmic::iced::Font;
+use cosmic::iced_winit::{Command, Length};
+use cosmic::widget::icon;
+use cosmic::widget::settings::{item, view_column, view_section};
+use cosmic::{theme, Element};
+use symmetry_core::configuration::Configuration;
+use symmetry_core::context::SymmetryContext;
+use symmetry_core::secrets::Secrets;
+use symmetry_core::sync::lan::pairing::{Invite, Joined, Joining, Request};
+use symmetry_core::trust::identity::Identity;
+use symmetry_core::trust::invitation::Invitation;
+use symmetry_core::trust::{self, TrustedDevice};
+
+use super::{configuration, Page};
+
+pub struct State {
+    id: String,
+    devices: Vec<(String, TrustedDevice)>,
+    invite: Option<Arc<Invite>>,
+    qr_code: String,
+    request: Option<Request>,
+    join_address: String,
+    joining_code: Option<String>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        let mut state = Self {
+            id: String::new(),
+            devices: vec![],
+            invite: None,
+            qr_code: String::new(),
+            request: None,
+            join_address: String::new(),
+            joining_code: None,
+        };
+        state.refresh();
+        state
+    }
+}
+
+/// Hands a value that can't be cloned over through a message.
+#[derive(Clone)]
+pub struct Handoff<T>(Arc<Mutex<Option<T>>>);
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Invite,
+    StopInviting,
+    Requested(Result<Handoff<Request>, String>),
+    Approve,
+    Decline,
+    JoinAddressChanged(String),
+    Join,
+    Joining(Result<Handoff<Joining>, String>),
+    Joined(Result<Joined, String>),
+    Remove(String),
+}
+
+pub enum Output {
+    Message(String),
+    Error(String),
+    Command(Command<Message>),
+    Sync,
+}
+
+impl State {
+    pub fn view<'a>(&'a self, app: &'a Symmetry) -> Element<'a, Message> {
+        let mut trusted = view_section("Trusted devices");
+        if self.devices.is_empty() {
+            trusted = trusted.add(text("This device isn't paired with any other yet."));
+        }
+        for (id, device) in &self.devices {
+            let name = if *id == self.id {
+                format!("{} (this device)", device.name)
+            } else {
+                device.name.clone()
+            };
+            trusted = trusted.add(item(
+                name,
+                row![
+                    text(id).size(14),
+                    button(icon("user-trash-symbolic", 16).style(theme::Svg::SymbolicPrimary))
+                        .padding(10)
+                        .on_press(Message::Remove(id.clone())),
+                ]
+                .spacing(10),
+            ));
+        }
+
+        let mut invite = view_section("Invite a device");
+        match (&self.invite, &self.request) {
+            (_, Some(request)) => {
+                let prompt = if request.is_verified() {
+                    format!(
+                        "{} scanned the invitation and asks to join.",
+                        request.device().name
+                    )
+                } else {
+                    format!(
+                        "{} asks to join. Make sure it shows the code {}.",
+                        request.device().name,
+                        request.code()
+                    )
+                };
+                invite = invite.add(item(
+                    prompt,
+                    row![
+                        button(text("Approve")).on_press(Message::Approve),
+                        button(text("Decline")).on_press(Message::Decline),
+                    ]
+                    .spacing(10),
+                ));
+            }
+            (Some(pending), None) => {
+                let invitation = pending.invitation();
+                invite = invite
+                    .add(text(&self.qr_code).font(Font::MONOSPACE).size(8))
+                    .add(item(
+                        "Scan the code or enter this link on the new device",
+                        text(invitation.link()).size(12),
+                    ))
+                    .add(item(
+                        "Or pair by address, comparing codes",
+                        row![
+                            text(invitation.address.to_string()),
+                            button(text("Stop")).on_press(Message::StopInviting),
+                        ]
+                        .spacing(10),
+                    ));
+            }
+            (None, None) => {
+                invite = invite.add(item(
+                    "Let a new device synchronize with this one",
+                    button(text("Invite")).on_press(Message::Invite),
+                ));
+            }
+        }
+
+        let join_prompt = match &self.joining_code {
+            Some(code) => format!("Waiting for approval, the other device should show {code}"),
+            None => "Pairing link or address".into(),
+        };
+        let join = view_section("Join a device").add(item(
+            join_prompt,
+            row![
+                text_input(
+                    "symmetry://pair?… or 192.168.1.20:41234",
+                    &self.join_address,
+                    Message::JoinAddressChanged,
+                )
+                .padding(10)
+                .size(16)
+                .width(Length::FillPortion(20)),
+                button(text("Join")).on_press(Message::Join),
+            ]
+            .spacing(10),
+        ));
+
+        view_column(vec![
+            app.page_title(Page::Devices),
+            text("Devices pair directly with each other to synchronize over the local network.")
+                .size(16)
+                .into(),
+            trusted.into(),
+            invite.into(),
+            join.into(),
+        ])
+        .into()
+    }
+
+    pub fn update(&mut self, message: Message) -> Option<Output> {
+        match message {
+            Message::Invite => {
+                let invite = identity().and_then(|identity| {
+                    let invite = Invite::new(&identity, ("0.0.0.0", 0))?;
+                    self.qr_code = invite.invitation().qr_code()?;
+                    Ok(invite)
+                });
+                match invite {
+                    Ok(invite) => {
+                        let invite = Arc::new(invite);
+                        self.invite = Some(invite.clone());
+                        Some(Output::Command(wait_for_request(invite)))
+                    }
+                    Err(err) => Some(Output::Error(err.to_string())),
+                }
+            }
+            Message::StopInviting => {
+                // Wakes up the pending wait, which then ends with an error that's ignored.
+                if let Some(invite) = self.invite.take() {
+                    let _ = TcpStream::connect(invite.invitation().address);
+                }
+                self.request = None;
+                None
+            }
+            Message::Requested(Ok(request)) => {
+                self.request = request.take();
+                None
+            }
+            Message::Requested(Err(err)) => {
+                // A device failing to pair doesn't end the invitation for the others.
+                let invite = self.invite.clone()?;
+                eprintln!("{err}");
+                Some(Output::Command(wait_for_request(invite)))
+            }
+            Message::Approve => {
+                let request = self.request.take()?;
+                let approved = identity().and_then(|identity| {
+                    let mut config = configuration()?;
+                    let context = SymmetryContext::current()?;
+                    let name = request.device().name.clone();
+                    Secrets::init(&context, &config)?;
+                    request.approve(&identity, &Secrets::key(&context)?, &mut config)?;
+                    config.write()?;
+                    Ok(name)
+                });
+                self.refresh();
+                self.invite = None;
+                match approved {
+                    Ok(name) => Some(Output::Message(format!("{name} is now trusted"))),
+                    Err(err) => Some(Output::Error(err.to_string())),
+                }
+            }
+            Message::Decline => {
+                self.request = None;
+                let invite = self.invite.clone()?;
+                Some(Output::Command(wait_for_request(invite)))
+            }
+            Message::JoinAddressChanged(address) => {
+                self.join_address = address;
+                None
+            }
+            Message::Join => {
+                let address = self.join_address.trim().to_string();
+                let command = Command::perform(
+                    background(move || {
+                        let identity = identity()?;
+                        let joining = if address.starts_with("symmetry://") {
+                            Joining::with_invitation(&Invitation::parse(&address)?, &identity)?
+                        } else {
+                            Joining::new(address.parse::<SocketAddr>()?, &identity)?
+                        };
+                        Ok(Handoff::new(joining))
+                    }),
+                    Message::Joining,
+                );
+                Some(Output::Command(command))
+            }
+            Message::Joining(Ok(joining)) => {
+                let joining = joining.take()?;
+                self.joining_code = Some(joining.code().to_string());
+                let command =
+                    Command::perform(background(move || joining.finish()), Message::Joined);
+                Some(Output::Command(command))
+            }
+            Message::Joining(Err(err)) => Some(Output::Error(err)),
+            Message::Joined(result) => {
+                self.joining_code = None;
+                let joined = result.and_then(|joined| {
+                    let mut config = configuration().map_err(|err| err.to_string())?;
+                    SymmetryContext::current()
+                        .and_then(|context| Secrets::set_key(&context, &config, &joined.key))
+                        .map_err(|err| err.to_string())?;
+                    config.devices = joined.devices;
+                    config.write().map_err(|err| err.to_string())
+                });
+                self.refresh();
+                match joined {
+                    Ok(()) => {
+                        self.join_address.clear();
+                        Some(Output::Sync)
+                    }
+                    Err(err) => Some(Output::Error(err)),
+                }
+            }
+            Message::Remove(id) => {
+                if id == self.id {
+                    return Some(Output::Error(
+                        "Remove this device from another one instead".into(),
+                    ));
+                }
+                let removed = configuration().and_then(|mut config| {
+                    let context = SymmetryContext::current()?;
+                    Secrets::init(&context, &config)?;
+                    let new_key = trust::revoke(&mut config, &id, &Secrets::key(&context)?)?;
+                    Secrets::set_key(&context, &config, &new_key)?;
+                    config.write()
+                });
+                self.refresh();
+                match removed {
+                    // The others learn about it, and pick up the new key, on their next sync.
+                    Ok(()) => Some(Output::Sync),
+                    Err(err) => Some(Output::Error(err.to_string())),
+                }
+            }
+        }
+    }
+
+    /// Reloads the trusted devices from the configuration.
+    pub fn refresh(&mut self) {
+        self.id = identity().map(|identity| identity.id()).unwrap_or_default();
+        self.devices = Configuration::current()
+            .map(|config| config.devices.trusted.into_iter().collect())
+            .unwrap_or_default();
+    }
+}
+
+impl<T> Handoff<T> {
+    fn new(value: T) -> Self {
+        Self(Arc::new(Mutex::new(Some(value))))
+    }
+
+    fn take(&self) -> Option<T> {
+        self.0.lock().ok()?.take()
+    }
+}
+
+impl<T> Debug for Handoff<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handoff")
+    }
+}
+
+fn identity() -> anyhow::Result<Identity> {
+    Identity::load(&SymmetryContext::current()?.state_path())
+}
+
+/// Waits in the background for a device to answer the invitation.
+fn wait_for_request(invite: Arc<Invite>) -> Command<Message> {
+    Command::perform(
+        background(move || invite.accept().map(Handoff::new)),
+        Message::Requested,
+    )
+}
+
+/// Runs blocking work on its own thread, so the interface stays responsive.
+fn background<T: Send + 'static>(
+    work: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> impl Future<Output = Result<T, String>> {
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(work().map_err(|err| err.to_string()));
+    });
+    async move {
+        receiver
+            .await
+            .unwrap_or_else(|_| Err("The pairing was interrupted".into()))
+    }
+}
@@ -0,0 +1,300 @@
+use std::path::PathBuf;
+
+use crate::app::Symmetry;
+use cosmic::iced::widget::{button, column, radio, row, text, text_input};
+use cosmic::iced_winit::widget::horizontal_space;
+use cosmic::iced_winit::Length;
+use cosmic::widget::settings::{item, view_column, view_section};
+use cosmic::widget::{icon, scrollable, toggler};
+use cosmic::{theme, Element};
+use symmetry_core::configuration::Configuration;
+use symmetry_core::context::SymmetryContext;
+use symmetry_core::device::Device;
+use symmetry_core::files::tracked_file::{DeployMode, FileState, TrackedFile};
+use symmetry_core::files::{Difference, Files};
+
+use super::Page;
+
+pub struct State {
+    files: Vec<(TrackedFile, FileState)>,
+    differences: Vec<Difference>,
+    source: String,
+    include: String,
+    exclude: String,
+    device: Device,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        let mut state = Self {
+            files: vec![],
+            differences: vec![],
+            source: String::new(),
+            include: String::new(),
+            exclude: String::new(),
+            device: Device::current(),
+        };
+        state.refresh();
+        state
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    SourceChanged(String),
+    IncludeChanged(String),
+    ExcludeChanged(String),
+    AddFile,
+    RemoveFile(usize),
+    ToggleFile(usize, bool),
+    ModeChanged(usize, DeployMode),
+    ToggleTemplate(usize, bool),
+    ToggleSecret(usize, bool),
+    RestoreOriginals,
+    Accept(PathBuf),
+    Keep(PathBuf),
+}
+
+pub enum Output {
+    Message(String),
+    Error(String),
+}
+
+impl State {
+    pub fn view<'a>(&'a self, app: &'a Symmetry) -> Element<'a, Message> {
+        let add_section = view_section("Track a file")
+            .add(item(
+                "Path",
+                row![
+                    text_input("~/.gitconfig", &self.source, Message::SourceChanged)
+                        .padding(10)
+                        .size(16)
+                        .width(Length::FillPortion(20)),
+                    button(icon("list-add-symbolic", 16).style(theme::Svg::SymbolicPrimary))
+                        .padding(10)
+                        .on_press(Message::AddFile)
+                ]
+                .spacing(10),
+            ))
+            .add(item(
+                "Include",
+                text_input(
+                    "Comma separated globs, e.g. *.lua",
+                    &self.include,
+                    Message::IncludeChanged,
+                )
+                .padding(10)
+                .size(16)
+                .width(Length::FillPortion(20)),
+            ))
+            .add(item(
+                "Exclude",
+                text_input(
+                    "Comma separated globs, e.g. cache/**",
+                    &self.exclude,
+                    Message::ExcludeChanged,
+                )
+                .padding(10)
+                .size(16)
+                .width(Length::FillPortion(20)),
+            ));
+
+        let mut files_section = view_section("Tracked files");
+        for (index, (file, state)) in self.files.iter().enumerate() {
+            files_section = files_section.add(item(
+                file.source.as_str(),
+                row![
+                    horizontal_space(Length::Fill),
+                    text(state.title()),
+                    radio("Copy", DeployMode::Copy, Some(file.mode), move |mode| {
+                        Message::ModeChanged(index, mode)
+                    }),
+                    radio(
+                        "Symlink",
+                        DeployMode::Symlink,
+                        Some(file.mode),
+                        move |mode| { Message::ModeChanged(index, mode) }
+                    ),
+                    toggler(Some("Template".into()), file.template, move |template| {
+                        Message::ToggleTemplate(index, template)
+                    }),
+                    toggler(Some("Secret".into()), file.secret, move |secret| {
+                        Message::ToggleSecret(index, secret)
+                    }),
+                    toggler(None, file.is_enabled(&self.device), move |enabled| {
+                        Message::ToggleFile(index, enabled)
+                    }),
+                    button(icon("user-trash-symbolic", 16).style(theme::Svg::SymbolicPrimary))
+                        .padding(10)
+                        .on_press(Message::RemoveFile(index))
+                ]
+                .spacing(10),
+            ));
+        }
+
+        let mut differences_section = view_section("Differences");
+        for difference in &self.differences {
+            differences_section = differences_section.add(item(
+                difference.source.to_string_lossy(),
+                column![
+                    text(&difference.diff).size(14),
+                    row![
+                        horizontal_space(Length::Fill),
+                        button(text("Keep this version"))
+                            .on_press(Message::Keep(difference.source.clone())),
+                        button(text("Replace"))
+                            .on_press(Message::Accept(difference.source.clone()))
+                    ]
+                    .spacing(10)
+                ]
+                .spacing(10),
+            ));
+        }
+
+        let mut sections = vec![
+            app.page_title(Page::Files),
+            text("The files page allows you to synchronize dotfiles and other files.")
+                .size(16)
+                .into(),
+        ];
+        if !self.differences.is_empty() {
+            sections.push(
+                text("These files differ from their synchronized version, replace them or keep them.")
+                    .size(16)
+                    .into(),
+            );
+            sections.push(differences_section.into());
+        }
+        sections.extend([add_section.into(), files_section.into()]);
+        sections.push(
+            view_section("Deployment")
+                .add(item(
+                    "Restore the files that existed before they were synchronized",
+                    row![
+                        horizontal_space(Length::Fill),
+                        button(text("Restore originals")).on_press(Message::RestoreOriginals)
+                    ],
+                ))
+                .into(),
+        );
+        scrollable(view_column(sections)).into()
+    }
+
+    pub fn update(&mut self, message: Message) -> Option<Output> {
+        match message {
+            Message::SourceChanged(source) => {
+                self.source = source;
+                None
+            }
+            Message::IncludeChanged(include) => {
+                self.include = include;
+                None
+            }
+            Message::ExcludeChanged(exclude) => {
+                self.exclude = exclude;
+                None
+            }
+            Message::AddFile => {
+                if self.source.is_empty() {
+                    return None;
+                }
+                let mut file = TrackedFile::new(self.source.trim());
+                file.include = split_globs(&self.include);
+                file.exclude = split_globs(&self.exclude);
+                self.source.clear();
+                self.include.clear();
+                self.exclude.clear();
+                self.write_to_config(|files| files.push(file))
+            }
+            Message::RemoveFile(index) => self.write_to_config(|files| {
+                if index < files.len() {
+                    files.remove(index);
+                }
+            }),
+            Message::ToggleFile(index, enabled) => {
+                let device = self.device.clone();
+                self.write_to_config(|files| {
+                    if let Some(file) = files.get_mut(index) {
+                        file.set_enabled(&device, enabled);
+                    }
+                })
+            }
+            Message::ModeChanged(index, mode) => self.write_to_config(|files| {
+                if let Some(file) = files.get_mut(index) {
+                    file.mode = mode;
+                }
+            }),
+            Message::ToggleTemplate(index, template) => self.write_to_config(|files| {
+                if let Some(file) = files.get_mut(index) {
+                    file.template = template;
+                }
+            }),
+            Message::ToggleSecret(index, secret) => self.write_to_config(|files| {
+                if let Some(file) = files.get_mut(index) {
+                    file.secret = secret;
+                }
+            }),
+            Message::RestoreOriginals => {
+                let (Some(mut config), Some(files)) = (Configuration::current(), Self::files())
+                else {
+                    return None;
+                };
+                let output = match files.restore(&mut config).and_then(|()| config.write()) {
+                    Ok(_) => Output::Message("Original files restored".into()),
+                    Err(err) => Output::Error(err.to_string()),
+                };
+                self.refresh();
+                Some(output)
+            }
+            Message::Accept(source) => self.review(|files, config| files.accept(config, &source)),
+            Message::Keep(source) => self.review(|files, config| files.keep(config, &source)),
+        }
+    }
+
+    /// Settles a held file, either replacing it or keeping the device version.
+    fn review(
+        &mut self,
+        decide: impl FnOnce(&Files, &Configuration) -> anyhow::Result<()>,
+    ) -> Option<Output> {
+        let (Some(config), Some(files)) = (Configuration::current(), Self::files()) else {
+            return None;
+        };
+        let output = decide(&files, &config)
+            .err()
+            .map(|err| Output::Error(err.to_string()));
+        self.refresh();
+        output
+    }
+
+    /// Reloads the tracked files and their state.
+    pub fn refresh(&mut self) {
+        if let (Some(config), Some(files)) = (Configuration::current(), Self::files()) {
+            self.files = files.status(&config);
+            self.differences = files.differences(&config).unwrap_or_default();
+        }
+    }
+
+    fn files() -> Option<Files> {
+        Some(Files::new(&SymmetryContext::current().ok()?))
+    }
+
+    fn write_to_config(&mut self, edit: impl FnOnce(&mut Vec<TrackedFile>)) -> Option<Output> {
+        if let Some(mut config) = Configuration::current() {
+            edit(&mut config.files);
+            if let Err(err) = config.write() {
+                return Some(Output::Error(err.to_string()));
+            }
+            self.refresh();
+        }
+        None
+    }
+}
+
+fn split_globs(globs: &str) -> Vec<String> {
+    globs
+        .split(',')
+        .map(str::trim)
+        .filter(|glob| !glob.is_empty())
+        .map(String::from)
+        .collect()
+}
@@ -1,14 +1,23 @@
+pub mod conflicts;
 pub mod desktop;
+pub mod devices;
+pub mod files;
+pub mod review;
 pub mod services;
 pub mod settings;
 pub mod welcome;
 
+use anyhow::Context;
+use symmetry_core::configuration::Configuration;
+
 #[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Page {
     #[default]
     Welcome,
     Desktop,
+    Files,
     Services,
+    Devices,
     Settings,
 }
 
@@ -18,7 +27,9 @@ impl Page {
         match self {
             Welcome => "Welcome",
             Desktop => "Desktop",
+            Files => "Files",
             Services => "Services",
+            Devices => "Devices",
             Settings => "Settings",
         }
     }
@@ -28,8 +39,16 @@ impl Page {
         match self {
             Welcome => "face-smile-big-symbolic",
             Desktop => "computer-symbolic",
+            Files => "folder-documents-symbolic",
             Services => "network-server-symbolic",
+            Devices => "network-workgroup-symbolic",
             Settings => "preferences-system-symbolic",
         }
     }
 }
+
+/// Reads the configuration to change it. Failing is safer than starting from a default
+/// one, which would replace the configuration on every device once written.
+fn configuration() -> anyhow::Result<Configuration> {
+    Configuration::current().context("The configuration couldn't be read, nothing was changed")
+}
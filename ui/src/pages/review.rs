@@ -0,0 +1,92 @@
+use cosmic::iced::widget::{button, row, text};
+use cosmic::iced_winit::widget::horizontal_space;
+use cosmic::iced_winit::Length;
+use cosmic::widget::settings::{item, view_column, view_section};
+use cosmic::widget::{scrollable, toggler};
+use cosmic::Element;
+use symmetry_core::configuration::changes::{Change, Field};
+
+/// Incoming changes waiting to be reviewed, shown in place of the current page.
+pub struct State {
+    changes: Vec<(Change, bool)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    ToggleChange(usize, bool),
+    AcceptAll,
+    AcceptSelected,
+    Postpone,
+}
+
+pub enum Output {
+    AcceptAll,
+    Accept(Vec<Field>),
+    Postpone,
+}
+
+impl State {
+    pub fn new(changes: Vec<Change>) -> Self {
+        Self {
+            changes: changes.into_iter().map(|change| (change, true)).collect(),
+        }
+    }
+
+    pub fn view(&self) -> Element<Message> {
+        let mut changes = view_section("Incoming changes");
+        for (index, (change, selected)) in self.changes.iter().enumerate() {
+            changes = changes.add(item(
+                change.field.name(),
+                row![
+                    text(change.summary.trim_start_matches(':').trim().to_string()),
+                    horizontal_space(Length::Fill),
+                    toggler(None, *selected, move |selected| {
+                        Message::ToggleChange(index, selected)
+                    }),
+                ]
+                .spacing(10),
+            ));
+        }
+
+        let review = view_column(vec![
+            row!(
+                text("Review changes").size(30),
+                horizontal_space(Length::Fill)
+            )
+            .into(),
+            text("Another device changed these settings, choose which ones to apply here.")
+                .size(16)
+                .into(),
+            changes.into(),
+            row![
+                horizontal_space(Length::Fill),
+                button(text("Postpone")).on_press(Message::Postpone),
+                button(text("Accept selected")).on_press(Message::AcceptSelected),
+                button(text("Accept all")).on_press(Message::AcceptAll),
+            ]
+            .spacing(10)
+            .into(),
+        ]);
+        scrollable(review).into()
+    }
+
+    pub fn update(&mut self, message: Message) -> Option<Output> {
+        match message {
+            Message::ToggleChange(index, selected) => {
+                if let Some((_, current)) = self.changes.get_mut(index) {
+                    *current = selected;
+                }
+                None
+            }
+            Message::AcceptAll => Some(Output::AcceptAll),
+            Message::AcceptSelected => Some(Output::Accept(
+                self.changes
+                    .iter()
+                    .filter(|(_, selected)| *selected)
+                    .map(|(change, _)| change.field)
+                    .collect(),
+            )),
+            Message::Postpone => Some(Output::Postpone),
+        }
+    }
+}
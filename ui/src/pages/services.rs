@@ -1,45 +1,55 @@
+use std::collections::BTreeMap;
+
 use crate::app::Symmetry;
-use cosmic::iced::widget::{button, row, text, text_input};
+use anyhow::Result;
+use cosmic::iced::widget::{button, radio, row, text, text_input};
 use cosmic::iced_winit::widget::horizontal_space;
 use cosmic::iced_winit::Length;
 use cosmic::widget::settings::{item, view_column, view_section};
 use cosmic::widget::{icon, toggler};
 use cosmic::{theme, Element};
-use once_cell::sync::Lazy;
 use symmetry_core::configuration::repository_type::Service;
 use symmetry_core::configuration::Configuration;
+use symmetry_core::context::SymmetryContext;
+use symmetry_core::secrets::Secrets;
 use symmetry_core::sync::providers::config::Services;
+use symmetry_core::sync::providers::registry::{self, Kind, Setting};
 
 use super::Page;
 
-static INPUT_ID: Lazy<text_input::Id> = Lazy::new(text_input::Id::unique);
+/// A setting of a registered backend, as its name and the key of the setting.
+type Key = (&'static str, &'static str);
 
 pub struct State {
     pub active_service: Service,
     service_config: Services,
+    /// What the text fields hold, saved when their button is pressed.
+    values: BTreeMap<Key, String>,
 }
 
 impl Default for State {
     fn default() -> Self {
-        if let Some(config) = Configuration::current() {
-            Self {
-                service_config: config.service_config,
-                active_service: config.active_service,
-            }
-        } else {
-            Self {
-                service_config: Services::default(),
-                active_service: Default::default(),
-            }
+        let (service_config, active_service) = match Configuration::current() {
+            Some(config) => (config.service_config, config.active_service),
+            None => (Services::default(), Default::default()),
+        };
+        Self {
+            values: values(&service_config),
+            service_config,
+            active_service,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    GitUrlChanged(String),
-    InitializeGitRepo,
-    ToggleService(Service, bool),
+    /// A text field was edited.
+    Edited(Key, String),
+    /// A text field should be saved.
+    Save(Key),
+    /// A toggle or choice was picked, saved right away.
+    Changed(Key, String),
+    ToggleService(&'static str, bool),
 }
 
 pub enum Output {
@@ -49,84 +59,142 @@ pub enum Output {
 
 impl State {
     pub fn view<'a>(&'a self, app: &'a Symmetry) -> Element<'a, Message> {
-        let mut git_section = view_section("Git").add(item(
-            "Status",
-            row![
+        let mut sections = vec![
+            app.page_title(Page::Services),
+            text("The settings page allows you manage your sync services. Enabled services run together: CRDT keeps devices in sync as changes happen, Git keeps a history of every change.")
+                .size(16)
+                .into(),
+        ];
+        for backend in registry::backends() {
+            let name = backend.name();
+            let enabled = backend.enabled(&self.service_config);
+            let mut section = view_section(backend.title()).add(item(
+                "Status",
+                row![
+                    horizontal_space(Length::Fill),
+                    toggler(Some(backend.description().into()), enabled, move |state| {
+                        Message::ToggleService(name, state)
+                    })
+                ],
+            ));
+            if enabled {
+                for setting in backend.schema() {
+                    let current = backend.get(&self.service_config, setting.key);
+                    section = section.add(self.view_setting(name, setting, current));
+                }
+            }
+            sections.push(section.into());
+        }
+        view_column(sections).into()
+    }
+
+    fn view_setting<'a>(
+        &'a self,
+        name: &'static str,
+        setting: Setting,
+        current: Option<String>,
+    ) -> Element<'a, Message> {
+        let key = (name, setting.key);
+        let field = match setting.kind {
+            Kind::Toggle => row![
                 horizontal_space(Length::Fill),
-                toggler(
-                    Some("Free and open source distributed version control system".into()),
-                    self.service_config.git.enabled,
-                    |state| Message::ToggleService(Service::Git, state)
-                )
+                toggler(None, current.as_deref() == Some("true"), move |state| {
+                    Message::Changed(key, state.to_string())
+                })
             ],
-        ));
-        if self.service_config.git.enabled {
-            git_section = git_section.add(item(
-                "Url",
+            Kind::Choice(choices) => {
+                let selected = choices
+                    .iter()
+                    .copied()
+                    .find(|choice| current.as_deref() == Some(*choice));
+                let mut choices_row = row![horizontal_space(Length::Fill)].spacing(10);
+                for choice in choices {
+                    choices_row =
+                        choices_row.push(radio(*choice, *choice, selected, move |choice| {
+                            Message::Changed(key, choice.to_string())
+                        }));
+                }
+                choices_row
+            }
+            Kind::Text | Kind::Number { .. } | Kind::Secret => {
+                let value = self
+                    .values
+                    .get(&key)
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                let mut input = text_input(setting.placeholder, value, move |value| {
+                    Message::Edited(key, value)
+                })
+                .padding(10)
+                .size(16)
+                .width(Length::FillPortion(20));
+                if setting.kind == Kind::Secret {
+                    input = input.password();
+                }
                 row![
-                    text_input(
-                        "Paste the Git repo URL here.",
-                        &self.service_config.git.url,
-                        Message::GitUrlChanged,
-                    )
-                    .padding(10)
-                    .size(16)
-                    .width(Length::FillPortion(20))
-                    .id(INPUT_ID.clone()),
-                    button(icon("object-select-symbolic", 16).style(theme::Svg::SymbolicPrimary))
+                    input,
+                    button(icon("document-save-symbolic", 16).style(theme::Svg::SymbolicPrimary))
                         .padding(10)
-                        .on_press(Message::InitializeGitRepo)
+                        .on_press(Message::Save(key))
                 ]
-                .spacing(10),
-            ))
-        }
-        let preferences = view_column(vec![
-            app.page_title(Page::Services),
-            text("The settings page allows you manage your sync services.")
-                .size(16)
-                .into(),
-            git_section.into(),
-            view_section("CRDT")
-                .add(item(
-                    "Status",
-                    row![
-                        horizontal_space(Length::Fill),
-                        toggler(
-                            Some("Allows multiple devices to collaborate without conflicts".into()),
-                            self.service_config.crdt.enabled,
-                            |state| Message::ToggleService(Service::Crdt, state)
-                        )
-                    ],
-                ))
-                .into(),
-        ]);
-        preferences.into()
+                .spacing(10)
+            }
+        };
+        item(setting.label, field).into()
     }
 
     pub fn update(&mut self, message: Message) -> Option<Output> {
         match message {
-            Message::GitUrlChanged(url) => {
-                self.service_config.git.url = url;
+            Message::Edited(key, value) => {
+                self.values.insert(key, value);
                 None
             }
-            Message::InitializeGitRepo => {
-                self.service_config = self.service_config.clone();
-                self.active_service = Service::Git;
-                match self.write_to_config() {
-                    Some(output) => Some(output),
-                    None => Some(Output::Sync),
+            Message::Save((name, key)) => {
+                let backend = registry::find(name)?;
+                let value = self.values.get(&(name, key)).cloned().unwrap_or_default();
+                let secret = Setting::find(&backend.schema(), key)
+                    .map(|setting| setting.kind == Kind::Secret)
+                    .unwrap_or_default();
+                if secret {
+                    self.values.remove(&(name, key));
+                    return self.save_secret(key, &value);
                 }
+                self.change(|services| backend.set(services, key, &value))
             }
-            Message::ToggleService(service, state) => match service {
-                Service::Git => {
-                    self.service_config.git.enabled = state;
-                    self.write_to_config()
-                }
-                Service::Crdt => {
-                    self.service_config.crdt.enabled = state;
-                    self.write_to_config()
-                }
-            },
+            Message::Changed((name, key), value) => {
+                let backend = registry::find(name)?;
+                self.change(|services| backend.set(services, key, &value))
+            }
+            // Syncing picks up the services that are now enabled.
+            Message::ToggleService(name, state) => {
+                let backend = registry::find(name)?;
+                self.change(|services| backend.set_enabled(services, state))
+            }
+        }
+    }
+
+    /// Applies a change to the services and saves them, then syncs with the new settings.
+    fn change(&mut self, change: impl FnOnce(&mut Services) -> Result<()>) -> Option<Output> {
+        if let Err(err) = change(&mut self.service_config) {
+            return Some(Output::Error(err.to_string()));
+        }
+        self.values = values(&self.service_config);
+        match self.write_to_config() {
+            Some(output) => Some(output),
+            None => Some(Output::Sync),
+        }
+    }
+
+    /// Keeps a secret in the system keyring rather than the configuration.
+    fn save_secret(&self, key: &str, value: &str) -> Option<Output> {
+        let mut config = Configuration::current()?;
+        let result = SymmetryContext::current()
+            .and_then(|context| Secrets::init(&context, &config))
+            .and_then(|secrets| secrets.set(&mut config, key, value))
+            .and_then(|_| config.write());
+        match result {
+            Ok(_) => None,
+            Err(err) => Some(Output::Error(err.to_string())),
         }
     }
 
@@ -142,3 +210,19 @@ impl State {
         None
     }
 }
+
+/// The text of every setting of the registered backends, secrets left out.
+fn values(services: &Services) -> BTreeMap<Key, String> {
+    let mut values = BTreeMap::new();
+    for backend in registry::backends() {
+        for setting in backend.schema() {
+            if setting.kind == Kind::Secret {
+                continue;
+            }
+            if let Some(value) = backend.get(services, setting.key) {
+                values.insert((backend.name(), setting.key), value);
+            }
+        }
+    }
+    values
+}
@@ -1,30 +1,54 @@
+use std::path::Path;
+
 use crate::app::Symmetry;
-use cosmic::iced::widget::{radio, row, text};
+use ashpd::desktop::file_chooser::{OpenFileRequest, SaveFileRequest};
+use ashpd::WindowIdentifier;
+use cosmic::iced::widget::{button, radio, row, text, text_input};
 use cosmic::iced_winit::widget::horizontal_space;
 use cosmic::iced_winit::Length;
 use cosmic::theme::ThemeType;
 use cosmic::widget::settings::{item, view_column, view_section};
 use cosmic::{Element, Theme};
+use symmetry_core::configuration::bundle::{Bundle, Mode};
+use symmetry_core::context::SymmetryContext;
+use symmetry_core::secrets::Secrets;
 
-use super::Page;
+use super::{configuration, Page};
 
 #[derive(Debug, Default)]
 pub struct State {
     pub theme: ThemeType,
+    sync_key: String,
+    bundle_mode: Mode,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ChangeTheme(ThemeType),
+    SyncKeyChanged(String),
+    ShowSyncKey,
+    SaveSyncKey,
+    BundleModeChanged(Mode),
+    Export,
+    ExportTo(String),
+    Import,
+    ImportFrom(String),
 }
 
 pub enum Output {
     ChangeTheme(Theme),
+    SaveFilePicker(SaveFileRequest),
+    OpenFilePicker(OpenFileRequest),
+    /// A bundle was imported, the configuration on disk changed.
+    Imported(String),
+    Message(String),
+    Error(String),
 }
 
 impl State {
     pub fn view<'a>(&'a self, app: &'a Symmetry) -> Element<'a, Message> {
         let theme = Some(self.theme);
+        let bundle_mode = Some(self.bundle_mode);
         let preferences = view_column(vec![
             app.page_title(Page::Settings),
             text("The settings page allows you to tailor your application experience to your preferences.")
@@ -41,6 +65,44 @@ impl State {
                     .spacing(10),
                 ))
                 .into(),
+            view_section("Secrets")
+                .add(item(
+                    "Sync key",
+                    row![
+                        text_input(
+                            "Paste the sync key from another device here.",
+                            &self.sync_key,
+                            Message::SyncKeyChanged,
+                        )
+                        .padding(10)
+                        .size(16)
+                        .width(Length::FillPortion(20)),
+                        button(text("Show")).on_press(Message::ShowSyncKey),
+                        button(text("Save")).on_press(Message::SaveSyncKey),
+                    ]
+                    .spacing(10),
+                ))
+                .into(),
+            view_section("Bundle")
+                .add(item(
+                    "On import",
+                    row![
+                        horizontal_space(Length::Fill),
+                        radio("Merge", Mode::Merge, bundle_mode, Message::BundleModeChanged),
+                        radio("Replace", Mode::Replace, bundle_mode, Message::BundleModeChanged),
+                    ]
+                    .spacing(10),
+                ))
+                .add(item(
+                    "Configuration, files and wallpaper",
+                    row![
+                        horizontal_space(Length::Fill),
+                        button(text("Export")).on_press(Message::Export),
+                        button(text("Import")).on_press(Message::Import),
+                    ]
+                    .spacing(10),
+                ))
+                .into(),
             ]);
         preferences.into()
     }
@@ -56,6 +118,81 @@ impl State {
                 };
                 Some(Output::ChangeTheme(theme))
             }
+            Message::SyncKeyChanged(key) => {
+                self.sync_key = key;
+                None
+            }
+            Message::ShowSyncKey => match SymmetryContext::current().and_then(|context| {
+                Secrets::init(&context, &configuration()?)?;
+                Secrets::key(&context)
+            }) {
+                Ok(key) => {
+                    self.sync_key = key;
+                    None
+                }
+                Err(err) => Some(Output::Error(err.to_string())),
+            },
+            Message::SaveSyncKey => {
+                let saved = SymmetryContext::current().and_then(|context| {
+                    Secrets::set_key(&context, &configuration()?, &self.sync_key)
+                });
+                match saved {
+                    Ok(_) => Some(Output::Message("Sync key saved".into())),
+                    Err(err) => Some(Output::Error(err.to_string())),
+                }
+            }
+            Message::BundleModeChanged(mode) => {
+                self.bundle_mode = mode;
+                None
+            }
+            Message::Export => {
+                let request = SaveFileRequest::default()
+                    .identifier(Some(WindowIdentifier::None))
+                    .modal(true)
+                    .title("Export the configuration")
+                    .current_name("configuration.symmetry")
+                    .accept_label("Export");
+                Some(Output::SaveFilePicker(request))
+            }
+            Message::ExportTo(path) => {
+                let exported = SymmetryContext::current()
+                    .and_then(|context| Bundle::export(&context, Path::new(&path)));
+                match exported {
+                    Ok(_) => Some(Output::Message(format!("Configuration exported to {path}"))),
+                    Err(err) => Some(Output::Error(err.to_string())),
+                }
+            }
+            Message::Import => {
+                let request = OpenFileRequest::default()
+                    .directory(false)
+                    .identifier(Some(WindowIdentifier::None))
+                    .modal(true)
+                    .title("Import a configuration bundle")
+                    .multiple(false)
+                    .accept_label("Import");
+                Some(Output::OpenFilePicker(request))
+            }
+            Message::ImportFrom(path) => {
+                let imported = SymmetryContext::current().and_then(|context| {
+                    let bundle = Bundle::open(Path::new(&path))?;
+                    let failed = bundle.import(&context, self.bundle_mode)?;
+                    Ok((bundle, failed))
+                });
+                match imported {
+                    Ok((bundle, failed)) if failed.is_empty() => Some(Output::Imported(format!(
+                        "Imported the configuration of {} ({}), pair with it to synchronize",
+                        bundle.signer.name,
+                        bundle.signer.id()
+                    ))),
+                    Ok((bundle, failed)) => Some(Output::Imported(format!(
+                        "Imported the configuration of {} ({}), but couldn't apply {}",
+                        bundle.signer.name,
+                        bundle.signer.id(),
+                        failed.join(", ")
+                    ))),
+                    Err(err) => Some(Output::Error(err.to_string())),
+                }
+            }
         }
     }
 }